@@ -5,6 +5,8 @@
 #include <cstdio>
 #include <cstring>
 #include "amf_bridge.h"
+#include "caps.h"
+#include "ltr.h"
 
 #define AMF_DBG(fmt, ...) do { fprintf(stderr, "[AMF] " fmt "\n", ##__VA_ARGS__); fflush(stderr); } while(0)
 
@@ -28,6 +30,7 @@
 #include "components/VideoEncoderVCE.h"
 #include "components/VideoEncoderHEVC.h"
 #include "components/VideoDecoderUVD.h"
+#include "components/ComponentCaps.h"
 using namespace amf;
 #endif
 
@@ -36,6 +39,11 @@ struct EncodedFrame {
     int32_t size;
     bool is_keyframe;
     int64_t timestamp;
+    int32_t picture_type;  // HWCODEC_PIC_*
+    int32_t avg_qp;        // -1: 未知
+    int32_t temporal_id;   // -1: 未知
+    int64_t dts;
+    bool has_dts;
 };
 struct DecodedFrame {
     uint8_t* texture;
@@ -75,6 +83,11 @@ struct AmfEncContext {
     int32_t width;
     int32_t height;
     int32_t codec_id;  // 0 = H.264, 1 = HEVC
+    int32_t async_depth;  // 0 = 仅同步 EncodeFrame
+    int32_t pending;      // 已 SubmitInput 尚未取回输出的帧数
+    int32_t bframes;      // B_PIC_PATTERN，仅异步模式下启用
+    bool draining;        // 已 Drain，取到 AMF_EOF 后须 Flush 才能继续提交
+    LtrState ltr;         // 长期参考与参考失效，仅 AVC
 };
 
 struct AmfDecContext {
@@ -84,14 +97,58 @@ struct AmfDecContext {
     amf::AMFComponent* decoder;
     int32_t width;
     int32_t height;
+    bool draining;  // 已调用 Drain，之后只取输出
 };
+
+static void amf_set_int(amf::AMFPropertyStorage* storage, const wchar_t* name, amf_int64 value) {
+    AMFVariantStruct var;
+    AMFVariantInit(&var);
+    AMFVariantAssignInt64(&var, value);
+    storage->SetProperty(name, var);
+}
+
+// H.273 矩阵码点与范围对应的 AMF 颜色配置
+static amf_int64 amf_color_profile(const ColorDesc& color) {
+    switch (color.matrix) {
+    case 1: return color.full_range ? AMF_VIDEO_CONVERTER_COLOR_PROFILE_FULL_709 : AMF_VIDEO_CONVERTER_COLOR_PROFILE_709;
+    case 9: return color.full_range ? AMF_VIDEO_CONVERTER_COLOR_PROFILE_FULL_2020 : AMF_VIDEO_CONVERTER_COLOR_PROFILE_2020;
+    default: return color.full_range ? AMF_VIDEO_CONVERTER_COLOR_PROFILE_FULL_601 : AMF_VIDEO_CONVERTER_COLOR_PROFILE_601;
+    }
+}
+
+// 帧的长期参考标记；失效后只参考仍有效的长期参考，没有时强制 IDR。SubmitInput 成功后须 ltr_commit
+static LtrControl amf_set_frame_refs(AmfEncContext* ctx, amf::AMFSurface* surface) {
+    LtrControl c = ltr_next_frame(ctx->ltr);
+    if (ctx->ltr.slots == 0) return c;
+    if (c.mark_slot >= 0) amf_set_int(surface, AMF_VIDEO_ENCODER_MARK_CURRENT_WITH_LTR_INDEX, c.mark_slot);
+    if (c.recover && c.use != 0)
+        amf_set_int(surface, AMF_VIDEO_ENCODER_FORCE_LTR_REFERENCE_BITFIELD, (amf_int64)c.use);
+    else if (c.recover)
+        amf_set_int(surface, AMF_VIDEO_ENCODER_FORCE_PICTURE_TYPE, AMF_VIDEO_ENCODER_PICTURE_TYPE_IDR);
+    return c;
+}
 #endif
 
-extern "C++" AmfEncoder* amf_CreateEncoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop) {
+// B 帧只在异步模式下启用：同步 EncodeFrame 提交一帧即等待其输出，被推迟的 B 帧会一直超时
+static AmfEncoder* amf_create_encoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444) {
     if (!IsAmfAvailable() || !device || width <= 0 || height <= 0) {
         AMF_DBG("CreateEncoder: 前置条件失败 (available=%d device=%p w=%d h=%d)", IsAmfAvailable() ? 1 : 0, (void*)device, width, height);
         return nullptr;
     }
+    // Main10 须设置 HEVC 组件的位深与档次，而 HEVC 组件不能 SetProperty（见下）；AVC 只有 8 bit
+    if (bit_depth == 10) {
+        AMF_DBG("CreateEncoder: 不支持 10 bit 编码");
+        return nullptr;
+    }
+    if (yuv444) {
+        AMF_DBG("CreateEncoder: 不支持 4:4:4 编码");
+        return nullptr;
+    }
+    // AMF 只能连续刷新，无法按 period 调度，也不写恢复点 SEI，接收端找不到可开始解码的帧
+    if (intra_refresh_duration > 0 && intra_refresh_period > intra_refresh_duration) {
+        AMF_DBG("CreateEncoder: 不支持帧内刷新");
+        return nullptr;
+    }
 #if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
     AMF_DBG("CreateEncoder: 使用 AMF 完整实现 (HWCODEC_AMF_FULL)");
     HMODULE dll = LoadLibraryA("amfrt64.dll");
@@ -115,6 +172,8 @@ extern "C++" AmfEncoder* amf_CreateEncoder(uint8_t* device, int32_t width, int32
     AMFRate rate = AMFConstructRate((amf_uint32)(framerate > 0 ? framerate : 30), 1);
     amf_int64 bitrateBits = (amf_int64)bitrate * 1000;
     amf_int64 idrPeriod = (gop > 0 && gop < 10000) ? (amf_int64)gop : 60;
+    // 长期参考与 B 帧不能同时使用
+    if (ltr_frames > 0) bframes = 0;
     AMF_MEMORY_TYPE memType = AMF_MEMORY_DX11;
     AMF_SURFACE_FORMAT inputFormat = AMF_SURFACE_BGRA;
     amf::AMFComponent* encoder = nullptr;
@@ -155,6 +214,40 @@ extern "C++" AmfEncoder* amf_CreateEncoder(uint8_t* device, int32_t width, int32
         encoder->SetProperty(AMF_VIDEO_ENCODER_IDR_PERIOD, varGop);
         AMFVariantInit(&varMem); AMFVariantAssignInt64(&varMem, (amf_int64)memType);
         encoder->SetProperty(AMF_VIDEO_ENCODER_MEMORY_TYPE, varMem);
+        // 输出携带平均 QP 等统计信息
+        AMFVariantStruct varStats;
+        AMFVariantInit(&varStats); AMFVariantAssignBool(&varStats, true);
+        encoder->SetProperty(AMF_VIDEO_ENCODER_STATISTICS_FEEDBACK, varStats);
+        // 环形提交最多 async_depth 帧，须容纳被推迟的 B 帧与其后的参考帧；驱动不支持时 SetProperty 失败，保持 IPPP
+        amf_int64 bPattern = bframes < 3 ? bframes : 3;
+        if (bPattern > async_depth - 1) bPattern = async_depth - 1;
+        if (bPattern > 0) {
+            AMFVariantStruct varMaxB, varB;
+            AMFVariantInit(&varMaxB); AMFVariantAssignInt64(&varMaxB, bPattern);
+            encoder->SetProperty(AMF_VIDEO_ENCODER_MAX_CONSECUTIVE_BPICTURES, varMaxB);
+            AMFVariantInit(&varB); AMFVariantAssignInt64(&varB, bPattern);
+            if (encoder->SetProperty(AMF_VIDEO_ENCODER_B_PIC_PATTERN, varB) != AMF_OK) {
+                AMF_DBG("CreateEncoder: 不支持 B 帧 (B_PIC_PATTERN=%d)", (int)bPattern);
+            }
+        }
+        // 颜色描述写入 VUI，RGB 输入也按其矩阵与范围转换；AMF 不支持色度位置
+        if (color.present) {
+            amf_set_int(encoder, AMF_VIDEO_ENCODER_OUTPUT_COLOR_PROFILE, amf_color_profile(color));
+            amf_set_int(encoder, AMF_VIDEO_ENCODER_OUTPUT_TRANSFER_CHARACTERISTIC, color.transfer);
+            amf_set_int(encoder, AMF_VIDEO_ENCODER_OUTPUT_COLOR_PRIMARIES, color.primaries);
+            AMFVariantStruct varRange;
+            AMFVariantInit(&varRange); AMFVariantAssignBool(&varRange, color.full_range);
+            encoder->SetProperty(AMF_VIDEO_ENCODER_FULL_RANGE_COLOR, varRange);
+        }
+        // KEEP_UNUSED：失效恢复时未列入 FORCE_LTR_REFERENCE_BITFIELD 的长期参考仍保留
+        if (ltr_frames > 0) {
+            AMFVariantStruct varLtr;
+            AMFVariantInit(&varLtr); AMFVariantAssignInt64(&varLtr, ltr_frames < HWCODEC_LTR_MAX_SLOTS ? ltr_frames : HWCODEC_LTR_MAX_SLOTS);
+            if (encoder->SetProperty(AMF_VIDEO_ENCODER_MAX_LTR_FRAMES, varLtr) == AMF_OK)
+                amf_set_int(encoder, AMF_VIDEO_ENCODER_LTR_MODE, AMF_VIDEO_ENCODER_LTR_MODE_KEEP_UNUSED);
+            else
+                AMF_DBG("CreateEncoder: 不支持长期参考 (MAX_LTR_FRAMES=%d)", (int)ltr_frames);
+        }
         r = encoder->Init(inputFormat, width, height);
         if (r != AMF_OK) {
             AMF_DBG("CreateEncoder: encoder->Init(BGRA %dx%d) 失败 res=%d", width, height, (int)r);
@@ -173,11 +266,27 @@ extern "C++" AmfEncoder* amf_CreateEncoder(uint8_t* device, int32_t width, int32
     ctx->width = width;
     ctx->height = height;
     ctx->codec_id = codec_id;
+    // AMF 的 SubmitInput/QueryOutput 本身即异步，无需在 Init 前另行配置
+    ctx->async_depth = async_depth > 0 ? async_depth : 0;
+    ctx->pending = 0;
+    ctx->bframes = 0;
+    ctx->draining = false;
+    if (codec_id == 0 && ctx->async_depth > 0) {
+        AMFVariantStruct varB;
+        if (encoder->GetProperty(AMF_VIDEO_ENCODER_B_PIC_PATTERN, &varB) == AMF_OK && varB.type == AMF_VARIANT_INT64)
+            ctx->bframes = (int32_t)varB.int64Value;
+    }
+    if (codec_id == 0 && ltr_frames > 0) {
+        AMFVariantStruct varLtr;
+        if (encoder->GetProperty(AMF_VIDEO_ENCODER_MAX_LTR_FRAMES, &varLtr) == AMF_OK && varLtr.type == AMF_VARIANT_INT64)
+            ctx->ltr.slots = (int32_t)varLtr.int64Value;
+        ctx->ltr.idr_period = (int32_t)idrPeriod;
+    }
     AmfEncoder* enc = new AmfEncoder();
     enc->impl = ctx;
     return enc;
 #else
-    (void)device; (void)width; (void)height; (void)codec_id; (void)bitrate; (void)framerate; (void)gop;
+    (void)device; (void)width; (void)height; (void)codec_id; (void)bitrate; (void)framerate; (void)gop; (void)async_depth; (void)bframes; (void)intra_refresh_period; (void)intra_refresh_duration; (void)ltr_frames; (void)color; (void)bit_depth; (void)yuv444;
     AMF_DBG("CreateEncoder: 无 externals/AMF_v1.4.35，编码不可用");
     AmfEncoder* enc = new AmfEncoder();
     enc->impl = nullptr;
@@ -185,6 +294,227 @@ extern "C++" AmfEncoder* amf_CreateEncoder(uint8_t* device, int32_t width, int32
 #endif
 }
 
+extern "C++" AmfEncoder* amf_CreateEncoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop) {
+    return amf_create_encoder(device, width, height, codec_id, bitrate, framerate, gop, 0, 0, 0, 0, 0, ColorDesc{}, 8, false);
+}
+
+extern "C++" AmfEncoder* amf_CreateEncoderAsync(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444) {
+    return amf_create_encoder(device, width, height, codec_id, bitrate, framerate, gop, async_depth, bframes, intra_refresh_period, intra_refresh_duration, ltr_frames, color, bit_depth, yuv444);
+}
+
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+// 将 QueryOutput 取到的数据转为 EncodedFrame，并释放 pData
+static EncodedFrame* amf_take_output(AmfEncContext* ctx, amf::AMFData* pData, int64_t timestamp) {
+    amf::AMFBuffer* pBuffer = nullptr;
+    if (pData->QueryInterface(amf::AMFBuffer::IID(), (void**)&pBuffer) != AMF_OK || !pBuffer) {
+        AMF_DBG("EncodeFrame: QueryInterface(AMFBuffer) 失败");
+        pData->Release();
+        return nullptr;
+    }
+    amf_size size = pBuffer->GetSize();
+    void* ptr = pBuffer->GetNative();
+    int32_t pictureType = HWCODEC_PIC_UNKNOWN;
+    AMFVariantStruct varType;
+    if (ctx->codec_id == 1) {
+        if (pData->GetProperty(AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE, &varType) == AMF_OK && varType.type == AMF_VARIANT_INT64) {
+            switch (varType.int64Value) {
+            case AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE_IDR: pictureType = HWCODEC_PIC_IDR; break;
+            case AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE_I: pictureType = HWCODEC_PIC_I; break;
+            case AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE_P: pictureType = HWCODEC_PIC_P; break;
+            default: break;
+            }
+        }
+    } else {
+        if (pData->GetProperty(AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE, &varType) == AMF_OK && varType.type == AMF_VARIANT_INT64) {
+            switch (varType.int64Value) {
+            case AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE_IDR: pictureType = HWCODEC_PIC_IDR; break;
+            case AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE_I: pictureType = HWCODEC_PIC_I; break;
+            case AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE_P: pictureType = HWCODEC_PIC_P; break;
+            case AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE_B: pictureType = HWCODEC_PIC_B; break;
+            default: break;
+            }
+        }
+    }
+    // 平均 QP 需开启 STATISTICS_FEEDBACK，未开启时属性不存在
+    int32_t avgQp = -1;
+    const wchar_t* qpName = ctx->codec_id == 1 ? AMF_VIDEO_ENCODER_HEVC_STATISTIC_AVERAGE_QP : AMF_VIDEO_ENCODER_STATISTIC_AVERAGE_QP;
+    AMFVariantStruct varQp;
+    if (pData->GetProperty(qpName, &varQp) == AMF_OK && varQp.type == AMF_VARIANT_INT64) avgQp = (int32_t)varQp.int64Value;
+    int32_t temporalId = -1;
+    const wchar_t* layerName = ctx->codec_id == 1 ? AMF_VIDEO_ENCODER_HEVC_OUTPUT_TEMPORAL_LAYER : AMF_VIDEO_ENCODER_OUTPUT_TEMPORAL_LAYER;
+    AMFVariantStruct varLayer;
+    if (pData->GetProperty(layerName, &varLayer) == AMF_OK && varLayer.type == AMF_VARIANT_INT64) temporalId = (int32_t)varLayer.int64Value;
+    EncodedFrame* frame = new EncodedFrame();
+    frame->size = (int32_t)size;
+    frame->data = (uint8_t*)malloc((size_t)size);
+    if (frame->data && size > 0) memcpy(frame->data, ptr, (size_t)size);
+    frame->is_keyframe = pictureType == HWCODEC_PIC_IDR || pictureType == HWCODEC_PIC_I;
+    frame->timestamp = timestamp;
+    frame->picture_type = pictureType;
+    frame->avg_qp = avgQp;
+    frame->temporal_id = temporalId;
+    frame->dts = 0;
+    frame->has_dts = false;
+    pBuffer->Release();
+    pData->Release();
+    return frame;
+}
+#endif
+
+extern "C++" int32_t amf_GetAsyncDepth(AmfEncoder* encoder) {
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    if (encoder && encoder->impl) return ((AmfEncContext*)encoder->impl)->async_depth;
+#else
+    (void)encoder;
+#endif
+    return 0;
+}
+
+extern "C++" int32_t amf_PendingFrames(AmfEncoder* encoder) {
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    if (encoder && encoder->impl) return ((AmfEncContext*)encoder->impl)->pending;
+#else
+    (void)encoder;
+#endif
+    return 0;
+}
+
+extern "C++" int32_t amf_GetReorderDepth(AmfEncoder* encoder) {
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    if (encoder && encoder->impl) return ((AmfEncContext*)encoder->impl)->bframes;
+#else
+    (void)encoder;
+#endif
+    return 0;
+}
+
+// Drain 使被推迟的 B 帧完成编码；其输出仍由 ReceiveFrame 取回
+extern "C++" int32_t amf_FlushEncoder(AmfEncoder* encoder) {
+    if (!encoder || !encoder->impl) return -1;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    AmfEncContext* ctx = (AmfEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0 || ctx->pending == 0 || ctx->draining) return 0;
+    AMF_RESULT res = ctx->encoder->Drain();
+    if (res != AMF_OK) {
+        AMF_DBG("FlushEncoder: Drain 失败 res=%d", (int)res);
+        return -1;
+    }
+    ctx->draining = true;
+    return 0;
+#else
+    return -1;
+#endif
+}
+
+// 返回 0 成功，1 会话未启用长期参考，负值为错误
+extern "C++" int32_t amf_MarkLtr(AmfEncoder* encoder) {
+    if (!encoder || !encoder->impl) return -1;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    AmfEncContext* ctx = (AmfEncContext*)encoder->impl;
+    if (ctx->ltr.slots == 0) return 1;
+    ctx->ltr.mark = true;
+    return 0;
+#else
+    return 1;
+#endif
+}
+
+// 返回值同 MarkLtr。AMF 没有失效接口：下一帧强制只参考范围之前标记的长期参考
+extern "C++" int32_t amf_InvalidateRefFrames(AmfEncoder* encoder, int64_t first, int64_t last) {
+    if (!encoder || !encoder->impl) return -1;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    AmfEncContext* ctx = (AmfEncContext*)encoder->impl;
+    if (ctx->ltr.slots == 0) return 1;
+    ltr_invalidate(ctx->ltr, first, last);
+    return 0;
+#else
+    (void)first; (void)last;
+    return 1;
+#endif
+}
+
+// 返回 0 已提交，1 编码器输入队列已满（AMF_INPUT_FULL）或仍在 Drain，负值为错误
+extern "C++" int32_t amf_SubmitFrame(AmfEncoder* encoder, uint8_t* texture, int64_t timestamp) {
+    if (!encoder || !encoder->impl || !texture) return -1;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    AmfEncContext* ctx = (AmfEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0) return -1;
+    if (ctx->pending >= ctx->async_depth || ctx->draining) return 1;
+    amf::AMFSurface* surface = nullptr;
+    AMF_RESULT res = ctx->context->CreateSurfaceFromDX11Native(texture, &surface, nullptr);
+    if (res != AMF_OK || !surface) {
+        AMF_DBG("SubmitFrame: CreateSurfaceFromDX11Native 失败 res=%d", (int)res);
+        return -1;
+    }
+    // 输出数据继承输入 surface 的 pts，取回时据此还原时间戳
+    surface->SetPts(timestamp);
+    AMFVariantStruct varPts;
+    AMFVariantInit(&varPts);
+    AMFVariantAssignInt64(&varPts, timestamp);
+    surface->SetProperty(AMF_VIDEO_ENCODER_PRESENTATION_TIME_STAMP, varPts);
+    LtrControl refs = amf_set_frame_refs(ctx, surface);
+    res = ctx->encoder->SubmitInput(surface);
+    surface->Release();
+    if (res == AMF_INPUT_FULL) return 1;
+    if (res != AMF_OK) {
+        AMF_DBG("SubmitFrame: SubmitInput 失败 res=%d", (int)res);
+        return -1;
+    }
+    ctx->pending++;
+    if (ctx->ltr.slots > 0) ltr_commit(ctx->ltr, refs, timestamp);
+    return 0;
+#else
+    (void)timestamp;
+    return -1;
+#endif
+}
+
+// status: 0 取到一帧，1 wait_ms 内没有输出（或没有在途帧），负值为错误
+extern "C++" EncodedFrame* amf_ReceiveFrame(AmfEncoder* encoder, uint32_t wait_ms, int32_t* status) {
+    if (!status) return nullptr;
+    *status = -1;
+    if (!encoder || !encoder->impl) return nullptr;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    AmfEncContext* ctx = (AmfEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0) return nullptr;
+    if (ctx->pending == 0) { *status = 1; return nullptr; }
+    ULONGLONG start = GetTickCount64();
+    amf::AMFData* pData = nullptr;
+    for (;;) {
+        AMF_RESULT res = ctx->encoder->QueryOutput(&pData);
+        if (res == AMF_OK && pData) break;
+        if (pData) { pData->Release(); pData = nullptr; }
+        // Drain 后全部输出已取回（通常在 pending 归零时已 Flush）；Flush 后编码器重新接受输入
+        if (res == AMF_EOF) {
+            ctx->encoder->Flush();
+            ctx->draining = false;
+            ctx->pending = 0;
+            *status = 1;
+            return nullptr;
+        }
+        if (res != AMF_OK && res != AMF_REPEAT && res != AMF_NEED_MORE_INPUT) {
+            AMF_DBG("ReceiveFrame: QueryOutput 失败 res=%d", (int)res);
+            // 与 NV/MFX 一致：出错时视为最早的在途帧已结束，避免调用方无限重试
+            ctx->pending--;
+            return nullptr;
+        }
+        if (GetTickCount64() - start >= wait_ms) { *status = 1; return nullptr; }
+        Sleep(1);
+    }
+    ctx->pending--;
+    if (ctx->draining && ctx->pending == 0) {
+        ctx->encoder->Flush();
+        ctx->draining = false;
+    }
+    EncodedFrame* frame = amf_take_output(ctx, pData, pData->GetPts());
+    if (frame) *status = 0;
+    return frame;
+#else
+    (void)wait_ms;
+    return nullptr;
+#endif
+}
+
 extern "C++" EncodedFrame* amf_EncodeFrame(AmfEncoder* encoder, uint8_t* texture, int64_t timestamp) {
     if (!encoder || !IsAmfAvailable()) return nullptr;
     if (!encoder->impl) {
@@ -204,6 +534,7 @@ extern "C++" EncodedFrame* amf_EncodeFrame(AmfEncoder* encoder, uint8_t* texture
     AMFVariantInit(&varPts);
     AMFVariantAssignInt64(&varPts, timestamp);
     surface->SetProperty(AMF_VIDEO_ENCODER_PRESENTATION_TIME_STAMP, varPts);
+    LtrControl refs = amf_set_frame_refs(ctx, surface);
     AMF_RESULT res = ctx->encoder->SubmitInput(surface);
     if (res == AMF_INPUT_FULL) {
         amf::AMFData* drainData = nullptr;
@@ -220,6 +551,7 @@ extern "C++" EncodedFrame* amf_EncodeFrame(AmfEncoder* encoder, uint8_t* texture
         AMF_DBG("EncodeFrame: SubmitInput 失败 res=%d", (int)res);
         return nullptr;
     }
+    if (ctx->ltr.slots > 0) ltr_commit(ctx->ltr, refs, timestamp);
     amf::AMFData* pData = nullptr;
     int queryCount = 0;
     for (int i = 0; i < 500; i++) {
@@ -238,38 +570,7 @@ extern "C++" EncodedFrame* amf_EncodeFrame(AmfEncoder* encoder, uint8_t* texture
         AMF_DBG("EncodeFrame: QueryOutput 超时未取到数据 (res=%d 轮询 %d 次)", (int)res, queryCount);
         return nullptr;
     }
-    amf::AMFBuffer* pBuffer = nullptr;
-    if (pData->QueryInterface(amf::AMFBuffer::IID(), (void**)&pBuffer) != AMF_OK || !pBuffer) {
-        AMF_DBG("EncodeFrame: QueryInterface(AMFBuffer) 失败");
-        pData->Release();
-        return nullptr;
-    }
-    amf_size size = pBuffer->GetSize();
-    void* ptr = pBuffer->GetNative();
-    bool isKeyframe = false;
-    AMFVariantStruct varType;
-    if (ctx->codec_id == 1) {
-        if (pData->GetProperty(AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE, &varType) == AMF_OK) {
-            if (varType.type == AMF_VARIANT_INT64 && (varType.int64Value == AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE_IDR || varType.int64Value == AMF_VIDEO_ENCODER_HEVC_OUTPUT_DATA_TYPE_I)) {
-                isKeyframe = true;
-            }
-        }
-    } else {
-        if (pData->GetProperty(AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE, &varType) == AMF_OK) {
-            if (varType.type == AMF_VARIANT_INT64 && (varType.int64Value == AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE_IDR || varType.int64Value == AMF_VIDEO_ENCODER_OUTPUT_DATA_TYPE_I)) {
-                isKeyframe = true;
-            }
-        }
-    }
-    EncodedFrame* frame = new EncodedFrame();
-    frame->size = (int32_t)size;
-    frame->data = (uint8_t*)malloc((size_t)size);
-    if (frame->data && size > 0) memcpy(frame->data, ptr, (size_t)size);
-    frame->is_keyframe = isKeyframe;
-    frame->timestamp = timestamp;
-    pBuffer->Release();
-    pData->Release();
-    return frame;
+    return amf_take_output(ctx, pData, timestamp);
 #else
     (void)texture; (void)timestamp;
     return nullptr;
@@ -344,7 +645,7 @@ extern "C++" void amf_SetFramerate(AmfEncoder* encoder, int32_t framerate) {
 }
 
 // AmfDecoder: full implementation when HWCODEC_AMF_FULL
-extern "C++" AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id) {
+extern "C++" AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id, int32_t bit_depth) {
     if (!IsAmfAvailable() || !device) return nullptr;
 #if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
     HMODULE dll = LoadLibraryA("amfrt64.dll");
@@ -364,7 +665,8 @@ extern "C++" AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id) {
     if (factory->CreateComponent(context, decoderId, &decoder) != AMF_OK || !decoder) {
         context->Release(); FreeLibrary(dll); return nullptr;
     }
-    AMF_RESULT r = decoder->Init(AMF_SURFACE_NV12, 0, 0);
+    // 输出 NV12 或 P010 纹理
+    AMF_RESULT r = decoder->Init(bit_depth == 10 ? AMF_SURFACE_P010 : AMF_SURFACE_NV12, 0, 0);
     if (r != AMF_OK) {
         AMF_DBG("CreateDecoder: decoder->Init failed res=%d", (int)r);
         decoder->Release(); context->Release(); FreeLibrary(dll); return nullptr;
@@ -376,6 +678,7 @@ extern "C++" AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id) {
     ctx->decoder = decoder;
     ctx->width = 0;
     ctx->height = 0;
+    ctx->draining = false;
     AmfDecoder* dec = new AmfDecoder();
     dec->impl = ctx;
     AMF_DBG("CreateDecoder: ok");
@@ -387,6 +690,30 @@ extern "C++" AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id) {
 #endif
 }
 
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+// 解码输出转为 DecodedFrame，消耗 pData 的引用
+static DecodedFrame* amf_frame_from_data(AmfDecContext* ctx, amf::AMFData* pData) {
+    amf::AMFSurface* pSurface = nullptr;
+    if (pData->QueryInterface(amf::AMFSurface::IID(), (void**)&pSurface) != AMF_OK || !pSurface) {
+        pData->Release(); return nullptr;
+    }
+    AMFPlane* plane = pSurface->GetPlaneAt(0);
+    if (!plane) { pSurface->Release(); pData->Release(); return nullptr; }
+    void* native = plane->GetNative();
+    int32_t w = (int32_t)plane->GetWidth();
+    int32_t h = (int32_t)plane->GetHeight();
+    if (ctx->width == 0 || ctx->height == 0) { ctx->width = w; ctx->height = h; }
+    pSurface->Release();
+    pData->Release();
+    if (!native) return nullptr;
+    DecodedFrame* frame = new DecodedFrame();
+    frame->texture = (uint8_t*)native;
+    frame->width = w;
+    frame->height = h;
+    return frame;
+}
+#endif
+
 extern "C++" DecodedFrame* amf_DecodeFrame(AmfDecoder* decoder, uint8_t* data, int32_t length) {
     if (!decoder || !IsAmfAvailable() || !data || length <= 0) return nullptr;
 #if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
@@ -422,30 +749,39 @@ extern "C++" DecodedFrame* amf_DecodeFrame(AmfDecoder* decoder, uint8_t* data, i
         Sleep(1);
     }
     if (r != AMF_OK || !pData) return nullptr;
-    amf::AMFSurface* pSurface = nullptr;
-    if (pData->QueryInterface(amf::AMFSurface::IID(), (void**)&pSurface) != AMF_OK || !pSurface) {
-        pData->Release(); return nullptr;
-    }
-    AMFPlane* plane = pSurface->GetPlaneAt(0);
-    if (!plane) { pSurface->Release(); pData->Release(); return nullptr; }
-    void* native = plane->GetNative();
-    int32_t w = (int32_t)plane->GetWidth();
-    int32_t h = (int32_t)plane->GetHeight();
-    if (ctx->width == 0 || ctx->height == 0) { ctx->width = w; ctx->height = h; }
-    pSurface->Release();
-    pData->Release();
-    if (!native) return nullptr;
-    DecodedFrame* frame = new DecodedFrame();
-    frame->texture = (uint8_t*)native;
-    frame->width = w;
-    frame->height = h;
-    return frame;
+    return amf_frame_from_data(ctx, pData);
 #else
     (void)data; (void)length;
     return nullptr;
 #endif
 }
 
+// 流结束：第一次调用时 Drain，之后每次取出一帧扣住的图像，取完（AMF_EOF）返回 nullptr
+extern "C++" DecodedFrame* amf_DrainFrame(AmfDecoder* decoder) {
+    if (!decoder || !IsAmfAvailable()) return nullptr;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    if (!decoder->impl) return nullptr;
+    AmfDecContext* ctx = (AmfDecContext*)decoder->impl;
+    if (!ctx->draining) {
+        if (ctx->decoder->Drain() != AMF_OK) return nullptr;
+        ctx->draining = true;
+    }
+    amf::AMFData* pData = nullptr;
+    AMF_RESULT r = AMF_REPEAT;
+    for (int i = 0; i < 200; i++) {
+        r = ctx->decoder->QueryOutput(&pData);
+        if (r == AMF_OK && pData) break;
+        if (pData) { pData->Release(); pData = nullptr; }
+        if (r != AMF_REPEAT && r != AMF_OK) return nullptr;
+        Sleep(1);
+    }
+    if (!pData) return nullptr;
+    return amf_frame_from_data(ctx, pData);
+#else
+    return nullptr;
+#endif
+}
+
 extern "C++" void amf_DestroyDecoder(AmfDecoder* decoder) {
     if (!decoder) return;
 #if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
@@ -478,3 +814,150 @@ extern "C++" int32_t amf_GetHeight(AmfDecoder* decoder) {
     return 0;
 #endif
 }
+
+
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+static bool amf_open_context(uint8_t* device, HMODULE* outDll, amf::AMFFactory** outFactory, amf::AMFContext** outContext) {
+    HMODULE dll = LoadLibraryA("amfrt64.dll");
+    if (!dll) return false;
+    typedef AMF_RESULT (AMF_CDECL_CALL *AMFInit_Fn)(amf_uint64 version, amf::AMFFactory** ppFactory);
+    AMFInit_Fn initFn = (AMFInit_Fn)GetProcAddress(dll, "AMFInit");
+    amf::AMFFactory* factory = nullptr;
+    if (!initFn || initFn(AMF_FULL_VERSION, &factory) != AMF_OK || !factory) { FreeLibrary(dll); return false; }
+    amf::AMFContext* context = nullptr;
+    if (factory->CreateContext(&context) != AMF_OK || !context) { FreeLibrary(dll); return false; }
+    if (context->InitDX11(device, AMF_DX11_0) != AMF_OK) { context->Release(); FreeLibrary(dll); return false; }
+    *outDll = dll;
+    *outFactory = factory;
+    *outContext = context;
+    return true;
+}
+
+static amf_int64 amf_caps_int(amf::AMFCaps* caps, const wchar_t* name) {
+    AMFVariantStruct v;
+    AMFVariantInit(&v);
+    if (caps->GetProperty(name, &v) != AMF_OK) return 0;
+    if (v.type == AMF_VARIANT_INT64) return v.int64Value;
+    if (v.type == AMF_VARIANT_BOOL) return v.boolValue ? 1 : 0;
+    return 0;
+}
+
+/* 读取 IO caps 的尺寸范围，并检查是否支持 P010（10 bit） */
+static bool amf_io_caps(amf::AMFIOCaps* io, int32_t* minW, int32_t* minH, int32_t* maxW, int32_t* maxH) {
+    amf_int32 w0 = 0, w1 = 0, h0 = 0, h1 = 0;
+    io->GetWidthRange(&w0, &w1);
+    io->GetHeightRange(&h0, &h1);
+    *minW = w0; *maxW = w1; *minH = h0; *maxH = h1;
+    bool p010 = false;
+    for (amf_int32 i = 0; i < io->GetNumOfFormats(); i++) {
+        AMF_SURFACE_FORMAT format = AMF_SURFACE_UNKNOWN;
+        amf_bool native = false;
+        if (io->GetFormatAt(i, &format, &native) == AMF_OK && format == AMF_SURFACE_P010) p010 = true;
+    }
+    return p010;
+}
+#endif
+
+// 能力查询：只创建组件并读取 GetCaps，不调用 SetProperty/Init（HEVC 组件 SetProperty 在部分驱动下会崩溃）
+extern "C++" EncodeCaps amf_GetEncodeCaps(uint8_t* device, int32_t codec_id) {
+    EncodeCaps caps = {};
+    if (!IsAmfAvailable() || !device) return caps;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    HMODULE dll = nullptr;
+    amf::AMFFactory* factory = nullptr;
+    amf::AMFContext* context = nullptr;
+    if (!amf_open_context(device, &dll, &factory, &context)) return caps;
+    bool hevc = codec_id == 1;
+    amf::AMFComponent* encoder = nullptr;
+    if (factory->CreateComponent(context, hevc ? AMFVideoEncoder_HEVC : AMFVideoEncoderVCE_AVC, &encoder) == AMF_OK && encoder) {
+        amf::AMFCaps* encoderCaps = nullptr;
+        if (encoder->GetCaps(&encoderCaps) == AMF_OK && encoderCaps) {
+            caps.supported = true;
+            amf::AMFIOCaps* input = nullptr;
+            if (encoderCaps->GetInputCaps(&input) == AMF_OK && input) {
+                amf_io_caps(input, &caps.min_width, &caps.min_height, &caps.max_width, &caps.max_height);
+                input->Release();
+            }
+            if (hevc) {
+                amf_int64 maxProfile = amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_HEVC_CAP_MAX_PROFILE);
+                caps.profiles = HWCODEC_PROFILE_HEVC_MAIN;
+                if (maxProfile >= AMF_VIDEO_ENCODER_HEVC_PROFILE_MAIN_10) caps.profiles |= HWCODEC_PROFILE_HEVC_MAIN10;
+                // 硬件支持 Main10，但 CreateEncoder 无法配置（HEVC 组件不能 SetProperty），不报告 10 bit
+                caps.ten_bit = false;
+                // AMF_LEVEL_* 为 level*30，统一成 level*10
+                caps.max_level = (int32_t)amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_HEVC_CAP_MAX_LEVEL) / 3;
+                caps.max_bframes = 0;
+                caps.lookahead = amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_HEVC_CAP_PRE_ANALYSIS) != 0;
+                caps.max_sessions = (int32_t)amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_HEVC_CAP_NUM_OF_STREAMS);
+            } else {
+                amf_int64 maxProfile = amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_CAP_MAX_PROFILE);
+                caps.profiles = HWCODEC_PROFILE_H264_BASELINE | HWCODEC_PROFILE_H264_MAIN;
+                if (maxProfile == AMF_VIDEO_ENCODER_PROFILE_HIGH || maxProfile == AMF_VIDEO_ENCODER_PROFILE_CONSTRAINED_HIGH)
+                    caps.profiles |= HWCODEC_PROFILE_H264_HIGH;
+                caps.max_level = (int32_t)amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_CAP_MAX_LEVEL);
+                // B_PIC_PATTERN 上限为 3
+                caps.max_bframes = amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_CAP_BFRAMES) != 0 ? 3 : 0;
+                caps.lookahead = amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_CAP_PRE_ANALYSIS) != 0;
+                caps.max_sessions = (int32_t)amf_caps_int(encoderCaps, AMF_VIDEO_ENCODER_CAP_NUM_OF_STREAMS);
+                // 没有对应的 caps 项，取属性声明的上限（只读，不触发 SetProperty）
+                const amf::AMFPropertyInfo* ltrInfo = nullptr;
+                if (encoder->GetPropertyInfo(AMF_VIDEO_ENCODER_MAX_LTR_FRAMES, &ltrInfo) == AMF_OK && ltrInfo && ltrInfo->maxValue.type == AMF_VARIANT_INT64)
+                    caps.max_ltr_frames = (int32_t)ltrInfo->maxValue.int64Value;
+            }
+            caps.yuv444 = false;
+            caps.rate_control = HWCODEC_RC_CQP | HWCODEC_RC_CBR | HWCODEC_RC_VBR | HWCODEC_RC_QVBR;
+            encoderCaps->Release();
+        }
+        encoder->Release();
+    }
+    context->Release();
+    FreeLibrary(dll);
+#else
+    (void)codec_id;
+#endif
+    return caps;
+}
+
+extern "C++" DecodeCaps amf_GetDecodeCaps(uint8_t* device, int32_t codec_id) {
+    DecodeCaps caps = {};
+    if (!IsAmfAvailable() || !device) return caps;
+#if defined(_WIN32) && defined(_WIN64) && defined(HWCODEC_AMF_FULL)
+    HMODULE dll = nullptr;
+    amf::AMFFactory* factory = nullptr;
+    amf::AMFContext* context = nullptr;
+    if (!amf_open_context(device, &dll, &factory, &context)) return caps;
+    bool hevc = codec_id == 1;
+    amf::AMFComponent* decoder = nullptr;
+    if (factory->CreateComponent(context, hevc ? AMFVideoDecoderHW_H265_HEVC : AMFVideoDecoderUVD_H264_AVC, &decoder) == AMF_OK && decoder) {
+        amf::AMFCaps* decoderCaps = nullptr;
+        if (decoder->GetCaps(&decoderCaps) == AMF_OK && decoderCaps) {
+            caps.supported = true;
+            amf::AMFIOCaps* input = nullptr;
+            if (decoderCaps->GetInputCaps(&input) == AMF_OK && input) {
+                amf_io_caps(input, &caps.min_width, &caps.min_height, &caps.max_width, &caps.max_height);
+                input->Release();
+            }
+            amf::AMFIOCaps* output = nullptr;
+            if (decoderCaps->GetOutputCaps(&output) == AMF_OK && output) {
+                int32_t w0, h0, w1, h1;
+                caps.ten_bit = hevc && amf_io_caps(output, &w0, &h0, &w1, &h1);
+                output->Release();
+            }
+            caps.yuv444 = false;
+            if (hevc) {
+                caps.profiles = HWCODEC_PROFILE_HEVC_MAIN;
+                if (caps.ten_bit) caps.profiles |= HWCODEC_PROFILE_HEVC_MAIN10;
+            } else {
+                caps.profiles = HWCODEC_PROFILE_H264_BASELINE | HWCODEC_PROFILE_H264_MAIN | HWCODEC_PROFILE_H264_HIGH;
+            }
+            decoderCaps->Release();
+        }
+        decoder->Release();
+    }
+    context->Release();
+    FreeLibrary(dll);
+#else
+    (void)codec_id;
+#endif
+    return caps;
+}
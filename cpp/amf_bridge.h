@@ -3,6 +3,7 @@
 /* 避免 /FI 下先处理本头文件时找不到 <cstdint>：MSVC 下用内置类型 */
 #if defined(_MSC_VER)
 typedef unsigned __int8 uint8_t;
+typedef unsigned __int32 uint32_t;
 typedef __int32  int32_t;
 typedef __int64  int64_t;
 #else
@@ -13,6 +14,9 @@ struct AmfEncoder { void* impl; };
 struct AmfDecoder { void* impl; };
 struct EncodedFrame;
 struct DecodedFrame;
+struct EncodeCaps;
+struct ColorDesc;
+struct DecodeCaps;
 
 bool amf_IsDriverAvailable();
 /** True when AMF decode is available (HWCODEC_AMF_FULL build). */
@@ -24,13 +28,26 @@ extern "C++" {
     void amf_DestroyEncoder(AmfEncoder* encoder);
     void amf_SetBitrate(AmfEncoder* encoder, int32_t bitrate);
     void amf_SetFramerate(AmfEncoder* encoder, int32_t framerate);
+    AmfEncoder* amf_CreateEncoderAsync(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444);
+    int32_t amf_GetAsyncDepth(AmfEncoder* encoder);
+    int32_t amf_PendingFrames(AmfEncoder* encoder);
+    int32_t amf_GetReorderDepth(AmfEncoder* encoder);
+    int32_t amf_FlushEncoder(AmfEncoder* encoder);
+    int32_t amf_MarkLtr(AmfEncoder* encoder);
+    int32_t amf_InvalidateRefFrames(AmfEncoder* encoder, int64_t first, int64_t last);
+    int32_t amf_SubmitFrame(AmfEncoder* encoder, uint8_t* texture, int64_t timestamp);
+    EncodedFrame* amf_ReceiveFrame(AmfEncoder* encoder, uint32_t wait_ms, int32_t* status);
 
-    AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id);
+    AmfDecoder* amf_CreateDecoder(uint8_t* device, int32_t codec_id, int32_t bit_depth);
     DecodedFrame* amf_DecodeFrame(AmfDecoder* decoder, uint8_t* data, int32_t length);
+    DecodedFrame* amf_DrainFrame(AmfDecoder* decoder);
     void amf_DestroyDecoder(AmfDecoder* decoder);
     int32_t amf_GetWidth(AmfDecoder* decoder);
     int32_t amf_GetHeight(AmfDecoder* decoder);
 
     void amf_FreeEncodedFrame(EncodedFrame* frame);
     void amf_FreeDecodedFrame(DecodedFrame* frame);
+
+    EncodeCaps amf_GetEncodeCaps(uint8_t* device, int32_t codec_id);
+    DecodeCaps amf_GetDecodeCaps(uint8_t* device, int32_t codec_id);
 }
@@ -0,0 +1,66 @@
+#pragma once
+
+#include <cstdint>
+
+/* 能力查询共用位定义，须与 src/vram/mod.rs 中 Profile / RateControl 的声明顺序一致 */
+
+#define HWCODEC_PROFILE_H264_BASELINE (1u << 0)
+#define HWCODEC_PROFILE_H264_MAIN     (1u << 1)
+#define HWCODEC_PROFILE_H264_HIGH     (1u << 2)
+#define HWCODEC_PROFILE_H264_HIGH10   (1u << 3)
+#define HWCODEC_PROFILE_H264_HIGH444  (1u << 4)
+#define HWCODEC_PROFILE_HEVC_MAIN     (1u << 5)
+#define HWCODEC_PROFILE_HEVC_MAIN10   (1u << 6)
+#define HWCODEC_PROFILE_HEVC_REXT     (1u << 7)
+
+#define HWCODEC_RC_CQP  (1u << 0)
+#define HWCODEC_RC_CBR  (1u << 1)
+#define HWCODEC_RC_VBR  (1u << 2)
+#define HWCODEC_RC_QVBR (1u << 3)
+
+/* EncodedFrame::picture_type，须与 src/vram/metadata.rs 中 PictureType 的声明顺序一致 */
+#define HWCODEC_PIC_UNKNOWN 0
+#define HWCODEC_PIC_IDR     1
+#define HWCODEC_PIC_I       2
+#define HWCODEC_PIC_P       3
+#define HWCODEC_PIC_B       4
+
+/* 与 cxx bridge 中共享结构体字段顺序一致。
+ * 仅由 *_bridge.cpp 包含（不要放进强制包含的 *_bridge.h），cxx 生成代码中有自己的同名定义。 */
+struct EncodeCaps {
+    bool supported;
+    int32_t min_width;
+    int32_t min_height;
+    int32_t max_width;
+    int32_t max_height;
+    uint32_t profiles;
+    int32_t max_level;
+    bool ten_bit;
+    bool yuv444;
+    int32_t max_bframes;
+    bool lookahead;
+    int32_t max_sessions;
+    uint32_t rate_control;
+    int32_t max_ltr_frames;
+};
+
+/* 码流 VUI 中的颜色描述，取值为 H.273 码点；present 为 false 时不写入 */
+struct ColorDesc {
+    bool present;
+    int32_t primaries;
+    int32_t transfer;
+    int32_t matrix;
+    bool full_range;
+    int32_t chroma_location;
+};
+
+struct DecodeCaps {
+    bool supported;
+    int32_t min_width;
+    int32_t min_height;
+    int32_t max_width;
+    int32_t max_height;
+    uint32_t profiles;
+    bool ten_bit;
+    bool yuv444;
+};
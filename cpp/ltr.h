@@ -0,0 +1,70 @@
+#pragma once
+
+#include <cstdint>
+#include <deque>
+#include <utility>
+#include <vector>
+
+/* 长期参考帧与参考失效的簿记，三个 bridge 共用。
+ * 这里只记录状态，由各 SDK 的逐帧参数（NVENC codecPicParams、AMF surface 属性、MFX mfxExtAVCRefListCtrl）落实。 */
+
+#define HWCODEC_LTR_MAX_SLOTS 16
+/* 失效范围只在最近提交的这些帧中查找，超出 DPB 的帧不再被参考 */
+#define HWCODEC_LTR_HISTORY 32
+
+struct LtrState {
+    int32_t slots = 0;        /* 会话可用的长期参考槽位，0 表示不支持 */
+    int32_t idr_period = 0;   /* 周期 IDR 的间隔，IDR 会清空所有长期参考；0 表示只有首帧 */
+    int32_t next = 0;         /* 下一次标记使用的槽位，轮转覆盖最旧的长期参考 */
+    bool mark = false;        /* MarkLtr 后下一帧标记为长期参考 */
+    bool recover = false;     /* 有帧失效，下一帧须避开失效帧 */
+    uint32_t valid = 0;       /* 持有有效帧的槽位位图 */
+    int64_t slot_pts[HWCODEC_LTR_MAX_SLOTS] = {};
+    uint32_t slot_order[HWCODEC_LTR_MAX_SLOTS] = {};
+    uint32_t frame_order = 0;
+    std::deque<std::pair<int64_t, uint32_t>> history;  /* 最近提交的 (pts, 编码序号) */
+};
+
+/* 一帧的参考控制 */
+struct LtrControl {
+    int32_t mark_slot;  /* 本帧标记到的槽位，-1 不标记 */
+    bool recover;       /* 本帧须避开失效帧 */
+    uint32_t use;       /* recover 时仍有效的长期参考位图，为 0 时须编码为 IDR */
+    uint32_t order;     /* 本帧的编码序号（MFX FrameOrder） */
+};
+
+/* 下一帧的参考控制。SDK 接受该帧后须调用 ltr_commit，被拒绝（队列满等）时重试仍得到相同结果 */
+static inline LtrControl ltr_next_frame(const LtrState& s) {
+    uint32_t valid = s.valid;
+    if (s.frame_order == 0 || (s.idr_period > 0 && s.frame_order % (uint32_t)s.idr_period == 0)) valid = 0;
+    LtrControl c = { -1, s.recover, valid, s.frame_order };
+    if (s.mark && s.slots > 0) c.mark_slot = s.next;
+    return c;
+}
+
+/* 记录已提交的一帧。recover 且 use 为 0 时该帧为 IDR，之前的长期参考全部作废 */
+static inline void ltr_commit(LtrState& s, const LtrControl& c, int64_t pts) {
+    s.valid = c.use;
+    if (c.mark_slot >= 0) {
+        s.next = (c.mark_slot + 1) % s.slots;
+        s.slot_pts[c.mark_slot] = pts;
+        s.slot_order[c.mark_slot] = c.order;
+        s.valid |= 1u << c.mark_slot;
+    }
+    s.frame_order = c.order + 1;
+    s.mark = false;
+    s.recover = false;
+    s.history.emplace_back(pts, c.order);
+    if (s.history.size() > HWCODEC_LTR_HISTORY) s.history.pop_front();
+}
+
+/* 使 pts 在 [first, last] 内的帧失效，返回其中仍在历史内的 (pts, 编码序号) */
+static inline std::vector<std::pair<int64_t, uint32_t>> ltr_invalidate(LtrState& s, int64_t first, int64_t last) {
+    std::vector<std::pair<int64_t, uint32_t>> frames;
+    for (const auto& f : s.history)
+        if (f.first >= first && f.first <= last) frames.push_back(f);
+    for (int32_t i = 0; i < s.slots; i++)
+        if ((s.valid >> i) & 1u && s.slot_pts[i] >= first && s.slot_pts[i] <= last) s.valid &= ~(1u << i);
+    s.recover = true;
+    return frames;
+}
@@ -3,6 +3,8 @@
 #include <cstdlib>
 #include <cstring>
 #include "mfx_bridge.h"
+#include "caps.h"
+#include "ltr.h"
 
 #if defined(_WIN32) || defined(_WIN64)
 #include <windows.h>
@@ -16,6 +18,11 @@ struct EncodedFrame {
     int32_t size;
     bool is_keyframe;
     int64_t timestamp;
+    int32_t picture_type;  // HWCODEC_PIC_*
+    int32_t avg_qp;        // -1: 未知
+    int32_t temporal_id;   // -1: 未知
+    int64_t dts;
+    bool has_dts;
 };
 struct DecodedFrame {
     uint8_t* texture;
@@ -157,7 +164,7 @@ static mfxFrameAllocator s_passthrough_allocator = {
     AllocPassthrough, LockPassthrough, UnlockPassthrough, GetHDLPassthrough, FreePassthrough
 };
 
-/* Decode allocator: allocates D3D11 NV12 textures for decoder output */
+/* Decode allocator: allocates D3D11 NV12 (P010 for 10-bit streams) textures for decoder output */
 struct DecAllocContext {
     ID3D11Device* dev;
     ID3D11Texture2D** textures;
@@ -181,7 +188,7 @@ static mfxStatus MFX_CDECL DecAlloc_Alloc(mfxHDL pthis, mfxFrameAllocRequest* re
     desc.Height = h;
     desc.MipLevels = 1;
     desc.ArraySize = 1;
-    desc.Format = (DXGI_FORMAT)0x3231564E; /* DXGI_FORMAT_NV12 */
+    desc.Format = request->Info.FourCC == MFX_FOURCC_P010 ? DXGI_FORMAT_P010 : DXGI_FORMAT_NV12;
     desc.SampleDesc.Count = 1;
     desc.SampleDesc.Quality = 0;
     desc.Usage = D3D11_USAGE_DEFAULT;
@@ -241,6 +248,85 @@ static mfxFrameAllocator s_decode_allocator = {
     DecAlloc_Alloc, DecAlloc_Lock, DecAlloc_Unlock, DecAlloc_GetHDL, DecAlloc_Free
 };
 
+/* One in-flight output of the async pipeline; the bitstream must outlive the sync point.
+ * With B-frames outputs come in coding order, so input surfaces are pooled separately. */
+struct MfxAsyncSlot {
+    mfxBitstream bs;
+    mfxSyncPoint syncp;
+    uint8_t* buffer;
+};
+
+/* 编码扩展参数；mfxVideoParam 只保存指针，Reset 时仍须有效 */
+struct MfxExtParams {
+    mfxExtCodingOption co;
+    mfxExtCodingOption2 co2;
+    mfxExtCodingOption3 co3;
+    mfxExtVideoSignalInfo vsi;
+    mfxExtChromaLocInfo cli;
+    mfxExtBuffer* buffers[5];
+};
+
+/* 输入或输出格式；P010 的样本在高 10 位 (Shift) */
+static void mfx_set_format(mfxVideoParam* param, mfxU32 fourcc) {
+    param->mfx.FrameInfo.FourCC = fourcc;
+    if (fourcc == MFX_FOURCC_P010) {
+        param->mfx.FrameInfo.BitDepthLuma = 10;
+        param->mfx.FrameInfo.BitDepthChroma = 10;
+        param->mfx.FrameInfo.Shift = 1;
+    } else if (fourcc == MFX_FOURCC_AYUV) {
+        param->mfx.FrameInfo.ChromaFormat = MFX_CHROMAFORMAT_YUV444;
+    }
+}
+
+/* 帧内刷新：垂直刷新带每 period 帧扫过画面一次，历时 duration 帧，波首帧带 recovery point SEI */
+static void mfx_set_intra_refresh(MfxExtParams& ext, int32_t period, int32_t duration) {
+    ext.co.Header.BufferId = MFX_EXTBUFF_CODING_OPTION;
+    ext.co.Header.BufferSz = sizeof(ext.co);
+    ext.co.RecoveryPointSEI = MFX_CODINGOPTION_ON;
+    ext.co2.Header.BufferId = MFX_EXTBUFF_CODING_OPTION2;
+    ext.co2.Header.BufferSz = sizeof(ext.co2);
+    ext.co2.IntRefType = MFX_REFRESH_VERTICAL;
+    ext.co2.IntRefCycleSize = (mfxU16)duration;
+    ext.co3.Header.BufferId = MFX_EXTBUFF_CODING_OPTION3;
+    ext.co3.Header.BufferSz = sizeof(ext.co3);
+    ext.co3.IntRefCycleDist = (mfxU16)period;
+}
+
+/* VUI 颜色描述与色度位置 */
+static void mfx_set_color(MfxExtParams& ext, const ColorDesc& color) {
+    ext.vsi.Header.BufferId = MFX_EXTBUFF_VIDEO_SIGNAL_INFO;
+    ext.vsi.Header.BufferSz = sizeof(ext.vsi);
+    ext.vsi.VideoFormat = 5; /* unspecified */
+    ext.vsi.VideoFullRange = color.full_range ? 1 : 0;
+    ext.vsi.ColourDescriptionPresent = 1;
+    ext.vsi.ColourPrimaries = (mfxU16)color.primaries;
+    ext.vsi.TransferCharacteristics = (mfxU16)color.transfer;
+    ext.vsi.MatrixCoefficients = (mfxU16)color.matrix;
+    ext.cli.Header.BufferId = MFX_EXTBUFF_CHROMA_LOC_INFO;
+    ext.cli.Header.BufferSz = sizeof(ext.cli);
+    ext.cli.ChromaLocInfoPresentFlag = 1;
+    ext.cli.ChromaSampleLocTypeTopField = (mfxU16)color.chroma_location;
+    ext.cli.ChromaSampleLocTypeBottomField = (mfxU16)color.chroma_location;
+}
+
+/* 将已设置（BufferId 非 0）的扩展参数挂到 param；复制 MfxExtParams 后须重新挂接 */
+static void mfx_attach_ext(MfxExtParams& ext, mfxVideoParam& param) {
+    mfxExtBuffer* all[] = { &ext.co.Header, &ext.co2.Header, &ext.co3.Header, &ext.vsi.Header, &ext.cli.Header };
+    mfxU16 n = 0;
+    for (mfxExtBuffer* b : all)
+        if (b->BufferId != 0) ext.buffers[n++] = b;
+    if (n == 0) return;
+    param.ExtParam = ext.buffers;
+    param.NumExtParam = n;
+}
+
+/* 一帧的参考控制；mfxEncodeCtrl 只保存指针，须保持到该帧编码完成 */
+struct MfxRefCtrl {
+    mfxEncodeCtrl ctrl;
+    mfxExtAVCRefListCtrl refs;
+    mfxExtBuffer* ext;
+};
+
 /* Encoder context */
 struct MfxEncContext {
     mfxSession session;
@@ -249,21 +335,125 @@ struct MfxEncContext {
     int32_t height;
     uint8_t* bs_buffer;
     mfxU32 bs_buffer_size;
+    int32_t async_depth;  /* 0 = 仅同步 EncodeFrame */
+    MfxAsyncSlot* slots;
+    int32_t slot_head;
+    int32_t slot_count;
+    /* 输入 surface 池，Data.Locked 非 0 表示仍被编码器引用 */
+    mfxFrameSurface1* surfaces;
+    int32_t bframes;   /* GopRefDist - 1，仅异步模式下启用 */
+    int32_t held;      /* 已提交但编码器缓存、尚无输出的帧数 */
+    bool draining;     /* FlushEncoder 后以空 surface 取出缓存的帧 */
+    MfxExtParams ext;
+    LtrState ltr;       /* 仅 AVC */
+    MfxRefCtrl* surface_refs;  /* 与 surfaces 一一对应 */
+    std::vector<mfxU32> rejected;  /* 待 recover 的帧拒绝的编码序号 */
 };
 
+static void mfx_fill_surface(MfxEncContext* ctx, mfxFrameSurface1* surf, uint8_t* texture, int64_t timestamp) {
+    *surf = {};
+    surf->Info.FourCC = ctx->param.mfx.FrameInfo.FourCC;
+    surf->Info.BitDepthLuma = ctx->param.mfx.FrameInfo.BitDepthLuma;
+    surf->Info.BitDepthChroma = ctx->param.mfx.FrameInfo.BitDepthChroma;
+    surf->Info.Shift = ctx->param.mfx.FrameInfo.Shift;
+    surf->Info.Width = (mfxU16)ctx->width;
+    surf->Info.Height = (mfxU16)ctx->height;
+    surf->Info.CropW = (mfxU16)ctx->width;
+    surf->Info.CropH = (mfxU16)ctx->height;
+    surf->Info.FrameRateExtN = ctx->param.mfx.FrameInfo.FrameRateExtN;
+    surf->Info.FrameRateExtD = ctx->param.mfx.FrameInfo.FrameRateExtD;
+    surf->Info.PicStruct = MFX_PICSTRUCT_PROGRESSIVE;
+    surf->Info.ChromaFormat = MFX_CHROMAFORMAT_YUV420;
+    surf->Data.MemId = (mfxMemId)texture;
+    // Media SDK 以 90 kHz 计算 DecodeTimeStamp，毫秒时间戳换算后传入
+    surf->Data.TimeStamp = (mfxU64)(timestamp * 90);
+}
+
+/* 按长期参考状态填写本帧的参考控制，会话未启用长期参考时返回 nullptr。
+ * 标记：LongTermRefList 放入本帧；槽位覆盖时旧帧放入 RejectedRefList。
+ * 恢复：PreferredRefList 为仍有效的长期参考，RejectedRefList 为失效帧；没有可用参考时强制 IDR。 */
+static mfxEncodeCtrl* mfx_set_frame_refs(MfxEncContext* ctx, MfxRefCtrl& r, mfxFrameSurface1* surface, const LtrControl& c) {
+    if (ctx->ltr.slots == 0) return nullptr;
+    surface->Data.FrameOrder = c.order;
+    r = {};
+    r.refs.Header.BufferId = MFX_EXTBUFF_AVC_REFLIST_CTRL;
+    r.refs.Header.BufferSz = sizeof(r.refs);
+    for (auto& e : r.refs.PreferredRefList) e.FrameOrder = (mfxU32)MFX_FRAMEORDER_UNKNOWN;
+    for (auto& e : r.refs.RejectedRefList) e.FrameOrder = (mfxU32)MFX_FRAMEORDER_UNKNOWN;
+    for (auto& e : r.refs.LongTermRefList) e.FrameOrder = (mfxU32)MFX_FRAMEORDER_UNKNOWN;
+    const size_t maxRejected = sizeof(r.refs.RejectedRefList) / sizeof(r.refs.RejectedRefList[0]);
+    size_t rejected = 0;
+    if (c.mark_slot >= 0) {
+        r.refs.LongTermRefList[0].FrameOrder = c.order;
+        r.refs.LongTermRefList[0].PicStruct = MFX_PICSTRUCT_PROGRESSIVE;
+        r.refs.LongTermRefList[0].LongTermIdx = (mfxU16)c.mark_slot;
+        if ((c.use >> c.mark_slot) & 1u)
+            r.refs.RejectedRefList[rejected++].FrameOrder = ctx->ltr.slot_order[c.mark_slot];
+    }
+    if (c.recover && c.use != 0) {
+        size_t preferred = 0;
+        for (int32_t i = 0; i < ctx->ltr.slots; i++)
+            if ((c.use >> i) & 1u && i != c.mark_slot) r.refs.PreferredRefList[preferred++].FrameOrder = ctx->ltr.slot_order[i];
+        for (size_t i = 0; i < ctx->rejected.size() && rejected < maxRejected; i++)
+            r.refs.RejectedRefList[rejected++].FrameOrder = ctx->rejected[i];
+    } else if (c.recover) {
+        r.ctrl.FrameType = MFX_FRAMETYPE_I | MFX_FRAMETYPE_REF | MFX_FRAMETYPE_IDR;
+    }
+    r.ext = &r.refs.Header;
+    r.ctrl.ExtParam = &r.ext;
+    r.ctrl.NumExtParam = 1;
+    return &r.ctrl;
+}
+
+/* 编码器接受本帧后记录长期参考状态 */
+static void mfx_commit_frame_refs(MfxEncContext* ctx, const LtrControl& c, int64_t timestamp) {
+    if (ctx->ltr.slots == 0) return;
+    ltr_commit(ctx->ltr, c, timestamp);
+    if (c.recover) ctx->rejected.clear();
+}
+
+static EncodedFrame* mfx_frame_from_bitstream(const mfxBitstream& bs, int64_t timestamp) {
+    EncodedFrame* frame = new EncodedFrame();
+    frame->size = (int32_t)bs.DataLength;
+    frame->data = (uint8_t*)malloc((size_t)bs.DataLength);
+    if (frame->data && bs.DataLength > 0)
+        memcpy(frame->data, bs.Data + bs.DataOffset, (size_t)bs.DataLength);
+    frame->is_keyframe = (bs.FrameType & MFX_FRAMETYPE_IDR) != 0;
+    frame->timestamp = timestamp;
+    if (bs.FrameType & MFX_FRAMETYPE_IDR) frame->picture_type = HWCODEC_PIC_IDR;
+    else if (bs.FrameType & MFX_FRAMETYPE_I) frame->picture_type = HWCODEC_PIC_I;
+    else if (bs.FrameType & MFX_FRAMETYPE_P) frame->picture_type = HWCODEC_PIC_P;
+    else if (bs.FrameType & MFX_FRAMETYPE_B) frame->picture_type = HWCODEC_PIC_B;
+    else frame->picture_type = HWCODEC_PIC_UNKNOWN;
+    // 编码器不报告 QP 与时域层
+    frame->avg_qp = -1;
+    frame->temporal_id = -1;
+    // 90 kHz 换回毫秒，向下取整以保证 dts <= pts
+    int64_t dts = (int64_t)bs.DecodeTimeStamp;
+    frame->dts = dts >= 0 ? dts / 90 : -((-dts + 89) / 90);
+    frame->has_dts = true;
+    return frame;
+}
+
 /* Decoder context */
 struct MfxDecContext {
     mfxSession session;
     mfxVideoParam param;
     int32_t width;
     int32_t height;
+    int32_t bit_depth;  /* 8 输出 NV12，10 输出 P010 */
     DecAllocContext alloc_ctx;
     mfxFrameAllocator allocator;
 };
 #endif
 
-extern "C++" MfxEncoder* mfx_CreateEncoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop) {
+/* B-frames are only enabled for async sessions: the sync EncodeFrame returns one packet per call. */
+static MfxEncoder* mfx_create_encoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444) {
     if (!IsMfxAvailable() || !device || width <= 0 || height <= 0) return nullptr;
+    /* Main10 只支持 HEVC，输入为 P010 纹理 */
+    if (bit_depth == 10 && codec_id != 1) return nullptr;
+    /* 4:4:4 须输入 AYUV 纹理，编码端尚未实现 */
+    if (yuv444) return nullptr;
 #if defined(_WIN32) || defined(_WIN64)
     if (!LoadMfxProcs()) {
         MFX_DBG("CreateEncoder: LoadMfxProcs failed");
@@ -307,19 +497,59 @@ extern "C++" MfxEncoder* mfx_CreateEncoder(uint8_t* device, int32_t width, int32
     param.mfx.FrameInfo.FrameRateExtD = 1;
     param.mfx.FrameInfo.PicStruct = MFX_PICSTRUCT_PROGRESSIVE;
     param.mfx.FrameInfo.ChromaFormat = MFX_CHROMAFORMAT_YUV420;
+    if (bit_depth == 10) {
+        param.mfx.CodecProfile = MFX_PROFILE_HEVC_MAIN10;
+        mfx_set_format(&param, MFX_FOURCC_P010);
+    }
     param.mfx.GopPicSize = (mfxU16)(gop > 0 && gop < 10000 ? gop : 60);
-    param.mfx.GopRefDist = 1;
+    /* 长期参考只对 AVC 实现，另留一个短期参考；参考控制按提交顺序编号，B 帧随之关闭 */
+    int32_t ltr = codec_id == 0 && ltr_frames > 0 ? ltr_frames : 0;
+    if (ltr > HWCODEC_LTR_MAX_SLOTS) ltr = HWCODEC_LTR_MAX_SLOTS;
+    if (ltr > 0) {
+        param.mfx.NumRefFrame = (mfxU16)(ltr + 1);
+        bframes = 0;
+    }
+    /* 帧内刷新取代周期 IDR：只有首帧为 IDR，B 帧随之关闭 */
+    MfxExtParams ext = {};
+    if (intra_refresh_duration > 0 && intra_refresh_period > intra_refresh_duration) {
+        param.mfx.GopPicSize = 0xffff;
+        bframes = 0;
+        mfx_set_intra_refresh(ext, intra_refresh_period, intra_refresh_duration);
+    }
+    if (color.present) mfx_set_color(ext, color);
+    mfx_attach_ext(ext, param);
+    /* 在途输出槽位须容纳缓存的 B 帧与其后的参考帧 */
+    int32_t b = async_depth > 0 && bframes > 0 ? bframes : 0;
+    if (b > async_depth - 1) b = async_depth - 1;
+    if (b < 0) b = 0;
+    param.mfx.GopRefDist = (mfxU16)(b + 1);
     param.mfx.RateControlMethod = MFX_RATECONTROL_CBR;
     param.mfx.TargetKbps = (mfxU16)(bitrate > 0 ? (bitrate / 1000) : 4000);
     param.IOPattern = MFX_IOPATTERN_IN_VIDEO_MEMORY;
-    param.AsyncDepth = 1;
+    param.AsyncDepth = (mfxU16)(async_depth > 0 ? async_depth : 1);
     mfxVideoParam outParam = {};
+    MfxExtParams outExt = ext;
+    mfx_attach_ext(outExt, outParam);
     st = pMFXVideoENCODE_Query(session, &param, &outParam);
     if (st != MFX_ERR_NONE) {
         MFX_DBG("CreateEncoder: ENCODE_Query failed st=%d", (int)st);
         pMFXClose(session);
         return nullptr;
     }
+    if (ltr > 0) {
+        if (outParam.mfx.NumRefFrame < 2) {
+            MFX_DBG("CreateEncoder: long-term references unsupported");
+            pMFXClose(session);
+            return nullptr;
+        }
+        if (outParam.mfx.NumRefFrame < param.mfx.NumRefFrame) {
+            param.mfx.NumRefFrame = outParam.mfx.NumRefFrame;
+            ltr = outParam.mfx.NumRefFrame - 1;
+        }
+    }
+    /* 驱动可能调低 GopRefDist */
+    if (outParam.mfx.GopRefDist >= 1 && outParam.mfx.GopRefDist < param.mfx.GopRefDist)
+        param.mfx.GopRefDist = outParam.mfx.GopRefDist;
     st = pMFXVideoENCODE_Init(session, &param);
     if (st != MFX_ERR_NONE) {
         MFX_DBG("CreateEncoder: ENCODE_Init failed st=%d", (int)st);
@@ -329,49 +559,236 @@ extern "C++" MfxEncoder* mfx_CreateEncoder(uint8_t* device, int32_t width, int32
     MfxEncContext* ctx = new MfxEncContext();
     ctx->session = session;
     ctx->param = param;
+    ctx->ext = ext;
+    mfx_attach_ext(ctx->ext, ctx->param);
+    ctx->ltr.slots = ltr;
+    ctx->ltr.idr_period = param.mfx.GopPicSize;
     ctx->width = width;
     ctx->height = height;
     ctx->bs_buffer_size = (mfxU32)(width * height * 2);
     if (ctx->bs_buffer_size < 200000) ctx->bs_buffer_size = 200000;
     ctx->bs_buffer = (uint8_t*)malloc(ctx->bs_buffer_size);
+    if (async_depth > 0) {
+        ctx->async_depth = async_depth;
+        ctx->slots = new MfxAsyncSlot[async_depth]();
+        ctx->surfaces = new mfxFrameSurface1[async_depth]();
+        ctx->surface_refs = new MfxRefCtrl[async_depth]();
+        for (int32_t i = 0; i < async_depth; i++)
+            ctx->slots[i].buffer = (uint8_t*)malloc(ctx->bs_buffer_size);
+        ctx->bframes = param.mfx.GopRefDist - 1;
+    }
     MfxEncoder* enc = new MfxEncoder();
     enc->impl = ctx;
-    MFX_DBG("CreateEncoder: ok %dx%d", width, height);
+    MFX_DBG("CreateEncoder: ok %dx%d async_depth=%d", width, height, async_depth);
     return enc;
 #else
-    (void)device; (void)width; (void)height; (void)codec_id; (void)bitrate; (void)framerate; (void)gop;
+    (void)device; (void)width; (void)height; (void)codec_id; (void)bitrate; (void)framerate; (void)gop; (void)async_depth; (void)bframes; (void)intra_refresh_period; (void)intra_refresh_duration; (void)ltr_frames; (void)color; (void)bit_depth; (void)yuv444;
     MfxEncoder* enc = new MfxEncoder();
     enc->impl = nullptr;
     return enc;
 #endif
 }
 
+extern "C++" MfxEncoder* mfx_CreateEncoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop) {
+    return mfx_create_encoder(device, width, height, codec_id, bitrate, framerate, gop, 0, 0, 0, 0, 0, ColorDesc{}, 8, false);
+}
+
+extern "C++" MfxEncoder* mfx_CreateEncoderAsync(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444) {
+    return mfx_create_encoder(device, width, height, codec_id, bitrate, framerate, gop, async_depth, bframes, intra_refresh_period, intra_refresh_duration, ltr_frames, color, bit_depth, yuv444);
+}
+
+extern "C++" int32_t mfx_GetAsyncDepth(MfxEncoder* encoder) {
+#if defined(_WIN32) || defined(_WIN64)
+    if (encoder && encoder->impl) return ((MfxEncContext*)encoder->impl)->async_depth;
+#else
+    (void)encoder;
+#endif
+    return 0;
+}
+
+extern "C++" int32_t mfx_PendingFrames(MfxEncoder* encoder) {
+#if defined(_WIN32) || defined(_WIN64)
+    if (encoder && encoder->impl) {
+        MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+        return ctx->slot_count + ctx->held;
+    }
+#else
+    (void)encoder;
+#endif
+    return 0;
+}
+
+extern "C++" int32_t mfx_GetReorderDepth(MfxEncoder* encoder) {
+#if defined(_WIN32) || defined(_WIN64)
+    if (encoder && encoder->impl) return ((MfxEncContext*)encoder->impl)->bframes;
+#else
+    (void)encoder;
+#endif
+    return 0;
+}
+
+#if defined(_WIN32) || defined(_WIN64)
+/* 以空 surface 取出编码器缓存的帧，直到输出槽位用尽或没有缓存的帧 */
+static int32_t mfx_drain(MfxEncContext* ctx) {
+    while (ctx->held > 0 && ctx->slot_count < ctx->async_depth) {
+        MfxAsyncSlot& slot = ctx->slots[(ctx->slot_head + ctx->slot_count) % ctx->async_depth];
+        slot.bs = {};
+        slot.bs.Data = slot.buffer;
+        slot.bs.MaxLength = ctx->bs_buffer_size;
+        slot.syncp = nullptr;
+        mfxStatus st = pMFXVideoENCODE_EncodeFrameAsync(ctx->session, nullptr, nullptr, &slot.bs, &slot.syncp);
+        if (st == MFX_WRN_DEVICE_BUSY) return 0;
+        if (st == MFX_ERR_MORE_DATA) { ctx->held = 0; break; }
+        if (st != MFX_ERR_NONE || !slot.syncp) {
+            MFX_DBG("FlushEncoder: EncodeFrameAsync st=%d", (int)st);
+            ctx->held = 0;
+            ctx->draining = false;
+            return -1;
+        }
+        ctx->held--;
+        ctx->slot_count++;
+    }
+    if (ctx->held == 0) ctx->draining = false;
+    return 0;
+}
+#endif
+
+// 返回 0 成功，1 会话未启用长期参考，负值为错误
+extern "C++" int32_t mfx_MarkLtr(MfxEncoder* encoder) {
+    if (!encoder || !encoder->impl) return -1;
+#if defined(_WIN32) || defined(_WIN64)
+    MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+    if (ctx->ltr.slots == 0) return 1;
+    ctx->ltr.mark = true;
+    return 0;
+#else
+    return 1;
+#endif
+}
+
+// 返回值同 MarkLtr。失效帧放入下一帧的 RejectedRefList，该帧优先参考范围之前标记的长期参考
+extern "C++" int32_t mfx_InvalidateRefFrames(MfxEncoder* encoder, int64_t first, int64_t last) {
+    if (!encoder || !encoder->impl) return -1;
+#if defined(_WIN32) || defined(_WIN64)
+    MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+    if (ctx->ltr.slots == 0) return 1;
+    for (const auto& f : ltr_invalidate(ctx->ltr, first, last))
+        ctx->rejected.push_back(f.second);
+    return 0;
+#else
+    (void)first; (void)last;
+    return 1;
+#endif
+}
+
+/* 开始取出缓存的 B 帧；槽位不足时其余部分在 ReceiveFrame 让出槽位后继续 */
+extern "C++" int32_t mfx_FlushEncoder(MfxEncoder* encoder) {
+    if (!encoder || !encoder->impl) return -1;
+#if defined(_WIN32) || defined(_WIN64)
+    if (!LoadMfxProcs()) return -1;
+    MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0 || ctx->held == 0) return 0;
+    ctx->draining = true;
+    return mfx_drain(ctx);
+#else
+    return -1;
+#endif
+}
+
+/* Returns 0 when queued, 1 when all slots are in flight or the device is busy, negative on error. */
+extern "C++" int32_t mfx_SubmitFrame(MfxEncoder* encoder, uint8_t* texture, int64_t timestamp) {
+    if (!encoder || !encoder->impl || !texture) return -1;
+#if defined(_WIN32) || defined(_WIN64)
+    if (!LoadMfxProcs()) return -1;
+    MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0 || !ctx->slots) return -1;
+    if (ctx->draining || ctx->slot_count + ctx->held >= ctx->async_depth) return 1;
+    MfxAsyncSlot& slot = ctx->slots[(ctx->slot_head + ctx->slot_count) % ctx->async_depth];
+    if (!slot.buffer) return -1;
+    mfxFrameSurface1* surface = nullptr;
+    for (int32_t i = 0; i < ctx->async_depth && !surface; i++)
+        if (ctx->surfaces[i].Data.Locked == 0) surface = &ctx->surfaces[i];
+    if (!surface) return 1;
+    mfx_fill_surface(ctx, surface, texture, timestamp);
+    LtrControl refs = ltr_next_frame(ctx->ltr);
+    mfxEncodeCtrl* ctrl = mfx_set_frame_refs(ctx, ctx->surface_refs[surface - ctx->surfaces], surface, refs);
+    slot.bs = {};
+    slot.bs.Data = slot.buffer;
+    slot.bs.MaxLength = ctx->bs_buffer_size;
+    slot.syncp = nullptr;
+    mfxStatus st = pMFXVideoENCODE_EncodeFrameAsync(ctx->session, ctrl, surface, &slot.bs, &slot.syncp);
+    if (st == MFX_WRN_DEVICE_BUSY) return 1;
+    /* 本帧被缓存（B 帧或前瞻），输出在后续提交或 FlushEncoder 时产生 */
+    if (st == MFX_ERR_MORE_DATA) {
+        ctx->held++;
+        mfx_commit_frame_refs(ctx, refs, timestamp);
+        return 0;
+    }
+    if (st != MFX_ERR_NONE || !slot.syncp) {
+        MFX_DBG("SubmitFrame: EncodeFrameAsync st=%d", (int)st);
+        return -1;
+    }
+    mfx_commit_frame_refs(ctx, refs, timestamp);
+    ctx->slot_count++;
+    return 0;
+#else
+    (void)timestamp;
+    return -1;
+#endif
+}
+
+/* status: 0 = frame returned, 1 = oldest frame not done within wait_ms (or nothing in flight), negative on error. */
+extern "C++" EncodedFrame* mfx_ReceiveFrame(MfxEncoder* encoder, uint32_t wait_ms, int32_t* status) {
+    if (!status) return nullptr;
+    *status = -1;
+    if (!encoder || !encoder->impl) return nullptr;
+#if defined(_WIN32) || defined(_WIN64)
+    if (!LoadMfxProcs()) return nullptr;
+    MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0 || !ctx->slots) return nullptr;
+    if (ctx->slot_count == 0) { *status = 1; return nullptr; }
+    MfxAsyncSlot& slot = ctx->slots[ctx->slot_head];
+    mfxStatus st = pMFXVideoCORE_SyncOperation(ctx->session, slot.syncp, (mfxU32)wait_ms);
+    if (st == MFX_WRN_IN_EXECUTION) { *status = 1; return nullptr; }
+    ctx->slot_head = (ctx->slot_head + 1) % ctx->async_depth;
+    ctx->slot_count--;
+    if (st != MFX_ERR_NONE) {
+        MFX_DBG("ReceiveFrame: SyncOperation st=%d", (int)st);
+        return nullptr;
+    }
+    /* 有 B 帧时输出按编码顺序，pts 取码流回传的 TimeStamp；复制出数据后槽位才可用于继续 drain */
+    EncodedFrame* frame = mfx_frame_from_bitstream(slot.bs, (int64_t)(slot.bs.TimeStamp / 90));
+    if (ctx->draining) mfx_drain(ctx);
+    *status = 0;
+    return frame;
+#else
+    (void)wait_ms;
+    return nullptr;
+#endif
+}
+
 extern "C++" EncodedFrame* mfx_EncodeFrame(MfxEncoder* encoder, uint8_t* texture, int64_t timestamp) {
     if (!encoder || !IsMfxAvailable()) return nullptr;
     if (!encoder->impl) return nullptr;
 #if defined(_WIN32) || defined(_WIN64)
     if (!texture || !LoadMfxProcs()) return nullptr;
     MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
-    mfxFrameSurface1 surf = {};
-    surf.Info.FourCC = MFX_FOURCC_NV12;
-    surf.Info.Width = (mfxU16)ctx->width;
-    surf.Info.Height = (mfxU16)ctx->height;
-    surf.Info.CropW = (mfxU16)ctx->width;
-    surf.Info.CropH = (mfxU16)ctx->height;
-    surf.Info.FrameRateExtN = ctx->param.mfx.FrameInfo.FrameRateExtN;
-    surf.Info.FrameRateExtD = ctx->param.mfx.FrameInfo.FrameRateExtD;
-    surf.Info.PicStruct = MFX_PICSTRUCT_PROGRESSIVE;
-    surf.Info.ChromaFormat = MFX_CHROMAFORMAT_YUV420;
-    surf.Data.MemId = (mfxMemId)texture;
-    surf.Data.TimeStamp = (mfxU64)timestamp;
+    mfxFrameSurface1 surf;
+    mfx_fill_surface(ctx, &surf, texture, timestamp);
+    LtrControl refs = ltr_next_frame(ctx->ltr);
+    MfxRefCtrl refCtrl;
+    mfxEncodeCtrl* ctrl = mfx_set_frame_refs(ctx, refCtrl, &surf, refs);
     mfxBitstream bs = {};
     bs.Data = ctx->bs_buffer;
     bs.MaxLength = ctx->bs_buffer_size;
     bs.DataOffset = 0;
     bs.DataLength = 0;
     mfxSyncPoint syncp = nullptr;
-    mfxStatus st = pMFXVideoENCODE_EncodeFrameAsync(ctx->session, nullptr, &surf, &bs, &syncp);
-    if (st == MFX_ERR_MORE_DATA) return nullptr;
+    mfxStatus st = pMFXVideoENCODE_EncodeFrameAsync(ctx->session, ctrl, &surf, &bs, &syncp);
+    if (st == MFX_ERR_MORE_DATA) {
+        mfx_commit_frame_refs(ctx, refs, timestamp);
+        return nullptr;
+    }
     if (st == MFX_ERR_MORE_BITSTREAM) {
         MFX_DBG("EncodeFrame: output buffer too small");
         return nullptr;
@@ -380,16 +797,10 @@ extern "C++" EncodedFrame* mfx_EncodeFrame(MfxEncoder* encoder, uint8_t* texture
         MFX_DBG("EncodeFrame: EncodeFrameAsync st=%d", (int)st);
         return nullptr;
     }
+    mfx_commit_frame_refs(ctx, refs, timestamp);
     st = pMFXVideoCORE_SyncOperation(ctx->session, syncp, 3000);
     if (st != MFX_ERR_NONE) return nullptr;
-    EncodedFrame* frame = new EncodedFrame();
-    frame->size = (int32_t)bs.DataLength;
-    frame->data = (uint8_t*)malloc((size_t)bs.DataLength);
-    if (frame->data && bs.DataLength > 0)
-        memcpy(frame->data, bs.Data + bs.DataOffset, (size_t)bs.DataLength);
-    frame->is_keyframe = (bs.FrameType & MFX_FRAMETYPE_IDR) != 0;
-    frame->timestamp = timestamp;
-    return frame;
+    return mfx_frame_from_bitstream(bs, timestamp);
 #else
     (void)encoder; (void)texture; (void)timestamp;
     return nullptr;
@@ -411,6 +822,16 @@ extern "C++" void mfx_DestroyEncoder(MfxEncoder* encoder) {
 #if defined(_WIN32) || defined(_WIN64)
     if (encoder->impl) {
         MfxEncContext* ctx = (MfxEncContext*)encoder->impl;
+        if (ctx->slots) {
+            /* 等待在途帧完成后再关闭会话并释放其输出缓冲 */
+            for (int32_t i = 0; i < ctx->slot_count && pMFXVideoCORE_SyncOperation; i++)
+                pMFXVideoCORE_SyncOperation(ctx->session, ctx->slots[(ctx->slot_head + i) % ctx->async_depth].syncp, 1000);
+            for (int32_t i = 0; i < ctx->async_depth; i++)
+                if (ctx->slots[i].buffer) free(ctx->slots[i].buffer);
+            delete[] ctx->slots;
+            delete[] ctx->surfaces;
+            delete[] ctx->surface_refs;
+        }
         if (pMFXVideoENCODE_Close) pMFXVideoENCODE_Close(ctx->session);
         if (pMFXClose) pMFXClose(ctx->session);
         if (ctx->bs_buffer) free(ctx->bs_buffer);
@@ -443,7 +864,7 @@ extern "C++" void mfx_SetFramerate(MfxEncoder* encoder, int32_t framerate) {
 }
 
 /* Decoder: init with first chunk to get width/height; decode returns output surface's texture. */
-extern "C++" MfxDecoder* mfx_CreateDecoder(uint8_t* device, int32_t codec_id) {
+extern "C++" MfxDecoder* mfx_CreateDecoder(uint8_t* device, int32_t codec_id, int32_t bit_depth) {
     if (!IsMfxAvailable() || !device) return nullptr;
 #if defined(_WIN32) || defined(_WIN64)
     if (!LoadMfxProcs()) return nullptr;
@@ -465,6 +886,7 @@ extern "C++" MfxDecoder* mfx_CreateDecoder(uint8_t* device, int32_t codec_id) {
     ctx->param.mfx.CodecId = (codec_id == 0) ? MFX_CODEC_AVC : MFX_CODEC_HEVC;
     ctx->width = 0;
     ctx->height = 0;
+    ctx->bit_depth = bit_depth == 10 ? 10 : 8;
     ctx->alloc_ctx.dev = (ID3D11Device*)device;
     ctx->alloc_ctx.textures = nullptr;
     ctx->alloc_ctx.mids = nullptr;
@@ -478,13 +900,33 @@ extern "C++" MfxDecoder* mfx_CreateDecoder(uint8_t* device, int32_t codec_id) {
     MFX_DBG("CreateDecoder: session ok, call DecodeFrame with first NAL to init");
     return dec;
 #else
-    (void)device; (void)codec_id;
+    (void)device; (void)codec_id; (void)bit_depth;
     MfxDecoder* dec = new MfxDecoder();
     dec->impl = nullptr;
     return dec;
 #endif
 }
 
+#if defined(_WIN32) || defined(_WIN64)
+/* bs 为 nullptr 时取出解码器缓存的帧 */
+static DecodedFrame* mfx_decode_bitstream(MfxDecContext* ctx, mfxBitstream* bs) {
+    mfxFrameSurface1* surface_out = nullptr;
+    mfxSyncPoint syncp = nullptr;
+    mfxStatus st = pMFXVideoDECODE_DecodeFrameAsync(ctx->session, bs, nullptr, &surface_out, &syncp);
+    if (st == MFX_ERR_MORE_DATA) return nullptr;
+    if (st != MFX_ERR_NONE || !surface_out) return nullptr;
+    st = pMFXVideoCORE_SyncOperation(ctx->session, syncp, 3000);
+    if (st != MFX_ERR_NONE) return nullptr;
+    mfxHDL hdl = nullptr;
+    ctx->allocator.GetHDL(ctx->allocator.pthis, surface_out->Data.MemId, &hdl);
+    DecodedFrame* frame = new DecodedFrame();
+    frame->texture = (uint8_t*)hdl;
+    frame->width = ctx->width;
+    frame->height = ctx->height;
+    return frame;
+}
+#endif
+
 extern "C++" DecodedFrame* mfx_DecodeFrame(MfxDecoder* decoder, uint8_t* data, int32_t length) {
     if (!decoder || !decoder->impl || !IsMfxAvailable() || !data || length <= 0) return nullptr;
 #if defined(_WIN32) || defined(_WIN64)
@@ -499,6 +941,13 @@ extern "C++" DecodedFrame* mfx_DecodeFrame(MfxDecoder* decoder, uint8_t* data, i
         mfxVideoParam par = {};
         mfxStatus st = pMFXVideoDECODE_DecodeHeader(ctx->session, &bs, &par);
         if (st != MFX_ERR_NONE) return nullptr;
+        /* 码流位深与配置不符时不解码；10 bit 输出 P010 */
+        bool ten_bit = par.mfx.FrameInfo.BitDepthLuma > 8 || par.mfx.FrameInfo.FourCC == MFX_FOURCC_P010;
+        if (ten_bit != (ctx->bit_depth == 10)) {
+            MFX_DBG("DecodeFrame: stream bit depth does not match the decoder (%d)", (int)ctx->bit_depth);
+            return nullptr;
+        }
+        if (ten_bit) mfx_set_format(&par, MFX_FOURCC_P010);
         ctx->param = par;
         ctx->param.IOPattern = MFX_IOPATTERN_OUT_VIDEO_MEMORY;
         ctx->param.AsyncDepth = 1;
@@ -515,26 +964,27 @@ extern "C++" DecodedFrame* mfx_DecodeFrame(MfxDecoder* decoder, uint8_t* data, i
     bs.DataLength = (mfxU32)length;
     bs.MaxLength = (mfxU32)length;
     bs.DataOffset = 0;
-    mfxFrameSurface1* surface_out = nullptr;
-    mfxSyncPoint syncp = nullptr;
-    mfxStatus st = pMFXVideoDECODE_DecodeFrameAsync(ctx->session, &bs, nullptr, &surface_out, &syncp);
-    if (st == MFX_ERR_MORE_DATA) return nullptr;
-    if (st != MFX_ERR_NONE || !surface_out) return nullptr;
-    st = pMFXVideoCORE_SyncOperation(ctx->session, syncp, 3000);
-    if (st != MFX_ERR_NONE) return nullptr;
-    mfxHDL hdl = nullptr;
-    ctx->allocator.GetHDL(ctx->allocator.pthis, surface_out->Data.MemId, &hdl);
-    DecodedFrame* frame = new DecodedFrame();
-    frame->texture = (uint8_t*)hdl;
-    frame->width = ctx->width;
-    frame->height = ctx->height;
-    return frame;
+    return mfx_decode_bitstream(ctx, &bs);
 #else
     (void)decoder; (void)data; (void)length;
     return nullptr;
 #endif
 }
 
+/* 流结束：以空码流调用 DecodeFrameAsync，每次取出一帧缓存的图像，取完（MFX_ERR_MORE_DATA）返回 nullptr */
+extern "C++" DecodedFrame* mfx_DrainFrame(MfxDecoder* decoder) {
+    if (!decoder || !decoder->impl || !IsMfxAvailable()) return nullptr;
+#if defined(_WIN32) || defined(_WIN64)
+    if (!LoadMfxProcs()) return nullptr;
+    MfxDecContext* ctx = (MfxDecContext*)decoder->impl;
+    if (ctx->width == 0) return nullptr;
+    return mfx_decode_bitstream(ctx, nullptr);
+#else
+    (void)decoder;
+    return nullptr;
+#endif
+}
+
 extern "C++" void mfx_DestroyDecoder(MfxDecoder* decoder) {
     if (!decoder) return;
 #if defined(_WIN32) || defined(_WIN64)
@@ -570,3 +1020,189 @@ extern "C++" int32_t mfx_GetHeight(MfxDecoder* decoder) {
     return 0;
 #endif
 }
+
+#if defined(_WIN32) || defined(_WIN64)
+static mfxSession mfx_open_caps_session(uint8_t* device) {
+    if (!LoadMfxProcs() || !pMFXVideoENCODE_Query) return nullptr;
+    mfxInitParam initPar = {};
+    initPar.Implementation = MFX_IMPL_HARDWARE | MFX_IMPL_VIA_D3D11;
+    initPar.Version.Major = 1;
+    initPar.Version.Minor = 35;
+    mfxSession session = nullptr;
+    if (pMFXInitEx(initPar, &session) != MFX_ERR_NONE || !session) return nullptr;
+    if (pMFXVideoCORE_SetHandle(session, MFX_HANDLE_D3D11_DEVICE, (mfxHDL)device) != MFX_ERR_NONE) {
+        pMFXClose(session);
+        return nullptr;
+    }
+    return session;
+}
+
+/* 能力探测用的基础参数：1080p、NV12、CBR，再按需改单个字段后交给 Query */
+static mfxVideoParam mfx_caps_param(int32_t codec_id, mfxU16 width, mfxU16 height) {
+    mfxVideoParam param = {};
+    param.mfx.CodecId = (codec_id == 0) ? MFX_CODEC_AVC : MFX_CODEC_HEVC;
+    param.mfx.CodecProfile = (codec_id == 0) ? MFX_PROFILE_AVC_MAIN : MFX_PROFILE_HEVC_MAIN;
+    param.mfx.FrameInfo.FourCC = MFX_FOURCC_NV12;
+    param.mfx.FrameInfo.ChromaFormat = MFX_CHROMAFORMAT_YUV420;
+    param.mfx.FrameInfo.Width = width;
+    param.mfx.FrameInfo.Height = height;
+    param.mfx.FrameInfo.CropW = width;
+    param.mfx.FrameInfo.CropH = height;
+    param.mfx.FrameInfo.FrameRateExtN = 30;
+    param.mfx.FrameInfo.FrameRateExtD = 1;
+    param.mfx.FrameInfo.PicStruct = MFX_PICSTRUCT_PROGRESSIVE;
+    param.mfx.GopPicSize = 60;
+    param.mfx.GopRefDist = 1;
+    param.mfx.RateControlMethod = MFX_RATECONTROL_CBR;
+    param.mfx.TargetKbps = 4000;
+    param.IOPattern = MFX_IOPATTERN_IN_VIDEO_MEMORY;
+    param.AsyncDepth = 1;
+    return param;
+}
+
+typedef mfxStatus (MFX_CDECL *Fn_Query)(mfxSession session, mfxVideoParam *in, mfxVideoParam *out);
+
+static bool mfx_query_ok(Fn_Query query, mfxSession session, mfxVideoParam* param) {
+    mfxVideoParam out = *param;
+    return query(session, param, &out) == MFX_ERR_NONE;
+}
+
+/* 尺寸上下限：逐档尝试，按 16 对齐 */
+static void mfx_query_size_range(Fn_Query query, mfxSession session, int32_t codec_id, bool decode, int32_t* minW, int32_t* minH, int32_t* maxW, int32_t* maxH) {
+    static const mfxU16 maxSizes[][2] = { {8192, 8192}, {8192, 4320}, {4096, 4096}, {4096, 2304}, {1920, 1088} };
+    static const mfxU16 minSizes[][2] = { {16, 16}, {32, 32}, {64, 64}, {128, 128} };
+    for (auto& size : maxSizes) {
+        mfxVideoParam param = mfx_caps_param(codec_id, size[0], size[1]);
+        if (decode) param.IOPattern = MFX_IOPATTERN_OUT_VIDEO_MEMORY;
+        if (mfx_query_ok(query, session, &param)) { *maxW = size[0]; *maxH = size[1]; break; }
+    }
+    for (auto& size : minSizes) {
+        mfxVideoParam param = mfx_caps_param(codec_id, size[0], size[1]);
+        if (decode) param.IOPattern = MFX_IOPATTERN_OUT_VIDEO_MEMORY;
+        if (mfx_query_ok(query, session, &param)) { *minW = size[0]; *minH = size[1]; break; }
+    }
+}
+
+struct MfxProfileProbe {
+    mfxU16 profile;
+    mfxU32 fourcc;
+    uint32_t bit;
+};
+
+static const MfxProfileProbe s_avc_profiles[] = {
+    { MFX_PROFILE_AVC_BASELINE, MFX_FOURCC_NV12, HWCODEC_PROFILE_H264_BASELINE },
+    { MFX_PROFILE_AVC_MAIN, MFX_FOURCC_NV12, HWCODEC_PROFILE_H264_MAIN },
+    { MFX_PROFILE_AVC_HIGH, MFX_FOURCC_NV12, HWCODEC_PROFILE_H264_HIGH },
+};
+
+static const MfxProfileProbe s_hevc_profiles[] = {
+    { MFX_PROFILE_HEVC_MAIN, MFX_FOURCC_NV12, HWCODEC_PROFILE_HEVC_MAIN },
+    { MFX_PROFILE_HEVC_MAIN10, MFX_FOURCC_P010, HWCODEC_PROFILE_HEVC_MAIN10 },
+    { MFX_PROFILE_HEVC_REXT, MFX_FOURCC_AYUV, HWCODEC_PROFILE_HEVC_REXT },
+};
+
+static uint32_t mfx_query_profiles(Fn_Query query, mfxSession session, int32_t codec_id, bool decode) {
+    const MfxProfileProbe* probes = (codec_id == 0) ? s_avc_profiles : s_hevc_profiles;
+    size_t count = (codec_id == 0) ? sizeof(s_avc_profiles) / sizeof(s_avc_profiles[0]) : sizeof(s_hevc_profiles) / sizeof(s_hevc_profiles[0]);
+    uint32_t profiles = 0;
+    for (size_t i = 0; i < count; i++) {
+        mfxVideoParam param = mfx_caps_param(codec_id, 1920, 1088);
+        if (decode) param.IOPattern = MFX_IOPATTERN_OUT_VIDEO_MEMORY;
+        param.mfx.CodecProfile = probes[i].profile;
+        mfx_set_format(&param, probes[i].fourcc);
+        if (mfx_query_ok(query, session, &param)) profiles |= probes[i].bit;
+    }
+    return profiles;
+}
+#endif
+
+// 能力查询：MFX 没有能力表，用 MFXVideoENCODE_Query 逐项探测参数是否被接受
+extern "C++" EncodeCaps mfx_GetEncodeCaps(uint8_t* device, int32_t codec_id) {
+    EncodeCaps caps = {};
+    if (!IsMfxAvailable() || !device) return caps;
+#if defined(_WIN32) || defined(_WIN64)
+    mfxSession session = mfx_open_caps_session(device);
+    if (!session) return caps;
+    Fn_Query query = pMFXVideoENCODE_Query;
+    mfxVideoParam base = mfx_caps_param(codec_id, 1920, 1088);
+    if (mfx_query_ok(query, session, &base)) {
+        caps.supported = true;
+        mfx_query_size_range(query, session, codec_id, false, &caps.min_width, &caps.min_height, &caps.max_width, &caps.max_height);
+        caps.profiles = mfx_query_profiles(query, session, codec_id, false);
+        caps.ten_bit = (caps.profiles & HWCODEC_PROFILE_HEVC_MAIN10) != 0;
+        /* 硬件支持 RExt，但 CreateEncoder 不接受 4:4:4，不报告 */
+        caps.yuv444 = false;
+
+        // 请求最高 level，Query 会在 out 中修正为实际支持的值（MFX level 即 level*10）
+        mfxVideoParam level = base;
+        level.mfx.CodecLevel = (codec_id == 0) ? MFX_LEVEL_AVC_52 : MFX_LEVEL_HEVC_62;
+        mfxVideoParam levelOut = level;
+        if (query(session, &level, &levelOut) >= MFX_ERR_NONE) caps.max_level = levelOut.mfx.CodecLevel;
+
+        mfxVideoParam bframes = base;
+        bframes.mfx.GopRefDist = 4;
+        mfxVideoParam bframesOut = bframes;
+        if (query(session, &bframes, &bframesOut) >= MFX_ERR_NONE && bframesOut.mfx.GopRefDist > 1)
+            caps.max_bframes = bframesOut.mfx.GopRefDist - 1;
+
+        mfxVideoParam la = base;
+        la.mfx.RateControlMethod = MFX_RATECONTROL_LA;
+        caps.lookahead = mfx_query_ok(query, session, &la);
+
+        // 长期参考只对 AVC 实现（mfxExtAVCRefListCtrl），留一个短期参考给普通帧
+        if (codec_id == 0) {
+            mfxVideoParam refs = base;
+            refs.mfx.NumRefFrame = HWCODEC_LTR_MAX_SLOTS;
+            mfxVideoParam refsOut = refs;
+            if (query(session, &refs, &refsOut) >= MFX_ERR_NONE && refsOut.mfx.NumRefFrame > 1)
+                caps.max_ltr_frames = refsOut.mfx.NumRefFrame - 1;
+        }
+        // MFX 不提供会话数上限，0 表示未知
+        caps.max_sessions = 0;
+
+        static const struct { mfxU16 method; uint32_t bit; } rcModes[] = {
+            { MFX_RATECONTROL_CQP, HWCODEC_RC_CQP },
+            { MFX_RATECONTROL_CBR, HWCODEC_RC_CBR },
+            { MFX_RATECONTROL_VBR, HWCODEC_RC_VBR },
+            { MFX_RATECONTROL_QVBR, HWCODEC_RC_QVBR },
+        };
+        for (auto& rc : rcModes) {
+            mfxVideoParam param = base;
+            param.mfx.RateControlMethod = rc.method;
+            if (rc.method == MFX_RATECONTROL_CQP) {
+                param.mfx.QPI = 26;
+                param.mfx.QPP = 26;
+                param.mfx.QPB = 26;
+            }
+            if (mfx_query_ok(query, session, &param)) caps.rate_control |= rc.bit;
+        }
+    }
+    pMFXClose(session);
+#else
+    (void)codec_id;
+#endif
+    return caps;
+}
+
+extern "C++" DecodeCaps mfx_GetDecodeCaps(uint8_t* device, int32_t codec_id) {
+    DecodeCaps caps = {};
+    if (!IsMfxAvailable() || !device) return caps;
+#if defined(_WIN32) || defined(_WIN64)
+    mfxSession session = mfx_open_caps_session(device);
+    if (!session) return caps;
+    Fn_Query query = pMFXVideoDECODE_Query;
+    mfxVideoParam base = mfx_caps_param(codec_id, 1920, 1088);
+    base.IOPattern = MFX_IOPATTERN_OUT_VIDEO_MEMORY;
+    if (query && mfx_query_ok(query, session, &base)) {
+        caps.supported = true;
+        mfx_query_size_range(query, session, codec_id, true, &caps.min_width, &caps.min_height, &caps.max_width, &caps.max_height);
+        caps.profiles = mfx_query_profiles(query, session, codec_id, true);
+        caps.ten_bit = (caps.profiles & HWCODEC_PROFILE_HEVC_MAIN10) != 0;
+        caps.yuv444 = (caps.profiles & HWCODEC_PROFILE_HEVC_REXT) != 0;
+    }
+    pMFXClose(session);
+#else
+    (void)codec_id;
+#endif
+    return caps;
+}
@@ -6,6 +6,9 @@ struct MfxEncoder { void* impl; };
 struct MfxDecoder { void* impl; };
 struct EncodedFrame;
 struct DecodedFrame;
+struct EncodeCaps;
+struct ColorDesc;
+struct DecodeCaps;
 
 bool mfx_IsDriverAvailable();
 
@@ -15,13 +18,26 @@ extern "C++" {
     void mfx_DestroyEncoder(MfxEncoder* encoder);
     void mfx_SetBitrate(MfxEncoder* encoder, int32_t bitrate);
     void mfx_SetFramerate(MfxEncoder* encoder, int32_t framerate);
+    MfxEncoder* mfx_CreateEncoderAsync(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444);
+    int32_t mfx_GetAsyncDepth(MfxEncoder* encoder);
+    int32_t mfx_PendingFrames(MfxEncoder* encoder);
+    int32_t mfx_GetReorderDepth(MfxEncoder* encoder);
+    int32_t mfx_FlushEncoder(MfxEncoder* encoder);
+    int32_t mfx_MarkLtr(MfxEncoder* encoder);
+    int32_t mfx_InvalidateRefFrames(MfxEncoder* encoder, int64_t first, int64_t last);
+    int32_t mfx_SubmitFrame(MfxEncoder* encoder, uint8_t* texture, int64_t timestamp);
+    EncodedFrame* mfx_ReceiveFrame(MfxEncoder* encoder, uint32_t wait_ms, int32_t* status);
 
-    MfxDecoder* mfx_CreateDecoder(uint8_t* device, int32_t codec_id);
+    MfxDecoder* mfx_CreateDecoder(uint8_t* device, int32_t codec_id, int32_t bit_depth);
     DecodedFrame* mfx_DecodeFrame(MfxDecoder* decoder, uint8_t* data, int32_t length);
+    DecodedFrame* mfx_DrainFrame(MfxDecoder* decoder);
     void mfx_DestroyDecoder(MfxDecoder* decoder);
     int32_t mfx_GetWidth(MfxDecoder* decoder);
     int32_t mfx_GetHeight(MfxDecoder* decoder);
 
     void mfx_FreeEncodedFrame(EncodedFrame* frame);
     void mfx_FreeDecodedFrame(DecodedFrame* frame);
+
+    EncodeCaps mfx_GetEncodeCaps(uint8_t* device, int32_t codec_id);
+    DecodeCaps mfx_GetDecodeCaps(uint8_t* device, int32_t codec_id);
 }
@@ -1,7 +1,11 @@
 #include <cstdint>
 #include <cstdlib>
 #include <cstring>
+#include <deque>
+#include <vector>
 #include "nv_bridge.h"
+#include "caps.h"
+#include "ltr.h"
 
 #if defined(_WIN32) || defined(_WIN64)
 #include <windows.h>
@@ -59,6 +63,11 @@ struct EncodedFrame {
     int32_t size;
     bool is_keyframe;
     int64_t timestamp;
+    int32_t picture_type;  // HWCODEC_PIC_*
+    int32_t avg_qp;        // -1: 未知
+    int32_t temporal_id;   // -1: 未知
+    int64_t dts;
+    bool has_dts;
 };
 struct DecodedFrame {
     uint8_t* texture;
@@ -66,6 +75,39 @@ struct DecodedFrame {
     int32_t height;
 };
 
+#if defined(_WIN32) || defined(_WIN64)
+// 复制码流并填写 NV_ENC_LOCK_BITSTREAM 报告的帧信息；NVENC 不报告 dts
+static EncodedFrame* nv_frame_from_bitstream(const NV_ENC_LOCK_BITSTREAM& lockBs, int64_t timestamp) {
+    EncodedFrame* frame = new EncodedFrame();
+    frame->size = (int32_t)lockBs.bitstreamSizeInBytes;
+    frame->data = (uint8_t*)malloc((size_t)frame->size);
+    if (frame->data && frame->size > 0) memcpy(frame->data, lockBs.bitstreamBufferPtr, (size_t)frame->size);
+    frame->is_keyframe = (lockBs.pictureType == NV_ENC_PIC_TYPE_IDR || lockBs.pictureType == NV_ENC_PIC_TYPE_I);
+    frame->timestamp = timestamp;
+    switch (lockBs.pictureType) {
+    case NV_ENC_PIC_TYPE_IDR: frame->picture_type = HWCODEC_PIC_IDR; break;
+    case NV_ENC_PIC_TYPE_I:
+    case NV_ENC_PIC_TYPE_INTRA_REFRESH: frame->picture_type = HWCODEC_PIC_I; break;
+    case NV_ENC_PIC_TYPE_P:
+    case NV_ENC_PIC_TYPE_NONREF_P: frame->picture_type = HWCODEC_PIC_P; break;
+    case NV_ENC_PIC_TYPE_B:
+    case NV_ENC_PIC_TYPE_BI: frame->picture_type = HWCODEC_PIC_B; break;
+    default: frame->picture_type = HWCODEC_PIC_UNKNOWN; break;
+    }
+    frame->avg_qp = (int32_t)lockBs.frameAvgQP;
+    frame->temporal_id = (int32_t)lockBs.temporalId;
+    frame->dts = 0;
+    frame->has_dts = false;
+    return frame;
+}
+#endif
+
+// 一个在途帧：完成事件与输出缓冲。有 B 帧时输出缓冲按编码顺序填充，与提交的输入不一一对应
+struct NvAsyncSlot {
+    void* event;
+    void* bitstream;
+};
+
 // NvEncoder: real implementation attempts NVENC API when driver is available.
 // CreateEncoder tries to load nvEncodeAPI and open session; on success stores context in encoder->impl.
 // EncodeFrame uses stored context to encode when possible; returns nullptr on failure or when not initialized.
@@ -80,11 +122,116 @@ struct NvEncContext {
     int32_t framerate;
     int32_t gop;
     bool initialized;
+    // 异步模式（enableEncodeAsync）：保存函数表，槽位按提交顺序环形使用
+    void* api;
+    int32_t async_depth;
+    NvAsyncSlot* slots;
+    int32_t slot_head;
+    int32_t slot_count;
+    // B 帧数（frameIntervalP - 1），仅异步模式下启用
+    int32_t bframes;
+    // 按提交顺序注册的输入纹理；B 帧编码前其输入须保持注册
+    std::deque<void*> inputs;
+    // EOS 使用的完成事件
+    void* eos_event;
+    // 长期参考与参考失效
+    LtrState ltr;
+    // 输入纹理的 NV_ENC_BUFFER_FORMAT：8 bit 为 ARGB (B8G8R8A8)，Main10 为 ABGR10 (R10G10B10A2)
+    uint32_t buffer_format;
+    // 4:4:4 会话另接受 AYUV 纹理，按纹理格式逐帧选择
+    bool yuv444;
 };
 
+#if defined(_WIN32) || defined(_WIN64)
+static int nv_query_cap(NV_ENCODE_API_FUNCTION_LIST& nvenc, void* hEncoder, GUID codecGuid, NV_ENC_CAPS cap);
+
+// 输入纹理的 NV_ENC_BUFFER_FORMAT；8 bit 4:4:4 会话中 AYUV 纹理不经 RGB 转换直接编码
+static NV_ENC_BUFFER_FORMAT nv_input_format(NvEncContext* ctx, uint8_t* texture) {
+    if (ctx->yuv444 && ctx->buffer_format == NV_ENC_BUFFER_FORMAT_ARGB) {
+        D3D11_TEXTURE2D_DESC desc = {};
+        ((ID3D11Texture2D*)texture)->GetDesc(&desc);
+        if (desc.Format == DXGI_FORMAT_AYUV) return NV_ENC_BUFFER_FORMAT_AYUV;
+    }
+    return (NV_ENC_BUFFER_FORMAT)ctx->buffer_format;
+}
+
+static bool nv_setup_async(NvEncContext* ctx, NV_ENCODE_API_FUNCTION_LIST* api, int32_t depth) {
+    if (!api->nvEncRegisterAsyncEvent || !api->nvEncCreateBitstreamBuffer) return false;
+    ctx->slots = new NvAsyncSlot[depth]();
+    ctx->async_depth = depth;
+    HANDLE eos = CreateEventA(nullptr, FALSE, FALSE, nullptr);
+    if (!eos) return false;
+    NV_ENC_EVENT_PARAMS eosParams = { NV_ENC_EVENT_PARAMS_VER };
+    eosParams.completionEvent = eos;
+    if (api->nvEncRegisterAsyncEvent(ctx->hEncoder, &eosParams) != NV_ENC_SUCCESS) {
+        CloseHandle(eos);
+        return false;
+    }
+    ctx->eos_event = eos;
+    for (int32_t i = 0; i < depth; i++) {
+        NvAsyncSlot& slot = ctx->slots[i];
+        HANDLE event = CreateEventA(nullptr, FALSE, FALSE, nullptr);
+        if (!event) return false;
+        NV_ENC_EVENT_PARAMS eventParams = { NV_ENC_EVENT_PARAMS_VER };
+        eventParams.completionEvent = event;
+        if (api->nvEncRegisterAsyncEvent(ctx->hEncoder, &eventParams) != NV_ENC_SUCCESS) {
+            CloseHandle(event);
+            return false;
+        }
+        slot.event = event;
+        NV_ENC_CREATE_BITSTREAM_BUFFER createBs = { NV_ENC_CREATE_BITSTREAM_BUFFER_VER };
+        if (api->nvEncCreateBitstreamBuffer(ctx->hEncoder, &createBs) != NV_ENC_SUCCESS) return false;
+        slot.bitstream = createBs.bitstreamBuffer;
+    }
+    return true;
+}
+
+// 释放已编码完的输入：在途 slot_count 帧中最多 bframes 帧尚未编码，更早的输入已不再被引用
+static void nv_release_inputs(NvEncContext* ctx, size_t keep) {
+    NV_ENCODE_API_FUNCTION_LIST* api = (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
+    while (ctx->inputs.size() > keep) {
+        if (api->nvEncUnregisterResource) api->nvEncUnregisterResource(ctx->hEncoder, ctx->inputs.front());
+        ctx->inputs.pop_front();
+    }
+}
+
+static void nv_release_async(NvEncContext* ctx) {
+    if (!ctx->slots) return;
+    NV_ENCODE_API_FUNCTION_LIST* api = (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
+    // 先等在途帧完成，再释放其输入与输出资源
+    for (int32_t i = 0; i < ctx->slot_count; i++) {
+        NvAsyncSlot& slot = ctx->slots[(ctx->slot_head + i) % ctx->async_depth];
+        WaitForSingleObject((HANDLE)slot.event, 1000);
+    }
+    nv_release_inputs(ctx, 0);
+    if (ctx->eos_event) {
+        NV_ENC_EVENT_PARAMS eventParams = { NV_ENC_EVENT_PARAMS_VER };
+        eventParams.completionEvent = ctx->eos_event;
+        if (api->nvEncUnregisterAsyncEvent) api->nvEncUnregisterAsyncEvent(ctx->hEncoder, &eventParams);
+        CloseHandle((HANDLE)ctx->eos_event);
+        ctx->eos_event = nullptr;
+    }
+    for (int32_t i = 0; i < ctx->async_depth; i++) {
+        NvAsyncSlot& slot = ctx->slots[i];
+        if (slot.bitstream && api->nvEncDestroyBitstreamBuffer) api->nvEncDestroyBitstreamBuffer(ctx->hEncoder, slot.bitstream);
+        if (slot.event) {
+            NV_ENC_EVENT_PARAMS eventParams = { NV_ENC_EVENT_PARAMS_VER };
+            eventParams.completionEvent = slot.event;
+            if (api->nvEncUnregisterAsyncEvent) api->nvEncUnregisterAsyncEvent(ctx->hEncoder, &eventParams);
+            CloseHandle((HANDLE)slot.event);
+        }
+    }
+    delete[] ctx->slots;
+    ctx->slots = nullptr;
+    ctx->slot_count = 0;
+}
+#endif
+
 static void nv_destroy_encoder_impl(NvEncContext* ctx) {
     if (!ctx) return;
 #if defined(_WIN32) || defined(_WIN64)
+    if (ctx->hEncoder) nv_release_async(ctx);
+    if (ctx->api) delete (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
     if (ctx->hEncoder && ctx->nvenc_dll) {
         typedef NVENCSTATUS (NVENCAPI *DestroyEncoderFn)(void*);
         DestroyEncoderFn nvEncDestroyEncoder = (DestroyEncoderFn)GetProcAddress((HMODULE)ctx->nvenc_dll, "NvEncDestroyEncoder");
@@ -95,7 +242,65 @@ static void nv_destroy_encoder_impl(NvEncContext* ctx) {
     delete ctx;
 }
 
-extern "C++" NvEncoder* nv_CreateEncoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop) {
+// H.264 与 HEVC 配置的帧内刷新字段同名
+template <typename Config>
+static void nv_set_intra_refresh(Config& config, int32_t period, int32_t duration) {
+    config.idrPeriod = NVENC_INFINITE_GOPLENGTH;
+    config.enableIntraRefresh = 1;
+    config.intraRefreshPeriod = (uint32_t)period;
+    config.intraRefreshCnt = (uint32_t)duration;
+    config.outputRecoveryPointSEI = 1;
+}
+
+template <typename Config>
+static void nv_set_ltr(Config& config, int32_t frames) {
+    config.enableLTR = 1;
+    config.ltrTrustMode = 0;
+    config.ltrNumFrames = (uint32_t)frames;
+}
+
+// H.264 与 HEVC 的 VUI 结构相同；NVENC 对 RGB 输入也按其中的矩阵与范围做颜色转换
+static void nv_set_vui(NV_ENC_CONFIG_H264_VUI_PARAMETERS& vui, const ColorDesc& color) {
+    vui.videoSignalTypePresentFlag = 1;
+    vui.videoFormat = NV_ENC_VUI_VIDEO_FORMAT_UNSPECIFIED;
+    vui.videoFullRangeFlag = color.full_range ? 1 : 0;
+    vui.colourDescriptionPresentFlag = 1;
+    vui.colourPrimaries = (NV_ENC_VUI_COLOR_PRIMARIES)color.primaries;
+    vui.transferCharacteristics = (NV_ENC_VUI_TRANSFER_CHARACTERISTIC)color.transfer;
+    vui.colourMatrix = (NV_ENC_VUI_MATRIX_COEFFS)color.matrix;
+    vui.chromaSampleLocationFlag = 1;
+    vui.chromaSampleLocationTop = (uint32_t)color.chroma_location;
+    vui.chromaSampleLocationBot = (uint32_t)color.chroma_location;
+}
+
+template <typename PicParams>
+static void nv_apply_ltr(PicParams& pic, const LtrControl& c) {
+    if (c.mark_slot >= 0) {
+        pic.ltrMarkFrame = 1;
+        pic.ltrMarkFrameIdx = (uint32_t)c.mark_slot;
+    }
+    if (c.recover && c.use != 0) {
+        pic.ltrUseFrames = 1;
+        pic.ltrUseFrameBitmap = c.use;
+    }
+}
+
+// 帧的长期参考标记；失效后没有可用的长期参考时强制 IDR。帧提交成功后须 ltr_commit
+static LtrControl nv_set_frame_refs(NvEncContext* ctx, NV_ENC_PIC_PARAMS& picParams) {
+    LtrControl c = ltr_next_frame(ctx->ltr);
+    if (ctx->ltr.slots == 0) return c;
+    if (c.recover && c.use == 0)
+        picParams.encodePicFlags |= NV_ENC_PIC_FLAG_FORCEIDR | NV_ENC_PIC_FLAG_OUTPUT_SPSPPS;
+    if (ctx->codec_id == 1)
+        nv_apply_ltr(picParams.codecPicParams.hevcPicParams, c);
+    else
+        nv_apply_ltr(picParams.codecPicParams.h264PicParams, c);
+    return c;
+}
+
+// async_depth > 0 且设备支持时以异步模式初始化，否则为同步模式（GetAsyncDepth 返回 0）。
+// B 帧只在异步模式下启用：同步模式一次只取一个输出缓冲，无法取回被 B 帧推迟的输出
+static NvEncoder* nv_create_encoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444) {
     if (!IsNvidiaEncodeAvailable() || !device || width <= 0 || height <= 0) return nullptr;
 #if defined(_WIN32) || defined(_WIN64)
     HMODULE nvenc_dll = LoadLibraryA("nvEncodeAPI64.dll");
@@ -122,6 +327,8 @@ extern "C++" NvEncoder* nv_CreateEncoder(uint8_t* device, int32_t width, int32_t
     ctx->framerate = framerate;
     ctx->gop = gop;
     ctx->initialized = false;
+    ctx->buffer_format = bit_depth == 10 ? NV_ENC_BUFFER_FORMAT_ABGR10 : NV_ENC_BUFFER_FORMAT_ARGB;
+    ctx->yuv444 = yuv444;
     if (nvenc.nvEncInitializeEncoder) {
         GUID codecGuid = (codec_id == 1) ? NV_ENC_CODEC_HEVC_GUID : NV_ENC_CODEC_H264_GUID;
         NV_ENC_PRESET_CONFIG presetConfig = { NV_ENC_PRESET_CONFIG_VER, { NV_ENC_CONFIG_VER } };
@@ -141,15 +348,240 @@ extern "C++" NvEncoder* nv_CreateEncoder(uint8_t* device, int32_t width, int32_t
             initParams.encodeConfig->rcParams.averageBitRate = (uint32_t)(bitrate * 1000);
             initParams.encodeConfig->rcParams.maxBitRate = (uint32_t)(bitrate * 1000);
             initParams.encodeConfig->gopLength = (gop > 0 && gop < (int32_t)0xffff) ? (uint32_t)gop : NVENC_INFINITE_GOPLENGTH;
-            if (nvenc.nvEncInitializeEncoder(hEncoder, &initParams) == NV_ENC_SUCCESS)
+            // 帧内刷新取代周期 IDR：只有首帧为 IDR，下面的 B 帧也随无限 GOP 关闭。
+            // 调用方明确要求时不支持即创建失败，避免悄悄退回周期 IDR
+            if (intra_refresh_duration > 0 && intra_refresh_period > intra_refresh_duration) {
+                if (!nvenc.nvEncGetEncodeCaps || nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORT_INTRA_REFRESH) == 0) {
+                    nv_destroy_encoder_impl(ctx);
+                    return nullptr;
+                }
+                initParams.encodeConfig->gopLength = NVENC_INFINITE_GOPLENGTH;
+                if (codec_id == 1)
+                    nv_set_intra_refresh(initParams.encodeConfig->encodeCodecConfig.hevcConfig, intra_refresh_period, intra_refresh_duration);
+                else
+                    nv_set_intra_refresh(initParams.encodeConfig->encodeCodecConfig.h264Config, intra_refresh_period, intra_refresh_duration);
+            }
+            // Main10 只支持 HEVC；与帧内刷新一样，不支持时创建失败而不是退回 8 bit
+            if (bit_depth == 10) {
+                if (codec_id != 1 || !nvenc.nvEncGetEncodeCaps || nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORT_10BIT_ENCODE) == 0) {
+                    nv_destroy_encoder_impl(ctx);
+                    return nullptr;
+                }
+                initParams.encodeConfig->profileGUID = NV_ENC_HEVC_PROFILE_MAIN10_GUID;
+                initParams.encodeConfig->encodeCodecConfig.hevcConfig.pixelBitDepthMinus8 = 2;
+            }
+            // 4:4:4 为 H.264 High 4:4:4 Predictive 或 HEVC RExt（含 10 bit）；RGB 输入由 NVENC 转为 YUV444，不经色度下采样
+            if (yuv444) {
+                if (!nvenc.nvEncGetEncodeCaps || nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORT_YUV444_ENCODE) == 0) {
+                    nv_destroy_encoder_impl(ctx);
+                    return nullptr;
+                }
+                if (codec_id == 1) {
+                    initParams.encodeConfig->profileGUID = NV_ENC_HEVC_PROFILE_FREXT_GUID;
+                    initParams.encodeConfig->encodeCodecConfig.hevcConfig.chromaFormatIDC = 3;
+                } else {
+                    initParams.encodeConfig->profileGUID = NV_ENC_H264_PROFILE_HIGH_444_GUID;
+                    initParams.encodeConfig->encodeCodecConfig.h264Config.chromaFormatIDC = 3;
+                }
+            }
+            if (color.present) {
+                if (codec_id == 1)
+                    nv_set_vui(initParams.encodeConfig->encodeCodecConfig.hevcConfig.hevcVUIParameters, color);
+                else
+                    nv_set_vui(initParams.encodeConfig->encodeCodecConfig.h264Config.h264VUIParameters, color);
+            }
+            bool async = async_depth > 0 && nvenc.nvEncGetEncodeCaps
+                && nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_ASYNC_ENCODE_SUPPORT) != 0;
+            initParams.enableEncodeAsync = async ? 1 : 0;
+            // 长期参考使用逐帧标记模式，NVENC 的长期参考不支持 B 帧
+            int32_t ltr = 0;
+            if (ltr_frames > 0 && nvenc.nvEncGetEncodeCaps) {
+                ltr = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_NUM_MAX_LTR_FRAMES);
+                if (ltr > ltr_frames) ltr = ltr_frames;
+                if (ltr > HWCODEC_LTR_MAX_SLOTS) ltr = HWCODEC_LTR_MAX_SLOTS;
+                if (ltr > 0) {
+                    if (codec_id == 1)
+                        nv_set_ltr(initParams.encodeConfig->encodeCodecConfig.hevcConfig, ltr);
+                    else
+                        nv_set_ltr(initParams.encodeConfig->encodeCodecConfig.h264Config, ltr);
+                    bframes = 0;
+                }
+            }
+            // 无限 GOP 时 frameIntervalP 须为 1
+            int32_t b = 0;
+            if (async && bframes > 0 && initParams.encodeConfig->gopLength != NVENC_INFINITE_GOPLENGTH) {
+                int maxB = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_NUM_MAX_BFRAMES);
+                b = bframes < maxB ? bframes : maxB;
+                // 环形槽位须容纳被推迟的 B 帧与其后的参考帧
+                if (b > async_depth - 1) b = async_depth - 1;
+                if (b < 0) b = 0;
+            }
+            initParams.encodeConfig->frameIntervalP = b + 1;
+            ctx->bframes = b;
+            ctx->ltr.slots = ltr;
+            ctx->ltr.idr_period = gop > 0 && gop < (int32_t)0xffff ? gop : 0;
+            if (nvenc.nvEncInitializeEncoder(hEncoder, &initParams) == NV_ENC_SUCCESS) {
                 ctx->initialized = true;
+                // 同步模式也保存函数表，供参考失效使用
+                NV_ENCODE_API_FUNCTION_LIST* api = new NV_ENCODE_API_FUNCTION_LIST(nvenc);
+                ctx->api = api;
+                if (async) {
+                    // 异步模式下没有完成事件无法编码，建立失败则整个编码器不可用
+                    if (!nv_setup_async(ctx, api, async_depth)) {
+                        nv_destroy_encoder_impl(ctx);
+                        return nullptr;
+                    }
+                }
+            }
         }
     }
     NvEncoder* enc = new NvEncoder();
     enc->impl = ctx;
     return enc;
 #else
-    (void)device; (void)width; (void)height; (void)codec_id; (void)bitrate; (void)framerate; (void)gop;
+    (void)device; (void)width; (void)height; (void)codec_id; (void)bitrate; (void)framerate; (void)gop; (void)async_depth; (void)bframes; (void)intra_refresh_period; (void)intra_refresh_duration; (void)ltr_frames; (void)color; (void)bit_depth; (void)yuv444;
+    return nullptr;
+#endif
+}
+
+extern "C++" NvEncoder* nv_CreateEncoder(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop) {
+    return nv_create_encoder(device, width, height, codec_id, bitrate, framerate, gop, 0, 0, 0, 0, 0, ColorDesc{}, 8, false);
+}
+
+extern "C++" NvEncoder* nv_CreateEncoderAsync(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444) {
+    return nv_create_encoder(device, width, height, codec_id, bitrate, framerate, gop, async_depth, bframes, intra_refresh_period, intra_refresh_duration, ltr_frames, color, bit_depth, yuv444);
+}
+
+extern "C++" int32_t nv_GetReorderDepth(NvEncoder* encoder) {
+    if (!encoder || !encoder->impl) return 0;
+    return ((NvEncContext*)encoder->impl)->bframes;
+}
+
+// 发送 EOS，使被推迟的 B 帧完成编码；其输出仍由 ReceiveFrame 按编码顺序取回
+extern "C++" int32_t nv_FlushEncoder(NvEncoder* encoder) {
+    if (!encoder || !encoder->impl) return -1;
+    NvEncContext* ctx = (NvEncContext*)encoder->impl;
+    if (!ctx->initialized || ctx->async_depth <= 0 || ctx->slot_count == 0) return 0;
+#if defined(_WIN32) || defined(_WIN64)
+    NV_ENCODE_API_FUNCTION_LIST* api = (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
+    NV_ENC_PIC_PARAMS picParams = { NV_ENC_PIC_PARAMS_VER };
+    picParams.encodePicFlags = NV_ENC_PIC_FLAG_EOS;
+    picParams.completionEvent = ctx->eos_event;
+    if (api->nvEncEncodePicture(ctx->hEncoder, &picParams) != NV_ENC_SUCCESS) return -1;
+    return 0;
+#else
+    return -1;
+#endif
+}
+
+extern "C++" int32_t nv_GetAsyncDepth(NvEncoder* encoder) {
+    if (!encoder || !encoder->impl) return 0;
+    return ((NvEncContext*)encoder->impl)->async_depth;
+}
+
+extern "C++" int32_t nv_PendingFrames(NvEncoder* encoder) {
+    if (!encoder || !encoder->impl) return 0;
+    return ((NvEncContext*)encoder->impl)->slot_count;
+}
+
+// 返回 0 成功，1 会话未启用长期参考，负值为错误
+extern "C++" int32_t nv_MarkLtr(NvEncoder* encoder) {
+    if (!encoder || !encoder->impl) return -1;
+    NvEncContext* ctx = (NvEncContext*)encoder->impl;
+    if (ctx->ltr.slots == 0) return 1;
+    ctx->ltr.mark = true;
+    return 0;
+}
+
+// 返回值同 MarkLtr。NVENC 立即将范围内的帧移出参考，之后的帧只参考其前标记的长期参考
+extern "C++" int32_t nv_InvalidateRefFrames(NvEncoder* encoder, int64_t first, int64_t last) {
+    if (!encoder || !encoder->impl) return -1;
+    NvEncContext* ctx = (NvEncContext*)encoder->impl;
+    if (ctx->ltr.slots == 0) return 1;
+#if defined(_WIN32) || defined(_WIN64)
+    NV_ENCODE_API_FUNCTION_LIST* api = (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
+    if (!api || !api->nvEncInvalidateRefFrames) return -1;
+    for (const auto& f : ltr_invalidate(ctx->ltr, first, last))
+        api->nvEncInvalidateRefFrames(ctx->hEncoder, (uint64_t)f.first);
+    return 0;
+#else
+    (void)first; (void)last;
+    return -1;
+#endif
+}
+
+// 返回 0 已提交，1 槽位已满，负值为错误
+extern "C++" int32_t nv_SubmitFrame(NvEncoder* encoder, uint8_t* texture, int64_t timestamp) {
+    if (!encoder || !encoder->impl || !texture) return -1;
+    NvEncContext* ctx = (NvEncContext*)encoder->impl;
+    if (!ctx->initialized || ctx->async_depth <= 0) return -1;
+    if (ctx->slot_count >= ctx->async_depth) return 1;
+#if defined(_WIN32) || defined(_WIN64)
+    NV_ENCODE_API_FUNCTION_LIST* api = (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
+    if (!api->nvEncRegisterResource || !api->nvEncEncodePicture) return -1;
+    NvAsyncSlot& slot = ctx->slots[(ctx->slot_head + ctx->slot_count) % ctx->async_depth];
+    NV_ENC_REGISTER_RESOURCE regRes = { NV_ENC_REGISTER_RESOURCE_VER };
+    regRes.resourceType = NV_ENC_INPUT_RESOURCE_TYPE_DIRECTX;
+    regRes.resourceToRegister = texture;
+    regRes.width = (uint32_t)ctx->width;
+    regRes.height = (uint32_t)ctx->height;
+    regRes.bufferFormat = nv_input_format(ctx, texture);
+    if (api->nvEncRegisterResource(ctx->hEncoder, &regRes) != NV_ENC_SUCCESS) return -1;
+    NV_ENC_PIC_PARAMS picParams = { NV_ENC_PIC_PARAMS_VER };
+    picParams.inputBuffer = regRes.registeredResource;
+    picParams.bufferFmt = regRes.bufferFormat;
+    picParams.inputWidth = (uint32_t)ctx->width;
+    picParams.inputHeight = (uint32_t)ctx->height;
+    picParams.inputPitch = (uint32_t)(ctx->width * 4);
+    picParams.outputBitstream = slot.bitstream;
+    picParams.completionEvent = slot.event;
+    picParams.inputTimeStamp = (uint64_t)timestamp;
+    LtrControl refs = nv_set_frame_refs(ctx, picParams);
+    // NEED_MORE_INPUT：该帧作为 B 帧被推迟，输出缓冲在其后的参考帧提交时按编码顺序填充
+    NVENCSTATUS st = api->nvEncEncodePicture(ctx->hEncoder, &picParams);
+    if (st != NV_ENC_SUCCESS && st != NV_ENC_ERR_NEED_MORE_INPUT) {
+        if (api->nvEncUnregisterResource) api->nvEncUnregisterResource(ctx->hEncoder, regRes.registeredResource);
+        return -1;
+    }
+    ctx->inputs.push_back(regRes.registeredResource);
+    ctx->slot_count++;
+    if (ctx->ltr.slots > 0) ltr_commit(ctx->ltr, refs, timestamp);
+    return 0;
+#else
+    (void)timestamp;
+    return -1;
+#endif
+}
+
+// status: 0 取到一帧，1 最早的在途帧在 wait_ms 内未完成（或没有在途帧），负值为错误
+extern "C++" EncodedFrame* nv_ReceiveFrame(NvEncoder* encoder, uint32_t wait_ms, int32_t* status) {
+    if (!status) return nullptr;
+    *status = -1;
+    if (!encoder || !encoder->impl) return nullptr;
+    NvEncContext* ctx = (NvEncContext*)encoder->impl;
+    if (ctx->async_depth <= 0) return nullptr;
+    if (ctx->slot_count == 0) { *status = 1; return nullptr; }
+#if defined(_WIN32) || defined(_WIN64)
+    NV_ENCODE_API_FUNCTION_LIST* api = (NV_ENCODE_API_FUNCTION_LIST*)ctx->api;
+    NvAsyncSlot& slot = ctx->slots[ctx->slot_head];
+    DWORD waited = WaitForSingleObject((HANDLE)slot.event, (DWORD)wait_ms);
+    if (waited == WAIT_TIMEOUT) { *status = 1; return nullptr; }
+    // 无论成功与否都让出槽位，避免一帧出错后后续帧全部卡住
+    ctx->slot_head = (ctx->slot_head + 1) % ctx->async_depth;
+    ctx->slot_count--;
+    EncodedFrame* frame = nullptr;
+    NV_ENC_LOCK_BITSTREAM lockBs = { NV_ENC_LOCK_BITSTREAM_VER };
+    lockBs.outputBitstream = slot.bitstream;
+    if (waited == WAIT_OBJECT_0 && api->nvEncLockBitstream && api->nvEncLockBitstream(ctx->hEncoder, &lockBs) == NV_ENC_SUCCESS) {
+        // 有 B 帧时输出不按提交顺序，pts 取驱动回传的 inputTimeStamp
+        frame = nv_frame_from_bitstream(lockBs, (int64_t)lockBs.outputTimeStamp);
+        if (api->nvEncUnlockBitstream) api->nvEncUnlockBitstream(ctx->hEncoder, slot.bitstream);
+        *status = 0;
+    }
+    nv_release_inputs(ctx, ctx->slot_count == 0 ? 0 : (size_t)(ctx->slot_count + ctx->bframes));
+    return frame;
+#else
+    (void)wait_ms;
     return nullptr;
 #endif
 }
@@ -158,6 +590,12 @@ extern "C++" EncodedFrame* nv_EncodeFrame(NvEncoder* encoder, uint8_t* texture,
     if (!encoder || !encoder->impl || !IsNvidiaEncodeAvailable()) return nullptr;
     NvEncContext* ctx = (NvEncContext*)encoder->impl;
     if (!ctx->initialized || !texture) return nullptr;
+    if (ctx->async_depth > 0) {
+        // 异步模式下同步编码即提交后等待最早的一帧；与 SubmitFrame 混用时返回的是更早提交的帧
+        if (nv_SubmitFrame(encoder, texture, timestamp) != 0) return nullptr;
+        int32_t status = 0;
+        return nv_ReceiveFrame(encoder, 3000, &status);
+    }
 #if defined(_WIN32) || defined(_WIN64)
     HMODULE nvenc_dll = (HMODULE)ctx->nvenc_dll;
     typedef NVENCSTATUS (NVENCAPI *RegisterResourceFn)(void*, NV_ENC_REGISTER_RESOURCE*);
@@ -181,17 +619,19 @@ extern "C++" EncodedFrame* nv_EncodeFrame(NvEncoder* encoder, uint8_t* texture,
     regRes.resourceToRegister = texture;
     regRes.width = (uint32_t)ctx->width;
     regRes.height = (uint32_t)ctx->height;
-    regRes.bufferFormat = NV_ENC_BUFFER_FORMAT_ARGB;
+    regRes.bufferFormat = nv_input_format(ctx, texture);
     if (nvEncRegisterResource(ctx->hEncoder, &regRes) != NV_ENC_SUCCESS) return nullptr;
     NV_ENC_REGISTERED_PTR registered = regRes.registeredResource;
     NV_ENC_PIC_PARAMS picParams = { NV_ENC_PIC_PARAMS_VER };
     picParams.inputBuffer = registered;
-    picParams.bufferFmt = NV_ENC_BUFFER_FORMAT_ARGB;
+    picParams.bufferFmt = regRes.bufferFormat;
     picParams.inputWidth = (uint32_t)ctx->width;
     picParams.inputHeight = (uint32_t)ctx->height;
     picParams.inputPitch = (uint32_t)(ctx->width * 4);
     picParams.outputBitstream = outputBitstream;
     picParams.encodePicFlags = 0;
+    picParams.inputTimeStamp = (uint64_t)timestamp;
+    LtrControl refs = nv_set_frame_refs(ctx, picParams);
     if (nvEncEncodePicture(ctx->hEncoder, &picParams) != NV_ENC_SUCCESS) {
         typedef NVENCSTATUS (NVENCAPI *UnregisterFn)(void*, NV_ENC_REGISTERED_PTR);
         UnregisterFn nvEncUnregister = (UnregisterFn)GetProcAddress(nvenc_dll, "NvEncUnregisterResource");
@@ -199,6 +639,7 @@ extern "C++" EncodedFrame* nv_EncodeFrame(NvEncoder* encoder, uint8_t* texture,
         nvEncDestroyBitstreamBuffer(ctx->hEncoder, outputBitstream);
         return nullptr;
     }
+    if (ctx->ltr.slots > 0) ltr_commit(ctx->ltr, refs, timestamp);
     NV_ENC_LOCK_BITSTREAM lockBs = { NV_ENC_LOCK_BITSTREAM_VER };
     lockBs.outputBitstream = outputBitstream;
     if (nvEncLockBitstream(ctx->hEncoder, &lockBs) != NV_ENC_SUCCESS) {
@@ -208,12 +649,7 @@ extern "C++" EncodedFrame* nv_EncodeFrame(NvEncoder* encoder, uint8_t* texture,
         nvEncDestroyBitstreamBuffer(ctx->hEncoder, outputBitstream);
         return nullptr;
     }
-    EncodedFrame* frame = new EncodedFrame();
-    frame->size = (int32_t)lockBs.bitstreamSizeInBytes;
-    frame->data = (uint8_t*)malloc((size_t)frame->size);
-    if (frame->data && frame->size > 0) memcpy(frame->data, lockBs.bitstreamBufferPtr, (size_t)frame->size);
-    frame->is_keyframe = (lockBs.pictureType == NV_ENC_PIC_TYPE_IDR || lockBs.pictureType == NV_ENC_PIC_TYPE_I);
-    frame->timestamp = timestamp;
+    EncodedFrame* frame = nv_frame_from_bitstream(lockBs, timestamp);
     if (lockBs.outputBitstream) nvEncUnlockBitstream(ctx->hEncoder, lockBs.outputBitstream);
     nvEncDestroyBitstreamBuffer(ctx->hEncoder, outputBitstream);
     typedef NVENCSTATUS (NVENCAPI *UnregisterFn)(void*, NV_ENC_REGISTERED_PTR);
@@ -251,6 +687,98 @@ extern "C++" void nv_SetFramerate(NvEncoder* encoder, int32_t framerate) {
     if (encoder && encoder->impl) ((NvEncContext*)encoder->impl)->framerate = framerate;
 }
 
+#if defined(_WIN32) || defined(_WIN64)
+static bool nv_guid_equal(const GUID& a, const GUID& b) {
+    return memcmp(&a, &b, sizeof(GUID)) == 0;
+}
+
+static int nv_query_cap(NV_ENCODE_API_FUNCTION_LIST& nvenc, void* hEncoder, GUID codecGuid, NV_ENC_CAPS cap) {
+    NV_ENC_CAPS_PARAM param = { NV_ENC_CAPS_PARAM_VER };
+    param.capsToQuery = cap;
+    int value = 0;
+    if (nvenc.nvEncGetEncodeCaps(hEncoder, codecGuid, &param, &value) != NV_ENC_SUCCESS) return 0;
+    return value;
+}
+#endif
+
+// 能力查询：临时打开一个编码会话，逐项 nvEncGetEncodeCaps 后关闭；不初始化编码器
+extern "C++" EncodeCaps nv_GetEncodeCaps(uint8_t* device, int32_t codec_id) {
+    EncodeCaps caps = {};
+    if (!IsNvidiaEncodeAvailable() || !device) return caps;
+#if defined(_WIN32) || defined(_WIN64)
+    HMODULE nvenc_dll = LoadLibraryA("nvEncodeAPI64.dll");
+    if (!nvenc_dll) return caps;
+    typedef NVENCSTATUS (NVENCAPI *CreateInstanceFn)(NV_ENCODE_API_FUNCTION_LIST*);
+    CreateInstanceFn createInstance = (CreateInstanceFn)GetProcAddress(nvenc_dll, "NvEncodeAPICreateInstance");
+    NV_ENCODE_API_FUNCTION_LIST nvenc = { NV_ENCODE_API_FUNCTION_LIST_VER };
+    if (!createInstance || createInstance(&nvenc) != NV_ENC_SUCCESS || !nvenc.nvEncOpenEncodeSessionEx || !nvenc.nvEncGetEncodeCaps) {
+        FreeLibrary(nvenc_dll);
+        return caps;
+    }
+    NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS sessionParams = { NV_ENC_OPEN_ENCODE_SESSION_EX_PARAMS_VER };
+    sessionParams.deviceType = NV_ENC_DEVICE_TYPE_DIRECTX;
+    sessionParams.device = device;
+    sessionParams.apiVersion = NVENCAPI_VERSION;
+    void* hEncoder = nullptr;
+    if (nvenc.nvEncOpenEncodeSessionEx(&sessionParams, &hEncoder) != NV_ENC_SUCCESS) { FreeLibrary(nvenc_dll); return caps; }
+
+    GUID codecGuid = (codec_id == 1) ? NV_ENC_CODEC_HEVC_GUID : NV_ENC_CODEC_H264_GUID;
+    GUID guids[16];
+    uint32_t count = 0;
+    bool codecFound = false;
+    if (nvenc.nvEncGetEncodeGUIDs && nvenc.nvEncGetEncodeGUIDs(hEncoder, guids, 16, &count) == NV_ENC_SUCCESS) {
+        for (uint32_t i = 0; i < count; i++) {
+            if (nv_guid_equal(guids[i], codecGuid)) codecFound = true;
+        }
+    }
+    if (codecFound) {
+        caps.supported = true;
+        caps.min_width = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_WIDTH_MIN);
+        caps.min_height = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_HEIGHT_MIN);
+        caps.max_width = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_WIDTH_MAX);
+        caps.max_height = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_HEIGHT_MAX);
+        caps.max_level = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_LEVEL_MAX);
+        // HEVC 的 NV_ENC_LEVEL 为 level*30，统一成 level*10
+        if (codec_id == 1) caps.max_level /= 3;
+        caps.ten_bit = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORT_10BIT_ENCODE) != 0;
+        caps.yuv444 = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORT_YUV444_ENCODE) != 0;
+        caps.max_bframes = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_NUM_MAX_BFRAMES);
+        caps.lookahead = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORT_LOOKAHEAD) != 0;
+        caps.max_ltr_frames = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_NUM_MAX_LTR_FRAMES);
+        // NVENC 不提供会话数上限查询（消费级驱动限制随版本变化），0 表示未知
+        caps.max_sessions = 0;
+        int rcModes = nv_query_cap(nvenc, hEncoder, codecGuid, NV_ENC_CAPS_SUPPORTED_RATECONTROL_MODES);
+        caps.rate_control = HWCODEC_RC_CQP;
+        if (rcModes & NV_ENC_PARAMS_RC_VBR) caps.rate_control |= HWCODEC_RC_VBR;
+        if (rcModes & NV_ENC_PARAMS_RC_CBR) caps.rate_control |= HWCODEC_RC_CBR;
+        count = 0;
+        if (nvenc.nvEncGetEncodeProfileGUIDs && nvenc.nvEncGetEncodeProfileGUIDs(hEncoder, codecGuid, guids, 16, &count) == NV_ENC_SUCCESS) {
+            for (uint32_t i = 0; i < count; i++) {
+                if (nv_guid_equal(guids[i], NV_ENC_H264_PROFILE_BASELINE_GUID)) caps.profiles |= HWCODEC_PROFILE_H264_BASELINE;
+                else if (nv_guid_equal(guids[i], NV_ENC_H264_PROFILE_MAIN_GUID)) caps.profiles |= HWCODEC_PROFILE_H264_MAIN;
+                else if (nv_guid_equal(guids[i], NV_ENC_H264_PROFILE_HIGH_GUID)) caps.profiles |= HWCODEC_PROFILE_H264_HIGH;
+                else if (nv_guid_equal(guids[i], NV_ENC_H264_PROFILE_HIGH_444_GUID)) caps.profiles |= HWCODEC_PROFILE_H264_HIGH444;
+                else if (nv_guid_equal(guids[i], NV_ENC_HEVC_PROFILE_MAIN_GUID)) caps.profiles |= HWCODEC_PROFILE_HEVC_MAIN;
+                else if (nv_guid_equal(guids[i], NV_ENC_HEVC_PROFILE_MAIN10_GUID)) caps.profiles |= HWCODEC_PROFILE_HEVC_MAIN10;
+                else if (nv_guid_equal(guids[i], NV_ENC_HEVC_PROFILE_FREXT_GUID)) caps.profiles |= HWCODEC_PROFILE_HEVC_REXT;
+            }
+        }
+    }
+    if (nvenc.nvEncDestroyEncoder) nvenc.nvEncDestroyEncoder(hEncoder);
+    FreeLibrary(nvenc_dll);
+#endif
+    return caps;
+}
+
+// 显示回调复制到主机内存、等待取走的一帧；尺寸随帧保存，序列参数变化不影响已排队的帧
+struct NvHostPicture {
+    std::vector<uint8_t> data;
+    unsigned int width = 0;
+    unsigned int height = 0;
+    size_t pitch = 0;
+    bool p010 = false;
+};
+
 // NVDEC decode context: all CUDA/cuvid loaded at runtime via dynlink (no link-time dependency)
 struct NvDecContext {
     CudaFunctions* cudl = nullptr;
@@ -271,10 +799,12 @@ struct NvDecContext {
     int nBPP = 1;
     int nNumChromaPlanes = 1;
     cudaVideoSurfaceFormat outFormat = cudaVideoSurfaceFormat_NV12;
-    uint8_t* hostFrame = nullptr;
     size_t hostFrameSize = 0;
     size_t hostPitch = 0;
-    bool frameReady = false;
+    // 一次解析可能显示多帧（B 帧重排、流结束时），按显示顺序排队
+    std::deque<NvHostPicture> displayed;
+    // 配置的位深：8 输出 NV12 纹理，10 输出 P010 纹理
+    int bitDepth = 8;
 };
 
 static int CUDAAPI HandleVideoSequence(void* pUserData, CUVIDEOFORMAT* pVideoFormat) {
@@ -288,10 +818,17 @@ static int CUDAAPI HandleVideoSequence(void* pUserData, CUVIDEOFORMAT* pVideoFor
         return 0;
     if ((unsigned)pVideoFormat->coded_width > caps.nMaxWidth || (unsigned)pVideoFormat->coded_height > caps.nMaxHeight)
         return 0;
+    // 码流位深与配置不符时不解码，输出纹理格式由配置决定
+    if ((pVideoFormat->bit_depth_luma_minus8 > 0) != (ctx->bitDepth == 10))
+        return 0;
     int nDecodeSurface = pVideoFormat->min_num_decode_surfaces;
     if (nDecodeSurface < 1) nDecodeSurface = 4;
     cudaVideoSurfaceFormat outFmt = (pVideoFormat->bit_depth_luma_minus8 > 0) ? cudaVideoSurfaceFormat_P016 : cudaVideoSurfaceFormat_NV12;
-    if (!(caps.nOutputFormatMask & (1 << outFmt))) outFmt = cudaVideoSurfaceFormat_NV12;
+    if (!(caps.nOutputFormatMask & (1 << outFmt))) {
+        // P016 的有效位在高位，与 P010 相同；没有它无法输出 10 bit
+        if (outFmt == cudaVideoSurfaceFormat_P016) return 0;
+        outFmt = cudaVideoSurfaceFormat_NV12;
+    }
     unsigned int w = pVideoFormat->display_area.right - pVideoFormat->display_area.left;
     unsigned int h = pVideoFormat->display_area.bottom - pVideoFormat->display_area.top;
     if (w == 0) w = pVideoFormat->coded_width;
@@ -334,8 +871,6 @@ static int CUDAAPI HandleVideoSequence(void* pUserData, CUVIDEOFORMAT* pVideoFor
     ctx->nNumChromaPlanes = numChromaPlanes;
     ctx->outFormat = outFmt;
     size_t frameSize = (size_t)ctx->outWidth * ctx->outLumaHeight * ctx->nBPP + (size_t)ctx->outWidth * ctx->outChromaHeight * ctx->nNumChromaPlanes * ctx->nBPP;
-    if (ctx->hostFrame) { delete[] ctx->hostFrame; ctx->hostFrame = nullptr; }
-    ctx->hostFrame = new (std::nothrow) uint8_t[frameSize];
     ctx->hostFrameSize = frameSize;
     ctx->hostPitch = (size_t)ctx->outWidth * ctx->nBPP;
     return nDecodeSurface;
@@ -350,7 +885,8 @@ static int CUDAAPI HandlePictureDecode(void* pUserData, CUVIDPICPARAMS* pPicPara
     return (r == CUDA_SUCCESS) ? 1 : 0;
 }
 
-static DecodedFrame* CreateD3D11FrameFromHostNV12(ID3D11Device* dev, ID3D11DeviceContext* imm, const uint8_t* host, int w, int h, size_t pitch) {
+// 主机内存中的 NV12 或 P016 帧上传为 NV12 或 P010 纹理
+static DecodedFrame* CreateD3D11FrameFromHost(ID3D11Device* dev, ID3D11DeviceContext* imm, const uint8_t* host, int w, int h, size_t pitch, bool p010) {
     if (!dev || !imm || !host || w <= 0 || h <= 0) return nullptr;
     UINT fullH = (UINT)(h + (h / 2));
     D3D11_TEXTURE2D_DESC desc = {};
@@ -358,7 +894,7 @@ static DecodedFrame* CreateD3D11FrameFromHostNV12(ID3D11Device* dev, ID3D11Devic
     desc.Height = fullH;
     desc.MipLevels = 1;
     desc.ArraySize = 1;
-    desc.Format = (DXGI_FORMAT)0x3231564E; /* NV12 */
+    desc.Format = p010 ? DXGI_FORMAT_P010 : DXGI_FORMAT_NV12;
     desc.SampleDesc.Count = 1;
     desc.SampleDesc.Quality = 0;
     desc.Usage = D3D11_USAGE_DEFAULT;
@@ -378,7 +914,14 @@ static DecodedFrame* CreateD3D11FrameFromHostNV12(ID3D11Device* dev, ID3D11Devic
 
 static int CUDAAPI HandlePictureDisplay(void* pUserData, CUVIDPARSERDISPINFO* pDispInfo) {
     NvDecContext* ctx = (NvDecContext*)pUserData;
-    if (!ctx || !ctx->hDecoder || !ctx->hostFrame || !ctx->cudl || !ctx->cvdl) return 0;
+    if (!ctx || !ctx->hDecoder || ctx->hostFrameSize == 0 || !ctx->cudl || !ctx->cvdl) return 0;
+    NvHostPicture picture;
+    picture.data.resize(ctx->hostFrameSize);
+    picture.width = ctx->outWidth;
+    picture.height = ctx->outLumaHeight;
+    picture.pitch = ctx->hostPitch;
+    picture.p010 = ctx->outFormat == cudaVideoSurfaceFormat_P016;
+    uint8_t* host = picture.data.data();
     CUVIDPROCPARAMS procParams = {};
     procParams.progressive_frame = pDispInfo->progressive_frame;
     procParams.second_field = pDispInfo->repeat_first_field + 1;
@@ -395,28 +938,62 @@ static int CUDAAPI HandlePictureDisplay(void* pUserData, CUVIDPARSERDISPINFO* pD
     m.srcDevice = dpSrc;
     m.srcPitch = srcPitch;
     m.dstMemoryType = CU_MEMORYTYPE_HOST;
-    m.dstHost = ctx->hostFrame;
+    m.dstHost = host;
     m.dstPitch = ctx->hostPitch;
     m.WidthInBytes = ctx->outWidth * ctx->nBPP;
     m.Height = ctx->outLumaHeight;
     ctx->cudl->cuMemcpy2DAsync(&m, ctx->stream);
     m.srcDevice = (CUdeviceptr)((uint8_t*)dpSrc + (size_t)srcPitch * ((ctx->outSurfaceHeight + 1) & ~1));
-    m.dstHost = ctx->hostFrame + ctx->hostPitch * ctx->outLumaHeight;
+    m.dstHost = host + ctx->hostPitch * ctx->outLumaHeight;
     m.Height = ctx->outChromaHeight;
     ctx->cudl->cuMemcpy2DAsync(&m, ctx->stream);
     if (ctx->nNumChromaPlanes == 2) {
         m.srcDevice = (CUdeviceptr)((uint8_t*)dpSrc + (size_t)srcPitch * ((ctx->outSurfaceHeight + 1) & ~1) * 2);
-        m.dstHost = ctx->hostFrame + ctx->hostPitch * ctx->outLumaHeight * 2;
+        m.dstHost = host + ctx->hostPitch * ctx->outLumaHeight * 2;
         ctx->cudl->cuMemcpy2DAsync(&m, ctx->stream);
     }
     ctx->cudl->cuStreamSynchronize(ctx->stream);
     ctx->cvdl->cuvidUnmapVideoFrame(ctx->hDecoder, dpSrc);
     ctx->cudl->cuCtxPopCurrent(nullptr);
-    ctx->frameReady = true;
+    ctx->displayed.push_back(std::move(picture));
     return 1;
 }
 
-extern "C++" NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id) {
+// 按 D3D11 设备所在适配器的 LUID 选择 CUDA 设备；没有匹配的设备（或驱动不支持 cuDeviceGetLuid）时失败
+static bool nv_cuda_device_for(CudaFunctions* cudl, uint8_t* device, CUdevice* out) {
+#if defined(_WIN32) || defined(_WIN64)
+    if (!device || !cudl->cuDeviceGetLuid) return false;
+    IDXGIDevice* dxgi = nullptr;
+    if (FAILED(((ID3D11Device*)device)->QueryInterface(__uuidof(IDXGIDevice), (void**)&dxgi)) || !dxgi) return false;
+    IDXGIAdapter* adapter = nullptr;
+    HRESULT hr = dxgi->GetAdapter(&adapter);
+    dxgi->Release();
+    if (FAILED(hr) || !adapter) return false;
+    DXGI_ADAPTER_DESC desc = {};
+    hr = adapter->GetDesc(&desc);
+    adapter->Release();
+    if (FAILED(hr)) return false;
+    int count = 0;
+    if (cudl->cuDeviceGetCount(&count) != CUDA_SUCCESS) return false;
+    for (int i = 0; i < count; i++) {
+        CUdevice dev = 0;
+        char luid[sizeof(LUID)] = {};
+        unsigned int nodeMask = 0;
+        if (cudl->cuDeviceGet(&dev, i) != CUDA_SUCCESS) continue;
+        if (cudl->cuDeviceGetLuid(luid, &nodeMask, dev) != CUDA_SUCCESS) continue;
+        if (memcmp(luid, &desc.AdapterLuid, sizeof(LUID)) == 0) {
+            *out = dev;
+            return true;
+        }
+    }
+    return false;
+#else
+    (void)cudl; (void)device; (void)out;
+    return false;
+#endif
+}
+
+extern "C++" NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id, int32_t bit_depth) {
     if (!IsNvidiaDecodeAvailable() || !device) return nullptr;
     CudaFunctions* cudl = nullptr;
     CuvidFunctions* cvdl = nullptr;
@@ -424,7 +1001,7 @@ extern "C++" NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id) {
     if (cuvid_load_functions(&cvdl, nullptr) != 0 || !cvdl) { cuda_free_functions(&cudl); return nullptr; }
     if (cudl->cuInit(0) != CUDA_SUCCESS) { cuvid_free_functions(&cvdl); cuda_free_functions(&cudl); return nullptr; }
     CUdevice cuDevice = 0;
-    if (cudl->cuDeviceGet(&cuDevice, 0) != CUDA_SUCCESS) { cuvid_free_functions(&cvdl); cuda_free_functions(&cudl); return nullptr; }
+    if (!nv_cuda_device_for(cudl, device, &cuDevice)) { cuvid_free_functions(&cvdl); cuda_free_functions(&cudl); return nullptr; }
     CUcontext cuCtx = nullptr;
     if (cudl->cuCtxCreate(&cuCtx, 0, cuDevice) != CUDA_SUCCESS) { cuvid_free_functions(&cvdl); cuda_free_functions(&cudl); return nullptr; }
     CUvideoctxlock ctxLock = nullptr;
@@ -439,6 +1016,7 @@ extern "C++" NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id) {
     ctx->cuDevice = cuDevice;
     ctx->ctxLock = ctxLock;
     ctx->stream = stream;
+    ctx->bitDepth = bit_depth == 10 ? 10 : 8;
 #if defined(_WIN32) || defined(_WIN64)
     ctx->d3d11 = (ID3D11Device*)device;
 #endif
@@ -468,26 +1046,36 @@ extern "C++" NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id) {
     return dec;
 }
 
-extern "C++" DecodedFrame* nv_DecodeFrame(NvDecoder* decoder, uint8_t* data, int32_t length) {
-    if (!decoder || !decoder->impl) return nullptr;
+// 解析一个包，显示的帧进入队列由 nv_NextDecodedFrame 取出；空包结束码流，送出解码器扣住的全部帧
+extern "C++" int32_t nv_DecodePacket(NvDecoder* decoder, uint8_t* data, int32_t length) {
+    if (!decoder || !decoder->impl) return -1;
     NvDecContext* ctx = (NvDecContext*)decoder->impl;
-    if (!ctx->cvdl || !ctx->hParser) return nullptr;
-    ctx->frameReady = false;
+    if (!ctx->cvdl || !ctx->hParser) return -1;
     CUVIDSOURCEDATAPACKET packet = {};
     packet.payload = data;
     packet.payload_size = (unsigned)(length > 0 ? length : 0);
     packet.flags = CUVID_PKT_TIMESTAMP;
     packet.timestamp = 0;
     if (!data || length <= 0) packet.flags |= CUVID_PKT_ENDOFSTREAM;
-    if (ctx->cvdl->cuvidParseVideoData(ctx->hParser, &packet) != CUDA_SUCCESS) return nullptr;
+    if (ctx->cvdl->cuvidParseVideoData(ctx->hParser, &packet) != CUDA_SUCCESS) return -1;
+    return 0;
+}
+
+// 取出最早显示的一帧并上传为纹理；队列为空时返回 nullptr
+extern "C++" DecodedFrame* nv_NextDecodedFrame(NvDecoder* decoder) {
+    if (!decoder || !decoder->impl) return nullptr;
+    NvDecContext* ctx = (NvDecContext*)decoder->impl;
 #if defined(_WIN32) || defined(_WIN64)
-    if (!ctx->frameReady || !ctx->hostFrame || !ctx->d3d11) return nullptr;
+    if (ctx->displayed.empty() || !ctx->d3d11) return nullptr;
+    NvHostPicture picture = std::move(ctx->displayed.front());
+    ctx->displayed.pop_front();
     ID3D11DeviceContext* imm = nullptr;
     ctx->d3d11->GetImmediateContext(&imm);
-    DecodedFrame* frame = CreateD3D11FrameFromHostNV12(ctx->d3d11, imm, ctx->hostFrame, (int)ctx->outWidth, (int)ctx->outLumaHeight, ctx->hostPitch);
+    DecodedFrame* frame = CreateD3D11FrameFromHost(ctx->d3d11, imm, picture.data.data(), (int)picture.width, (int)picture.height, picture.pitch, picture.p010);
     if (imm) imm->Release();
     return frame;
 #else
+    ctx->displayed.clear();
     return nullptr;
 #endif
 }
@@ -504,7 +1092,7 @@ static void nv_dec_context_destroy(NvDecContext* ctx) {
         ctx->cuCtx = nullptr;
     }
     if (ctx->ctxLock && ctx->cvdl) { ctx->cvdl->cuvidCtxLockDestroy(ctx->ctxLock); ctx->ctxLock = nullptr; }
-    if (ctx->hostFrame) { delete[] ctx->hostFrame; ctx->hostFrame = nullptr; }
+    ctx->displayed.clear();
     if (ctx->cvdl) { cuvid_free_functions(&ctx->cvdl); ctx->cvdl = nullptr; }
     if (ctx->cudl) { cuda_free_functions(&ctx->cudl); ctx->cudl = nullptr; }
     ctx->d3d11 = nullptr;
@@ -517,3 +1105,56 @@ extern "C++" void nv_DestroyDecoder(NvDecoder* decoder) {
     decoder->impl = nullptr;
     delete decoder;
 }
+
+
+// 解码能力：cuvidGetDecoderCaps 需要当前 CUDA 上下文（建在 device 所在适配器上），查询完即销毁
+extern "C++" DecodeCaps nv_GetDecodeCaps(uint8_t* device, int32_t codec_id) {
+    DecodeCaps caps = {};
+    if (!IsNvidiaDecodeAvailable() || !device) return caps;
+    CudaFunctions* cudl = nullptr;
+    CuvidFunctions* cvdl = nullptr;
+    if (cuda_load_functions(&cudl, nullptr) != 0 || !cudl) return caps;
+    if (cuvid_load_functions(&cvdl, nullptr) != 0 || !cvdl) { cuda_free_functions(&cudl); return caps; }
+    CUdevice cuDevice = 0;
+    CUcontext cuCtx = nullptr;
+    if (cudl->cuInit(0) != CUDA_SUCCESS || !nv_cuda_device_for(cudl, device, &cuDevice) ||
+        cudl->cuCtxCreate(&cuCtx, 0, cuDevice) != CUDA_SUCCESS) {
+        cuvid_free_functions(&cvdl);
+        cuda_free_functions(&cudl);
+        return caps;
+    }
+    cudaVideoCodec codec = (codec_id == 1) ? cudaVideoCodec_HEVC : cudaVideoCodec_H264;
+    CUVIDDECODECAPS base = {};
+    base.eCodecType = codec;
+    base.eChromaFormat = cudaVideoChromaFormat_420;
+    base.nBitDepthMinus8 = 0;
+    if (cvdl->cuvidGetDecoderCaps(&base) == CUDA_SUCCESS && base.bIsSupported) {
+        caps.supported = true;
+        caps.min_width = base.nMinWidth;
+        caps.min_height = base.nMinHeight;
+        caps.max_width = (int32_t)base.nMaxWidth;
+        caps.max_height = (int32_t)base.nMaxHeight;
+        CUVIDDECODECAPS tenBit = {};
+        tenBit.eCodecType = codec;
+        tenBit.eChromaFormat = cudaVideoChromaFormat_420;
+        tenBit.nBitDepthMinus8 = 2;
+        caps.ten_bit = cvdl->cuvidGetDecoderCaps(&tenBit) == CUDA_SUCCESS && tenBit.bIsSupported;
+        CUVIDDECODECAPS full = {};
+        full.eCodecType = codec;
+        full.eChromaFormat = cudaVideoChromaFormat_444;
+        full.nBitDepthMinus8 = 0;
+        caps.yuv444 = cvdl->cuvidGetDecoderCaps(&full) == CUDA_SUCCESS && full.bIsSupported;
+        if (codec_id == 1) {
+            caps.profiles = HWCODEC_PROFILE_HEVC_MAIN;
+            if (caps.ten_bit) caps.profiles |= HWCODEC_PROFILE_HEVC_MAIN10;
+            if (caps.yuv444) caps.profiles |= HWCODEC_PROFILE_HEVC_REXT;
+        } else {
+            // NVDEC 的 H.264 只支持 8 bit 4:2:0
+            caps.profiles = HWCODEC_PROFILE_H264_BASELINE | HWCODEC_PROFILE_H264_MAIN | HWCODEC_PROFILE_H264_HIGH;
+        }
+    }
+    cudl->cuCtxDestroy(cuCtx);
+    cuvid_free_functions(&cvdl);
+    cuda_free_functions(&cudl);
+    return caps;
+}
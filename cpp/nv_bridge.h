@@ -6,6 +6,9 @@ struct NvEncoder { void* impl; };
 struct NvDecoder { void* impl; };
 struct EncodedFrame;
 struct DecodedFrame;
+struct EncodeCaps;
+struct ColorDesc;
+struct DecodeCaps;
 
 bool nv_IsEncodeDriverAvailable();
 bool nv_IsDecodeDriverAvailable();
@@ -18,11 +21,24 @@ extern "C++" {
     void nv_DestroyEncoder(NvEncoder* encoder);
     void nv_SetBitrate(NvEncoder* encoder, int32_t bitrate);
     void nv_SetFramerate(NvEncoder* encoder, int32_t framerate);
+    NvEncoder* nv_CreateEncoderAsync(uint8_t* device, int32_t width, int32_t height, int32_t codec_id, int32_t bitrate, int32_t framerate, int32_t gop, int32_t async_depth, int32_t bframes, int32_t intra_refresh_period, int32_t intra_refresh_duration, int32_t ltr_frames, ColorDesc color, int32_t bit_depth, bool yuv444);
+    int32_t nv_GetAsyncDepth(NvEncoder* encoder);
+    int32_t nv_PendingFrames(NvEncoder* encoder);
+    int32_t nv_GetReorderDepth(NvEncoder* encoder);
+    int32_t nv_FlushEncoder(NvEncoder* encoder);
+    int32_t nv_MarkLtr(NvEncoder* encoder);
+    int32_t nv_InvalidateRefFrames(NvEncoder* encoder, int64_t first, int64_t last);
+    int32_t nv_SubmitFrame(NvEncoder* encoder, uint8_t* texture, int64_t timestamp);
+    EncodedFrame* nv_ReceiveFrame(NvEncoder* encoder, uint32_t wait_ms, int32_t* status);
 
-    NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id);
-    DecodedFrame* nv_DecodeFrame(NvDecoder* decoder, uint8_t* data, int32_t length);
+    NvDecoder* nv_CreateDecoder(uint8_t* device, int32_t codec_id, int32_t bit_depth);
+    int32_t nv_DecodePacket(NvDecoder* decoder, uint8_t* data, int32_t length);
+    DecodedFrame* nv_NextDecodedFrame(NvDecoder* decoder);
     void nv_DestroyDecoder(NvDecoder* decoder);
 
     void nv_FreeEncodedFrame(EncodedFrame* frame);
     void nv_FreeDecodedFrame(DecodedFrame* frame);
+
+    EncodeCaps nv_GetEncodeCaps(uint8_t* device, int32_t codec_id);
+    DecodeCaps nv_GetDecodeCaps(uint8_t* device, int32_t codec_id);
 }
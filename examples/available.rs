@@ -2,10 +2,18 @@ use env_logger::{init_from_env, Env, DEFAULT_FILTER_ENV};
 use hwcodec::common::get_gpu_signature;
 
 fn main() {
+    // 以 --hwcodec-probe 启动时作为探测子进程运行
+    if let Some(code) = hwcodec::vram::probe::child_main() {
+        std::process::exit(code);
+    }
     let start = std::time::Instant::now();
     init_from_env(Env::default().filter_or(DEFAULT_FILTER_ENV, "info"));
-    #[cfg(windows)]
-    vram();
+    if std::env::args().any(|a| a == "--isolated") {
+        isolated();
+    } else {
+        #[cfg(windows)]
+        vram();
+    }
     log::info!(
         "signature: {:?}, elapsed: {:?}",
         get_gpu_signature(),
@@ -13,9 +21,34 @@ fn main() {
     );
 }
 
+fn isolated() {
+    use hwcodec::common::{BitDepth, MAX_GOP};
+    use hwcodec::vram::{probe, DynamicContext};
+    let available = probe::probe_isolated_current_exe(
+        DynamicContext {
+            width: 1920,
+            height: 1080,
+            kbitrate: 5000,
+            framerate: 30,
+            gop: MAX_GOP as _,
+            bframes: 0,
+            intra_refresh: None,
+            ltr_frames: 0,
+            color: None,
+            bit_depth: BitDepth::Eight,
+            yuv444: false,
+            hdr: None,
+            scale: None,
+            device: None,
+        },
+        probe::DEFAULT_TIMEOUT,
+    );
+    println!("{}", available.serialize().unwrap_or_default());
+}
+
 #[cfg(windows)]
 fn vram() {
-    use hwcodec::common::MAX_GOP;
+    use hwcodec::common::{BitDepth, MAX_GOP};
     use hwcodec::vram::{decode, encode, DynamicContext};
     println!("vram:");
     println!("encoders:");
@@ -25,10 +58,28 @@ fn vram() {
         kbitrate: 5000,
         framerate: 30,
         gop: MAX_GOP as _,
+        bframes: 0,
+        intra_refresh: None,
+        ltr_frames: 0,
+        color: None,
+        bit_depth: BitDepth::Eight,
+        yuv444: false,
+        hdr: None,
+        scale: None,
         device: None,
     });
     encoders.iter().map(|e| println!("{:?}", e)).count();
     println!("decoders:");
     let decoders = decode::available();
     decoders.iter().map(|e| println!("{:?}", e)).count();
+    println!("encoder caps:");
+    encoders
+        .iter()
+        .map(|e| println!("{:?}", encode::capabilities(e)))
+        .count();
+    println!("decoder caps:");
+    decoders
+        .iter()
+        .map(|d| println!("{:?}", decode::capabilities(d)))
+        .count();
 }
@@ -1,11 +1,13 @@
-//! Demo: 创建色彩随时间变化的纹理，编码为 H.265 (HEVC) 并保存到文件。
+//! Demo: 用 testsrc 生成移动的测试图案，编码为 H.265 (HEVC) 并保存到文件。
 //!
 //! 运行: cargo run --example color_to_h265
 //! 输出: output/color_demo.h265
 
 use env_logger::{init_from_env, Env, DEFAULT_FILTER_ENV};
-use hwcodec::common::{DataFormat::H265, Driver, MAX_GOP};
-use hwcodec::vram::{encode, DynamicContext, EncodeContext};
+use hwcodec::common::{BitDepth, DataFormat::H265, Driver, MAX_GOP};
+use hwcodec::vram::select::{select_encoder, Policy};
+use hwcodec::vram::{encode, Available, DynamicContext, EncodeContext};
+use hwcodec::testsrc::{Pattern, TestSource};
 use std::fs::File;
 use std::io::Write;
 use std::os::raw::c_void;
@@ -17,11 +19,9 @@ use windows::{
     Win32::Graphics::Direct3D::{D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL_11_0},
     Win32::Graphics::Direct3D11::{
         D3D11CreateDevice, D3D11_SDK_VERSION, D3D11_CREATE_DEVICE_BGRA_SUPPORT,
-        D3D11_CREATE_DEVICE_VIDEO_SUPPORT, D3D11_BIND_SHADER_RESOURCE, D3D11_CPU_ACCESS_WRITE,
-        D3D11_MAP_WRITE, D3D11_MAPPED_SUBRESOURCE, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
-        D3D11_USAGE_STAGING, ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D,
+        D3D11_CREATE_DEVICE_VIDEO_SUPPORT, ID3D11Device, ID3D11DeviceContext,
     },
-    Win32::Graphics::Dxgi::Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC},
+    Win32::Graphics::Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM,
     Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIAdapter, IDXGIFactory1},
 };
 
@@ -37,11 +37,11 @@ fn main() {
 
     let total_frames = FRAMERATE * DURATION_SEC;
     log::info!(
-        "Demo: 色彩变化纹理 -> H.265 (HEVC), {}x{}, {} fps, {} 秒, {} 帧",
+        "Demo: 测试图案 -> H.265 (HEVC), {}x{}, {} fps, {} 秒, {} 帧",
         WIDTH, HEIGHT, FRAMERATE, DURATION_SEC, total_frames
     );
 
-    let (device, texture) = match create_d3d11_device_and_texture(WIDTH, HEIGHT) {
+    let device = match create_d3d11_device() {
         Ok(r) => r,
         Err(e) => {
             log::error!("创建 D3D11 设备失败: {:?}", e);
@@ -59,6 +59,14 @@ fn main() {
         kbitrate: 4000,
         framerate: FRAMERATE,
         gop: MAX_GOP as i32,
+        bframes: 0,
+        intra_refresh: None,
+        ltr_frames: 0,
+        color: None,
+        bit_depth: BitDepth::Eight,
+        yuv444: false,
+        hdr: None,
+        scale: None,
     };
 
     let available = encode::available(dynamic_ctx.clone());
@@ -72,23 +80,29 @@ fn main() {
                             .join(", ")
     );
 
-    let encoder_feature = match available
-        .iter()
-        .find(|e| e.data_format == H265 && e.driver == Driver::NV)
-        .or_else(|| available.iter().find(|e| e.data_format == H265))
-    {
-        Some(e) => {
-            log::info!("使用 H.265 编码器: {:?}", e.driver);
-            e.clone()
+    let available = Available {
+        e: available,
+        ..Default::default()
+    };
+    let typed_device = hwcodec::platform::win::Device::from(device.clone());
+    let policy = Policy::new(H265).prefer_device(&typed_device);
+    let encoder_feature = match select_encoder(&available, &policy).into_iter().next() {
+        Some(r) => {
+            log::info!(
+                "使用 H.265 编码器: {:?} ({})",
+                r.feature.driver,
+                r.reasons.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", ")
+            );
+            r.feature
         }
         None => {
             log::error!("未找到 H.265 编码器");
             log::error!("请确认：1) 驱动可用 (NVIDIA/AMD/Intel)；2) 纹理与编码器须使用同一 D3D11 设备");
-            if available.is_empty() {
+            if available.e.is_empty() {
                 log::error!("当前无任何编码器通过检测 (无驱动报告 H.264/H.265 或 test 未通过)");
                 log::error!("可设置 RUST_LOG=debug 查看编码器候选与各驱动 test 结果");
             } else {
-                log::error!("有 {} 个编码器可用但均非 H.265，当前仅请求 H.265", available.len());
+                log::error!("有 {} 个编码器可用但均非 H.265，当前仅请求 H.265", available.e.len());
             }
             return;
         }
@@ -122,17 +136,24 @@ fn main() {
         }
     };
 
-    let context = unsafe { device.GetImmediateContext().unwrap() };
     let frame_duration_ms = 1000i64 / FRAMERATE as i64;
 
+    // 移动的波带片加帧计数，解码后可用 testsrc::read_counter 核对丢帧与乱序
+    let mut source = TestSource::new(Pattern::ZonePlate, WIDTH as usize, HEIGHT as usize);
+
     log::info!("编码中: {} 帧 -> {}", total_frames, OUTPUT_PATH);
 
     for frame_num in 0..total_frames {
-        fill_texture_color_cycle(&device, &context, &texture, WIDTH, HEIGHT, frame_num, total_frames);
-        unsafe { context.Flush() };
+        let texture = match source.next_texture(&typed_device, DXGI_FORMAT_B8G8R8A8_UNORM) {
+            Ok(t) => t,
+            Err(e) => {
+                log::error!("上传测试图案失败 帧 {}: {}", frame_num, e);
+                return;
+            }
+        };
 
         let pts = frame_num as i64 * frame_duration_ms;
-        let texture_ptr = texture.as_raw() as *mut c_void;
+        let texture_ptr = texture.as_raw();
 
         match encoder.encode(texture_ptr, pts) {
             Ok(frames) => {
@@ -163,12 +184,9 @@ fn main() {
     log::info!("完成: 已保存 {}", OUTPUT_PATH);
 }
 
-/// 创建 D3D11 设备和 BGRA 纹理
+/// 创建支持视频与 BGRA 的 D3D11 设备
 #[cfg(windows)]
-fn create_d3d11_device_and_texture(
-    width: i32,
-    height: i32,
-) -> std::result::Result<(ID3D11Device, ID3D11Texture2D), Error> {
+fn create_d3d11_device() -> std::result::Result<ID3D11Device, Error> {
     unsafe {
         let factory: IDXGIFactory1 = CreateDXGIFactory1()?;
         let adapter1 = factory.EnumAdapters1(0).map_err(|_| Error::from(E_FAIL))?;
@@ -191,123 +209,10 @@ fn create_d3d11_device_and_texture(
             Some(&mut _ctx),
         )?;
 
-        let device = device.ok_or(Error::from(E_FAIL))?;
-
-        let desc = D3D11_TEXTURE2D_DESC {
-            Width: width as u32,
-            Height: height as u32,
-            MipLevels: 1,
-            ArraySize: 1,
-            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
-            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
-            Usage: D3D11_USAGE_DEFAULT,
-            BindFlags: D3D11_BIND_SHADER_RESOURCE.0 as u32,
-            CPUAccessFlags: 0,
-            MiscFlags: Default::default(),
-        };
-
-        let mut texture: Option<ID3D11Texture2D> = None;
-        device.CreateTexture2D(&desc, None, Some(&mut texture))?;
-        let texture = texture.ok_or(Error::from(E_FAIL))?;
-
-        Ok((device, texture))
+        device.ok_or(Error::from(E_FAIL))
     }
 }
 
-/// 用“色彩循环”填充纹理
-#[cfg(windows)]
-fn fill_texture_color_cycle(
-    device: &ID3D11Device,
-    context: &ID3D11DeviceContext,
-    texture: &ID3D11Texture2D,
-    width: i32,
-    height: i32,
-    frame_num: i32,
-    total_frames: i32,
-) {
-    unsafe {
-        let staging_desc = D3D11_TEXTURE2D_DESC {
-            Width: width as u32,
-            Height: height as u32,
-            MipLevels: 1,
-            ArraySize: 1,
-            Format: DXGI_FORMAT_B8G8R8A8_UNORM,
-            SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
-            Usage: D3D11_USAGE_STAGING,
-            BindFlags: 0,
-            CPUAccessFlags: D3D11_CPU_ACCESS_WRITE.0 as u32,
-            MiscFlags: Default::default(),
-        };
-
-        let mut staging: Option<ID3D11Texture2D> = None;
-        if device.CreateTexture2D(&staging_desc, None, Some(&mut staging)).is_err() {
-            return;
-        }
-        let staging = match staging {
-            Some(t) => t,
-            None => return,
-        };
-
-        let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
-        if context
-            .Map(&staging, 0, D3D11_MAP_WRITE, 0, Some(&mut mapped))
-            .is_err()
-        {
-            return;
-        }
-
-        let row_pitch = mapped.RowPitch as usize;
-        let data = std::slice::from_raw_parts_mut(
-            mapped.pData as *mut u8,
-            row_pitch * height as usize,
-        );
-
-        let t = (frame_num as f32 / total_frames.max(1) as f32) * 3.0_f32;
-        let phase = t.fract();
-
-        for y in 0..height {
-            for x in 0..width {
-                let offset = (y * row_pitch as i32 + x * 4) as usize;
-                if offset + 3 >= data.len() {
-                    continue;
-                }
-                let xr = x as f32 / width.max(1) as f32;
-                let yr = y as f32 / height.max(1) as f32;
-                let edge = (xr * (1.0 - xr) * 4.0).min(1.0) * (yr * (1.0 - yr) * 4.0).min(1.0);
-                let (r, g, b) = hue_cycle_to_rgb(phase, xr, yr);
-                let r = (r * 255.0 * edge + 30.0) as u8;
-                let g = (g * 255.0 * edge + 30.0) as u8;
-                let b = (b * 255.0 * edge + 30.0) as u8;
-                data[offset] = b;
-                data[offset + 1] = g;
-                data[offset + 2] = r;
-                data[offset + 3] = 255;
-            }
-        }
-
-        context.Unmap(&staging, 0);
-        context.CopyResource(texture, &staging);
-    }
-}
-
-#[cfg(windows)]
-fn hue_cycle_to_rgb(phase: f32, x_ratio: f32, y_ratio: f32) -> (f32, f32, f32) {
-    let hue = phase + (x_ratio + y_ratio) * 0.2;
-    let hue = hue.fract();
-    let h = hue * 6.0;
-    let i = h.floor() as i32;
-    let f = h - i as f32;
-    let (r, g, b) = match i % 6 {
-        0 => (1.0, f, 0.0),
-        1 => (1.0 - f, 1.0, 0.0),
-        2 => (0.0, 1.0, f),
-        3 => (0.0, 1.0 - f, 1.0),
-        4 => (f, 0.0, 1.0),
-        _ => (1.0, 0.0, 1.0 - f),
-    };
-    (r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0))
-}
-
 #[cfg(not(windows))]
 fn main() {
     println!("此 demo 仅支持 Windows (D3D11 + 硬件编码)");
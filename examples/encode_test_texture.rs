@@ -1,5 +1,5 @@
 use env_logger::{init_from_env, Env, DEFAULT_FILTER_ENV};
-use hwcodec::common::{DataFormat::H264, MAX_GOP};
+use hwcodec::common::{BitDepth, DataFormat::H264, MAX_GOP};
 use hwcodec::vram::{encode, DynamicContext, EncodeContext};
 use std::fs::File;
 use std::io::Write;
@@ -53,6 +53,14 @@ fn main() {
         kbitrate: 5000,
         framerate,
         gop: MAX_GOP as i32,
+        bframes: 0,
+        intra_refresh: None,
+        ltr_frames: 0,
+        color: None,
+        bit_depth: BitDepth::Eight,
+        yuv444: false,
+        hdr: None,
+        scale: None,
     };
     
     // Removed debug logging as requested
@@ -0,0 +1,90 @@
+// Source of nv12_to_bgra.bin, built with:
+//   fxc /T ps_4_0 /E PS /Qstrip_reflect /Qstrip_debug /Fo nv12_to_bgra.bin nv12_to_bgra.hlsl
+//
+// The colour matrix and range live in the constant buffer, filled from
+// hwcodec::convert::YuvToRgbConstants; keep the layout of ColorConversion in step with it.
+
+Texture2D<float> g_luma : register(t0);
+Texture2D<float2> g_chroma : register(t1);
+SamplerState g_sampler : register(s0);
+
+cbuffer ColorConversion : register(b0)
+{
+    // Weights of (Y, U, V, 1) for R, G and B.
+    float4 g_r;
+    float4 g_g;
+    float4 g_b;
+    // 0: SDR, 1: PQ, 2: HLG
+    uint g_transfer;
+};
+
+struct PS_INPUT
+{
+    float4 Pos : SV_POSITION;
+    float2 Tex : TEXCOORD0;
+};
+
+// Reference white of HDR content mapped to SDR white (BT.2408), in nits.
+static const float SDR_WHITE = 203.0;
+
+// PQ and HLG content has BT.2020 primaries; linear BT.2020 to BT.709 (BT.2087).
+static const float3x3 BT2020_TO_BT709 =
+{
+     1.6605, -0.5876, -0.0728,
+    -0.1246,  1.1329, -0.0083,
+    -0.0182, -0.1006,  1.1187,
+};
+
+// ST 2084 EOTF, in nits.
+float3 pq_to_nits(float3 e)
+{
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    float3 p = pow(saturate(e), 1.0 / m2);
+    return 10000.0 * pow(max(p - c1, 0.0) / (c2 - c3 * p), 1.0 / m1);
+}
+
+// HLG inverse OETF and OOTF (system gamma 1.2) for a 1000 nit display, in nits.
+float3 hlg_to_nits(float3 e)
+{
+    const float a = 0.17883277;
+    const float b = 0.28466892;
+    const float c = 0.55991073;
+    e = saturate(e);
+    float3 scene = e <= 0.5 ? e * e / 3.0 : (exp((e - c) / a) + b) / 12.0;
+    float luma = dot(scene, float3(0.2627, 0.6780, 0.0593));
+    return 1000.0 * scene * pow(max(luma, 1e-6), 0.2);
+}
+
+// Linear SDR (1.0 = SDR white) back to the BT.709 / sRGB-like gamma the output expects.
+float3 encode_sdr(float3 linear_rgb)
+{
+    float3 x = saturate(linear_rgb);
+    return pow(x, 1.0 / 2.2);
+}
+
+float4 PS(PS_INPUT input) : SV_TARGET
+{
+    float4 yuv = float4(
+        g_luma.Sample(g_sampler, input.Tex),
+        g_chroma.Sample(g_sampler, input.Tex),
+        1.0);
+    float3 rgb = float3(dot(g_r, yuv), dot(g_g, yuv), dot(g_b, yuv));
+    if (g_transfer != 0)
+    {
+        float3 nits;
+        if (g_transfer == 1)
+        {
+            nits = pq_to_nits(rgb);
+        }
+        else
+        {
+            nits = hlg_to_nits(rgb);
+        }
+        rgb = encode_sdr(mul(BT2020_TO_BT709, nits) / SDR_WHITE);
+    }
+    return float4(saturate(rgb), 1.0);
+}
@@ -0,0 +1,20 @@
+// Source of vertex_shader.bin, compiled with:
+//   fxc /T vs_4_0_level_9_3 /E VS /Fo vertex_shader.bin vertex_shader.hlsl
+// The quad is already in clip space, so the vertices pass through unchanged.
+
+struct VS_INPUT
+{
+    float4 Pos : POSITION;
+    float2 Tex : TEXCOORD;
+};
+
+struct VS_OUTPUT
+{
+    float4 Pos : SV_POSITION;
+    float2 Tex : TEXCOORD;
+};
+
+VS_OUTPUT VS(VS_INPUT input)
+{
+    return input;
+}
@@ -20,6 +20,24 @@ pub enum DataFormat {
     AV1 = 4,
 }
 
+/// Bits per sample of a coded stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    /// HEVC Main10, through P010 (YUV) or R10G10B10A2 (RGB) textures.
+    Ten,
+}
+
+impl BitDepth {
+    pub fn bits(self) -> i32 {
+        match self {
+            BitDepth::Eight => 8,
+            BitDepth::Ten => 10,
+        }
+    }
+}
+
 pub type EncodeCallback =
     extern "C" fn(*const u8, i32, i32, *const c_void, i64);
 pub type DecodeCallback = extern "C" fn(*mut c_void, *mut c_void);
@@ -125,6 +143,52 @@ pub fn get_gpu_signature() -> u64 {
     }
 }
 
+/// 检查特定 GPU 是否使用混合解码
+pub fn is_format_hybrid_decoded_by_hardware(
+    format: DataFormat,
+    vendor_id: u32,
+    device_id: u32,
+) -> bool {
+    if vendor_id == AdapterVendor::ADAPTER_VENDOR_INTEL as u32 {
+        // Intel GPU 混合解码检测
+        // 参考：https://github.com/moonlight-stream/moonlight-qt
+        match device_id & 0xFF00 {
+            0x0400 | 0x0A00 | 0x0D00 => {
+                // Haswell
+                return format == DataFormat::H265;
+            }
+            0x1600 => {
+                // Broadwell
+                return format == DataFormat::H265;
+            }
+            0x2200 => {
+                // Cherry Trail and Braswell
+                return format == DataFormat::H265;
+            }
+            _ => {}
+        }
+    } else if vendor_id == AdapterVendor::ADAPTER_VENDOR_NVIDIA as u32 {
+        // NVIDIA GPU 混合解码检测（Feature Set E）
+        // 参考：https://en.wikipedia.org/wiki/Nvidia_PureVideo
+        if (device_id >= 0x1180 && device_id <= 0x11BF) // GK104
+            || (device_id >= 0x11C0 && device_id <= 0x11FF) // GK106
+            || (device_id >= 0x0FC0 && device_id <= 0x0FFF) // GK107
+            || (device_id >= 0x1000 && device_id <= 0x103F) // GK110/GK110B
+            || (device_id >= 0x1280 && device_id <= 0x12BF) // GK208
+            || (device_id >= 0x1340 && device_id <= 0x137F) // GM108
+            || (device_id >= 0x1380 && device_id <= 0x13BF) // GM107
+            || (device_id >= 0x13C0 && device_id <= 0x13FF) // GM204
+            || (device_id >= 0x1617 && device_id <= 0x161A) // GM204
+            || (device_id == 0x1667) // GM204
+            || (device_id >= 0x17C0 && device_id <= 0x17FF) // GM200
+        {
+            return format == DataFormat::H265;
+        }
+    }
+
+    false
+}
+
 // called by child process
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn setup_parent_death_signal() {
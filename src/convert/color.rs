@@ -0,0 +1,364 @@
+//! Colour matrices and the fixed-point coefficients the conversion kernels use.
+
+use serde_derive::{Deserialize, Serialize};
+
+/// YUV ↔ RGB matrix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Matrix {
+    /// SD (BT.601 / SMPTE 170M).
+    #[default]
+    Bt601,
+    /// HD (BT.709).
+    Bt709,
+    /// UHD (BT.2020 non-constant luminance).
+    Bt2020,
+}
+
+impl Matrix {
+    /// Luma weights `(Kr, Kb)`; `Kg = 1 - Kr - Kb`.
+    pub fn weights(self) -> (f64, f64) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+            Matrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+
+    /// H.273 `MatrixCoefficients`.
+    pub fn code(self) -> u8 {
+        match self {
+            Matrix::Bt709 => 1,
+            Matrix::Bt601 => 6,
+            Matrix::Bt2020 => 9,
+        }
+    }
+
+    /// Accepts BT.470 BG (5) as BT.601, which uses the same coefficients.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Matrix::Bt709),
+            5 | 6 => Some(Matrix::Bt601),
+            9 => Some(Matrix::Bt2020),
+            _ => None,
+        }
+    }
+}
+
+/// Quantisation range of the YUV samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Range {
+    /// Studio swing: Y in 16..=235, U/V in 16..=240 (scaled by 4 for 10 bits).
+    #[default]
+    Limited,
+    /// Y and U/V span every code value.
+    Full,
+}
+
+/// Transfer characteristics (OETF) of the coded samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Transfer {
+    /// SDR gamma (BT.709, also used by BT.601 and BT.2020 SDR).
+    #[default]
+    Bt709,
+    /// sRGB, as produced by screen capture.
+    Srgb,
+    /// HDR10 perceptual quantizer (SMPTE ST 2084).
+    Pq,
+    /// Hybrid log-gamma (ARIB STD-B67).
+    Hlg,
+}
+
+impl Transfer {
+    /// Whether samples are already display-referred SDR and need no tone mapping.
+    pub fn is_sdr(self) -> bool {
+        matches!(self, Transfer::Bt709 | Transfer::Srgb)
+    }
+
+    /// H.273 `TransferCharacteristics`.
+    pub fn code(self) -> u8 {
+        match self {
+            Transfer::Bt709 => 1,
+            Transfer::Srgb => 13,
+            Transfer::Pq => 16,
+            Transfer::Hlg => 18,
+        }
+    }
+
+    /// Accepts BT.601 (6) and BT.2020 (14, 15) as BT.709, which share its curve.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 | 6 | 14 | 15 => Some(Transfer::Bt709),
+            13 => Some(Transfer::Srgb),
+            16 => Some(Transfer::Pq),
+            18 => Some(Transfer::Hlg),
+            _ => None,
+        }
+    }
+}
+
+/// Colour primaries of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Primaries {
+    /// SMPTE 170M (NTSC); PAL (BT.470 BG) is read as this too.
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl Primaries {
+    /// H.273 `ColourPrimaries`.
+    pub fn code(self) -> u8 {
+        match self {
+            Primaries::Bt709 => 1,
+            Primaries::Bt601 => 6,
+            Primaries::Bt2020 => 9,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Primaries::Bt709),
+            5 | 6 => Some(Primaries::Bt601),
+            9 => Some(Primaries::Bt2020),
+            _ => None,
+        }
+    }
+}
+
+/// Position of 4:2:0 chroma samples relative to luma (H.273 `Chroma420SampleLocType`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ChromaLocation {
+    /// Co-sited horizontally, between rows (MPEG-2, H.264 and HEVC default).
+    #[default]
+    Left,
+    Center,
+    /// Co-sited with the top-left luma sample (BT.2020, BT.2100).
+    TopLeft,
+    Top,
+    BottomLeft,
+    Bottom,
+}
+
+impl ChromaLocation {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        [
+            ChromaLocation::Left,
+            ChromaLocation::Center,
+            ChromaLocation::TopLeft,
+            ChromaLocation::Top,
+            ChromaLocation::BottomLeft,
+            ChromaLocation::Bottom,
+        ]
+        .get(code as usize)
+        .copied()
+    }
+}
+
+/// Colour description signalled in the bitstream (the SPS VUI), so that decoders do not have to
+/// guess the matrix and range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct ColorDescription {
+    pub primaries: Primaries,
+    pub transfer: Transfer,
+    pub matrix: Matrix,
+    pub full_range: bool,
+    pub chroma_location: ChromaLocation,
+}
+
+impl ColorDescription {
+    /// From H.273 code points, `None` for values outside the enums.
+    pub fn from_codes(
+        primaries: u8,
+        transfer: u8,
+        matrix: u8,
+        full_range: bool,
+        chroma_location: u8,
+    ) -> Option<Self> {
+        Some(Self {
+            primaries: Primaries::from_code(primaries)?,
+            transfer: Transfer::from_code(transfer)?,
+            matrix: Matrix::from_code(matrix)?,
+            full_range,
+            chroma_location: ChromaLocation::from_code(chroma_location)?,
+        })
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        let range = if self.full_range {
+            Range::Full
+        } else {
+            Range::Limited
+        };
+        ColorSpace::new(self.matrix, range).with_transfer(self.transfer)
+    }
+}
+
+impl From<ColorSpace> for ColorDescription {
+    /// Primaries follow the matrix; BT.2020 uses top-left chroma siting as BT.2100 requires.
+    fn from(color: ColorSpace) -> Self {
+        let (primaries, chroma_location) = match color.matrix {
+            Matrix::Bt601 => (Primaries::Bt601, ChromaLocation::Left),
+            Matrix::Bt709 => (Primaries::Bt709, ChromaLocation::Left),
+            Matrix::Bt2020 => (Primaries::Bt2020, ChromaLocation::TopLeft),
+        };
+        Self {
+            primaries,
+            transfer: color.transfer,
+            matrix: color.matrix,
+            full_range: color.range == Range::Full,
+            chroma_location,
+        }
+    }
+}
+
+/// How YUV samples map to RGB. The default, BT.601 limited range SDR, is what the GPU paths
+/// assumed before they took a colour space.
+///
+/// The CPU conversion only applies `matrix` and `range`; `transfer` describes the samples and is
+/// acted on by the GPU shader, which tone maps PQ and HLG to SDR.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct ColorSpace {
+    pub matrix: Matrix,
+    pub range: Range,
+    #[serde(default)]
+    pub transfer: Transfer,
+}
+
+impl ColorSpace {
+    pub const fn new(matrix: Matrix, range: Range) -> Self {
+        Self {
+            matrix,
+            range,
+            transfer: Transfer::Bt709,
+        }
+    }
+
+    pub const fn with_transfer(self, transfer: Transfer) -> Self {
+        Self { transfer, ..self }
+    }
+
+    /// `(luma offset, luma scale, chroma offset, chroma scale)` of `depth`-bit samples: code
+    /// value = offset + scale * normalized value.
+    pub(crate) fn quantization(self, depth: u32) -> (f64, f64, f64, f64) {
+        let chroma_offset = (1u32 << (depth - 1)) as f64;
+        match self.range {
+            Range::Limited => {
+                let scale = (1u32 << (depth - 8)) as f64;
+                (16.0 * scale, 219.0 * scale, chroma_offset, 224.0 * scale)
+            }
+            Range::Full => {
+                let max = ((1u32 << depth) - 1) as f64;
+                (0.0, max, chroma_offset, max)
+            }
+        }
+    }
+}
+
+/// Fractional bits of the RGB → YUV coefficients. Chroma is computed from the sum of a 2x2
+/// block and shifted by two more bits.
+pub(crate) const RGB_TO_YUV_SHIFT: i32 = 13;
+/// Fractional bits of the YUV → RGB coefficients.
+pub(crate) const YUV_TO_RGB_SHIFT: i32 = 13;
+pub(crate) const YUV_TO_RGB_ROUND: i16 = 1 << (YUV_TO_RGB_SHIFT - 1);
+
+/// Byte position of R, G and B in a packed 32-bit pixel.
+pub(crate) type ChannelOrder = [usize; 3];
+pub(crate) const ORDER_BGRA: ChannelOrder = [2, 1, 0];
+pub(crate) const ORDER_RGBA: ChannelOrder = [0, 1, 2];
+
+/// 8-bit RGB → `depth`-bit YUV. Coefficients are indexed by byte position in the pixel, so the
+/// kernels need no channel swizzle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RgbToYuv {
+    pub y: [i16; 3],
+    pub u: [i16; 3],
+    pub v: [i16; 3],
+    /// Luma offset and rounding, in `RGB_TO_YUV_SHIFT` fixed point.
+    pub y_bias: i32,
+    /// Chroma offset and rounding, in `RGB_TO_YUV_SHIFT + 2` fixed point.
+    pub c_bias: i32,
+    pub max: i16,
+}
+
+impl RgbToYuv {
+    pub fn new(color: ColorSpace, depth: u32, order: ChannelOrder) -> Self {
+        let (kr, kb) = color.matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_scale, c_offset, c_scale) = color.quantization(depth);
+        let one = (1 << RGB_TO_YUV_SHIFT) as f64;
+        let ys = y_scale / 255.0 * one;
+        let cs = c_scale / 255.0 * one;
+        // 以 R、B 取整，G 补足差值，使灰阶精确落在 Y 标度与 UV 中点上
+        let fixed = |r: f64, b: f64, total: f64| {
+            let (r, b) = (r.round() as i32, b.round() as i32);
+            [r, total.round() as i32 - r - b, b]
+        };
+        let y = fixed(kr * ys, kb * ys, (kr + kg + kb) * ys);
+        let u = fixed(-kr / (2.0 * (1.0 - kb)) * cs, 0.5 * cs, 0.0);
+        let v = fixed(0.5 * cs, -kb / (2.0 * (1.0 - kr)) * cs, 0.0);
+        let by_byte = |rgb: [i32; 3]| {
+            let mut out = [0i16; 3];
+            for (channel, &byte) in order.iter().enumerate() {
+                out[byte] = rgb[channel] as i16;
+            }
+            out
+        };
+        Self {
+            y: by_byte(y),
+            u: by_byte(u),
+            v: by_byte(v),
+            y_bias: ((y_offset as i32) << RGB_TO_YUV_SHIFT) + (1 << (RGB_TO_YUV_SHIFT - 1)),
+            c_bias: ((c_offset as i32) << (RGB_TO_YUV_SHIFT + 2)) + (1 << (RGB_TO_YUV_SHIFT + 1)),
+            max: ((1 << depth) - 1) as i16,
+        }
+    }
+}
+
+/// `depth`-bit YUV → 8-bit RGB, output coefficients indexed by byte position in the pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct YuvToRgb {
+    pub y: i16,
+    pub u: [i16; 3],
+    pub v: [i16; 3],
+    pub y_offset: i16,
+    pub c_offset: i16,
+}
+
+impl YuvToRgb {
+    pub fn new(color: ColorSpace, depth: u32, order: ChannelOrder) -> Self {
+        let (kr, kb) = color.matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_scale, c_offset, c_scale) = color.quantization(depth);
+        let one = (1 << YUV_TO_RGB_SHIFT) as f64;
+        let cs = 255.0 / c_scale * one;
+        let q = |x: f64| x.round() as i16;
+        // R = Y + 2(1-Kr)Cr，B = Y + 2(1-Kb)Cb，G 由 Y 反解
+        let u = [
+            0,
+            q(-2.0 * (1.0 - kb) * kb / kg * cs),
+            q(2.0 * (1.0 - kb) * cs),
+        ];
+        let v = [
+            q(2.0 * (1.0 - kr) * cs),
+            q(-2.0 * (1.0 - kr) * kr / kg * cs),
+            0,
+        ];
+        let mut by_byte_u = [0i16; 3];
+        let mut by_byte_v = [0i16; 3];
+        for (channel, &byte) in order.iter().enumerate() {
+            by_byte_u[byte] = u[channel];
+            by_byte_v[byte] = v[channel];
+        }
+        Self {
+            y: q(255.0 / y_scale * one),
+            u: by_byte_u,
+            v: by_byte_v,
+            y_offset: y_offset as i16,
+            c_offset: c_offset as i16,
+        }
+    }
+}
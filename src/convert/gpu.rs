@@ -0,0 +1,125 @@
+//! Coefficient tables for the GPU conversion shaders in `shaders/`. The shaders hold no colour
+//! constants of their own: everything comes from these tables through a constant buffer.
+
+use super::color::{ColorSpace, Transfer};
+
+/// Constant buffer of `shaders/nv12_to_bgra.hlsl` (`cbuffer ColorConversion`).
+///
+/// The Y and UV planes are sampled as UNORM, so each 8-bit sample arrives as `code / 255`;
+/// `rgb` rows map `(Y, U, V, 1)` in that scale straight to non-linear R'G'B' in `[0, 1]`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YuvToRgbConstants {
+    /// Weights of `(Y, U, V, 1)` for R, G and B.
+    pub rgb: [[f32; 4]; 3],
+    /// 0: SDR, written as is; 1: PQ and 2: HLG, BT.2020 tone mapped to BT.709 SDR.
+    pub transfer: u32,
+    _padding: [u32; 3],
+}
+
+impl YuvToRgbConstants {
+    pub fn new(color: ColorSpace) -> Self {
+        let (kr, kb) = color.matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_scale, c_offset, c_scale) = color.quantization(8);
+        // 与 YuvToRgb 相同的推导：R = Y + 2(1-Kr)Cr，B = Y + 2(1-Kb)Cb，G 由 Y 反解
+        let u = [0.0, -2.0 * (1.0 - kb) * kb / kg, 2.0 * (1.0 - kb)];
+        let v = [2.0 * (1.0 - kr), -2.0 * (1.0 - kr) * kr / kg, 0.0];
+        let rgb = std::array::from_fn(|k| {
+            let offset = -y_offset / y_scale - (u[k] + v[k]) * c_offset / c_scale;
+            [
+                (255.0 / y_scale) as f32,
+                (u[k] * 255.0 / c_scale) as f32,
+                (v[k] * 255.0 / c_scale) as f32,
+                offset as f32,
+            ]
+        });
+        let transfer = match color.transfer {
+            Transfer::Bt709 | Transfer::Srgb => 0,
+            Transfer::Pq => 1,
+            Transfer::Hlg => 2,
+        };
+        Self {
+            rgb,
+            transfer,
+            _padding: [0; 3],
+        }
+    }
+
+    /// The buffer as uploaded to the GPU.
+    pub fn as_bytes(&self) -> &[u8] {
+        // repr(C)，只含 f32/u32，无填充字节
+        unsafe {
+            std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::color::ORDER_RGBA;
+    use super::super::{scalar, Matrix, Range, YuvToRgb};
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// 测试着色器系数与 CPU 参考转换的结果相差不超过 1
+    #[test]
+    fn test_matches_cpu_reference() {
+        let mut rng = StdRng::seed_from_u64(2084);
+        for matrix in [Matrix::Bt601, Matrix::Bt709, Matrix::Bt2020] {
+            for range in [Range::Limited, Range::Full] {
+                let color = ColorSpace::new(matrix, range);
+                let k = YuvToRgbConstants::new(color);
+                let c = YuvToRgb::new(color, 8, ORDER_RGBA);
+                for _ in 0..4096 {
+                    let yuv: [u8; 3] = rng.gen();
+                    let mut px = [0u8; 4];
+                    let sample = |i: usize| yuv[i] as u16;
+                    scalar::yuv_to_rgb(&[sample(0)], &[sample(1)], &[sample(2)], &c, &mut px);
+                    for (i, row) in k.rgb.iter().enumerate() {
+                        // UNORM 渲染目标：截断到 [0, 1] 后乘 255 取整
+                        let x = row[0] * yuv[0] as f32 / 255.0
+                            + row[1] * yuv[1] as f32 / 255.0
+                            + row[2] * yuv[2] as f32 / 255.0
+                            + row[3];
+                        let x = (x.clamp(0.0, 1.0) * 255.0).round() as i32;
+                        assert!(
+                            (x - px[i] as i32).abs() <= 1,
+                            "{:?} {:?} channel {}: {} vs {}",
+                            color,
+                            yuv,
+                            i,
+                            x,
+                            px[i]
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// 测试常量缓冲区布局与传输特性编码
+    #[test]
+    fn test_layout() {
+        assert_eq!(std::mem::size_of::<YuvToRgbConstants>(), 64);
+        let color = ColorSpace::new(Matrix::Bt709, Range::Limited);
+        assert_eq!(YuvToRgbConstants::new(color).transfer, 0);
+        let srgb = color.with_transfer(Transfer::Srgb);
+        assert_eq!(YuvToRgbConstants::new(srgb).transfer, 0);
+        let pq = color.with_transfer(Transfer::Pq);
+        assert_eq!(YuvToRgbConstants::new(pq).transfer, 1);
+        let hlg = color.with_transfer(Transfer::Hlg);
+        assert_eq!(YuvToRgbConstants::new(hlg).transfer, 2);
+        let bytes = YuvToRgbConstants::new(pq).as_bytes().to_vec();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(&bytes[48..52], &1u32.to_ne_bytes());
+
+        // 黑、白与中性灰精确落在 0 与 1
+        let k = YuvToRgbConstants::new(color);
+        for row in &k.rgb {
+            let at = |y: f32, c: f32| row[0] * y + (row[1] + row[2]) * c + row[3];
+            assert!(at(16.0 / 255.0, 128.0 / 255.0).abs() < 1e-5);
+            assert!((at(235.0 / 255.0, 128.0 / 255.0) - 1.0).abs() < 1e-5);
+        }
+    }
+}
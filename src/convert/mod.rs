@@ -0,0 +1,986 @@
+//! CPU colour conversion between packed RGB and YUV layouts.
+//!
+//! ```ignore
+//! let src = Image::packed(PixelFormat::Bgra, 1920, 1080, &bgra)?;
+//! let mut dst = ImageMut::packed(PixelFormat::Nv12, 1920, 1080, &mut nv12)?;
+//! convert::convert(&src, &mut dst, ColorSpace::new(Matrix::Bt709, Range::Limited))?;
+//! ```
+//!
+//! The arithmetic is fixed point and identical on every platform: the SSE2/AVX2/NEON kernels
+//! produce the same bytes as the scalar reference. RGB → 4:2:0 chroma is the average of each
+//! 2x2 block; 4:2:0 → RGB repeats each chroma sample over its block.
+//!
+//! `place` and `Rect` hold the rectangle arithmetic of the GPU scaling stage.
+
+mod color;
+mod gpu;
+#[cfg(target_arch = "aarch64")]
+mod neon;
+mod scalar;
+mod scale;
+#[cfg(target_arch = "x86_64")]
+mod x86;
+
+pub use color::{
+    ChromaLocation, ColorDescription, ColorSpace, Matrix, Primaries, Range, Transfer,
+};
+pub use gpu::YuvToRgbConstants;
+pub use scale::{place, Fit, Placement, Rect, ScaleFilter, Size};
+pub(crate) use color::{RgbToYuv, YuvToRgb};
+
+use color::{ChannelOrder, ORDER_BGRA, ORDER_RGBA};
+use serde_derive::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Pixel layout of a CPU image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum PixelFormat {
+    /// 8-bit B, G, R, A bytes.
+    Bgra,
+    /// 8-bit R, G, B, A bytes.
+    Rgba,
+    /// 8-bit 4:2:0: Y plane, then interleaved U/V plane.
+    Nv12,
+    /// 8-bit 4:2:0: Y, U and V planes.
+    I420,
+    /// 10-bit 4:2:0 laid out as NV12 with 16-bit little-endian samples in the high bits.
+    P010,
+    /// 8-bit 4:2:2 packed as Y0 U Y1 V.
+    Yuy2,
+}
+
+impl PixelFormat {
+    pub fn is_rgb(self) -> bool {
+        matches!(self, PixelFormat::Bgra | PixelFormat::Rgba)
+    }
+
+    pub fn bit_depth(self) -> u32 {
+        match self {
+            PixelFormat::P010 => 10,
+            _ => 8,
+        }
+    }
+
+    pub fn plane_count(self) -> usize {
+        match self {
+            PixelFormat::Bgra | PixelFormat::Rgba | PixelFormat::Yuy2 => 1,
+            PixelFormat::Nv12 | PixelFormat::P010 => 2,
+            PixelFormat::I420 => 3,
+        }
+    }
+
+    /// Bytes of one row of `plane` without padding.
+    pub fn row_bytes(self, plane: usize, width: usize) -> usize {
+        match (self, plane) {
+            (PixelFormat::Bgra | PixelFormat::Rgba, _) => width * 4,
+            (PixelFormat::Yuy2, _) => width * 2,
+            (PixelFormat::Nv12 | PixelFormat::I420, 0) => width,
+            (PixelFormat::Nv12, _) => width / 2 * 2,
+            (PixelFormat::I420, _) => width / 2,
+            (PixelFormat::P010, 0) => width * 2,
+            (PixelFormat::P010, _) => width / 2 * 4,
+        }
+    }
+
+    /// Rows of `plane`.
+    pub fn plane_rows(self, plane: usize, height: usize) -> usize {
+        if plane > 0 && self.is_420() {
+            height / 2
+        } else {
+            height
+        }
+    }
+
+    /// Bytes of a tightly packed image, as `Image::packed` expects.
+    pub fn frame_size(self, width: usize, height: usize) -> usize {
+        (0..self.plane_count())
+            .map(|plane| self.row_bytes(plane, width) * self.plane_rows(plane, height))
+            .sum()
+    }
+
+    fn is_420(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Nv12 | PixelFormat::I420 | PixelFormat::P010
+        )
+    }
+
+    fn check_size(self, width: usize, height: usize) -> Result<(), ConvertError> {
+        let odd = match self {
+            PixelFormat::Bgra | PixelFormat::Rgba => false,
+            PixelFormat::Yuy2 => !width.is_multiple_of(2),
+            _ => !width.is_multiple_of(2) || !height.is_multiple_of(2),
+        };
+        if odd {
+            return Err(ConvertError::OddSize(self, width, height));
+        }
+        Ok(())
+    }
+
+    fn channel_order(self) -> ChannelOrder {
+        match self {
+            PixelFormat::Rgba => ORDER_RGBA,
+            _ => ORDER_BGRA,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConvertError {
+    #[error("source is {0}x{1} but destination is {2}x{3}")]
+    SizeMismatch(usize, usize, usize, usize),
+
+    #[error("{0:?} needs an even size, got {1}x{2}")]
+    OddSize(PixelFormat, usize, usize),
+
+    #[error("plane {0} is too small for its stride and size")]
+    PlaneTooSmall(usize),
+}
+
+/// One plane of an image; `stride` is the distance between rows in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Plane<'a> {
+    pub data: &'a [u8],
+    pub stride: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct PlaneMut<'a> {
+    pub data: &'a mut [u8],
+    pub stride: usize,
+}
+
+/// Source image. Planes past `format.plane_count()` are ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    pub format: PixelFormat,
+    pub width: usize,
+    pub height: usize,
+    pub planes: [Plane<'a>; 3],
+}
+
+/// Destination image. Planes past `format.plane_count()` are ignored.
+#[derive(Debug)]
+pub struct ImageMut<'a> {
+    pub format: PixelFormat,
+    pub width: usize,
+    pub height: usize,
+    pub planes: [PlaneMut<'a>; 3],
+}
+
+impl<'a> Image<'a> {
+    /// Splits a tightly packed buffer (planes back to back, no row padding) into planes.
+    pub fn packed(
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+        data: &'a [u8],
+    ) -> Result<Self, ConvertError> {
+        let mut planes = [Plane::default(); 3];
+        let mut rest = data;
+        for (i, plane) in planes.iter_mut().enumerate().take(format.plane_count()) {
+            let stride = format.row_bytes(i, width);
+            let len = stride * format.plane_rows(i, height);
+            if rest.len() < len {
+                return Err(ConvertError::PlaneTooSmall(i));
+            }
+            let (data, tail) = rest.split_at(len);
+            *plane = Plane { data, stride };
+            rest = tail;
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            planes,
+        })
+    }
+
+    fn check(&self) -> Result<(), ConvertError> {
+        self.format.check_size(self.width, self.height)?;
+        for (i, plane) in self
+            .planes
+            .iter()
+            .enumerate()
+            .take(self.format.plane_count())
+        {
+            check_plane(
+                self.format,
+                i,
+                self.width,
+                self.height,
+                plane.data.len(),
+                plane.stride,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn row(&self, plane: usize, row: usize) -> &[u8] {
+        let plane_data = &self.planes[plane];
+        let start = row * plane_data.stride;
+        &plane_data.data[start..start + self.format.row_bytes(plane, self.width)]
+    }
+}
+
+impl<'a> ImageMut<'a> {
+    /// Splits a tightly packed buffer (planes back to back, no row padding) into planes.
+    pub fn packed(
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+        data: &'a mut [u8],
+    ) -> Result<Self, ConvertError> {
+        let mut planes: [PlaneMut<'a>; 3] = Default::default();
+        let mut rest = data;
+        for (i, plane) in planes.iter_mut().enumerate().take(format.plane_count()) {
+            let stride = format.row_bytes(i, width);
+            let len = stride * format.plane_rows(i, height);
+            if rest.len() < len {
+                return Err(ConvertError::PlaneTooSmall(i));
+            }
+            let (data, tail) = std::mem::take(&mut rest).split_at_mut(len);
+            *plane = PlaneMut { data, stride };
+            rest = tail;
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            planes,
+        })
+    }
+
+    fn check(&self) -> Result<(), ConvertError> {
+        self.format.check_size(self.width, self.height)?;
+        for (i, plane) in self
+            .planes
+            .iter()
+            .enumerate()
+            .take(self.format.plane_count())
+        {
+            check_plane(
+                self.format,
+                i,
+                self.width,
+                self.height,
+                plane.data.len(),
+                plane.stride,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn row_mut(&mut self, plane: usize, row: usize) -> &mut [u8] {
+        let bytes = self.format.row_bytes(plane, self.width);
+        let plane = &mut self.planes[plane];
+        let start = row * plane.stride;
+        &mut plane.data[start..start + bytes]
+    }
+}
+
+fn check_plane(
+    format: PixelFormat,
+    plane: usize,
+    width: usize,
+    height: usize,
+    len: usize,
+    stride: usize,
+) -> Result<(), ConvertError> {
+    let bytes = format.row_bytes(plane, width);
+    let rows = format.plane_rows(plane, height);
+    if rows > 0 && (stride < bytes || len < stride * (rows - 1) + bytes) {
+        return Err(ConvertError::PlaneTooSmall(plane));
+    }
+    Ok(())
+}
+
+/// Converts `src` into `dst`, which must have the same size.
+///
+/// `color` applies to conversions between RGB and YUV. Between two YUV formats samples are
+/// only repacked, resampled between 4:2:0 and 4:2:2 and shifted between bit depths; between
+/// BGRA and RGBA the channels are swapped.
+pub fn convert(src: &Image, dst: &mut ImageMut, color: ColorSpace) -> Result<(), ConvertError> {
+    if (src.width, src.height) != (dst.width, dst.height) {
+        return Err(ConvertError::SizeMismatch(
+            src.width, src.height, dst.width, dst.height,
+        ));
+    }
+    src.check()?;
+    dst.check()?;
+    if src.format.is_rgb() && dst.format.is_rgb() {
+        rgb_to_rgb(src, dst);
+        return Ok(());
+    }
+    let kernels = Kernels::get();
+    let (src_depth, dst_depth) = (src.format.bit_depth(), dst.format.bit_depth());
+    let to_yuv = RgbToYuv::new(color, dst_depth, src.format.channel_order());
+    let to_rgb = YuvToRgb::new(color, src_depth, dst.format.channel_order());
+    // 任一侧为 4:2:0 时按行对处理，4:2:0 的高度必为偶数
+    let step = if src.format.is_420() || dst.format.is_420() {
+        2
+    } else {
+        1
+    };
+    let mut rows = YuvRows::new(src.width);
+    for row in (0..src.height).step_by(step) {
+        if src.format.is_rgb() {
+            rows.load_rgb(kernels, &to_yuv, src, row, step, dst.format.is_420());
+        } else {
+            rows.load(src, row, step);
+        }
+        if dst.format.is_rgb() {
+            rows.store_rgb(kernels, &to_rgb, dst, row, step);
+        } else {
+            if !src.format.is_rgb() {
+                rows.rescale(src_depth, dst_depth);
+            }
+            rows.store(dst, row, step);
+        }
+    }
+    Ok(())
+}
+
+fn rgb_to_rgb(src: &Image, dst: &mut ImageMut) {
+    let swap = src.format != dst.format;
+    for row in 0..src.height {
+        let (from, to) = (src.row(0, row), dst.row_mut(0, row));
+        if swap {
+            for (s, d) in from.chunks_exact(4).zip(to.chunks_exact_mut(4)) {
+                d.copy_from_slice(&[s[2], s[1], s[0], s[3]]);
+            }
+        } else {
+            to.copy_from_slice(from);
+        }
+    }
+}
+
+/// One or two rows as planar samples at the YUV side's bit depth, chroma at 4:2:2.
+struct YuvRows {
+    y: [Vec<u16>; 2],
+    u: [Vec<u16>; 2],
+    v: [Vec<u16>; 2],
+    /// 4:2:0 的两行共用第一行的色度
+    shared: bool,
+}
+
+impl YuvRows {
+    fn new(width: usize) -> Self {
+        let row = |n: usize| [vec![0u16; n], vec![0u16; n]];
+        Self {
+            y: row(width),
+            u: row(width / 2),
+            v: row(width / 2),
+            shared: false,
+        }
+    }
+
+    fn chroma(&self, i: usize) -> (&[u16], &[u16]) {
+        let i = if self.shared { 0 } else { i };
+        (&self.u[i], &self.v[i])
+    }
+
+    fn load_rgb(
+        &mut self,
+        k: &Kernels,
+        c: &RgbToYuv,
+        src: &Image,
+        row: usize,
+        n: usize,
+        to_420: bool,
+    ) {
+        for i in 0..n {
+            (k.rgb_to_y)(src.row(0, row + i), c, &mut self.y[i]);
+        }
+        self.shared = to_420;
+        if to_420 {
+            let [u, _] = &mut self.u;
+            let [v, _] = &mut self.v;
+            (k.rgb_to_uv)(src.row(0, row), src.row(0, row + 1), c, u, v);
+        } else {
+            for i in 0..n {
+                let px = src.row(0, row + i);
+                (k.rgb_to_uv)(px, px, c, &mut self.u[i], &mut self.v[i]);
+            }
+        }
+    }
+
+    fn store_rgb(&self, k: &Kernels, c: &YuvToRgb, dst: &mut ImageMut, row: usize, n: usize) {
+        for i in 0..n {
+            let (u, v) = self.chroma(i);
+            (k.yuv_to_rgb)(&self.y[i], u, v, c, dst.row_mut(0, row + i));
+        }
+    }
+
+    fn load(&mut self, src: &Image, row: usize, n: usize) {
+        match src.format {
+            PixelFormat::Nv12 | PixelFormat::I420 | PixelFormat::P010 => {
+                for i in 0..n {
+                    let luma = src.row(0, row + i);
+                    if src.format == PixelFormat::P010 {
+                        read_p010(luma, &mut self.y[i]);
+                    } else {
+                        widen(luma, &mut self.y[i]);
+                    }
+                }
+                let (u, v) = (&mut self.u[0], &mut self.v[0]);
+                match src.format {
+                    PixelFormat::I420 => {
+                        widen(src.row(1, row / 2), u);
+                        widen(src.row(2, row / 2), v);
+                    }
+                    PixelFormat::Nv12 => {
+                        for ((u, v), uv) in u
+                            .iter_mut()
+                            .zip(v.iter_mut())
+                            .zip(src.row(1, row / 2).chunks_exact(2))
+                        {
+                            (*u, *v) = (uv[0] as u16, uv[1] as u16);
+                        }
+                    }
+                    _ => {
+                        for ((u, v), uv) in u
+                            .iter_mut()
+                            .zip(v.iter_mut())
+                            .zip(src.row(1, row / 2).chunks_exact(4))
+                        {
+                            *u = u16::from_le_bytes([uv[0], uv[1]]) >> 6;
+                            *v = u16::from_le_bytes([uv[2], uv[3]]) >> 6;
+                        }
+                    }
+                }
+                self.shared = true;
+            }
+            PixelFormat::Yuy2 => {
+                for i in 0..n {
+                    let packed = src.row(0, row + i).chunks_exact(4);
+                    let y = self.y[i].chunks_exact_mut(2);
+                    for ((px, y), (u, v)) in packed
+                        .zip(y)
+                        .zip(self.u[i].iter_mut().zip(self.v[i].iter_mut()))
+                    {
+                        (y[0], *u, y[1], *v) =
+                            (px[0] as u16, px[1] as u16, px[2] as u16, px[3] as u16);
+                    }
+                }
+                self.shared = false;
+            }
+            PixelFormat::Bgra | PixelFormat::Rgba => unreachable!("RGB rows go through load_rgb"),
+        }
+    }
+
+    fn store(&self, dst: &mut ImageMut, row: usize, n: usize) {
+        match dst.format {
+            PixelFormat::Nv12 | PixelFormat::I420 | PixelFormat::P010 => {
+                let format = dst.format;
+                for i in 0..n {
+                    let luma = dst.row_mut(0, row + i);
+                    if format == PixelFormat::P010 {
+                        write_p010(&self.y[i], luma);
+                    } else {
+                        narrow(&self.y[i], luma);
+                    }
+                }
+                // 4:2:2 转 4:2:0 时取两行色度的平均
+                let average = |x: &[Vec<u16>; 2], i: usize| {
+                    if self.shared || n == 1 {
+                        x[0][i]
+                    } else {
+                        (x[0][i] + x[1][i] + 1) >> 1
+                    }
+                };
+                let (u, v) = (&self.u, &self.v);
+                match format {
+                    PixelFormat::I420 => {
+                        for (i, d) in dst.row_mut(1, row / 2).iter_mut().enumerate() {
+                            *d = average(u, i) as u8;
+                        }
+                        for (i, d) in dst.row_mut(2, row / 2).iter_mut().enumerate() {
+                            *d = average(v, i) as u8;
+                        }
+                    }
+                    PixelFormat::Nv12 => {
+                        for (i, d) in dst.row_mut(1, row / 2).chunks_exact_mut(2).enumerate() {
+                            d.copy_from_slice(&[average(u, i) as u8, average(v, i) as u8]);
+                        }
+                    }
+                    _ => {
+                        for (i, d) in dst.row_mut(1, row / 2).chunks_exact_mut(4).enumerate() {
+                            d[..2].copy_from_slice(&(average(u, i) << 6).to_le_bytes());
+                            d[2..].copy_from_slice(&(average(v, i) << 6).to_le_bytes());
+                        }
+                    }
+                }
+            }
+            PixelFormat::Yuy2 => {
+                for i in 0..n {
+                    let (u, v) = self.chroma(i);
+                    let packed = dst.row_mut(0, row + i).chunks_exact_mut(4);
+                    for ((px, y), (u, v)) in
+                        packed.zip(self.y[i].chunks_exact(2)).zip(u.iter().zip(v))
+                    {
+                        px.copy_from_slice(&[y[0] as u8, *u as u8, y[1] as u8, *v as u8]);
+                    }
+                }
+            }
+            PixelFormat::Bgra | PixelFormat::Rgba => unreachable!("RGB rows go through store_rgb"),
+        }
+    }
+
+    /// 8 ↔ 10 位之间移位，10 → 8 位四舍五入
+    fn rescale(&mut self, from: u32, to: u32) {
+        if from == to {
+            return;
+        }
+        let planes = self
+            .y
+            .iter_mut()
+            .chain(self.u.iter_mut())
+            .chain(self.v.iter_mut());
+        for x in planes.flatten() {
+            *x = if to > from {
+                *x << (to - from)
+            } else {
+                let shift = from - to;
+                ((*x + (1 << (shift - 1))) >> shift).min((1 << to) - 1)
+            };
+        }
+    }
+}
+
+fn widen(src: &[u8], dst: &mut [u16]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = *s as u16;
+    }
+}
+
+fn narrow(src: &[u16], dst: &mut [u8]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = *s as u8;
+    }
+}
+
+fn read_p010(src: &[u8], dst: &mut [u16]) {
+    for (d, s) in dst.iter_mut().zip(src.chunks_exact(2)) {
+        *d = u16::from_le_bytes([s[0], s[1]]) >> 6;
+    }
+}
+
+fn write_p010(src: &[u16], dst: &mut [u8]) {
+    for (d, s) in dst.chunks_exact_mut(2).zip(src) {
+        d.copy_from_slice(&(*s << 6).to_le_bytes());
+    }
+}
+
+type LumaKernel = fn(&[u8], &RgbToYuv, &mut [u16]);
+type ChromaKernel = fn(&[u8], &[u8], &RgbToYuv, &mut [u16], &mut [u16]);
+type RgbKernel = fn(&[u16], &[u16], &[u16], &YuvToRgb, &mut [u8]);
+
+/// The conversion kernels of one instruction set.
+#[derive(Clone, Copy)]
+struct Kernels {
+    name: &'static str,
+    rgb_to_y: LumaKernel,
+    rgb_to_uv: ChromaKernel,
+    yuv_to_rgb: RgbKernel,
+}
+
+impl Kernels {
+    const SCALAR: Kernels = Kernels {
+        name: "scalar",
+        rgb_to_y: scalar::rgb_to_y,
+        rgb_to_uv: scalar::rgb_to_uv,
+        yuv_to_rgb: scalar::yuv_to_rgb,
+    };
+
+    /// Kernel sets this CPU can run, the fastest last.
+    fn available() -> Vec<Kernels> {
+        #[allow(unused_mut)]
+        let mut kernels = vec![Self::SCALAR];
+        #[cfg(target_arch = "x86_64")]
+        {
+            kernels.push(Kernels {
+                name: "sse2",
+                rgb_to_y: x86::rgb_to_y_sse2,
+                rgb_to_uv: x86::rgb_to_uv_sse2,
+                yuv_to_rgb: x86::yuv_to_rgb_sse2,
+            });
+            if is_x86_feature_detected!("avx2") {
+                kernels.push(Kernels {
+                    name: "avx2",
+                    rgb_to_y: x86::rgb_to_y_avx2,
+                    rgb_to_uv: x86::rgb_to_uv_avx2,
+                    yuv_to_rgb: x86::yuv_to_rgb_avx2,
+                });
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        kernels.push(Kernels {
+            name: "neon",
+            rgb_to_y: neon::rgb_to_y,
+            rgb_to_uv: neon::rgb_to_uv,
+            yuv_to_rgb: neon::yuv_to_rgb,
+        });
+        kernels
+    }
+
+    fn get() -> &'static Kernels {
+        static KERNELS: OnceLock<Kernels> = OnceLock::new();
+        KERNELS.get_or_init(|| {
+            let kernels = *Self::available().last().unwrap();
+            log::debug!("color conversion uses {} kernels", kernels.name);
+            kernels
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::color::{ORDER_BGRA, ORDER_RGBA};
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn color_spaces() -> Vec<ColorSpace> {
+        let matrices = [Matrix::Bt601, Matrix::Bt709, Matrix::Bt2020];
+        let ranges = [Range::Limited, Range::Full];
+        matrices
+            .iter()
+            .flat_map(|&m| ranges.iter().map(move |&r| ColorSpace::new(m, r)))
+            .collect()
+    }
+
+    fn convert_packed(
+        from: PixelFormat,
+        data: &[u8],
+        to: PixelFormat,
+        width: usize,
+        height: usize,
+        color: ColorSpace,
+    ) -> Vec<u8> {
+        let src = Image::packed(from, width, height, data).unwrap();
+        let mut out = vec![0u8; to.frame_size(width, height)];
+        let mut dst = ImageMut::packed(to, width, height, &mut out).unwrap();
+        convert(&src, &mut dst, color).unwrap();
+        out
+    }
+
+    // 偏向 0 与 255，以覆盖饱和与截断
+    fn random_bytes(rng: &mut StdRng, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| match rng.gen_range(0..4) {
+                0 => 0,
+                1 => 255,
+                _ => rng.gen(),
+            })
+            .collect()
+    }
+
+    fn le16(data: &[u8], i: usize) -> u16 {
+        u16::from_le_bytes([data[i], data[i + 1]])
+    }
+
+    /// 测试标准色的参考值：BT.601/709、有限与全范围、8 位与 10 位
+    #[test]
+    fn test_reference_values() {
+        let yuv = |bgra: [u8; 4], format: PixelFormat, color: ColorSpace| {
+            let out = convert_packed(PixelFormat::Bgra, &bgra.repeat(4), format, 2, 2, color);
+            match format {
+                PixelFormat::P010 => (le16(&out, 0) >> 6, le16(&out, 8) >> 6, le16(&out, 10) >> 6),
+                _ => (out[0] as u16, out[4] as u16, out[5] as u16),
+            }
+        };
+        let (red, white, black) = ([0, 0, 255, 255], [255; 4], [0, 0, 0, 255]);
+        let bt601 = ColorSpace::default();
+        let bt709 = ColorSpace::new(Matrix::Bt709, Range::Limited);
+        assert_eq!(yuv(red, PixelFormat::Nv12, bt601), (81, 90, 240));
+        assert_eq!(yuv(white, PixelFormat::Nv12, bt601), (235, 128, 128));
+        assert_eq!(yuv(black, PixelFormat::Nv12, bt601), (16, 128, 128));
+        assert_eq!(yuv(red, PixelFormat::Nv12, bt709), (63, 102, 240));
+        assert_eq!(
+            yuv(
+                red,
+                PixelFormat::Nv12,
+                ColorSpace::new(Matrix::Bt601, Range::Full)
+            ),
+            (76, 85, 255)
+        );
+        assert_eq!(yuv(red, PixelFormat::P010, bt709), (250, 409, 960));
+        assert_eq!(yuv(white, PixelFormat::P010, bt709), (940, 512, 512));
+
+        let nv12 = [81, 81, 81, 81, 90, 240];
+        let bgra = convert_packed(PixelFormat::Nv12, &nv12, PixelFormat::Bgra, 2, 2, bt601);
+        for px in bgra.chunks_exact(4) {
+            assert!(
+                px[0] <= 2 && px[1] <= 2 && px[2] >= 253 && px[3] == 255,
+                "{:?}",
+                px
+            );
+        }
+    }
+
+    /// 测试各 SIMD 内核与标量参考实现逐字节一致，含不足一个向量的行尾
+    #[test]
+    fn test_kernels_bit_exact() {
+        let mut rng = StdRng::seed_from_u64(601);
+        let scalar = Kernels::SCALAR;
+        for k in Kernels::available().iter().skip(1) {
+            for color in color_spaces() {
+                for (depth, order) in [(8, ORDER_BGRA), (8, ORDER_RGBA), (10, ORDER_BGRA)] {
+                    let to_yuv = RgbToYuv::new(color, depth, order);
+                    let to_rgb = YuvToRgb::new(color, depth, order);
+                    for width in [2, 6, 8, 14, 16, 18, 30, 32, 34, 62, 64, 66] {
+                        let rows = [
+                            random_bytes(&mut rng, width * 4),
+                            random_bytes(&mut rng, width * 4),
+                        ];
+                        let mut expected = vec![0u16; width];
+                        let mut actual = vec![0u16; width];
+                        (scalar.rgb_to_y)(&rows[0], &to_yuv, &mut expected);
+                        (k.rgb_to_y)(&rows[0], &to_yuv, &mut actual);
+                        assert_eq!(
+                            actual, expected,
+                            "{} rgb_to_y {:?} {}",
+                            k.name, color, width
+                        );
+
+                        let mut expected = (vec![0u16; width / 2], vec![0u16; width / 2]);
+                        let mut actual = expected.clone();
+                        (scalar.rgb_to_uv)(
+                            &rows[0],
+                            &rows[1],
+                            &to_yuv,
+                            &mut expected.0,
+                            &mut expected.1,
+                        );
+                        (k.rgb_to_uv)(&rows[0], &rows[1], &to_yuv, &mut actual.0, &mut actual.1);
+                        assert_eq!(
+                            actual, expected,
+                            "{} rgb_to_uv {:?} {}",
+                            k.name, color, width
+                        );
+
+                        let max = (1u16 << depth) - 1;
+                        let mut samples =
+                            |n: usize| (0..n).map(|_| rng.gen_range(0..=max)).collect::<Vec<_>>();
+                        let (y, u, v) = (samples(width), samples(width / 2), samples(width / 2));
+                        let mut expected = vec![0u8; width * 4];
+                        let mut actual = vec![0u8; width * 4];
+                        (scalar.yuv_to_rgb)(&y, &u, &v, &to_rgb, &mut expected);
+                        (k.yuv_to_rgb)(&y, &u, &v, &to_rgb, &mut actual);
+                        assert_eq!(
+                            actual, expected,
+                            "{} yuv_to_rgb {:?} {}",
+                            k.name, color, width
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// 测试定点结果与浮点公式相差不超过 1
+    #[test]
+    fn test_float_reference() {
+        let mut rng = StdRng::seed_from_u64(709);
+        for color in color_spaces() {
+            for depth in [8, 10] {
+                let c = RgbToYuv::new(color, depth, ORDER_RGBA);
+                let (kr, kb) = color.matrix.weights();
+                let kg = 1.0 - kr - kb;
+                let (y_offset, y_scale, c_offset, c_scale) = color.quantization(depth);
+                let max = ((1 << depth) - 1) as f64;
+                let px = random_bytes(&mut rng, 64 * 4);
+                let mut y = vec![0u16; 64];
+                let (mut u, mut v) = (vec![0u16; 32], vec![0u16; 32]);
+                scalar::rgb_to_y(&px, &c, &mut y);
+                scalar::rgb_to_uv(&px, &px, &c, &mut u, &mut v);
+                for (i, p) in px.chunks_exact(8).enumerate() {
+                    let avg = |k: usize| (p[k] as f64 + p[k + 4] as f64) / 2.0 / 255.0;
+                    let luma = |r: f64, g: f64, b: f64| kr * r + kg * g + kb * b;
+                    let (r, g, b) = (avg(0), avg(1), avg(2));
+                    let cb = (b - luma(r, g, b)) / (2.0 * (1.0 - kb));
+                    let cr = (r - luma(r, g, b)) / (2.0 * (1.0 - kr));
+                    let expect_u = (c_offset + c_scale * cb).round().clamp(0.0, max);
+                    let expect_v = (c_offset + c_scale * cr).round().clamp(0.0, max);
+                    assert!(
+                        (u[i] as f64 - expect_u).abs() <= 1.0,
+                        "{:?} u {} {}",
+                        color,
+                        u[i],
+                        expect_u
+                    );
+                    assert!(
+                        (v[i] as f64 - expect_v).abs() <= 1.0,
+                        "{:?} v {} {}",
+                        color,
+                        v[i],
+                        expect_v
+                    );
+                    for j in 0..2 {
+                        let p = &p[j * 4..];
+                        let rgb = (
+                            p[0] as f64 / 255.0,
+                            p[1] as f64 / 255.0,
+                            p[2] as f64 / 255.0,
+                        );
+                        let expect = (y_offset + y_scale * luma(rgb.0, rgb.1, rgb.2)).round();
+                        assert!((y[i * 2 + j] as f64 - expect).abs() <= 1.0, "{:?} y", color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 测试 YUV 格式之间的重排无损，RGB 经 YUV 往返误差不超过 2
+    #[test]
+    fn test_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(2020);
+        let (width, height) = (34, 6);
+        let nv12 = random_bytes(&mut rng, PixelFormat::Nv12.frame_size(width, height));
+        for format in [PixelFormat::I420, PixelFormat::P010, PixelFormat::Yuy2] {
+            let color = ColorSpace::default();
+            let other = convert_packed(PixelFormat::Nv12, &nv12, format, width, height, color);
+            let back = convert_packed(format, &other, PixelFormat::Nv12, width, height, color);
+            assert_eq!(back, nv12, "{:?}", format);
+        }
+
+        // 2x2 块内同色，色度抽样不引入误差
+        let mut bgra = vec![0u8; width * height * 4];
+        for y in (0..height).step_by(2) {
+            for x in (0..width).step_by(2) {
+                let px = random_bytes(&mut rng, 4);
+                for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                    let i = ((y + dy) * width + x + dx) * 4;
+                    bgra[i..i + 4].copy_from_slice(&px);
+                }
+            }
+        }
+        for color in color_spaces() {
+            for format in [PixelFormat::Nv12, PixelFormat::P010, PixelFormat::Yuy2] {
+                let yuv = convert_packed(PixelFormat::Bgra, &bgra, format, width, height, color);
+                let back = convert_packed(format, &yuv, PixelFormat::Bgra, width, height, color);
+                for (i, (a, b)) in bgra.iter().zip(&back).enumerate() {
+                    let expected = if i % 4 == 3 { 255 } else { *a };
+                    assert!(
+                        expected.abs_diff(*b) <= 2,
+                        "{:?} {:?} {} {}",
+                        color,
+                        format,
+                        expected,
+                        b
+                    );
+                }
+            }
+        }
+
+        let rgba = convert_packed(
+            PixelFormat::Bgra,
+            &bgra,
+            PixelFormat::Rgba,
+            width,
+            height,
+            ColorSpace::default(),
+        );
+        assert_eq!(&rgba[..4], &[bgra[2], bgra[1], bgra[0], bgra[3]]);
+        let nv12_bgra = convert_packed(
+            PixelFormat::Bgra,
+            &bgra,
+            PixelFormat::Nv12,
+            width,
+            height,
+            ColorSpace::default(),
+        );
+        let nv12_rgba = convert_packed(
+            PixelFormat::Rgba,
+            &rgba,
+            PixelFormat::Nv12,
+            width,
+            height,
+            ColorSpace::default(),
+        );
+        assert_eq!(nv12_bgra, nv12_rgba);
+    }
+
+    /// 测试带行填充的平面与紧凑缓冲结果一致，以及尺寸与平面大小的错误
+    #[test]
+    fn test_strides_and_errors() {
+        let mut rng = StdRng::seed_from_u64(420);
+        let (width, height) = (6, 4);
+        let bgra = random_bytes(&mut rng, width * height * 4);
+        let color = ColorSpace::default();
+        let packed = convert_packed(
+            PixelFormat::Bgra,
+            &bgra,
+            PixelFormat::Nv12,
+            width,
+            height,
+            color,
+        );
+
+        let src = Image::packed(PixelFormat::Bgra, width, height, &bgra).unwrap();
+        let (y_stride, uv_stride) = (width + 10, width + 6);
+        let mut luma = vec![0u8; y_stride * height];
+        let mut chroma = vec![0u8; uv_stride * (height / 2 - 1) + width];
+        let mut dst = ImageMut {
+            format: PixelFormat::Nv12,
+            width,
+            height,
+            planes: [
+                PlaneMut {
+                    data: &mut luma,
+                    stride: y_stride,
+                },
+                PlaneMut {
+                    data: &mut chroma,
+                    stride: uv_stride,
+                },
+                PlaneMut::default(),
+            ],
+        };
+        convert(&src, &mut dst, color).unwrap();
+        for row in 0..height {
+            assert_eq!(
+                &luma[row * y_stride..][..width],
+                &packed[row * width..][..width]
+            );
+        }
+        for row in 0..height / 2 {
+            assert_eq!(
+                &chroma[row * uv_stride..][..width],
+                &packed[(height + row) * width..][..width]
+            );
+        }
+
+        let mut out = vec![0u8; PixelFormat::Nv12.frame_size(width, height + 2)];
+        let mut dst = ImageMut::packed(PixelFormat::Nv12, width, height + 2, &mut out).unwrap();
+        assert_eq!(
+            convert(&src, &mut dst, color),
+            Err(ConvertError::SizeMismatch(6, 4, 6, 6))
+        );
+        let odd = Image::packed(PixelFormat::Bgra, 5, 4, &bgra).unwrap();
+        let mut dst = ImageMut::packed(PixelFormat::I420, 5, 4, &mut out).unwrap();
+        assert_eq!(
+            convert(&odd, &mut dst, color),
+            Err(ConvertError::OddSize(PixelFormat::I420, 5, 4))
+        );
+        let mut short = vec![0u8; PixelFormat::Nv12.frame_size(width, height) - 1];
+        assert_eq!(
+            ImageMut::packed(PixelFormat::Nv12, width, height, &mut short).unwrap_err(),
+            ConvertError::PlaneTooSmall(1)
+        );
+        let mut dst = ImageMut::packed(PixelFormat::Nv12, width, height, &mut out).unwrap();
+        dst.planes[0].stride = width - 2;
+        assert_eq!(
+            convert(&src, &mut dst, color),
+            Err(ConvertError::PlaneTooSmall(0))
+        );
+
+        // RGB 之间没有尺寸限制
+        let mut rgba = vec![0u8; 5 * 3 * 4];
+        let odd = Image::packed(PixelFormat::Bgra, 5, 3, &bgra).unwrap();
+        let mut dst = ImageMut::packed(PixelFormat::Rgba, 5, 3, &mut rgba).unwrap();
+        assert_eq!(convert(&odd, &mut dst, color), Ok(()));
+        assert_eq!(&rgba[4..8], &[bgra[6], bgra[5], bgra[4], bgra[7]]);
+    }
+}
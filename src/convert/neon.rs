@@ -0,0 +1,113 @@
+//! NEON kernels (aarch64, where NEON is always present). They cover whole blocks of 8 (luma,
+//! YUV → RGB) or 16 (chroma) pixels and leave the rest of the row to `scalar`.
+
+use std::arch::aarch64::*;
+
+use super::color::{RgbToYuv, YuvToRgb, RGB_TO_YUV_SHIFT, YUV_TO_RGB_ROUND, YUV_TO_RGB_SHIFT};
+use super::scalar;
+
+const Y_SHIFT: i32 = RGB_TO_YUV_SHIFT;
+const C_SHIFT: i32 = RGB_TO_YUV_SHIFT + 2;
+const RGB_SHIFT: i32 = YUV_TO_RGB_SHIFT;
+
+pub(crate) fn rgb_to_y(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    let n = y.len() & !7;
+    let (head, tail) = y.split_at_mut(n);
+    unsafe { rgb_to_y_blocks(&src[..n * 4], c, head) };
+    scalar::rgb_to_y(&src[n * 4..], c, tail);
+}
+
+pub(crate) fn rgb_to_uv(src0: &[u8], src1: &[u8], c: &RgbToYuv, u: &mut [u16], v: &mut [u16]) {
+    let n = u.len() & !7;
+    let (u_head, u_tail) = u.split_at_mut(n);
+    let (v_head, v_tail) = v.split_at_mut(n);
+    unsafe { rgb_to_uv_blocks(&src0[..n * 8], &src1[..n * 8], c, u_head, v_head) };
+    scalar::rgb_to_uv(&src0[n * 8..], &src1[n * 8..], c, u_tail, v_tail);
+}
+
+pub(crate) fn yuv_to_rgb(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    let n = y.len() & !7;
+    let (head, tail) = dst.split_at_mut(n * 4);
+    unsafe { yuv_to_rgb_blocks(&y[..n], &u[..n / 2], &v[..n / 2], c, head) };
+    scalar::yuv_to_rgb(&y[n..], &u[n / 2..], &v[n / 2..], c, tail);
+}
+
+// 三个通道的加权和（含偏置）右移后饱和为 i16，再限制到 [0, max]
+#[target_feature(enable = "neon")]
+unsafe fn weigh<const SHIFT: i32>(
+    s: [int16x8_t; 3],
+    k: &[i16; 3],
+    bias: i32,
+    max: i16,
+) -> uint16x8_t {
+    let mut lo = vdupq_n_s32(bias);
+    let mut hi = vdupq_n_s32(bias);
+    for i in 0..3 {
+        lo = vmlal_n_s16(lo, vget_low_s16(s[i]), k[i]);
+        hi = vmlal_n_s16(hi, vget_high_s16(s[i]), k[i]);
+    }
+    let x = vcombine_s16(
+        vqmovn_s32(vshrq_n_s32::<SHIFT>(lo)),
+        vqmovn_s32(vshrq_n_s32::<SHIFT>(hi)),
+    );
+    vreinterpretq_u16_s16(vminq_s16(vmaxq_s16(x, vdupq_n_s16(0)), vdupq_n_s16(max)))
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn rgb_to_y_blocks(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    for (px, out) in src.chunks_exact(32).zip(y.chunks_exact_mut(8)) {
+        let p = vld4_u8(px.as_ptr());
+        let widen = |x: uint8x8_t| vreinterpretq_s16_u16(vmovl_u8(x));
+        let s = [widen(p.0), widen(p.1), widen(p.2)];
+        vst1q_u16(out.as_mut_ptr(), weigh::<Y_SHIFT>(s, &c.y, c.y_bias, c.max));
+    }
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn rgb_to_uv_blocks(src0: &[u8], src1: &[u8], c: &RgbToYuv, u: &mut [u16], v: &mut [u16]) {
+    let rows = src0.chunks_exact(64).zip(src1.chunks_exact(64));
+    let out = u.chunks_exact_mut(8).zip(v.chunks_exact_mut(8));
+    for ((p0, p1), (u, v)) in rows.zip(out) {
+        let a = vld4q_u8(p0.as_ptr());
+        let b = vld4q_u8(p1.as_ptr());
+        // 相邻两像素两两相加，再加上另一行，得到 2x2 块的通道和
+        let sum = |x: uint8x16_t, y: uint8x16_t| {
+            vreinterpretq_s16_u16(vaddq_u16(vpaddlq_u8(x), vpaddlq_u8(y)))
+        };
+        let s = [sum(a.0, b.0), sum(a.1, b.1), sum(a.2, b.2)];
+        vst1q_u16(u.as_mut_ptr(), weigh::<C_SHIFT>(s, &c.u, c.c_bias, c.max));
+        vst1q_u16(v.as_mut_ptr(), weigh::<C_SHIFT>(s, &c.v, c.c_bias, c.max));
+    }
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn yuv_to_rgb_blocks(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    let chroma = u.chunks_exact(4).zip(v.chunks_exact(4));
+    for ((y, (u, v)), px) in y.chunks_exact(8).zip(chroma).zip(dst.chunks_exact_mut(32)) {
+        let yv = vsubq_s16(
+            vreinterpretq_s16_u16(vld1q_u16(y.as_ptr())),
+            vdupq_n_s16(c.y_offset),
+        );
+        // 每个色度样本复制给相邻两个像素
+        let widen = |x: &[u16]| {
+            let x = vreinterpret_s16_u16(vld1_u16(x.as_ptr()));
+            let x = vcombine_s16(vzip1_s16(x, x), vzip2_s16(x, x));
+            vsubq_s16(x, vdupq_n_s16(c.c_offset))
+        };
+        let (uv, vv) = (widen(u), widen(v));
+        let channel = |k: usize| {
+            let half = |y: int16x4_t, u: int16x4_t, v: int16x4_t| {
+                let acc = vdupq_n_s32(YUV_TO_RGB_ROUND as i32);
+                let acc = vmlal_n_s16(acc, y, c.y);
+                let acc = vmlal_n_s16(acc, u, c.u[k]);
+                let acc = vmlal_n_s16(acc, v, c.v[k]);
+                vqmovn_s32(vshrq_n_s32::<RGB_SHIFT>(acc))
+            };
+            let lo = half(vget_low_s16(yv), vget_low_s16(uv), vget_low_s16(vv));
+            let hi = half(vget_high_s16(yv), vget_high_s16(uv), vget_high_s16(vv));
+            vqmovun_s16(vcombine_s16(lo, hi))
+        };
+        let out = uint8x8x4_t(channel(0), channel(1), channel(2), vdup_n_u8(255));
+        vst4_u8(px.as_mut_ptr(), out);
+    }
+}
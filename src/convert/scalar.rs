@@ -0,0 +1,46 @@
+//! Reference kernels. The SIMD kernels compute the same integer expressions and must match
+//! these bit for bit; they also use them for the tail of each row.
+
+use super::color::{RgbToYuv, YuvToRgb, RGB_TO_YUV_SHIFT, YUV_TO_RGB_ROUND, YUV_TO_RGB_SHIFT};
+
+/// Luma of `y.len()` packed 32-bit pixels.
+pub(crate) fn rgb_to_y(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    for (px, out) in src.chunks_exact(4).zip(y.iter_mut()) {
+        let sum = c.y[0] as i32 * px[0] as i32
+            + c.y[1] as i32 * px[1] as i32
+            + c.y[2] as i32 * px[2] as i32
+            + c.y_bias;
+        *out = (sum >> RGB_TO_YUV_SHIFT).clamp(0, c.max as i32) as u16;
+    }
+}
+
+/// Chroma of `u.len()` 2x2 blocks spanning rows `src0` and `src1`. Pass the same row twice
+/// for 4:2:2.
+pub(crate) fn rgb_to_uv(src0: &[u8], src1: &[u8], c: &RgbToYuv, u: &mut [u16], v: &mut [u16]) {
+    let blocks = src0.chunks_exact(8).zip(src1.chunks_exact(8));
+    for ((p0, p1), (u, v)) in blocks.zip(u.iter_mut().zip(v.iter_mut())) {
+        let s = |k: usize| p0[k] as i32 + p0[k + 4] as i32 + p1[k] as i32 + p1[k + 4] as i32;
+        let (s0, s1, s2) = (s(0), s(1), s(2));
+        let chroma = |k: &[i16; 3]| {
+            let sum = k[0] as i32 * s0 + k[1] as i32 * s1 + k[2] as i32 * s2 + c.c_bias;
+            (sum >> (RGB_TO_YUV_SHIFT + 2)).clamp(0, c.max as i32) as u16
+        };
+        *u = chroma(&c.u);
+        *v = chroma(&c.v);
+    }
+}
+
+/// `y.len()` packed 32-bit pixels, alpha 255. Chroma is shared by each pair of pixels.
+pub(crate) fn yuv_to_rgb(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    for (i, (y, px)) in y.iter().zip(dst.chunks_exact_mut(4)).enumerate() {
+        let y = *y as i32 - c.y_offset as i32;
+        let u = u[i / 2] as i32 - c.c_offset as i32;
+        let v = v[i / 2] as i32 - c.c_offset as i32;
+        for (k, out) in px[..3].iter_mut().enumerate() {
+            let sum =
+                c.y as i32 * y + c.u[k] as i32 * u + c.v[k] as i32 * v + YUV_TO_RGB_ROUND as i32;
+            *out = (sum >> YUV_TO_RGB_SHIFT).clamp(0, 255) as u8;
+        }
+        px[3] = 255;
+    }
+}
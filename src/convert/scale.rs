@@ -0,0 +1,297 @@
+//! Rectangle arithmetic for the GPU scaling stage (`NativeDevice::scale_crop`, `EncodeScale`).
+//!
+//! Everything is in whole pixels. Rectangles that the video processor reads or writes are kept
+//! on even coordinates so that they stay on 4:2:0 chroma sample boundaries.
+
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The largest even size within `max` with the aspect ratio of `self`, e.g. the encode size
+    /// for a capture of size `self`.
+    pub fn fit_within(self, max: Size) -> Size {
+        place(Rect::full(self), max, Fit::Letterbox).dst.size()
+    }
+}
+
+/// Pixel rectangle; it spans `left..left + width` and `top..top + height`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Rect {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(left: u32, top: u32, width: u32, height: u32) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    /// The whole of a surface of `size`.
+    pub fn full(size: Size) -> Self {
+        Self::new(0, 0, size.width, size.height)
+    }
+
+    pub fn right(self) -> u32 {
+        self.left.saturating_add(self.width)
+    }
+
+    pub fn bottom(self) -> u32 {
+        self.top.saturating_add(self.height)
+    }
+
+    pub fn size(self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.size().is_empty()
+    }
+
+    /// The part of the rectangle inside a surface of `bounds`; empty when they do not overlap.
+    pub fn clip(self, bounds: Size) -> Rect {
+        let left = self.left.min(bounds.width);
+        let top = self.top.min(bounds.height);
+        let right = self.right().min(bounds.width);
+        let bottom = self.bottom().min(bounds.height);
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// The largest rectangle inside this one with even edges.
+    pub fn align_even(self) -> Rect {
+        let left = self.left.saturating_add(1) & !1;
+        let top = self.top.saturating_add(1) & !1;
+        let right = self.right() & !1;
+        let bottom = self.bottom() & !1;
+        Rect::new(
+            left,
+            top,
+            right.saturating_sub(left),
+            bottom.saturating_sub(top),
+        )
+    }
+}
+
+/// How a source rectangle is mapped onto an output of another aspect ratio.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Fit {
+    /// Fills the output, distorting the picture.
+    Stretch,
+    /// Keeps the aspect ratio and fills the rest of the output with black bars.
+    #[default]
+    Letterbox,
+    /// Keeps the aspect ratio and fills the output, cutting off the edges of the source.
+    Crop,
+}
+
+/// Scaling quality, passed to the video processor as its usage hint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ScaleFilter {
+    #[default]
+    Fast,
+    Quality,
+}
+
+/// Source and destination rectangles of one scaling pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub src: Rect,
+    pub dst: Rect,
+}
+
+/// Maps `src` onto an output of size `output` as `fit` says. Both rectangles come out on even
+/// coordinates; letterbox bars are split evenly between the two sides.
+pub fn place(src: Rect, output: Size, fit: Fit) -> Placement {
+    let src = src.align_even();
+    let full = Rect::full(output).align_even();
+    if src.is_empty() || full.is_empty() {
+        return Placement::default();
+    }
+    let (sw, sh) = (src.width as u64, src.height as u64);
+    let (dw, dh) = (full.width as u64, full.height as u64);
+    // a * b / c 四舍五入，奇数进到偶数，至少为 2
+    let scale = |a: u64, b: u64, c: u64| (((a * b + c / 2) / c + 1) & !1).max(2) as u32;
+    match fit {
+        Fit::Stretch => Placement { src, dst: full },
+        Fit::Letterbox => {
+            // 源更窄时高度占满，否则宽度占满
+            let (w, h) = if sw * dh <= sh * dw {
+                (scale(sw, dh, sh).min(full.width), full.height)
+            } else {
+                (full.width, scale(sh, dw, sw).min(full.height))
+            };
+            let dst = Rect::new(
+                ((full.width - w) / 2) & !1,
+                ((full.height - h) / 2) & !1,
+                w,
+                h,
+            );
+            Placement { src, dst }
+        }
+        Fit::Crop => {
+            let (w, h) = if sw * dh > sh * dw {
+                (scale(sh, dw, dh).min(src.width), src.height)
+            } else {
+                (src.width, scale(sw, dh, dw).min(src.height))
+            };
+            let cropped = Rect::new(
+                src.left + (((src.width - w) / 2) & !1),
+                src.top + (((src.height - h) / 2) & !1),
+                w,
+                h,
+            );
+            Placement {
+                src: cropped,
+                dst: full,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试裁剪到表面范围与偶数对齐
+    #[test]
+    fn test_rect() {
+        let bounds = Size::new(1920, 1080);
+        assert_eq!(
+            Rect::new(100, 50, 400, 300).clip(bounds),
+            Rect::new(100, 50, 400, 300)
+        );
+        assert_eq!(
+            Rect::new(1800, 1000, 400, 300).clip(bounds),
+            Rect::new(1800, 1000, 120, 80)
+        );
+        assert!(Rect::new(2000, 0, 10, 10).clip(bounds).is_empty());
+        assert_eq!(
+            Rect::new(0, 0, u32::MAX, u32::MAX).clip(bounds),
+            Rect::full(bounds)
+        );
+
+        assert_eq!(Rect::new(1, 3, 10, 10).align_even(), Rect::new(2, 4, 8, 8));
+        assert_eq!(Rect::new(2, 4, 7, 9).align_even(), Rect::new(2, 4, 6, 8));
+        assert!(Rect::new(1, 1, 1, 1).align_even().is_empty());
+        assert_eq!(
+            (
+                Rect::new(2, 4, 6, 8).right(),
+                Rect::new(2, 4, 6, 8).bottom()
+            ),
+            (8, 12)
+        );
+    }
+
+    /// 测试 Stretch / Letterbox / Crop 的矩形，以及宽高比保持
+    #[test]
+    fn test_place() {
+        let uhd = Rect::full(Size::new(3840, 2160));
+        let hd = Size::new(1280, 720);
+        // 同宽高比三种方式一致
+        for fit in [Fit::Stretch, Fit::Letterbox, Fit::Crop] {
+            assert_eq!(
+                place(uhd, hd, fit),
+                Placement {
+                    src: uhd,
+                    dst: Rect::full(hd)
+                }
+            );
+        }
+
+        // 4:3 放进 16:9：左右黑边
+        let sxga = Rect::full(Size::new(1280, 960));
+        let p = place(sxga, hd, Fit::Letterbox);
+        assert_eq!(p.dst, Rect::new(160, 0, 960, 720));
+        assert_eq!(place(sxga, hd, Fit::Stretch).dst, Rect::full(hd));
+        // 裁掉上下
+        let p = place(sxga, hd, Fit::Crop);
+        assert_eq!(
+            (p.src, p.dst),
+            (Rect::new(0, 120, 1280, 720), Rect::full(hd))
+        );
+
+        // 超宽屏放进 16:9：上下黑边
+        let p = place(
+            Rect::full(Size::new(3440, 1440)),
+            Size::new(1920, 1080),
+            Fit::Letterbox,
+        );
+        assert_eq!(p.dst, Rect::new(0, 138, 1920, 804));
+        let p = place(
+            Rect::full(Size::new(3440, 1440)),
+            Size::new(1920, 1080),
+            Fit::Crop,
+        );
+        assert_eq!(p.src, Rect::new(440, 0, 2560, 1440));
+
+        // 源区域：窗口裁剪后缩放，奇数边先对齐
+        let p = place(
+            Rect::new(101, 51, 801, 601),
+            Size::new(400, 300),
+            Fit::Letterbox,
+        );
+        assert_eq!(p.src, Rect::new(102, 52, 800, 600));
+        assert_eq!(p.dst, Rect::new(0, 0, 400, 300));
+
+        // 所有结果都是偶数，且落在输出和源之内
+        for (w, h) in [(1366, 768), (1001, 999), (640, 1136), (7, 5), (2, 1000)] {
+            let src = Rect::full(Size::new(w, h));
+            for fit in [Fit::Stretch, Fit::Letterbox, Fit::Crop] {
+                let p = place(src, Size::new(854, 480), fit);
+                for r in [p.src, p.dst] {
+                    assert!(r.left % 2 == 0 && r.top % 2 == 0, "{:?}", p);
+                    assert!(
+                        r.width % 2 == 0 && r.height % 2 == 0 && !r.is_empty(),
+                        "{:?}",
+                        p
+                    );
+                }
+                assert!(p.dst.right() <= 854 && p.dst.bottom() <= 480, "{:?}", p);
+                assert!(p.src.right() <= w && p.src.bottom() <= h, "{:?}", p);
+                if fit != Fit::Stretch {
+                    // 宽高比误差不超过取偶带来的 2 个像素
+                    let lhs = p.src.width as i64 * p.dst.height as i64;
+                    let rhs = p.src.height as i64 * p.dst.width as i64;
+                    let tolerance = 2 * p.src.width.max(p.src.height).max(p.dst.width) as i64;
+                    assert!((lhs - rhs).abs() <= tolerance, "{:?} {:?}", fit, p);
+                }
+            }
+        }
+
+        assert_eq!(
+            place(Rect::default(), hd, Fit::Letterbox),
+            Placement::default()
+        );
+        assert_eq!(place(uhd, Size::new(1, 1), Fit::Crop), Placement::default());
+    }
+
+    /// 测试按采集尺寸选取编码尺寸
+    #[test]
+    fn test_fit_within() {
+        let max = Size::new(1920, 1080);
+        assert_eq!(Size::new(3840, 2160).fit_within(max), max);
+        assert_eq!(Size::new(2560, 1600).fit_within(max), Size::new(1728, 1080));
+        assert_eq!(Size::new(1366, 768).fit_within(max), Size::new(1920, 1080));
+        assert_eq!(Size::new(1080, 2400).fit_within(max), Size::new(486, 1080));
+        assert_eq!(Size::new(0, 1080).fit_within(max), Size::default());
+    }
+}
@@ -0,0 +1,312 @@
+//! SSE2 and AVX2 kernels. They cover whole blocks of 8 (SSE2) or 16 (AVX2) pixels and leave
+//! the rest of the row to `scalar`.
+//!
+//! Pixels are split with masks rather than byte shuffles, which SSE2 lacks: `x & 0x00ff00ff`
+//! holds bytes 0 and 2 of each pixel as 16-bit halves and `(x >> 8) & 0x00ff00ff` bytes 1 and
+//! 3, so one `madd` against a coefficient pair weighs two channels at once.
+
+use std::arch::x86_64::*;
+
+use super::color::{RgbToYuv, YuvToRgb, RGB_TO_YUV_SHIFT, YUV_TO_RGB_ROUND, YUV_TO_RGB_SHIFT};
+use super::scalar;
+
+const Y_SHIFT: i32 = RGB_TO_YUV_SHIFT;
+const C_SHIFT: i32 = RGB_TO_YUV_SHIFT + 2;
+
+// madd 的系数对：低 16 位乘字节 0（或 1），高 16 位乘字节 2（或 3）
+fn pair(lo: i16, hi: i16) -> i32 {
+    (lo as u16 as u32 | (hi as u16 as u32) << 16) as i32
+}
+
+pub(crate) fn rgb_to_y_sse2(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    let n = y.len() & !7;
+    let (head, tail) = y.split_at_mut(n);
+    // SSE2 是 x86_64 的基线指令集
+    unsafe { rgb_to_y_sse2_blocks(&src[..n * 4], c, head) };
+    scalar::rgb_to_y(&src[n * 4..], c, tail);
+}
+
+pub(crate) fn rgb_to_uv_sse2(src0: &[u8], src1: &[u8], c: &RgbToYuv, u: &mut [u16], v: &mut [u16]) {
+    let n = u.len() & !3;
+    let (u_head, u_tail) = u.split_at_mut(n);
+    let (v_head, v_tail) = v.split_at_mut(n);
+    unsafe { rgb_to_uv_sse2_blocks(&src0[..n * 8], &src1[..n * 8], c, u_head, v_head) };
+    scalar::rgb_to_uv(&src0[n * 8..], &src1[n * 8..], c, u_tail, v_tail);
+}
+
+pub(crate) fn yuv_to_rgb_sse2(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    let n = y.len() & !7;
+    let (head, tail) = dst.split_at_mut(n * 4);
+    unsafe { yuv_to_rgb_sse2_blocks(&y[..n], &u[..n / 2], &v[..n / 2], c, head) };
+    scalar::yuv_to_rgb(&y[n..], &u[n / 2..], &v[n / 2..], c, tail);
+}
+
+pub(crate) fn rgb_to_y_avx2(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    let n = y.len() & !15;
+    let (head, tail) = y.split_at_mut(n);
+    // 只在检测到 AVX2 后选用
+    unsafe { rgb_to_y_avx2_blocks(&src[..n * 4], c, head) };
+    rgb_to_y_sse2(&src[n * 4..], c, tail);
+}
+
+pub(crate) fn rgb_to_uv_avx2(src0: &[u8], src1: &[u8], c: &RgbToYuv, u: &mut [u16], v: &mut [u16]) {
+    let n = u.len() & !7;
+    let (u_head, u_tail) = u.split_at_mut(n);
+    let (v_head, v_tail) = v.split_at_mut(n);
+    unsafe { rgb_to_uv_avx2_blocks(&src0[..n * 8], &src1[..n * 8], c, u_head, v_head) };
+    rgb_to_uv_sse2(&src0[n * 8..], &src1[n * 8..], c, u_tail, v_tail);
+}
+
+pub(crate) fn yuv_to_rgb_avx2(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    let n = y.len() & !15;
+    let (head, tail) = dst.split_at_mut(n * 4);
+    unsafe { yuv_to_rgb_avx2_blocks(&y[..n], &u[..n / 2], &v[..n / 2], c, head) };
+    yuv_to_rgb_sse2(&y[n..], &u[n / 2..], &v[n / 2..], c, tail);
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn rgb_to_y_sse2_blocks(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    let mask = _mm_set1_epi32(0x00ff_00ff);
+    let k02 = _mm_set1_epi32(pair(c.y[0], c.y[2]));
+    let k1 = _mm_set1_epi32(pair(c.y[1], 0));
+    let bias = _mm_set1_epi32(c.y_bias);
+    let max = _mm_set1_epi16(c.max);
+    let zero = _mm_setzero_si128();
+    for (px, out) in src.chunks_exact(32).zip(y.chunks_exact_mut(8)) {
+        let luma = |x: __m128i| {
+            let lo = _mm_and_si128(x, mask);
+            let hi = _mm_and_si128(_mm_srli_epi32(x, 8), mask);
+            let sum = _mm_add_epi32(_mm_madd_epi16(lo, k02), _mm_madd_epi16(hi, k1));
+            _mm_srai_epi32(_mm_add_epi32(sum, bias), Y_SHIFT)
+        };
+        let a = luma(_mm_loadu_si128(px.as_ptr() as *const __m128i));
+        let b = luma(_mm_loadu_si128(px.as_ptr().add(16) as *const __m128i));
+        let luma = _mm_min_epi16(_mm_max_epi16(_mm_packs_epi32(a, b), zero), max);
+        _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, luma);
+    }
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn rgb_to_uv_sse2_blocks(
+    src0: &[u8],
+    src1: &[u8],
+    c: &RgbToYuv,
+    u: &mut [u16],
+    v: &mut [u16],
+) {
+    let mask = _mm_set1_epi32(0x00ff_00ff);
+    let ku02 = _mm_set1_epi32(pair(c.u[0], c.u[2]));
+    let ku1 = _mm_set1_epi32(pair(c.u[1], 0));
+    let kv02 = _mm_set1_epi32(pair(c.v[0], c.v[2]));
+    let kv1 = _mm_set1_epi32(pair(c.v[1], 0));
+    let bias = _mm_set1_epi32(c.c_bias);
+    let max = _mm_set1_epi16(c.max);
+    let zero = _mm_setzero_si128();
+    let rows = src0.chunks_exact(32).zip(src1.chunks_exact(32));
+    let out = u.chunks_exact_mut(4).zip(v.chunks_exact_mut(4));
+    for ((p0, p1), (u, v)) in rows.zip(out) {
+        // 2x2 块的通道和（不超过 1020），有效值在 32 位通道 0 与 2
+        let sums = |offset: usize| {
+            let r0 = _mm_loadu_si128(p0.as_ptr().add(offset) as *const __m128i);
+            let r1 = _mm_loadu_si128(p1.as_ptr().add(offset) as *const __m128i);
+            let lo = _mm_add_epi16(_mm_and_si128(r0, mask), _mm_and_si128(r1, mask));
+            let hi = _mm_add_epi16(
+                _mm_and_si128(_mm_srli_epi32(r0, 8), mask),
+                _mm_and_si128(_mm_srli_epi32(r1, 8), mask),
+            );
+            (
+                _mm_add_epi16(lo, _mm_srli_epi64(lo, 32)),
+                _mm_add_epi16(hi, _mm_srli_epi64(hi, 32)),
+            )
+        };
+        let (lo_a, hi_a) = sums(0);
+        let (lo_b, hi_b) = sums(16);
+        let chroma = |k02: __m128i, k1: __m128i| {
+            let block = |lo: __m128i, hi: __m128i| {
+                let sum = _mm_add_epi32(_mm_madd_epi16(lo, k02), _mm_madd_epi16(hi, k1));
+                let sum = _mm_srai_epi32(_mm_add_epi32(sum, bias), C_SHIFT);
+                _mm_shuffle_epi32(sum, 0b10_00_10_00)
+            };
+            let sum = _mm_unpacklo_epi64(block(lo_a, hi_a), block(lo_b, hi_b));
+            _mm_min_epi16(_mm_max_epi16(_mm_packs_epi32(sum, sum), zero), max)
+        };
+        _mm_storel_epi64(u.as_mut_ptr() as *mut __m128i, chroma(ku02, ku1));
+        _mm_storel_epi64(v.as_mut_ptr() as *mut __m128i, chroma(kv02, kv1));
+    }
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn yuv_to_rgb_sse2_blocks(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    let y_offset = _mm_set1_epi16(c.y_offset);
+    let c_offset = _mm_set1_epi16(c.c_offset);
+    let one = _mm_set1_epi16(1);
+    let alpha = _mm_set1_epi8(-1);
+    let kyu: [__m128i; 3] = std::array::from_fn(|k| _mm_set1_epi32(pair(c.y, c.u[k])));
+    let kvr: [__m128i; 3] = std::array::from_fn(|k| _mm_set1_epi32(pair(c.v[k], YUV_TO_RGB_ROUND)));
+    let chroma = u.chunks_exact(4).zip(v.chunks_exact(4));
+    for ((y, (u, v)), px) in y.chunks_exact(8).zip(chroma).zip(dst.chunks_exact_mut(32)) {
+        let yv = _mm_sub_epi16(_mm_loadu_si128(y.as_ptr() as *const __m128i), y_offset);
+        let uv = _mm_loadl_epi64(u.as_ptr() as *const __m128i);
+        let uv = _mm_sub_epi16(_mm_unpacklo_epi16(uv, uv), c_offset);
+        let vv = _mm_loadl_epi64(v.as_ptr() as *const __m128i);
+        let vv = _mm_sub_epi16(_mm_unpacklo_epi16(vv, vv), c_offset);
+        let (yu_lo, yu_hi) = (_mm_unpacklo_epi16(yv, uv), _mm_unpackhi_epi16(yv, uv));
+        let (vr_lo, vr_hi) = (_mm_unpacklo_epi16(vv, one), _mm_unpackhi_epi16(vv, one));
+        let channel = |k: usize| {
+            let lo = _mm_add_epi32(_mm_madd_epi16(yu_lo, kyu[k]), _mm_madd_epi16(vr_lo, kvr[k]));
+            let hi = _mm_add_epi32(_mm_madd_epi16(yu_hi, kyu[k]), _mm_madd_epi16(vr_hi, kvr[k]));
+            let x = _mm_packs_epi32(
+                _mm_srai_epi32(lo, YUV_TO_RGB_SHIFT),
+                _mm_srai_epi32(hi, YUV_TO_RGB_SHIFT),
+            );
+            _mm_packus_epi16(x, x)
+        };
+        let c01 = _mm_unpacklo_epi8(channel(0), channel(1));
+        let c23 = _mm_unpacklo_epi8(channel(2), alpha);
+        _mm_storeu_si128(
+            px.as_mut_ptr() as *mut __m128i,
+            _mm_unpacklo_epi16(c01, c23),
+        );
+        _mm_storeu_si128(
+            px.as_mut_ptr().add(16) as *mut __m128i,
+            _mm_unpackhi_epi16(c01, c23),
+        );
+    }
+}
+
+// 8 个 i32 饱和为 i16 并按原顺序放入 128 位（AVX2 的 pack 在两个 128 位半区内各自进行）
+#[target_feature(enable = "avx2")]
+unsafe fn pack_i32x8(x: __m256i) -> __m128i {
+    _mm256_castsi256_si128(_mm256_permute4x64_epi64(
+        _mm256_packs_epi32(x, x),
+        0b00_00_10_00,
+    ))
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn rgb_to_y_avx2_blocks(src: &[u8], c: &RgbToYuv, y: &mut [u16]) {
+    let mask = _mm256_set1_epi32(0x00ff_00ff);
+    let k02 = _mm256_set1_epi32(pair(c.y[0], c.y[2]));
+    let k1 = _mm256_set1_epi32(pair(c.y[1], 0));
+    let bias = _mm256_set1_epi32(c.y_bias);
+    let max = _mm256_set1_epi16(c.max);
+    let zero = _mm256_setzero_si256();
+    for (px, out) in src.chunks_exact(64).zip(y.chunks_exact_mut(16)) {
+        let luma = |x: __m256i| {
+            let lo = _mm256_and_si256(x, mask);
+            let hi = _mm256_and_si256(_mm256_srli_epi32(x, 8), mask);
+            let sum = _mm256_add_epi32(_mm256_madd_epi16(lo, k02), _mm256_madd_epi16(hi, k1));
+            pack_i32x8(_mm256_srai_epi32(_mm256_add_epi32(sum, bias), Y_SHIFT))
+        };
+        let a = luma(_mm256_loadu_si256(px.as_ptr() as *const __m256i));
+        let b = luma(_mm256_loadu_si256(px.as_ptr().add(32) as *const __m256i));
+        let luma = _mm256_min_epi16(_mm256_max_epi16(_mm256_set_m128i(b, a), zero), max);
+        _mm256_storeu_si256(out.as_mut_ptr() as *mut __m256i, luma);
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn rgb_to_uv_avx2_blocks(
+    src0: &[u8],
+    src1: &[u8],
+    c: &RgbToYuv,
+    u: &mut [u16],
+    v: &mut [u16],
+) {
+    let mask = _mm256_set1_epi32(0x00ff_00ff);
+    let ku02 = _mm256_set1_epi32(pair(c.u[0], c.u[2]));
+    let ku1 = _mm256_set1_epi32(pair(c.u[1], 0));
+    let kv02 = _mm256_set1_epi32(pair(c.v[0], c.v[2]));
+    let kv1 = _mm256_set1_epi32(pair(c.v[1], 0));
+    let bias = _mm256_set1_epi32(c.c_bias);
+    let max = _mm_set1_epi16(c.max);
+    let zero = _mm_setzero_si128();
+    let rows = src0.chunks_exact(64).zip(src1.chunks_exact(64));
+    let out = u.chunks_exact_mut(8).zip(v.chunks_exact_mut(8));
+    for ((p0, p1), (u, v)) in rows.zip(out) {
+        let sums = |offset: usize| {
+            let r0 = _mm256_loadu_si256(p0.as_ptr().add(offset) as *const __m256i);
+            let r1 = _mm256_loadu_si256(p1.as_ptr().add(offset) as *const __m256i);
+            let lo = _mm256_add_epi16(_mm256_and_si256(r0, mask), _mm256_and_si256(r1, mask));
+            let hi = _mm256_add_epi16(
+                _mm256_and_si256(_mm256_srli_epi32(r0, 8), mask),
+                _mm256_and_si256(_mm256_srli_epi32(r1, 8), mask),
+            );
+            (
+                _mm256_add_epi16(lo, _mm256_srli_epi64(lo, 32)),
+                _mm256_add_epi16(hi, _mm256_srli_epi64(hi, 32)),
+            )
+        };
+        let (lo_a, hi_a) = sums(0);
+        let (lo_b, hi_b) = sums(32);
+        let chroma = |k02: __m256i, k1: __m256i| {
+            // 有效值在 32 位通道 0、2、4、6，先收拢到低 128 位
+            let block = |lo: __m256i, hi: __m256i| {
+                let sum = _mm256_add_epi32(_mm256_madd_epi16(lo, k02), _mm256_madd_epi16(hi, k1));
+                let sum = _mm256_srai_epi32(_mm256_add_epi32(sum, bias), C_SHIFT);
+                let sum = _mm256_shuffle_epi32(sum, 0b10_00_10_00);
+                _mm256_permute4x64_epi64(sum, 0b00_00_10_00)
+            };
+            let sum = _mm256_permute2x128_si256(block(lo_a, hi_a), block(lo_b, hi_b), 0x20);
+            _mm_min_epi16(_mm_max_epi16(pack_i32x8(sum), zero), max)
+        };
+        _mm_storeu_si128(u.as_mut_ptr() as *mut __m128i, chroma(ku02, ku1));
+        _mm_storeu_si128(v.as_mut_ptr() as *mut __m128i, chroma(kv02, kv1));
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn yuv_to_rgb_avx2_blocks(y: &[u16], u: &[u16], v: &[u16], c: &YuvToRgb, dst: &mut [u8]) {
+    let y_offset = _mm256_set1_epi16(c.y_offset);
+    let c_offset = _mm256_set1_epi16(c.c_offset);
+    let one = _mm256_set1_epi16(1);
+    let alpha = _mm256_set1_epi8(-1);
+    let kyu: [__m256i; 3] = std::array::from_fn(|k| _mm256_set1_epi32(pair(c.y, c.u[k])));
+    let kvr: [__m256i; 3] =
+        std::array::from_fn(|k| _mm256_set1_epi32(pair(c.v[k], YUV_TO_RGB_ROUND)));
+    // 每个色度样本复制到相邻两个 16 位通道
+    let widen = |x: &[u16]| {
+        let x = _mm256_cvtepu16_epi32(_mm_loadu_si128(x.as_ptr() as *const __m128i));
+        _mm256_sub_epi16(_mm256_or_si256(x, _mm256_slli_epi32(x, 16)), c_offset)
+    };
+    let chroma = u.chunks_exact(8).zip(v.chunks_exact(8));
+    for ((y, (u, v)), px) in y.chunks_exact(16).zip(chroma).zip(dst.chunks_exact_mut(64)) {
+        let yv = _mm256_sub_epi16(_mm256_loadu_si256(y.as_ptr() as *const __m256i), y_offset);
+        let (uv, vv) = (widen(u), widen(v));
+        // unpack 在 128 位半区内进行：lo 为像素 0-3、8-11，hi 为 4-7、12-15
+        let (yu_lo, yu_hi) = (_mm256_unpacklo_epi16(yv, uv), _mm256_unpackhi_epi16(yv, uv));
+        let (vr_lo, vr_hi) = (
+            _mm256_unpacklo_epi16(vv, one),
+            _mm256_unpackhi_epi16(vv, one),
+        );
+        let channel = |k: usize| {
+            let lo = _mm256_add_epi32(
+                _mm256_madd_epi16(yu_lo, kyu[k]),
+                _mm256_madd_epi16(vr_lo, kvr[k]),
+            );
+            let hi = _mm256_add_epi32(
+                _mm256_madd_epi16(yu_hi, kyu[k]),
+                _mm256_madd_epi16(vr_hi, kvr[k]),
+            );
+            let x = _mm256_packs_epi32(
+                _mm256_srai_epi32(lo, YUV_TO_RGB_SHIFT),
+                _mm256_srai_epi32(hi, YUV_TO_RGB_SHIFT),
+            );
+            _mm256_packus_epi16(x, x)
+        };
+        let c01 = _mm256_unpacklo_epi8(channel(0), channel(1));
+        let c23 = _mm256_unpacklo_epi8(channel(2), alpha);
+        let (lo, hi) = (
+            _mm256_unpacklo_epi16(c01, c23),
+            _mm256_unpackhi_epi16(c01, c23),
+        );
+        _mm256_storeu_si256(
+            px.as_mut_ptr() as *mut __m256i,
+            _mm256_permute2x128_si256(lo, hi, 0x20),
+        );
+        _mm256_storeu_si256(
+            px.as_mut_ptr().add(32) as *mut __m256i,
+            _mm256_permute2x128_si256(lo, hi, 0x31),
+        );
+    }
+}
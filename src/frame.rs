@@ -0,0 +1,369 @@
+//! Planar YUV frames in system memory, as read from and written to Y4M files.
+//!
+//! `Frame::from_image` and `Frame::to_packed` bridge to the `convert` layouts; on Windows
+//! `Texture::upload` and `Texture::read_frame` move frames to and from D3D11 textures.
+
+use crate::common::BitDepth;
+use crate::convert::{self, ColorSpace, ConvertError, Image, ImageMut, Matrix, PixelFormat, Range};
+use serde_derive::{Deserialize, Serialize};
+
+/// Chroma subsampling of a planar frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Subsampling {
+    /// Chroma at half width and half height.
+    #[default]
+    Yuv420,
+    /// Chroma at half width.
+    Yuv422,
+    /// Chroma at full resolution.
+    Yuv444,
+}
+
+impl Subsampling {
+    /// Horizontal and vertical chroma divisors.
+    pub(crate) fn divisors(self) -> (usize, usize) {
+        match self {
+            Subsampling::Yuv420 => (2, 2),
+            Subsampling::Yuv422 => (2, 1),
+            Subsampling::Yuv444 => (1, 1),
+        }
+    }
+}
+
+/// Planar Y, U, V frame. Chroma planes of odd sizes round up, as in Y4M.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub subsampling: Subsampling,
+    pub bit_depth: BitDepth,
+    pub range: Range,
+    /// The three planes back to back without padding. 10-bit samples take two bytes,
+    /// little-endian, in the low bits.
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// A black frame.
+    pub fn new(
+        width: usize,
+        height: usize,
+        subsampling: Subsampling,
+        bit_depth: BitDepth,
+        range: Range,
+    ) -> Self {
+        let size = Self::frame_size(width, height, subsampling, bit_depth);
+        let mut frame = Self {
+            width,
+            height,
+            subsampling,
+            bit_depth,
+            range,
+            data: vec![0; size],
+        };
+        let depth = bit_depth.bits() as u32;
+        let black = match range {
+            Range::Limited => 16 << (depth - 8),
+            Range::Full => 0,
+        };
+        frame.fill(0, black);
+        frame.fill(1, 1 << (depth - 1));
+        frame.fill(2, 1 << (depth - 1));
+        frame
+    }
+
+    /// Bytes of a frame with these parameters.
+    pub fn frame_size(
+        width: usize,
+        height: usize,
+        subsampling: Subsampling,
+        bit_depth: BitDepth,
+    ) -> usize {
+        let (cw, ch) = chroma_size(width, height, subsampling);
+        (width * height + 2 * cw * ch) * sample_bytes(bit_depth)
+    }
+
+    /// Width and height of `plane` in samples.
+    pub fn plane_size(&self, plane: usize) -> (usize, usize) {
+        if plane == 0 {
+            (self.width, self.height)
+        } else {
+            chroma_size(self.width, self.height, self.subsampling)
+        }
+    }
+
+    pub fn plane(&self, plane: usize) -> &[u8] {
+        let range = self.plane_range(plane);
+        &self.data[range]
+    }
+
+    pub fn plane_mut(&mut self, plane: usize) -> &mut [u8] {
+        let range = self.plane_range(plane);
+        &mut self.data[range]
+    }
+
+    /// Sample of `plane` at column `x`, row `y`.
+    pub fn sample(&self, plane: usize, x: usize, y: usize) -> u16 {
+        let width = self.plane_size(plane).0;
+        read_sample(self.plane(plane), y * width + x, self.bit_depth)
+    }
+
+    pub fn set_sample(&mut self, plane: usize, x: usize, y: usize, value: u16) {
+        let width = self.plane_size(plane).0;
+        let depth = self.bit_depth;
+        write_sample(self.plane_mut(plane), y * width + x, depth, value);
+    }
+
+    /// The frame with its chroma averaged down to 4:2:0.
+    pub fn to_420(&self) -> Frame {
+        let size = Self::frame_size(self.width, self.height, Subsampling::Yuv420, self.bit_depth);
+        let mut out = Frame {
+            subsampling: Subsampling::Yuv420,
+            data: vec![0; size],
+            ..*self
+        };
+        out.plane_mut(0).copy_from_slice(self.plane(0));
+        let (dx, dy) = self.subsampling.divisors();
+        let (src_w, src_h) = self.plane_size(1);
+        let (w, h) = out.plane_size(1);
+        for plane in 1..3 {
+            for y in 0..h {
+                let rows = (y * 2 / dy)..((y * 2 + 2) / dy).min(src_h);
+                for x in 0..w {
+                    let cols = (x * 2 / dx)..((x * 2 + 2) / dx).min(src_w);
+                    let mut sum = 0u32;
+                    for sy in rows.clone() {
+                        for sx in cols.clone() {
+                            sum += self.sample(plane, sx, sy) as u32;
+                        }
+                    }
+                    let count = (rows.len() * cols.len()) as u32;
+                    out.set_sample(plane, x, y, ((sum + count / 2) / count) as u16);
+                }
+            }
+        }
+        out
+    }
+
+    /// Converts `src` into a 4:2:0 frame: 10-bit for P010, 8-bit otherwise. `color` applies to
+    /// RGB sources; its range is recorded in the frame.
+    pub fn from_image(src: &Image, color: ColorSpace) -> Result<Frame, ConvertError> {
+        let (width, height) = (src.width, src.height);
+        if src.format != PixelFormat::P010 {
+            let mut frame = Frame::new(
+                width,
+                height,
+                Subsampling::Yuv420,
+                BitDepth::Eight,
+                color.range,
+            );
+            let mut dst = ImageMut::packed(PixelFormat::I420, width, height, &mut frame.data)?;
+            convert::convert(src, &mut dst, color)?;
+            return Ok(frame);
+        }
+        // 先整理成无填充的 P010，再拆成平面
+        let mut p010 = vec![0; PixelFormat::P010.frame_size(width, height)];
+        let mut dst = ImageMut::packed(PixelFormat::P010, width, height, &mut p010)?;
+        convert::convert(src, &mut dst, color)?;
+        let mut frame = Frame::new(
+            width,
+            height,
+            Subsampling::Yuv420,
+            BitDepth::Ten,
+            color.range,
+        );
+        let (luma, chroma) = p010.split_at(width * height * 2);
+        let depth = BitDepth::Ten;
+        for (i, s) in luma.chunks_exact(2).enumerate() {
+            write_sample(frame.plane_mut(0), i, depth, from_p010(s));
+        }
+        for (i, s) in chroma.chunks_exact(4).enumerate() {
+            write_sample(frame.plane_mut(1), i, depth, from_p010(&s[..2]));
+            write_sample(frame.plane_mut(2), i, depth, from_p010(&s[2..]));
+        }
+        Ok(frame)
+    }
+
+    /// Converts the frame into a tightly packed image of `format`, averaging 4:2:2 and 4:4:4
+    /// chroma down to 4:2:0. `matrix` and the frame's range apply to RGB formats.
+    pub fn to_packed(&self, format: PixelFormat, matrix: Matrix) -> Result<Vec<u8>, ConvertError> {
+        if self.subsampling != Subsampling::Yuv420 {
+            return self.to_420().to_packed(format, matrix);
+        }
+        let (width, height) = (self.width, self.height);
+        if width % 2 == 1 || height % 2 == 1 {
+            return Err(ConvertError::OddSize(format, width, height));
+        }
+        let color = ColorSpace::new(matrix, self.range);
+        let (source, data) = match self.bit_depth {
+            BitDepth::Eight => (PixelFormat::I420, self.data.clone()),
+            BitDepth::Ten => {
+                // 10 bit 平面先打包成 P010，由 convert 处理其余格式
+                let mut p010 = vec![0; PixelFormat::P010.frame_size(width, height)];
+                let (luma, chroma) = p010.split_at_mut(width * height * 2);
+                let sample = |plane, i| read_sample(self.plane(plane), i, BitDepth::Ten);
+                for (i, d) in luma.chunks_exact_mut(2).enumerate() {
+                    d.copy_from_slice(&to_p010(sample(0, i)));
+                }
+                for (i, d) in chroma.chunks_exact_mut(4).enumerate() {
+                    d[..2].copy_from_slice(&to_p010(sample(1, i)));
+                    d[2..].copy_from_slice(&to_p010(sample(2, i)));
+                }
+                (PixelFormat::P010, p010)
+            }
+        };
+        if format == source {
+            return Ok(data);
+        }
+        let src = Image::packed(source, width, height, &data)?;
+        let mut out = vec![0; format.frame_size(width, height)];
+        let mut dst = ImageMut::packed(format, width, height, &mut out)?;
+        convert::convert(&src, &mut dst, color)?;
+        Ok(out)
+    }
+
+    fn plane_range(&self, plane: usize) -> std::ops::Range<usize> {
+        let bytes = sample_bytes(self.bit_depth);
+        let luma = self.width * self.height * bytes;
+        let (cw, ch) = self.plane_size(1);
+        let chroma = cw * ch * bytes;
+        match plane {
+            0 => 0..luma,
+            1 => luma..luma + chroma,
+            _ => luma + chroma..luma + 2 * chroma,
+        }
+    }
+
+    fn fill(&mut self, plane: usize, value: u16) {
+        let depth = self.bit_depth;
+        let data = self.plane_mut(plane);
+        match depth {
+            BitDepth::Eight => data.fill(value as u8),
+            BitDepth::Ten => {
+                for d in data.chunks_exact_mut(2) {
+                    d.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+    }
+}
+
+fn chroma_size(width: usize, height: usize, subsampling: Subsampling) -> (usize, usize) {
+    let (dx, dy) = subsampling.divisors();
+    (width.div_ceil(dx), height.div_ceil(dy))
+}
+
+fn sample_bytes(bit_depth: BitDepth) -> usize {
+    match bit_depth {
+        BitDepth::Eight => 1,
+        BitDepth::Ten => 2,
+    }
+}
+
+fn read_sample(data: &[u8], index: usize, bit_depth: BitDepth) -> u16 {
+    match bit_depth {
+        BitDepth::Eight => data[index] as u16,
+        BitDepth::Ten => u16::from_le_bytes([data[2 * index], data[2 * index + 1]]),
+    }
+}
+
+pub(crate) fn write_sample(data: &mut [u8], index: usize, bit_depth: BitDepth, value: u16) {
+    match bit_depth {
+        BitDepth::Eight => data[index] = value as u8,
+        BitDepth::Ten => data[2 * index..2 * index + 2].copy_from_slice(&value.to_le_bytes()),
+    }
+}
+
+// P010 把 10 bit 样本放在 16 bit 的高位
+fn from_p010(sample: &[u8]) -> u16 {
+    u16::from_le_bytes([sample[0], sample[1]]) >> 6
+}
+
+fn to_p010(value: u16) -> [u8; 2] {
+    (value << 6).to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试平面布局、黑帧与 4:4:4 / 4:2:2 降采样
+    #[test]
+    fn test_layout() {
+        let frame = Frame::new(5, 3, Subsampling::Yuv420, BitDepth::Eight, Range::Limited);
+        assert_eq!(frame.plane_size(1), (3, 2));
+        assert_eq!(frame.data.len(), 15 + 2 * 6);
+        assert!(frame.plane(0).iter().all(|&y| y == 16));
+        assert!(frame.plane(2).iter().all(|&v| v == 128));
+
+        let frame = Frame::new(4, 2, Subsampling::Yuv422, BitDepth::Ten, Range::Full);
+        assert_eq!(frame.data.len(), (8 + 2 * 4) * 2);
+        assert_eq!((frame.sample(0, 3, 1), frame.sample(1, 1, 1)), (0, 512));
+
+        let mut frame = Frame::new(4, 4, Subsampling::Yuv444, BitDepth::Ten, Range::Limited);
+        for y in 0..4 {
+            for x in 0..4 {
+                frame.set_sample(1, x, y, (x + 4 * y) as u16 * 10);
+            }
+        }
+        let half = frame.to_420();
+        assert_eq!(half.subsampling, Subsampling::Yuv420);
+        assert_eq!(half.plane(0), frame.plane(0));
+        // (0 + 10 + 40 + 50) / 4
+        assert_eq!(half.sample(1, 0, 0), 25);
+        assert_eq!(half.sample(1, 1, 1), 125);
+        assert_eq!(half.sample(2, 1, 1), 512);
+
+        let mut frame = Frame::new(2, 2, Subsampling::Yuv422, BitDepth::Eight, Range::Limited);
+        frame.set_sample(2, 0, 0, 100);
+        frame.set_sample(2, 0, 1, 201);
+        assert_eq!(frame.to_420().sample(2, 0, 0), 151);
+    }
+
+    /// 测试与 convert 打包格式的往返
+    #[test]
+    fn test_packed_round_trip() {
+        let (width, height) = (16, 8);
+        let mut frame = Frame::new(
+            width,
+            height,
+            Subsampling::Yuv420,
+            BitDepth::Ten,
+            Range::Limited,
+        );
+        for (plane, base) in [(0, 64), (1, 300), (2, 700)] {
+            let (w, h) = frame.plane_size(plane);
+            for y in 0..h {
+                for x in 0..w {
+                    frame.set_sample(plane, x, y, (base + x * 7 + y * 3) as u16);
+                }
+            }
+        }
+        let p010 = frame.to_packed(PixelFormat::P010, Matrix::Bt709).unwrap();
+        let image = Image::packed(PixelFormat::P010, width, height, &p010).unwrap();
+        let color = ColorSpace::new(Matrix::Bt709, Range::Limited);
+        assert_eq!(Frame::from_image(&image, color).unwrap(), frame);
+
+        // 8 bit 经 NV12 往返无损，10 bit 降到 8 bit 后四舍五入
+        let nv12 = frame.to_packed(PixelFormat::Nv12, Matrix::Bt709).unwrap();
+        let image = Image::packed(PixelFormat::Nv12, width, height, &nv12).unwrap();
+        let eight = Frame::from_image(&image, color).unwrap();
+        assert_eq!(eight.bit_depth, BitDepth::Eight);
+        assert_eq!(eight.sample(0, 3, 2), ((64 + 21 + 6 + 2) / 4) as u16);
+        let again = eight.to_packed(PixelFormat::Nv12, Matrix::Bt709).unwrap();
+        assert_eq!(again, nv12);
+
+        // 4:4:4 输出 RGB 前先降到 4:2:0
+        let frame = Frame::new(
+            width,
+            height,
+            Subsampling::Yuv444,
+            BitDepth::Eight,
+            Range::Full,
+        );
+        let bgra = frame.to_packed(PixelFormat::Bgra, Matrix::Bt601).unwrap();
+        assert!(bgra.chunks_exact(4).all(|px| px == [0, 0, 0, 255]));
+        let odd = Frame::new(5, 3, Subsampling::Yuv420, BitDepth::Eight, Range::Full);
+        assert!(odd.to_packed(PixelFormat::Nv12, Matrix::Bt601).is_err());
+    }
+}
@@ -1,8 +1,12 @@
 pub mod common;
+pub mod convert;
+pub mod frame;
 #[cfg(windows)]
 pub mod platform;
-#[cfg(windows)]
+pub mod quality;
+pub mod testsrc;
 pub mod vram;
+pub mod y4m;
 
 // 导出 FFI 函数（与 C++ 代码兼容）
 #[cfg(windows)]
@@ -1,14 +1,70 @@
 //! D3D11 设备管理核心实现
 
 use crate::common;
+use crate::convert::{
+    place, ColorSpace, Fit, Placement, Rect, ScaleFilter, Size, YuvToRgbConstants,
+};
 use crate::platform::win::error::{Result, WinPlatformError};
+use crate::platform::win::texture::get_texture_width_height;
 use windows::core::Interface;
+use windows::core::HRESULT;
 use windows::Win32::Foundation::HANDLE;
 use windows::Win32::Graphics::Direct3D::*;
 use windows::Win32::Graphics::Direct3D11::*;
 use windows::Win32::Graphics::Dxgi::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
 
+/// 持有 COM 引用的 D3D11 设备
+///
+/// 克隆会 AddRef，释放会 Release；用于编码器/解码器绑定设备，替代裸指针。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    inner: ID3D11Device,
+}
+
+impl Device {
+    /// 从裸指针创建设备，会增加一次引用计数；指针为空时返回 `None`
+    ///
+    /// # Safety
+    /// `ptr` 必须为空或指向有效的 `ID3D11Device`。
+    pub unsafe fn from_raw(ptr: *mut std::ffi::c_void) -> Option<Self> {
+        ID3D11Device::from_raw_borrowed(&ptr).map(|d| Self { inner: d.clone() })
+    }
+
+    /// 获取裸指针（不转移所有权），用于原始指针接口
+    pub fn as_raw(&self) -> *mut std::ffi::c_void {
+        self.inner.as_raw()
+    }
+
+    /// 获取内部 COM 接口
+    pub fn inner(&self) -> &ID3D11Device {
+        &self.inner
+    }
+
+    /// 获取设备所在适配器的 LUID
+    pub fn luid(&self) -> Result<i64> {
+        let dxgi_device: IDXGIDevice = Interface::cast(&self.inner)?;
+        let desc = unsafe { dxgi_device.GetAdapter()?.GetDesc()? };
+        Ok(((desc.AdapterLuid.HighPart as i64) << 32) | desc.AdapterLuid.LowPart as i64)
+    }
+
+    /// 设备被移除时返回原因（`GetDeviceRemovedReason`），正常时返回 `None`
+    pub fn removed_reason(&self) -> Option<HRESULT> {
+        unsafe { self.inner.GetDeviceRemovedReason() }.err().map(|e| e.code())
+    }
+
+    /// 设备是否已丢失（TDR、驱动重置等）
+    pub fn is_lost(&self) -> bool {
+        self.removed_reason().is_some()
+    }
+}
+
+impl From<ID3D11Device> for Device {
+    fn from(inner: ID3D11Device) -> Self {
+        Self { inner }
+    }
+}
+
 /// D3D11 原生设备，提供视频处理和格式转换功能
 pub struct NativeDevice {
     #[allow(dead_code)]
@@ -36,10 +92,17 @@ pub struct NativeDevice {
     srv: [Option<ID3D11ShaderResourceView>; 2],
     vertex_shader: Option<ID3D11VertexShader>,
     pixel_shader: Option<ID3D11PixelShader>,
+    input_layout: Option<ID3D11InputLayout>,
     sampler_linear: Option<ID3D11SamplerState>,
     nv12_srv_texture: Option<ID3D11Texture2D>,
+    color_buffer: Option<ID3D11Buffer>,
     last_nv12_to_bgra_width: u32,
     last_nv12_to_bgra_height: u32,
+    last_nv12_to_bgra_color: Option<ColorSpace>,
+
+    // 设备丢失后 recreate 所需
+    luid: i64,
+    generation: u64,
 }
 
 impl NativeDevice {
@@ -56,6 +119,9 @@ impl NativeDevice {
             Self::init_from_luid(luid)?
         };
 
+        let desc = unsafe { adapter1.GetDesc1()? };
+        let luid = ((desc.AdapterLuid.HighPart as i64) << 32) | desc.AdapterLuid.LowPart as i64;
+
         let mut native_device = Self {
             factory,
             adapter,
@@ -89,10 +155,15 @@ impl NativeDevice {
             srv: [None, None],
             vertex_shader: None,
             pixel_shader: None,
+            input_layout: None,
             sampler_linear: None,
             nv12_srv_texture: None,
+            color_buffer: None,
             last_nv12_to_bgra_width: 0,
             last_nv12_to_bgra_height: 0,
+            last_nv12_to_bgra_color: None,
+            luid,
+            generation: 0,
         };
 
         native_device.set_multithread_protected()?;
@@ -232,6 +303,53 @@ impl NativeDevice {
         Ok(())
     }
 
+    /// 适配器 LUID
+    pub fn luid(&self) -> i64 {
+        self.luid
+    }
+
+    /// 重建次数；每次 `recreate` 加一，调用方据此判断旧设备上的纹理、编解码器是否需要重建
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// 设备被移除时返回原因，正常时返回 `None`
+    pub fn removed_reason(&self) -> Option<HRESULT> {
+        unsafe { self.device.GetDeviceRemovedReason() }.err().map(|e| e.code())
+    }
+
+    /// 检查设备状态，已移除时返回 `DeviceLost`
+    pub fn check_device(&self) -> Result<()> {
+        match self.removed_reason() {
+            Some(hr) => Err(WinPlatformError::DeviceLost(hr)),
+            None => Ok(()),
+        }
+    }
+
+    /// 在同一 LUID 上重建设备、视频设备、查询对象、着色器与纹理池；视频处理器在下次使用时重建
+    ///
+    /// 由外部设备创建的实例重建后改用自建设备。旧设备上的纹理和编解码器全部失效，需由调用方重建。
+    pub fn recreate(&mut self) -> Result<()> {
+        let size = self.textures.first().and_then(|t| t.as_ref()).map(|t| unsafe {
+            let mut desc = std::mem::zeroed();
+            t.GetDesc(&mut desc);
+            (desc.Width, desc.Height)
+        });
+        let mut device = Self::new(self.luid, None, self.pool_size)?;
+        device.create_shaders()?;
+        if let Some((width, height)) = size {
+            device.ensure_texture(width, height)?;
+        }
+        device.generation = self.generation + 1;
+        *self = device;
+        log::info!(
+            "D3D11 device recreated on LUID {}, generation {}",
+            self.luid,
+            self.generation
+        );
+        Ok(())
+    }
+
     /// 获取设备
     pub fn device(&self) -> &ID3D11Device {
         &self.device
@@ -270,6 +388,7 @@ impl NativeDevice {
 
     /// 确保存在指定尺寸的共享纹理
     pub fn ensure_texture(&mut self, width: u32, height: u32) -> Result<()> {
+        self.check_device()?;
         // 检查现有纹理是否满足要求
         if let Some(Some(existing)) = self.textures.first() {
             let desc = unsafe {
@@ -382,6 +501,11 @@ impl NativeDevice {
                 if hr.is_ok() && result {
                     return Ok(true);
                 }
+                if let Err(e) = hr {
+                    if crate::platform::win::error::is_device_lost_hresult(e.code()) {
+                        return Err(WinPlatformError::DeviceLost(e.code()));
+                    }
+                }
 
                 attempts += 1;
                 if attempts > 100 {
@@ -417,6 +541,166 @@ impl NativeDevice {
         color_space_in: DXGI_COLOR_SPACE_TYPE,
         color_space_out: DXGI_COLOR_SPACE_TYPE,
         array_slice: u32,
+    ) -> Result<()> {
+        self.check_device()?;
+        self.ensure_video_processor(content_desc)?;
+        let rect = Rect::new(0, 0, width, height);
+        self.video_processor_blt(
+            input,
+            output,
+            Placement {
+                src: rect,
+                dst: rect,
+            },
+            color_space_in,
+            color_space_out,
+            array_slice,
+        )
+    }
+
+    /// 把 `input` 的 `src_rect` 区域缩放到 `output` 左上角 `dst_size` 大小的区域
+    ///
+    /// 输出纹理其余部分填充黑色。颜色空间按纹理格式选取：YUV 格式为 BT.709 有限范围，
+    /// 其余为 sRGB 全范围。
+    pub fn scale_crop(
+        &mut self,
+        input: &ID3D11Texture2D,
+        src_rect: Rect,
+        output: &ID3D11Texture2D,
+        dst_size: Size,
+        filter: ScaleFilter,
+    ) -> Result<()> {
+        let placement = Placement {
+            src: src_rect,
+            dst: Rect::full(dst_size),
+        };
+        self.scale(input, output, placement, filter, None)
+    }
+
+    /// 按 `fit` 把 `input` 的 `src_rect` 区域放进整个 `output`，返回实际使用的矩形
+    ///
+    /// `src_rect` 先裁剪到输入纹理范围；`Fit::Letterbox` 的黑边由视频处理器的背景色填充。
+    pub fn scale_fit(
+        &mut self,
+        input: &ID3D11Texture2D,
+        src_rect: Rect,
+        output: &ID3D11Texture2D,
+        fit: Fit,
+        filter: ScaleFilter,
+    ) -> Result<Placement> {
+        self.fit(input, src_rect, output, fit, filter, None)
+    }
+
+    /// 同 `scale_fit`，输出使用 `color_space_out` 而不是按纹理格式选取的颜色空间
+    ///
+    /// 用于 YUV 输出需与码流中的颜色描述（矩阵、范围）一致的场合。
+    pub fn scale_fit_to_color_space(
+        &mut self,
+        input: &ID3D11Texture2D,
+        src_rect: Rect,
+        output: &ID3D11Texture2D,
+        fit: Fit,
+        filter: ScaleFilter,
+        color_space_out: DXGI_COLOR_SPACE_TYPE,
+    ) -> Result<Placement> {
+        self.fit(input, src_rect, output, fit, filter, Some(color_space_out))
+    }
+
+    fn fit(
+        &mut self,
+        input: &ID3D11Texture2D,
+        src_rect: Rect,
+        output: &ID3D11Texture2D,
+        fit: Fit,
+        filter: ScaleFilter,
+        color_space_out: Option<DXGI_COLOR_SPACE_TYPE>,
+    ) -> Result<Placement> {
+        let (input_width, input_height) = get_texture_width_height(input)?;
+        let (output_width, output_height) = get_texture_width_height(output)?;
+        let placement = place(
+            src_rect.clip(Size::new(input_width, input_height)),
+            Size::new(output_width, output_height),
+            fit,
+        );
+        if placement.src.is_empty() {
+            return Err(WinPlatformError::InvalidParameter(format!(
+                "nothing to scale from {:?} into {}x{}",
+                src_rect, output_width, output_height
+            )));
+        }
+        self.scale(input, output, placement, filter, color_space_out)?;
+        Ok(placement)
+    }
+
+    fn scale(
+        &mut self,
+        input: &ID3D11Texture2D,
+        output: &ID3D11Texture2D,
+        placement: Placement,
+        filter: ScaleFilter,
+        color_space_out: Option<DXGI_COLOR_SPACE_TYPE>,
+    ) -> Result<()> {
+        self.check_device()?;
+        let input_desc = unsafe {
+            let mut desc = std::mem::zeroed();
+            input.GetDesc(&mut desc);
+            desc
+        };
+        let output_desc = unsafe {
+            let mut desc = std::mem::zeroed();
+            output.GetDesc(&mut desc);
+            desc
+        };
+
+        let input_size = Size::new(input_desc.Width, input_desc.Height);
+        let output_size = Size::new(output_desc.Width, output_desc.Height);
+        if placement.src.is_empty() || placement.src.clip(input_size) != placement.src {
+            return Err(WinPlatformError::InvalidParameter(format!(
+                "source rect {:?} is outside the {}x{} input",
+                placement.src, input_size.width, input_size.height
+            )));
+        }
+        if placement.dst.is_empty() || placement.dst.clip(output_size) != placement.dst {
+            return Err(WinPlatformError::InvalidParameter(format!(
+                "destination rect {:?} is outside the {}x{} output",
+                placement.dst, output_size.width, output_size.height
+            )));
+        }
+
+        let content_desc = D3D11_VIDEO_PROCESSOR_CONTENT_DESC {
+            InputFrameFormat: D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+            InputFrameRate: DXGI_RATIONAL {
+                Numerator: 30,
+                Denominator: 1,
+            },
+            InputWidth: input_size.width,
+            InputHeight: input_size.height,
+            OutputFrameRate: DXGI_RATIONAL {
+                Numerator: 30,
+                Denominator: 1,
+            },
+            OutputWidth: output_size.width,
+            OutputHeight: output_size.height,
+            Usage: match filter {
+                ScaleFilter::Fast => D3D11_VIDEO_USAGE_OPTIMAL_SPEED,
+                ScaleFilter::Quality => D3D11_VIDEO_USAGE_OPTIMAL_QUALITY,
+            },
+        };
+        self.ensure_video_processor(content_desc)?;
+        self.video_processor_blt(
+            input,
+            output,
+            placement,
+            video_processor_color_space(input_desc.Format),
+            color_space_out.unwrap_or_else(|| video_processor_color_space(output_desc.Format)),
+            0,
+        )
+    }
+
+    /// 按内容描述创建视频处理器，描述不变时复用
+    fn ensure_video_processor(
+        &mut self,
+        content_desc: D3D11_VIDEO_PROCESSOR_CONTENT_DESC,
     ) -> Result<()> {
         // 检查内容描述是否变化，如果变化则重新创建视频处理器
         let need_recreate = self
@@ -429,6 +713,7 @@ impl NativeDevice {
                     || last.InputHeight != content_desc.InputHeight
                     || last.OutputWidth != content_desc.OutputWidth
                     || last.OutputHeight != content_desc.OutputHeight
+                    || last.Usage != content_desc.Usage
             })
             .unwrap_or(true);
 
@@ -465,9 +750,35 @@ impl NativeDevice {
                     0,
                     D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
                 );
+                // 目标矩形之外（如黑边）填充黑色
+                let black = D3D11_VIDEO_COLOR {
+                    Anonymous: D3D11_VIDEO_COLOR_0 {
+                        RGBA: D3D11_VIDEO_COLOR_RGBA {
+                            R: 0.0,
+                            G: 0.0,
+                            B: 0.0,
+                            A: 1.0,
+                        },
+                    },
+                };
+                self.video_context
+                    .VideoProcessorSetOutputBackgroundColor(processor, false, &black);
             }
         }
 
+        Ok(())
+    }
+
+    /// 用当前视频处理器把 `placement.src` 区域缩放到 `placement.dst` 区域
+    fn video_processor_blt(
+        &mut self,
+        input: &ID3D11Texture2D,
+        output: &ID3D11Texture2D,
+        placement: Placement,
+        color_space_in: DXGI_COLOR_SPACE_TYPE,
+        color_space_out: DXGI_COLOR_SPACE_TYPE,
+        array_slice: u32,
+    ) -> Result<()> {
         let processor = self.video_processor.as_ref().unwrap();
 
         // 设置颜色空间
@@ -484,25 +795,27 @@ impl NativeDevice {
         }
 
         // 设置源和目标矩形
-        let rect = windows::Win32::Foundation::RECT {
-            left: 0,
-            top: 0,
-            right: width as i32,
-            bottom: height as i32,
+        let to_rect = |r: Rect| windows::Win32::Foundation::RECT {
+            left: r.left as i32,
+            top: r.top as i32,
+            right: r.right() as i32,
+            bottom: r.bottom() as i32,
         };
+        let src_rect = to_rect(placement.src);
+        let dst_rect = to_rect(placement.dst);
 
         unsafe {
             self.video_context.VideoProcessorSetStreamSourceRect(
                 processor,
                 0,
                 true,
-                Some(&rect),
+                Some(&src_rect),
             );
             self.video_context1.VideoProcessorSetStreamDestRect(
                 processor,
                 0,
                 true,
-                Some(&rect),
+                Some(&dst_rect),
             );
         }
 
@@ -639,6 +952,8 @@ impl NativeDevice {
     }
 
     /// 将 NV12 纹理转换为 BGRA 格式（使用 GPU 着色器）
+    ///
+    /// `color` 描述 NV12 的矩阵、范围与传输特性；PQ/HLG 内容按 BT.2020 原色色调映射到 BT.709 SDR。
     pub fn nv12_to_bgra(
         &mut self,
         nv12_texture: &ID3D11Texture2D,
@@ -646,20 +961,26 @@ impl NativeDevice {
         width: u32,
         height: u32,
         nv12_array_index: u32,
+        color: ColorSpace,
     ) -> Result<()> {
+        self.check_device()?;
         // 如果尺寸变化，重新设置着色器状态
-        if width != self.last_nv12_to_bgra_width
-            || height != self.last_nv12_to_bgra_height
-        {
+        let resized = width != self.last_nv12_to_bgra_width
+            || height != self.last_nv12_to_bgra_height;
+        if resized {
             self.nv12_to_bgra_set_srv(nv12_texture, width, height)?;
             self.nv12_to_bgra_set_viewport(width, height)?;
             self.nv12_to_bgra_set_sample()?;
             self.nv12_to_bgra_set_shader()?;
             self.nv12_to_bgra_set_vertex_buffer()?;
         }
+        if resized || self.last_nv12_to_bgra_color != Some(color) {
+            self.nv12_to_bgra_set_color(color)?;
+        }
 
         self.last_nv12_to_bgra_width = width;
         self.last_nv12_to_bgra_height = height;
+        self.last_nv12_to_bgra_color = Some(color);
 
         self.nv12_to_bgra_set_rtv(bgra_texture, width, height)?;
 
@@ -881,17 +1202,25 @@ impl NativeDevice {
         Ok(())
     }
 
-    /// 设置着色器
-    fn nv12_to_bgra_set_shader(&mut self) -> Result<()> {
+    /// 创建 NV12 转 BGRA 的着色器与输入布局
+    fn create_shaders(&mut self) -> Result<()> {
         use crate::platform::win::shader;
 
         self.vertex_shader = Some(shader::create_vertex_shader(&self.device)?);
         self.pixel_shader = Some(shader::create_pixel_shader(&self.device)?);
+        self.input_layout = Some(shader::create_input_layout(&self.device)?);
+        Ok(())
+    }
 
-        let input_layout = shader::create_input_layout(&self.device)?;
+    /// 设置着色器
+    fn nv12_to_bgra_set_shader(&mut self) -> Result<()> {
+        // 着色器与尺寸无关，已创建（包括 recreate 时）则直接绑定
+        if self.input_layout.is_none() {
+            self.create_shaders()?;
+        }
 
         unsafe {
-            self.context.IASetInputLayout(Some(&input_layout));
+            self.context.IASetInputLayout(self.input_layout.as_ref());
             self.context.VSSetShader(
                 Some(self.vertex_shader.as_ref().unwrap()),
                 None,
@@ -905,6 +1234,19 @@ impl NativeDevice {
         Ok(())
     }
 
+    /// 设置颜色转换常量缓冲区
+    fn nv12_to_bgra_set_color(&mut self, color: ColorSpace) -> Result<()> {
+        use crate::platform::win::shader;
+
+        let buffer = shader::create_color_buffer(&self.device, &YuvToRgbConstants::new(color))?;
+        unsafe {
+            self.context.PSSetConstantBuffers(0, Some(&[Some(buffer.clone())]));
+        }
+        self.color_buffer = Some(buffer);
+
+        Ok(())
+    }
+
     /// 设置顶点缓冲区
     fn nv12_to_bgra_set_vertex_buffer(&self) -> Result<()> {
         #[repr(C)]
@@ -992,19 +1334,30 @@ impl NativeDevice {
         Ok(())
     }
 
-    /// 检查是否支持硬件解码
-    pub fn support_decode(&self, format: common::DataFormat) -> Result<bool> {
-        let guid = match format {
-            common::DataFormat::H264 => D3D11_DECODER_PROFILE_H264_VLD_NOFGT,
-            common::DataFormat::H265 => D3D11_DECODER_PROFILE_HEVC_VLD_MAIN,
+    /// 检查是否支持硬件解码；10 bit 为 HEVC Main10 解码到 P010
+    pub fn support_decode(
+        &self,
+        format: common::DataFormat,
+        bit_depth: common::BitDepth,
+    ) -> Result<bool> {
+        let (guid, output) = match (format, bit_depth) {
+            (common::DataFormat::H264, common::BitDepth::Eight) => {
+                (D3D11_DECODER_PROFILE_H264_VLD_NOFGT, DXGI_FORMAT_NV12)
+            }
+            (common::DataFormat::H265, common::BitDepth::Eight) => {
+                (D3D11_DECODER_PROFILE_HEVC_VLD_MAIN, DXGI_FORMAT_NV12)
+            }
+            (common::DataFormat::H265, common::BitDepth::Ten) => {
+                (D3D11_DECODER_PROFILE_HEVC_VLD_MAIN10, DXGI_FORMAT_P010)
+            }
             _ => return Ok(false),
         };
 
+        // CheckVideoDecoderFormat 返回 BOOL 输出参数，调用成功不代表支持
         let supported = unsafe {
-            self.video_device.CheckVideoDecoderFormat(
-                &guid,
-                DXGI_FORMAT_NV12,
-            ).is_ok()
+            self.video_device
+                .CheckVideoDecoderFormat(&guid, output)
+                .is_ok_and(|supported| supported.as_bool())
         };
 
         if !supported {
@@ -1013,60 +1366,20 @@ impl NativeDevice {
 
         // 检查是否为混合解码
         let desc = unsafe { self.adapter1.GetDesc1()? };
-        let is_hybrid = self.is_format_hybrid_decoded_by_hardware(
-            format,
-            desc.VendorId,
-            desc.DeviceId,
-        );
+        let is_hybrid =
+            common::is_format_hybrid_decoded_by_hardware(format, desc.VendorId, desc.DeviceId);
 
         Ok(!is_hybrid)
     }
+}
 
-    /// 检查特定 GPU 是否使用混合解码
-    fn is_format_hybrid_decoded_by_hardware(
-        &self,
-        format: common::DataFormat,
-        vendor_id: u32,
-        device_id: u32,
-    ) -> bool {
-        if vendor_id == common::AdapterVendor::ADAPTER_VENDOR_INTEL as u32 {
-            // Intel GPU 混合解码检测
-            // 参考：https://github.com/moonlight-stream/moonlight-qt
-            match device_id & 0xFF00 {
-                0x0400 | 0x0A00 | 0x0D00 => {
-                    // Haswell
-                    return format == common::DataFormat::H265;
-                }
-                0x1600 => {
-                    // Broadwell
-                    return format == common::DataFormat::H265;
-                }
-                0x2200 => {
-                    // Cherry Trail and Braswell
-                    return format == common::DataFormat::H265;
-                }
-                _ => {}
-            }
-        } else if vendor_id == common::AdapterVendor::ADAPTER_VENDOR_NVIDIA as u32 {
-            // NVIDIA GPU 混合解码检测（Feature Set E）
-            // 参考：https://en.wikipedia.org/wiki/Nvidia_PureVideo
-            if (device_id >= 0x1180 && device_id <= 0x11BF) // GK104
-                || (device_id >= 0x11C0 && device_id <= 0x11FF) // GK106
-                || (device_id >= 0x0FC0 && device_id <= 0x0FFF) // GK107
-                || (device_id >= 0x1000 && device_id <= 0x103F) // GK110/GK110B
-                || (device_id >= 0x1280 && device_id <= 0x12BF) // GK208
-                || (device_id >= 0x1340 && device_id <= 0x137F) // GM108
-                || (device_id >= 0x1380 && device_id <= 0x13BF) // GM107
-                || (device_id >= 0x13C0 && device_id <= 0x13FF) // GM204
-                || (device_id >= 0x1617 && device_id <= 0x161A) // GM204
-                || (device_id == 0x1667) // GM204
-                || (device_id >= 0x17C0 && device_id <= 0x17FF) // GM200
-            {
-                return format == common::DataFormat::H265;
-            }
+/// 视频处理器按纹理格式使用的颜色空间：YUV 为 BT.709 有限范围，其余为 sRGB 全范围
+fn video_processor_color_space(format: DXGI_FORMAT) -> DXGI_COLOR_SPACE_TYPE {
+    match format {
+        DXGI_FORMAT_NV12 | DXGI_FORMAT_P010 | DXGI_FORMAT_AYUV | DXGI_FORMAT_YUY2 => {
+            DXGI_COLOR_SPACE_YCBCR_STUDIO_G22_LEFT_P709
         }
-
-        false
+        _ => DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
     }
 }
 
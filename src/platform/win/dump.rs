@@ -1,27 +1,62 @@
 //! 纹理转储功能
 //! 
-//! 将 NV12 格式纹理转储到文件
+//! 将 NV12 格式纹理转储到文件，裸数据或 Y4M
 
-use crate::platform::win::error::Result;
+use crate::convert::{ColorSpace, Matrix, Range};
+use crate::platform::win::error::{Result, WinPlatformError};
+use crate::platform::win::texture::Texture;
+use crate::y4m::{Y4mError, Y4mHeader, Y4mWriter};
 use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use windows::Win32::Graphics::Direct3D11::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
 
-/// 转储 NV12 纹理到文件
+/// 转储文件格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// 裸 NV12：Y 平面后接交错的 UV 平面，没有文件头
+    #[default]
+    RawNv12,
+    /// 单帧 Y4M：NV12 为 8 bit、P010 为 10 bit 的 4:2:0，BGRA/RGBA 按 BT.709 有限范围转换
+    Y4m,
+}
+
+/// 转储 NV12 纹理到 `texture` 目录下的文件，写入裸 NV12 数据
 pub fn dump_texture(
     device: &ID3D11Device,
     texture: &ID3D11Texture2D,
     crop_w: u32,
     crop_h: u32,
     filename: &str,
+) -> Result<()> {
+    dump_texture_with(
+        device,
+        texture,
+        crop_w,
+        crop_h,
+        filename,
+        DumpFormat::RawNv12,
+    )
+}
+
+/// 同 `dump_texture`，以 `format` 指定的格式写入
+pub fn dump_texture_with(
+    device: &ID3D11Device,
+    texture: &ID3D11Texture2D,
+    crop_w: u32,
+    crop_h: u32,
+    filename: &str,
+    format: DumpFormat,
 ) -> Result<()> {
     // 确保目录存在
     let dir = Path::new("texture");
     if !dir.exists() {
         std::fs::create_dir_all(dir)?;
     }
+    if format == DumpFormat::Y4m {
+        return dump_y4m(texture, crop_w, crop_h, &dir.join(filename));
+    }
 
     // 获取纹理描述
     let desc = unsafe {
@@ -104,3 +139,19 @@ pub fn dump_texture(
 
     Ok(())
 }
+
+fn dump_y4m(texture: &ID3D11Texture2D, crop_w: u32, crop_h: u32, path: &Path) -> Result<()> {
+    let color = ColorSpace::new(Matrix::Bt709, Range::Limited);
+    let frame = Texture::from(texture.clone()).read_frame(crop_w, crop_h, color)?;
+    // 单帧文件，帧率只为填写文件头
+    let header = Y4mHeader::for_frame(&frame, (30, 1));
+    let write = || -> std::result::Result<(), Y4mError> {
+        let mut writer = Y4mWriter::create(path, header)?;
+        writer.write_frame(&frame)?;
+        writer.flush()
+    };
+    write().map_err(|e| match e {
+        Y4mError::Io(e) => WinPlatformError::FileOperationFailed(e),
+        e => WinPlatformError::InvalidParameter(e.to_string()),
+    })
+}
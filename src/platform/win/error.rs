@@ -46,9 +46,38 @@ pub enum WinPlatformError {
     #[error("Unsupported format")]
     UnsupportedFormat,
 
+    /// 纹理与预期配置不匹配（格式、尺寸或所属设备）
+    #[error("Texture mismatch: {0}")]
+    TextureMismatch(String),
+
     /// 多线程保护设置失败
     #[error("Failed to set multithread protection")]
     MultithreadProtectionFailed,
+
+    /// 设备已移除（TDR、驱动重置或更新），需调用 `NativeDevice::recreate` 重建
+    #[error("D3D11 device lost: {0}")]
+    DeviceLost(HRESULT),
+}
+
+impl WinPlatformError {
+    /// 是否为设备丢失；包括直接返回设备移除 HRESULT 的 D3D 调用错误
+    pub fn is_device_lost(&self) -> bool {
+        match self {
+            WinPlatformError::DeviceLost(_) => true,
+            WinPlatformError::DeviceCreationFailed(e) => is_device_lost_hresult(e.code()),
+            _ => false,
+        }
+    }
+}
+
+/// 判断 HRESULT 是否表示设备丢失
+pub fn is_device_lost_hresult(hr: HRESULT) -> bool {
+    use windows::Win32::Graphics::Dxgi::*;
+
+    hr == DXGI_ERROR_DEVICE_REMOVED
+        || hr == DXGI_ERROR_DEVICE_RESET
+        || hr == DXGI_ERROR_DEVICE_HUNG
+        || hr == DXGI_ERROR_DRIVER_INTERNAL_ERROR
 }
 
 /// Result 类型别名
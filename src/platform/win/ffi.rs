@@ -9,6 +9,7 @@
 //! - `NativeDevice`, `Adapter`, `Adapters` 类 - 通过不透明指针提供 FFI 接口
 
 use crate::common;
+use crate::convert::{ColorSpace, Matrix, Range, Rect, ScaleFilter, Size, Transfer};
 use crate::platform::win::adapter::Adapters;
 use crate::platform::win::bmp;
 use crate::platform::win::device::NativeDevice;
@@ -145,13 +146,8 @@ pub extern "C" fn hwcodec_native_device_set_texture(
     }
 
     unsafe {
-        let texture_ptr = texture as *mut ID3D11Texture2D;
-        // 创建新的接口引用（不增加引用计数）
-        let texture_interface = Interface::from_raw(texture_ptr as *mut std::ffi::c_void);
-        // set_texture 会获取所有权，所以我们需要克隆引用
-        // 但由于这是外部管理的指针，我们直接传递
-        (*handle).set_texture(texture_interface);
-        // 不要忘记，因为 set_texture 会获取所有权
+        // 调用方的引用转移给 set_texture，这里不 AddRef
+        (*handle).set_texture(ID3D11Texture2D::from_raw(texture));
     }
 }
 
@@ -321,11 +317,23 @@ pub extern "C" fn hwcodec_native_device_get_vendor(
     }
 }
 
-/// 检查是否支持硬件解码
+/// 检查是否支持硬件解码（8 bit）
 #[no_mangle]
 pub extern "C" fn hwcodec_native_device_support_decode(
     handle: NativeDeviceHandle,
     format: c_int,
+) -> c_int {
+    hwcodec_native_device_support_decode_bit_depth(handle, format, 8)
+}
+
+/// 检查是否支持指定位深的硬件解码
+///
+/// - `bit_depth`: 8，或 10（仅 HEVC Main10，输出 P010）
+#[no_mangle]
+pub extern "C" fn hwcodec_native_device_support_decode_bit_depth(
+    handle: NativeDeviceHandle,
+    format: c_int,
+    bit_depth: c_int,
 ) -> c_int {
     if handle.is_null() {
         return 0;
@@ -336,9 +344,14 @@ pub extern "C" fn hwcodec_native_device_support_decode(
         1 => common::DataFormat::H265,
         _ => return 0,
     };
+    let bit_depth = match bit_depth {
+        8 => common::BitDepth::Eight,
+        10 => common::BitDepth::Ten,
+        _ => return 0,
+    };
 
     unsafe {
-        match (*handle).support_decode(data_format) {
+        match (*handle).support_decode(data_format, bit_depth) {
             Ok(true) => 1,
             Ok(false) => 0,
             Err(_) => 0,
@@ -523,6 +536,57 @@ pub extern "C" fn hwcodec_native_device_process(
     }
 }
 
+/// 把输入纹理的源矩形缩放到输出纹理左上角 `dst_width` x `dst_height` 的区域
+///
+/// - `filter`: 0 = 速度优先，非 0 = 质量优先
+///
+/// # Safety
+/// `handle` 必须为空或来自 `hwcodec_native_device_new`；`input`、`output` 必须为空或指向有效的
+/// `ID3D11Texture2D`。
+#[no_mangle]
+pub unsafe extern "C" fn hwcodec_native_device_scale_crop(
+    handle: NativeDeviceHandle,
+    input: *mut std::ffi::c_void,
+    src_left: u32,
+    src_top: u32,
+    src_width: u32,
+    src_height: u32,
+    output: *mut std::ffi::c_void,
+    dst_width: u32,
+    dst_height: u32,
+    filter: c_int,
+) -> c_int {
+    if handle.is_null() || input.is_null() || output.is_null() {
+        return 0;
+    }
+
+    unsafe {
+        let input_texture = Interface::from_raw(input);
+        let output_texture = Interface::from_raw(output);
+        let filter = if filter == 0 {
+            ScaleFilter::Fast
+        } else {
+            ScaleFilter::Quality
+        };
+
+        let result = (*handle).scale_crop(
+            &input_texture,
+            Rect::new(src_left, src_top, src_width, src_height),
+            &output_texture,
+            Size::new(dst_width, dst_height),
+            filter,
+        );
+
+        std::mem::forget(input_texture);
+        std::mem::forget(output_texture);
+
+        match result {
+            Ok(_) => 1,
+            Err(_) => 0,
+        }
+    }
+}
+
 /// 将 BGRA 纹理转换为 NV12
 #[no_mangle]
 pub extern "C" fn hwcodec_native_device_bgra_to_nv12(
@@ -554,7 +618,7 @@ pub extern "C" fn hwcodec_native_device_bgra_to_nv12(
     }
 }
 
-/// 将 NV12 纹理转换为 BGRA
+/// 将 NV12 纹理转换为 BGRA（BT.601 有限范围）
 #[no_mangle]
 pub extern "C" fn hwcodec_native_device_nv12_to_bgra(
     handle: NativeDeviceHandle,
@@ -563,16 +627,61 @@ pub extern "C" fn hwcodec_native_device_nv12_to_bgra(
     width: u32,
     height: u32,
     nv12_array_index: u32,
+) -> c_int {
+    hwcodec_native_device_nv12_to_bgra_color(
+        handle,
+        nv12_texture,
+        bgra_texture,
+        width,
+        height,
+        nv12_array_index,
+        0,
+        0,
+        0,
+    )
+}
+
+/// 将 NV12 纹理转换为 BGRA，指定颜色空间
+///
+/// - `matrix`: 0 = BT.601，1 = BT.709，2 = BT.2020
+/// - `full_range`: 0 = 有限范围，非 0 = 全范围
+/// - `transfer`: 0 = BT.709，1 = sRGB，2 = PQ，3 = HLG
+#[no_mangle]
+pub extern "C" fn hwcodec_native_device_nv12_to_bgra_color(
+    handle: NativeDeviceHandle,
+    nv12_texture: *mut std::ffi::c_void,
+    bgra_texture: *mut std::ffi::c_void,
+    width: u32,
+    height: u32,
+    nv12_array_index: u32,
+    matrix: c_int,
+    full_range: c_int,
+    transfer: c_int,
 ) -> c_int {
     if handle.is_null() || nv12_texture.is_null() || bgra_texture.is_null() {
         return 0;
     }
+    let matrix = match matrix {
+        0 => Matrix::Bt601,
+        1 => Matrix::Bt709,
+        2 => Matrix::Bt2020,
+        _ => return 0,
+    };
+    let range = if full_range != 0 { Range::Full } else { Range::Limited };
+    let transfer = match transfer {
+        0 => Transfer::Bt709,
+        1 => Transfer::Srgb,
+        2 => Transfer::Pq,
+        3 => Transfer::Hlg,
+        _ => return 0,
+    };
+    let color = ColorSpace::new(matrix, range).with_transfer(transfer);
 
     unsafe {
         let nv12 = Interface::from_raw(nv12_texture as *mut std::ffi::c_void);
         let bgra = Interface::from_raw(bgra_texture as *mut std::ffi::c_void);
 
-        let result = (*handle).nv12_to_bgra(&nv12, &bgra, width, height, nv12_array_index);
+        let result = (*handle).nv12_to_bgra(&nv12, &bgra, width, height, nv12_array_index, color);
 
         std::mem::forget(nv12);
         std::mem::forget(bgra);
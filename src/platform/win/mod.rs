@@ -19,8 +19,9 @@ pub mod ffi;
 
 // 重新导出主要类型
 pub use adapter::{Adapter, Adapters};
-pub use device::NativeDevice;
+pub use device::{Device, NativeDevice};
 pub use error::WinPlatformError;
+pub use texture::{Texture, TextureRef};
 
 // 重新导出工具函数用于测试
 pub use utils::{get_gpu_signature, add_process_to_new_job};
@@ -46,6 +47,76 @@ mod tests {
         }
     }
     
+    /// 测试 Texture/Device 包装的引用计数与格式检查（需要 GPU）
+    #[test]
+    #[ignore] // 需要 GPU，默认忽略
+    fn test_texture_wrapper() {
+        use windows::Win32::Graphics::Direct3D11::*;
+        use windows::Win32::Graphics::Dxgi::Common::*;
+
+        let native = NativeDevice::new(0, None, 1).or_else(|_| {
+            let adapters = Adapters::new(crate::common::AdapterVendor::ADAPTER_VENDOR_NVIDIA)?;
+            let luid = adapters.adapters().first().map(|a| a.luid()).unwrap_or(0);
+            NativeDevice::new(luid, None, 1)
+        });
+        let Ok(mut native) = native else {
+            println!("No adapter available");
+            return;
+        };
+        native.ensure_texture(64, 32).unwrap();
+        let raw = native.get_current_texture().unwrap().clone();
+
+        let texture = unsafe { Texture::from_raw(windows::core::Interface::as_raw(&raw)) }.unwrap();
+        assert_eq!((texture.width(), texture.height()), (64, 32));
+        assert!(texture.check(DXGI_FORMAT_B8G8R8A8_UNORM, 64, 32).is_ok());
+        assert!(texture.check(DXGI_FORMAT_NV12, 64, 32).is_err());
+        assert!(texture.check(DXGI_FORMAT_B8G8R8A8_UNORM, 32, 32).is_err());
+
+        let device = texture.device().unwrap();
+        assert_eq!(device.inner(), native.device());
+        assert!(unsafe { Texture::from_raw(std::ptr::null_mut()) }.is_none());
+        let _: &ID3D11Texture2D = texture.inner();
+    }
+
+    /// 测试 NativeDevice 在同一 LUID 上重建（需要 GPU）
+    #[test]
+    #[ignore] // 需要 GPU，默认忽略
+    fn test_native_device_recreate() {
+        let adapters = Adapters::new(crate::common::AdapterVendor::ADAPTER_VENDOR_NVIDIA)
+            .or_else(|_| Adapters::new(crate::common::AdapterVendor::ADAPTER_VENDOR_AMD))
+            .or_else(|_| Adapters::new(crate::common::AdapterVendor::ADAPTER_VENDOR_INTEL));
+        let Some(luid) = adapters.ok().and_then(|a| a.adapters().first().map(|a| a.luid())) else {
+            println!("No adapter available");
+            return;
+        };
+        let mut native = NativeDevice::new(luid, None, 2).unwrap();
+        native.ensure_texture(64, 32).unwrap();
+        assert!(native.check_device().is_ok());
+        let old = native.device().clone();
+
+        native.recreate().unwrap();
+        assert_eq!(native.generation(), 1);
+        assert_eq!(native.luid(), luid);
+        assert_ne!(native.device(), &old);
+        let texture = Texture::from(native.get_current_texture().unwrap().clone());
+        assert_eq!((texture.width(), texture.height()), (64, 32));
+        assert!(!Device::from(native.device().clone()).is_lost());
+    }
+
+    /// 测试设备丢失错误的识别
+    #[test]
+    fn test_device_lost_error() {
+        use windows::Win32::Graphics::Dxgi::*;
+
+        assert!(error::is_device_lost_hresult(DXGI_ERROR_DEVICE_REMOVED));
+        assert!(error::is_device_lost_hresult(DXGI_ERROR_DEVICE_HUNG));
+        assert!(!error::is_device_lost_hresult(DXGI_ERROR_INVALID_CALL));
+        assert!(WinPlatformError::DeviceLost(DXGI_ERROR_DEVICE_RESET).is_device_lost());
+        let e = windows::core::Error::from(DXGI_ERROR_DEVICE_REMOVED);
+        assert!(WinPlatformError::from(e).is_device_lost());
+        assert!(!WinPlatformError::AdapterNotFound.is_device_lost());
+    }
+
     /// 测试 GPU 签名计算
     #[test]
     fn test_gpu_signature() {
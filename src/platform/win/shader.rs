@@ -2,13 +2,15 @@
 //! 
 //! 提供 NV12 到 BGRA 转换所需的顶点和像素着色器
 
+use crate::convert::YuvToRgbConstants;
 use crate::platform::win::error::Result;
 use windows::Win32::Graphics::Direct3D11::*;
 use windows::Win32::Graphics::Dxgi::Common::*;
 
-// 着色器字节码（从 C++ 头文件提取）
+// 着色器字节码（源码见 shaders/vertex_shader.hlsl）
 const VERTEX_SHADER_BYTECODE: &[u8] = include_bytes!("../../../shaders/vertex_shader.bin");
-const PIXEL_SHADER_BYTECODE: &[u8] = include_bytes!("../../../shaders/pixel_shader_601.bin");
+// 颜色系数由常量缓冲区传入（源码见 shaders/nv12_to_bgra.hlsl）
+const PIXEL_SHADER_BYTECODE: &[u8] = include_bytes!("../../../shaders/nv12_to_bgra.bin");
 
 /// 创建顶点着色器
 pub fn create_vertex_shader(
@@ -77,3 +79,30 @@ pub fn create_input_layout(
         Ok(layout.unwrap())
     }
 }
+
+/// 创建颜色转换常量缓冲区（对应 nv12_to_bgra.hlsl 的 `ColorConversion`）
+pub fn create_color_buffer(
+    device: &ID3D11Device,
+    constants: &YuvToRgbConstants,
+) -> Result<ID3D11Buffer> {
+    let bytes = constants.as_bytes();
+    let desc = D3D11_BUFFER_DESC {
+        ByteWidth: bytes.len() as u32,
+        Usage: D3D11_USAGE_IMMUTABLE,
+        BindFlags: D3D11_BIND_CONSTANT_BUFFER.0 as u32,
+        CPUAccessFlags: Default::default(),
+        MiscFlags: Default::default(),
+        StructureByteStride: 0,
+    };
+    let data = D3D11_SUBRESOURCE_DATA {
+        pSysMem: bytes.as_ptr() as *const _,
+        SysMemPitch: 0,
+        SysMemSlicePitch: 0,
+    };
+
+    unsafe {
+        let mut buffer = None;
+        device.CreateBuffer(&desc, Some(&data), Some(&mut buffer))?;
+        Ok(buffer.unwrap())
+    }
+}
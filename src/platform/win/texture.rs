@@ -1,7 +1,20 @@
 //! 纹理管理工具函数
 
-use crate::platform::win::error::Result;
+use crate::common::BitDepth;
+use crate::convert::{ColorSpace, Image, Matrix, PixelFormat};
+use crate::frame::{Frame, Subsampling};
+use crate::platform::win::device::Device;
+use crate::platform::win::error::{Result, WinPlatformError};
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use windows::core::Interface;
 use windows::Win32::Graphics::Direct3D11::*;
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT, DXGI_FORMAT_AYUV, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12, DXGI_FORMAT_P010,
+    DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC,
+};
 
 /// 获取 D3D11 纹理的宽度和高度
 pub fn get_texture_width_height(texture: &ID3D11Texture2D) -> Result<(u32, u32)> {
@@ -11,3 +24,267 @@ pub fn get_texture_width_height(texture: &ID3D11Texture2D) -> Result<(u32, u32)>
         Ok((desc.Width, desc.Height))
     }
 }
+
+/// 持有 COM 引用的 D3D11 纹理
+///
+/// 克隆会 AddRef，释放会 Release，因此生命周期与普通 Rust 值一致。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Texture {
+    inner: ID3D11Texture2D,
+}
+
+impl Texture {
+    /// 从裸指针创建纹理，会增加一次引用计数；指针为空时返回 `None`
+    ///
+    /// # Safety
+    /// `ptr` 必须为空或指向有效的 `ID3D11Texture2D`。
+    pub unsafe fn from_raw(ptr: *mut c_void) -> Option<Self> {
+        ID3D11Texture2D::from_raw_borrowed(&ptr).map(|t| Self { inner: t.clone() })
+    }
+
+    /// 获取裸指针（不转移所有权），用于原始指针接口
+    pub fn as_raw(&self) -> *mut c_void {
+        self.inner.as_raw()
+    }
+
+    /// 获取内部 COM 接口
+    pub fn inner(&self) -> &ID3D11Texture2D {
+        &self.inner
+    }
+
+    /// 获取纹理描述
+    pub fn desc(&self) -> D3D11_TEXTURE2D_DESC {
+        unsafe {
+            let mut desc = std::mem::zeroed();
+            self.inner.GetDesc(&mut desc);
+            desc
+        }
+    }
+
+    /// 获取宽度
+    pub fn width(&self) -> u32 {
+        self.desc().Width
+    }
+
+    /// 获取高度
+    pub fn height(&self) -> u32 {
+        self.desc().Height
+    }
+
+    /// 获取像素格式
+    pub fn format(&self) -> DXGI_FORMAT {
+        self.desc().Format
+    }
+
+    /// 获取创建该纹理的设备
+    pub fn device(&self) -> Result<Device> {
+        let device = unsafe { self.inner.GetDevice()? };
+        Ok(Device::from(device))
+    }
+
+    /// 检查纹理格式与尺寸是否符合预期
+    pub fn check(&self, format: DXGI_FORMAT, width: u32, height: u32) -> Result<()> {
+        let desc = self.desc();
+        if desc.Format != format {
+            return Err(WinPlatformError::TextureMismatch(format!(
+                "format {:?}, expected {:?}",
+                desc.Format, format
+            )));
+        }
+        if desc.Width != width || desc.Height != height {
+            return Err(WinPlatformError::TextureMismatch(format!(
+                "size {}x{}, expected {}x{}",
+                desc.Width, desc.Height, width, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// 把 `frame` 上传为 `format` 格式的新纹理
+    ///
+    /// 支持 BGRA、RGBA、NV12、P010，以及 8 bit 4:4:4 帧的 AYUV；4:2:2 与 4:4:4 帧转其他格式时
+    /// 色度先平均到 4:2:0。转为 RGB 时使用 `matrix` 与帧的范围。
+    pub fn upload(
+        device: &Device,
+        frame: &Frame,
+        format: DXGI_FORMAT,
+        matrix: Matrix,
+    ) -> Result<Self> {
+        let (data, pitch) = if format == DXGI_FORMAT_AYUV {
+            (ayuv(frame)?, frame.width * 4)
+        } else {
+            let pixel_format = pixel_format(format)?;
+            let data = frame
+                .to_packed(pixel_format, matrix)
+                .map_err(|e| WinPlatformError::InvalidParameter(e.to_string()))?;
+            (data, pixel_format.row_bytes(0, frame.width))
+        };
+
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: frame.width as u32,
+            Height: frame.height as u32,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_SHADER_RESOURCE.0 | D3D11_BIND_RENDER_TARGET.0) as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+        // NV12/P010 的色度平面紧跟在亮度平面之后，行距相同
+        let initial = D3D11_SUBRESOURCE_DATA {
+            pSysMem: data.as_ptr() as *const c_void,
+            SysMemPitch: pitch as u32,
+            SysMemSlicePitch: 0,
+        };
+        let texture = unsafe {
+            let mut texture = None;
+            device
+                .inner()
+                .CreateTexture2D(&desc, Some(&initial), Some(&mut texture))?;
+            texture.unwrap()
+        };
+        Ok(Self { inner: texture })
+    }
+
+    /// 读回左上角 `width` x `height` 的区域，得到 4:2:0 帧：P010 为 10 bit，其余为 8 bit
+    ///
+    /// 支持 NV12、P010、BGRA 与 RGBA。YUV 纹理原样读出并标记为 `color.range`，RGB 纹理按
+    /// `color` 转换。
+    pub fn read_frame(&self, width: u32, height: u32, color: ColorSpace) -> Result<Frame> {
+        let desc = self.desc();
+        if width > desc.Width || height > desc.Height {
+            return Err(WinPlatformError::InvalidParameter(format!(
+                "cannot read {}x{} from a {}x{} texture",
+                width, height, desc.Width, desc.Height
+            )));
+        }
+        let pixel_format = pixel_format(desc.Format)?;
+
+        let staging_desc = D3D11_TEXTURE2D_DESC {
+            Width: desc.Width,
+            Height: desc.Height,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: desc.Format,
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_STAGING,
+            BindFlags: 0,
+            CPUAccessFlags: D3D11_CPU_ACCESS_READ.0 as u32,
+            MiscFlags: 0,
+        };
+        let device = self.device()?;
+        let context = unsafe { device.inner().GetImmediateContext()? };
+        let staging = unsafe {
+            let mut texture = None;
+            device
+                .inner()
+                .CreateTexture2D(&staging_desc, None, Some(&mut texture))?;
+            texture.unwrap()
+        };
+
+        // 纹理数组只读第 0 个切片
+        let (width, height) = (width as usize, height as usize);
+        let mut data = vec![0u8; pixel_format.frame_size(width, height)];
+        unsafe {
+            context.CopySubresourceRegion(&staging, 0, 0, 0, 0, &self.inner, 0, None);
+            let mut mapped = std::mem::zeroed();
+            context.Map(&staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+            let pitch = mapped.RowPitch as usize;
+            let mut offset = 0;
+            for plane in 0..pixel_format.plane_count() {
+                // 色度平面从纹理全高之后开始
+                let base = plane * pitch * desc.Height as usize;
+                let bytes = pixel_format.row_bytes(plane, width);
+                for row in 0..pixel_format.plane_rows(plane, height) {
+                    let src = (mapped.pData as *const u8).add(base + row * pitch);
+                    let src = std::slice::from_raw_parts(src, bytes);
+                    data[offset..offset + bytes].copy_from_slice(src);
+                    offset += bytes;
+                }
+            }
+            context.Unmap(&staging, 0);
+        }
+
+        Image::packed(pixel_format, width, height, &data)
+            .and_then(|image| Frame::from_image(&image, color))
+            .map_err(|e| WinPlatformError::InvalidParameter(e.to_string()))
+    }
+}
+
+// 纹理格式对应的 CPU 布局
+fn pixel_format(format: DXGI_FORMAT) -> Result<PixelFormat> {
+    match format {
+        DXGI_FORMAT_B8G8R8A8_UNORM => Ok(PixelFormat::Bgra),
+        DXGI_FORMAT_R8G8B8A8_UNORM => Ok(PixelFormat::Rgba),
+        DXGI_FORMAT_NV12 => Ok(PixelFormat::Nv12),
+        DXGI_FORMAT_P010 => Ok(PixelFormat::P010),
+        _ => Err(WinPlatformError::UnsupportedFormat),
+    }
+}
+
+// AYUV 每像素依次为 V、U、Y、A
+fn ayuv(frame: &Frame) -> Result<Vec<u8>> {
+    if frame.subsampling != Subsampling::Yuv444 || frame.bit_depth != BitDepth::Eight {
+        return Err(WinPlatformError::UnsupportedFormat);
+    }
+    let (y, u, v) = (frame.plane(0), frame.plane(1), frame.plane(2));
+    let mut data = Vec::with_capacity(y.len() * 4);
+    for i in 0..y.len() {
+        data.extend([v[i], u[i], y[i], 255]);
+    }
+    Ok(data)
+}
+
+impl From<ID3D11Texture2D> for Texture {
+    fn from(inner: ID3D11Texture2D) -> Self {
+        Self { inner }
+    }
+}
+
+/// 借用的纹理，不持有引用计数，生命周期受 `'a` 约束
+///
+/// 用于解码输出：解码器内部的纹理在下一次 `decode` 时可能被复用，
+/// 因此只能在借用期内访问。需要长期持有时使用 `to_owned()`。
+pub struct TextureRef<'a> {
+    inner: ManuallyDrop<Texture>,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> TextureRef<'a> {
+    /// 从裸指针借用纹理，不增加引用计数；指针为空时返回 `None`
+    ///
+    /// # Safety
+    /// `ptr` 必须为空或指向在 `'a` 期间保持有效的 `ID3D11Texture2D`。
+    pub unsafe fn from_raw(ptr: *mut c_void) -> Option<Self> {
+        if ptr.is_null() {
+            return None;
+        }
+        Some(Self {
+            inner: ManuallyDrop::new(Texture {
+                inner: ID3D11Texture2D::from_raw(ptr),
+            }),
+            _marker: PhantomData,
+        })
+    }
+
+    /// 增加引用计数，得到独立持有的纹理
+    pub fn to_owned(&self) -> Texture {
+        (*self.inner).clone()
+    }
+}
+
+impl Deref for TextureRef<'_> {
+    type Target = Texture;
+
+    fn deref(&self) -> &Texture {
+        &self.inner
+    }
+}
@@ -0,0 +1,649 @@
+//! Objective quality metrics between two CPU frames: PSNR, SSIM and MS-SSIM per plane.
+//!
+//! ```ignore
+//! let scores = quality::compare(&source, &decoded)?;
+//! println!("Y PSNR {:.2} dB, SSIM {:.4}", scores.psnr.y, scores.ssim.y);
+//! ```
+//!
+//! Frames are compared plane by plane at their own bit depth. SSIM uses 8x8 windows on a 4
+//! sample grid with the usual constants `(0.01 L)²` and `(0.03 L)²`, `L` the largest sample
+//! value; MS-SSIM runs it on five 2x2-averaged scales with the weights of Wang et al. The
+//! sums are integer and identical on every platform: the SSE2/AVX2/NEON kernels produce the
+//! same values as the scalar reference.
+
+#[cfg(target_arch = "aarch64")]
+mod neon;
+mod scalar;
+#[cfg(target_arch = "x86_64")]
+mod x86;
+
+use crate::common::BitDepth;
+use crate::convert::{ColorSpace, ConvertError, Image, PixelFormat};
+use crate::frame::Frame;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Weights of the MS-SSIM scales, finest first (Wang, Simoncelli and Bovik 2003).
+pub const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+// SSIM 窗口为 8x8，由 4x4 块的和拼成，窗口步长 4
+const WINDOW: usize = 8;
+const BLOCK: usize = 4;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum QualityError {
+    #[error("frames do not match: {0}")]
+    FrameMismatch(String),
+
+    #[error("a {0}x{1} plane is smaller than one SSIM window")]
+    TooSmall(usize, usize),
+
+    #[error("{0:?} is not a YUV format")]
+    NotYuv(PixelFormat),
+
+    #[error(transparent)]
+    Convert(#[from] ConvertError),
+}
+
+/// One score per plane, and `all` over the whole frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlaneScores {
+    pub y: f64,
+    pub u: f64,
+    pub v: f64,
+    /// PSNR of the mean squared error of all samples; for SSIM and MS-SSIM the plane scores
+    /// weighted by their sample counts.
+    pub all: f64,
+}
+
+impl PlaneScores {
+    fn weighted(scores: [f64; 3], counts: [usize; 3]) -> Self {
+        let total: usize = counts.iter().sum();
+        let sum: f64 = scores.iter().zip(counts).map(|(s, n)| s * n as f64).sum();
+        Self {
+            y: scores[0],
+            u: scores[1],
+            v: scores[2],
+            all: sum / total as f64,
+        }
+    }
+
+    fn map2(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        Self {
+            y: f(self.y, other.y),
+            u: f(self.u, other.u),
+            v: f(self.v, other.v),
+            all: f(self.all, other.all),
+        }
+    }
+}
+
+/// All three metrics of one frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QualityScores {
+    /// Decibels; `f64::INFINITY` for identical planes.
+    pub psnr: PlaneScores,
+    pub ssim: PlaneScores,
+    pub ms_ssim: PlaneScores,
+}
+
+impl QualityScores {
+    /// Arithmetic mean of per-frame scores, `None` without frames. A single identical frame
+    /// makes the PSNR mean infinite.
+    pub fn mean(scores: &[QualityScores]) -> Option<QualityScores> {
+        let n = scores.len() as f64;
+        let sum = scores.iter().copied().reduce(|a, b| QualityScores {
+            psnr: a.psnr.map2(b.psnr, |x, y| x + y),
+            ssim: a.ssim.map2(b.ssim, |x, y| x + y),
+            ms_ssim: a.ms_ssim.map2(b.ms_ssim, |x, y| x + y),
+        })?;
+        let div = |s: PlaneScores| s.map2(s, |x, _| x / n);
+        Some(QualityScores {
+            psnr: div(sum.psnr),
+            ssim: div(sum.ssim),
+            ms_ssim: div(sum.ms_ssim),
+        })
+    }
+}
+
+/// PSNR, SSIM and MS-SSIM of `b` against the reference `a`.
+pub fn compare(a: &Frame, b: &Frame) -> Result<QualityScores, QualityError> {
+    Ok(QualityScores {
+        psnr: psnr(a, b)?,
+        ssim: ssim(a, b)?,
+        ms_ssim: ms_ssim(a, b)?,
+    })
+}
+
+/// `compare` for NV12, I420 or P010 images, which may differ in layout but not in size.
+pub fn compare_images(a: &Image, b: &Image) -> Result<QualityScores, QualityError> {
+    let frame = |image: &Image| {
+        if image.format.is_rgb() {
+            return Err(QualityError::NotYuv(image.format));
+        }
+        // YUV 之间只搬运样本，色彩空间不参与计算
+        Ok(Frame::from_image(image, ColorSpace::default())?)
+    };
+    compare(&frame(a)?, &frame(b)?)
+}
+
+/// Peak signal-to-noise ratio per plane, in decibels.
+pub fn psnr(a: &Frame, b: &Frame) -> Result<PlaneScores, QualityError> {
+    check(a, b)?;
+    let kernels = Kernels::get();
+    let max = max_sample(a.bit_depth);
+    let db = |sse: u64, count: usize| {
+        if sse == 0 {
+            f64::INFINITY
+        } else {
+            10.0 * (max * max * count as f64 / sse as f64).log10()
+        }
+    };
+    let mut sse = [0u64; 3];
+    let mut counts = [0usize; 3];
+    for plane in 0..3 {
+        let (pa, pb) = (Samples::new(a, plane), Samples::new(b, plane));
+        sse[plane] = pa
+            .rows()
+            .zip(pb.rows())
+            .map(|(ra, rb)| (kernels.sse)(ra, rb))
+            .sum();
+        counts[plane] = pa.data.len();
+    }
+    Ok(PlaneScores {
+        y: db(sse[0], counts[0]),
+        u: db(sse[1], counts[1]),
+        v: db(sse[2], counts[2]),
+        all: db(sse.iter().sum(), counts.iter().sum()),
+    })
+}
+
+/// Mean structural similarity per plane, 1 for identical planes. Every plane needs at least
+/// 8x8 samples.
+pub fn ssim(a: &Frame, b: &Frame) -> Result<PlaneScores, QualityError> {
+    check(a, b)?;
+    let max = max_sample(a.bit_depth);
+    let mut scores = [0.0; 3];
+    let mut counts = [0; 3];
+    for plane in 0..3 {
+        let (pa, pb) = (Samples::new(a, plane), Samples::new(b, plane));
+        scores[plane] = ssim_plane(&pa, &pb, max)?.0;
+        counts[plane] = pa.data.len();
+    }
+    Ok(PlaneScores::weighted(scores, counts))
+}
+
+/// Multi-scale SSIM per plane, 1 for identical planes. Planes too small for five scales use
+/// the scales that hold an 8x8 window, with the weights of those scales renormalised.
+pub fn ms_ssim(a: &Frame, b: &Frame) -> Result<PlaneScores, QualityError> {
+    check(a, b)?;
+    let max = max_sample(a.bit_depth);
+    let mut scores = [0.0; 3];
+    let mut counts = [0; 3];
+    for plane in 0..3 {
+        let (pa, pb) = (Samples::new(a, plane), Samples::new(b, plane));
+        counts[plane] = pa.data.len();
+        scores[plane] = ms_ssim_plane(pa, pb, max)?;
+    }
+    Ok(PlaneScores::weighted(scores, counts))
+}
+
+fn check(a: &Frame, b: &Frame) -> Result<(), QualityError> {
+    let describe = |f: &Frame| {
+        format!(
+            "{}x{} {:?} {}-bit",
+            f.width,
+            f.height,
+            f.subsampling,
+            f.bit_depth.bits()
+        )
+    };
+    if (a.width, a.height, a.subsampling, a.bit_depth)
+        != (b.width, b.height, b.subsampling, b.bit_depth)
+    {
+        return Err(QualityError::FrameMismatch(format!(
+            "{} against {}",
+            describe(a),
+            describe(b)
+        )));
+    }
+    Ok(())
+}
+
+fn max_sample(depth: BitDepth) -> f64 {
+    ((1u32 << depth.bits()) - 1) as f64
+}
+
+/// One plane widened to 16-bit samples.
+struct Samples {
+    width: usize,
+    height: usize,
+    data: Vec<u16>,
+}
+
+impl Samples {
+    fn new(frame: &Frame, plane: usize) -> Self {
+        let (width, height) = frame.plane_size(plane);
+        let bytes = frame.plane(plane);
+        let data = match frame.bit_depth {
+            BitDepth::Eight => bytes.iter().map(|&s| s as u16).collect(),
+            BitDepth::Ten => bytes
+                .chunks_exact(2)
+                .map(|s| u16::from_le_bytes([s[0], s[1]]))
+                .collect(),
+        };
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn rows(&self) -> std::slice::ChunksExact<'_, u16> {
+        self.data.chunks_exact(self.width)
+    }
+
+    fn row(&self, y: usize) -> &[u16] {
+        &self.data[y * self.width..(y + 1) * self.width]
+    }
+
+    /// The four rows of the `y`th row of 4x4 blocks.
+    fn block_rows(&self, y: usize) -> [&[u16]; 4] {
+        std::array::from_fn(|i| self.row(y * BLOCK + i))
+    }
+
+    /// Half size, each sample the rounded mean of a 2x2 block; an odd last row or column is
+    /// dropped.
+    fn downsample(&self) -> Samples {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (r0, r1) = (self.row(2 * y), self.row(2 * y + 1));
+            data.extend((0..width).map(|x| {
+                let sum = r0[2 * x] as u32 + r0[2 * x + 1] as u32 + r1[2 * x] as u32;
+                ((sum + r1[2 * x + 1] as u32 + 2) >> 2) as u16
+            }));
+        }
+        Samples {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Mean SSIM and mean contrast-structure term over the windows of one plane.
+fn ssim_plane(a: &Samples, b: &Samples, max: f64) -> Result<(f64, f64), QualityError> {
+    if a.width < WINDOW || a.height < WINDOW {
+        return Err(QualityError::TooSmall(a.width, a.height));
+    }
+    let kernels = Kernels::get();
+    let (blocks_x, blocks_y) = (a.width / BLOCK, a.height / BLOCK);
+    let block_row = |y: usize, out: &mut Vec<[u32; 4]>| {
+        (kernels.block_stats)(a.block_rows(y), b.block_rows(y), out);
+    };
+    let c1 = (0.01 * max).powi(2);
+    let c2 = (0.03 * max).powi(2);
+    let mut above = vec![[0u32; 4]; blocks_x];
+    let mut below = vec![[0u32; 4]; blocks_x];
+    block_row(0, &mut above);
+    let (mut ssim, mut cs) = (0.0, 0.0);
+    for y in 1..blocks_y {
+        block_row(y, &mut below);
+        for x in 1..blocks_x {
+            let mut s = [0.0; 4];
+            for block in [above[x - 1], above[x], below[x - 1], below[x]] {
+                for (s, v) in s.iter_mut().zip(block) {
+                    *s += v as f64;
+                }
+            }
+            let (l, c) = window(s, c1, c2);
+            ssim += l * c;
+            cs += c;
+        }
+        std::mem::swap(&mut above, &mut below);
+    }
+    let windows = ((blocks_x - 1) * (blocks_y - 1)) as f64;
+    Ok((ssim / windows, cs / windows))
+}
+
+// 由窗口内 64 个样本的 [Σa, Σb, Σa²+Σb², Σab] 求亮度项与对比度-结构项，方差取无偏估计
+fn window(s: [f64; 4], c1: f64, c2: f64) -> (f64, f64) {
+    let n = (WINDOW * WINDOW) as f64;
+    let (mean_a, mean_b) = (s[0] / n, s[1] / n);
+    let variances = (s[2] - (s[0] * s[0] + s[1] * s[1]) / n) / (n - 1.0);
+    let covariance = (s[3] - s[0] * s[1] / n) / (n - 1.0);
+    let luminance = (2.0 * mean_a * mean_b + c1) / (mean_a * mean_a + mean_b * mean_b + c1);
+    let contrast_structure = (2.0 * covariance + c2) / (variances + c2);
+    (luminance, contrast_structure)
+}
+
+fn ms_ssim_plane(mut a: Samples, mut b: Samples, max: f64) -> Result<f64, QualityError> {
+    // 能容纳一个窗口的尺度数，最多 5 个
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len()
+        && a.width >> scales >= WINDOW
+        && a.height >> scales >= WINDOW
+    {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    // 五个尺度时按原权重（其和为 1.0001），与参考实现一致
+    let total: f64 = if scales == MS_SSIM_WEIGHTS.len() {
+        1.0
+    } else {
+        weights.iter().sum()
+    };
+    let mut score = 1.0;
+    for (i, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_plane(&a, &b, max)?;
+        // 最粗尺度取完整 SSIM，其余只取对比度-结构项；负值截为 0
+        let term = if i + 1 == scales { ssim } else { cs };
+        score *= term.max(0.0).powf(weight / total);
+        if i + 1 < scales {
+            a = a.downsample();
+            b = b.downsample();
+        }
+    }
+    Ok(score)
+}
+
+type SseKernel = fn(&[u16], &[u16]) -> u64;
+type BlockKernel = fn([&[u16]; 4], [&[u16]; 4], &mut [[u32; 4]]);
+
+/// One set of row kernels, picked once per process by CPU features.
+#[derive(Clone, Copy)]
+struct Kernels {
+    name: &'static str,
+    sse: SseKernel,
+    block_stats: BlockKernel,
+}
+
+impl Kernels {
+    const SCALAR: Kernels = Kernels {
+        name: "scalar",
+        sse: scalar::sse,
+        block_stats: scalar::block_stats,
+    };
+
+    /// Kernel sets this CPU can run, the fastest last.
+    fn available() -> Vec<Kernels> {
+        #[allow(unused_mut)]
+        let mut kernels = vec![Self::SCALAR];
+        #[cfg(target_arch = "x86_64")]
+        {
+            kernels.push(Kernels {
+                name: "sse2",
+                sse: x86::sse_sse2,
+                block_stats: x86::block_stats_sse2,
+            });
+            if is_x86_feature_detected!("avx2") {
+                kernels.push(Kernels {
+                    name: "avx2",
+                    sse: x86::sse_avx2,
+                    block_stats: x86::block_stats_avx2,
+                });
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        kernels.push(Kernels {
+            name: "neon",
+            sse: neon::sse,
+            block_stats: neon::block_stats,
+        });
+        kernels
+    }
+
+    fn get() -> &'static Kernels {
+        static KERNELS: OnceLock<Kernels> = OnceLock::new();
+        KERNELS.get_or_init(|| {
+            let kernels = *Self::available().last().unwrap();
+            log::debug!("quality metrics use {} kernels", kernels.name);
+            kernels
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{ImageMut, Range};
+    use crate::frame::Subsampling;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn frame(width: usize, height: usize, depth: BitDepth) -> Frame {
+        Frame::new(width, height, Subsampling::Yuv420, depth, Range::Full)
+    }
+
+    fn fill(frame: &mut Frame, plane: usize, mut f: impl FnMut(usize, usize) -> u16) {
+        let (width, height) = frame.plane_size(plane);
+        for y in 0..height {
+            for x in 0..width {
+                frame.set_sample(plane, x, y, f(x, y));
+            }
+        }
+    }
+
+    fn rows(r: &[Vec<u16>; 4]) -> [&[u16]; 4] {
+        std::array::from_fn(|i| r[i].as_slice())
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    /// 测试各 SIMD 内核与标量实现完全一致
+    #[test]
+    fn test_kernels_exact() {
+        let mut rng = StdRng::seed_from_u64(49);
+        let scalar = Kernels::SCALAR;
+        for k in Kernels::available().iter().skip(1) {
+            for max in [255u16, 1023] {
+                for width in [4, 8, 12, 16, 20, 28, 32, 36, 60, 64, 68, 132] {
+                    let mut row =
+                        || -> Vec<u16> { (0..width).map(|_| rng.gen_range(0..=max)).collect() };
+                    let a: [Vec<u16>; 4] = std::array::from_fn(|_| row());
+                    let b: [Vec<u16>; 4] = std::array::from_fn(|_| row());
+                    assert_eq!(
+                        (k.sse)(&a[0], &b[0]),
+                        (scalar.sse)(&a[0], &b[0]),
+                        "{} sse {} {}",
+                        k.name,
+                        max,
+                        width
+                    );
+
+                    let mut expected = vec![[0u32; 4]; width / 4];
+                    let mut actual = expected.clone();
+                    (scalar.block_stats)(rows(&a), rows(&b), &mut expected);
+                    (k.block_stats)(rows(&a), rows(&b), &mut actual);
+                    assert_eq!(actual, expected, "{} block_stats {} {}", k.name, max, width);
+                }
+                // 极值不溢出
+                let a = vec![max; 64];
+                let b = vec![0u16; 64];
+                assert_eq!((k.sse)(&a, &b), (scalar.sse)(&a, &b), "{}", k.name);
+                let mut expected = vec![[0u32; 4]; 16];
+                let mut actual = expected.clone();
+                (scalar.block_stats)([&a; 4], [&a; 4], &mut expected);
+                (k.block_stats)([&a; 4], [&a; 4], &mut actual);
+                assert_eq!(actual, expected, "{}", k.name);
+            }
+        }
+    }
+
+    /// 测试已知误差下的 PSNR
+    #[test]
+    fn test_psnr() {
+        let a = frame(64, 48, BitDepth::Eight);
+        let scores = psnr(&a, &a).unwrap();
+        assert!(scores.y.is_infinite() && scores.all.is_infinite());
+
+        // 所有样本相差 1：20·log10(255)
+        let mut b = a.clone();
+        for plane in 0..3 {
+            fill(&mut b, plane, |x, y| a.sample(plane, x, y) + 1);
+        }
+        let scores = psnr(&a, &b).unwrap();
+        for s in [scores.y, scores.u, scores.v, scores.all] {
+            assert_close(s, 48.1308036086791);
+        }
+
+        // 亮度一半样本相差 2：MSE 为 2，色度不变
+        let mut b = a.clone();
+        fill(&mut b, 0, |x, y| a.sample(0, x, y) + 2 * (x % 2) as u16);
+        let scores = psnr(&a, &b).unwrap();
+        assert_close(scores.y, 45.12050365203929);
+        assert!(scores.u.is_infinite() && scores.v.is_infinite());
+        // 全帧 MSE 为 2·(64·48) / (64·48·1.5)
+        assert_close(scores.all, 10.0 * (255.0f64 * 255.0 * 1.5 / 2.0).log10());
+
+        // 10 bit：20·log10(1023)
+        let a = frame(32, 32, BitDepth::Ten);
+        let mut b = a.clone();
+        fill(&mut b, 0, |x, y| a.sample(0, x, y) + 1);
+        assert_close(psnr(&a, &b).unwrap().y, 60.1975126742432);
+    }
+
+    /// 测试 SSIM 的已知值：纯色、亮度偏移与对比度丢失
+    #[test]
+    fn test_ssim() {
+        let mut a = frame(64, 64, BitDepth::Eight);
+        fill(&mut a, 0, |x, y| ((x * 7 + y * 13) % 200) as u16);
+        let scores = ssim(&a, &a).unwrap();
+        for s in [scores.y, scores.u, scores.v, scores.all] {
+            assert_close(s, 1.0);
+        }
+
+        // 纯色 100 对 110：只剩亮度项 (2·100·110 + C1) / (100² + 110² + C1)
+        let luminance = 0.9954764440915066;
+        fill(&mut a, 0, |_, _| 100);
+        let mut b = a.clone();
+        fill(&mut b, 0, |_, _| 110);
+        let scores = ssim(&a, &b).unwrap();
+        assert_close(scores.y, luminance);
+        assert_close(scores.u, 1.0);
+        assert_close(scores.all, (4.0 * luminance + 2.0) / 6.0);
+
+        // 棋盘格整体偏移 10：方差与协方差不变，每个窗口均值为 100 与 110
+        fill(&mut a, 0, |x, y| if (x + y) % 2 == 0 { 90 } else { 110 });
+        fill(&mut b, 0, |x, y| a.sample(0, x, y) + 10);
+        assert_close(ssim(&a, &b).unwrap().y, luminance);
+
+        // 棋盘格对纯色：均值相同，只剩 C2 / (σ² + C2)，σ² = 6400 / 63
+        let contrast = 0.36551478685138444;
+        fill(&mut b, 0, |_, _| 100);
+        assert_close(ssim(&a, &b).unwrap().y, contrast);
+        // 对称
+        assert_close(ssim(&b, &a).unwrap().y, contrast);
+
+        let a = frame(32, 32, BitDepth::Ten);
+        let mut b = a.clone();
+        fill(&mut b, 1, |_, _| 500);
+        let expected = (2.0 * 512.0 * 500.0 + (0.01f64 * 1023.0).powi(2))
+            / (512.0f64.powi(2) + 500.0f64.powi(2) + (0.01f64 * 1023.0).powi(2));
+        assert_close(ssim(&a, &b).unwrap().u, expected);
+    }
+
+    /// 测试 MS-SSIM：各尺度的权重与下采样
+    #[test]
+    fn test_ms_ssim() {
+        let mut a = frame(256, 256, BitDepth::Eight);
+        fill(&mut a, 0, |x, y| ((x * 3 + y * 5) % 256) as u16);
+        let scores = ms_ssim(&a, &a).unwrap();
+        assert_close(scores.y, 1.0);
+        assert_close(scores.all, 1.0);
+
+        // 棋盘格对纯色：下采样后棋盘格变为纯色 100，只有最细尺度的对比度项小于 1
+        fill(&mut a, 0, |x, y| if (x + y) % 2 == 0 { 90 } else { 110 });
+        let mut b = a.clone();
+        fill(&mut b, 0, |_, _| 100);
+        assert_close(ms_ssim(&a, &b).unwrap().y, 0.9559125024281886);
+
+        // 纯色 100 对 110：对比度项均为 1，只剩最粗尺度的亮度项
+        fill(&mut a, 0, |_, _| 100);
+        fill(&mut b, 0, |_, _| 110);
+        assert_close(ms_ssim(&a, &b).unwrap().y, 0.9993958246284796);
+
+        // 尺度不足时权重重新归一：32x32 亮度有 3 个尺度，16x16 色度有 2 个
+        let mut a = frame(32, 32, BitDepth::Eight);
+        let mut b = a.clone();
+        fill(&mut a, 0, |_, _| 100);
+        fill(&mut b, 0, |_, _| 110);
+        fill(&mut b, 1, |_, _| 118);
+        let scores = ms_ssim(&a, &b).unwrap();
+        assert_close(scores.y, 0.9954764440915066f64.powf(0.3001 / 0.6305));
+        let chroma =
+            (2.0 * 128.0 * 118.0 + 6.5025) / (128.0f64.powi(2) + 118.0f64.powi(2) + 6.5025);
+        assert_close(scores.u, chroma.powf(0.2856 / 0.3304));
+
+        // 噪声越强得分越低
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut a = frame(128, 128, BitDepth::Eight);
+        fill(&mut a, 0, |x, y| (x + y) as u16);
+        let noisy = |rng: &mut StdRng, amount: i32| {
+            let mut b = a.clone();
+            fill(&mut b, 0, |x, y| {
+                (a.sample(0, x, y) as i32 + rng.gen_range(-amount..=amount)).clamp(0, 255) as u16
+            });
+            b
+        };
+        let weak = noisy(&mut rng, 4);
+        let strong = noisy(&mut rng, 32);
+        let (weak, strong) = (compare(&a, &weak).unwrap(), compare(&a, &strong).unwrap());
+        assert!(weak.psnr.y > strong.psnr.y);
+        assert!(weak.ssim.y > strong.ssim.y);
+        assert!(weak.ms_ssim.y > strong.ms_ssim.y && strong.ms_ssim.y > 0.0);
+    }
+
+    /// 测试 NV12 与 I420 图像直接比较，以及错误
+    #[test]
+    fn test_compare_images() {
+        let (width, height) = (32, 16);
+        let mut i420 = frame(width, height, BitDepth::Eight);
+        fill(&mut i420, 0, |x, y| (x * 8 + y) as u16);
+        fill(&mut i420, 1, |x, _| 100 + x as u16);
+        let mut nv12 = vec![0; PixelFormat::Nv12.frame_size(width, height)];
+        let src = Image::packed(PixelFormat::I420, width, height, &i420.data).unwrap();
+        let mut dst = ImageMut::packed(PixelFormat::Nv12, width, height, &mut nv12).unwrap();
+        crate::convert::convert(&src, &mut dst, ColorSpace::default()).unwrap();
+        let nv12 = Image::packed(PixelFormat::Nv12, width, height, &nv12).unwrap();
+        let scores = compare_images(&src, &nv12).unwrap();
+        assert!(scores.psnr.all.is_infinite());
+        assert_close(scores.ssim.all, 1.0);
+        assert_close(scores.ms_ssim.all, 1.0);
+
+        let bgra = vec![0; PixelFormat::Bgra.frame_size(width, height)];
+        let bgra = Image::packed(PixelFormat::Bgra, width, height, &bgra).unwrap();
+        assert_eq!(
+            compare_images(&src, &bgra),
+            Err(QualityError::NotYuv(PixelFormat::Bgra))
+        );
+
+        let other = frame(width, height + 2, BitDepth::Eight);
+        assert!(matches!(
+            compare(&i420, &other),
+            Err(QualityError::FrameMismatch(_))
+        ));
+        let ten = frame(width, height, BitDepth::Ten);
+        assert!(matches!(
+            psnr(&i420, &ten),
+            Err(QualityError::FrameMismatch(_))
+        ));
+        // 16x8 亮度放不下 4:2:0 色度的 8x8 窗口，PSNR 不受限制
+        let tiny = frame(16, 8, BitDepth::Eight);
+        assert_eq!(ssim(&tiny, &tiny), Err(QualityError::TooSmall(8, 4)));
+        assert!(psnr(&tiny, &tiny).is_ok());
+
+        let scores =
+            QualityScores::mean(&[compare(&i420, &i420).unwrap(), QualityScores::default()])
+                .unwrap();
+        assert_close(scores.ssim.y, 0.5);
+        assert_eq!(QualityScores::mean(&[]), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! NEON kernels (aarch64, where NEON is always present). They cover whole blocks of 8 samples
+//! and leave the rest of the row to `scalar`.
+
+use std::arch::aarch64::*;
+
+use super::scalar;
+
+pub(crate) fn sse(a: &[u16], b: &[u16]) -> u64 {
+    let n = a.len().min(b.len()) & !7;
+    let head = unsafe { sse_blocks(&a[..n], &b[..n]) };
+    head + scalar::sse(&a[n..], &b[n..])
+}
+
+pub(crate) fn block_stats(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    let n = out.len() & !1;
+    let (head, tail) = out.split_at_mut(n);
+    unsafe { block_stats_blocks(a, b, head) };
+    scalar::block_stats(a.map(|r| &r[n * 4..]), b.map(|r| &r[n * 4..]), tail);
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn sse_blocks(a: &[u16], b: &[u16]) -> u64 {
+    let mut acc = vdupq_n_u64(0);
+    for (a, b) in a.chunks_exact(8).zip(b.chunks_exact(8)) {
+        let d = vabdq_u16(vld1q_u16(a.as_ptr()), vld1q_u16(b.as_ptr()));
+        let sq = vmull_u16(vget_low_u16(d), vget_low_u16(d));
+        let sq = vmlal_u16(sq, vget_high_u16(d), vget_high_u16(d));
+        acc = vpadalq_u32(acc, sq);
+    }
+    vaddvq_u64(acc)
+}
+
+#[target_feature(enable = "neon")]
+unsafe fn block_stats_blocks(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    for (i, blocks) in out.chunks_exact_mut(2).enumerate() {
+        let mut s1 = vdupq_n_u16(0);
+        let mut s2 = vdupq_n_u16(0);
+        // 低半部分属于第一块，高半部分属于第二块
+        let mut ss = [vdupq_n_u32(0); 2];
+        let mut s12 = [vdupq_n_u32(0); 2];
+        for (ra, rb) in a.iter().zip(b.iter()) {
+            let x = vld1q_u16(ra.as_ptr().add(i * 8));
+            let y = vld1q_u16(rb.as_ptr().add(i * 8));
+            s1 = vaddq_u16(s1, x);
+            s2 = vaddq_u16(s2, y);
+            let halves = [
+                (vget_low_u16(x), vget_low_u16(y)),
+                (vget_high_u16(x), vget_high_u16(y)),
+            ];
+            for (k, (x, y)) in halves.into_iter().enumerate() {
+                ss[k] = vmlal_u16(vmlal_u16(ss[k], x, x), y, y);
+                s12[k] = vmlal_u16(s12[k], x, y);
+            }
+        }
+        blocks[0] = [
+            vaddlv_u16(vget_low_u16(s1)),
+            vaddlv_u16(vget_low_u16(s2)),
+            vaddvq_u32(ss[0]),
+            vaddvq_u32(s12[0]),
+        ];
+        blocks[1] = [
+            vaddlv_u16(vget_high_u16(s1)),
+            vaddlv_u16(vget_high_u16(s2)),
+            vaddvq_u32(ss[1]),
+            vaddvq_u32(s12[1]),
+        ];
+    }
+}
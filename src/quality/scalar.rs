@@ -0,0 +1,30 @@
+//! Reference kernels. The SIMD kernels compute the same integer sums and must match these
+//! exactly; they also use them for the tail of each row.
+
+/// Sum of squared differences of two rows.
+pub(crate) fn sse(a: &[u16], b: &[u16]) -> u64 {
+    a.iter()
+        .zip(b)
+        .map(|(&a, &b)| {
+            let d = a as i32 - b as i32;
+            (d * d) as u64
+        })
+        .sum()
+}
+
+/// `[Σa, Σb, Σa² + Σb², Σab]` of the 4x4 blocks of four rows, one per element of `out`.
+pub(crate) fn block_stats(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    for (i, out) in out.iter_mut().enumerate() {
+        let mut s = [0u32; 4];
+        for (ra, rb) in a.iter().zip(b.iter()) {
+            for (&x, &y) in ra[i * 4..i * 4 + 4].iter().zip(&rb[i * 4..i * 4 + 4]) {
+                let (x, y) = (x as u32, y as u32);
+                s[0] += x;
+                s[1] += y;
+                s[2] += x * x + y * y;
+                s[3] += x * y;
+            }
+        }
+        *out = s;
+    }
+}
@@ -0,0 +1,153 @@
+//! SSE2 and AVX2 kernels. They cover whole blocks of 8 (SSE2) or 16 (AVX2) samples and leave
+//! the rest of the row to `scalar`.
+//!
+//! Samples have at most 10 bits, so differences, sums of four rows and products of two samples
+//! all fit the 16-bit lanes that `madd` takes.
+
+use std::arch::x86_64::*;
+
+use super::scalar;
+
+pub(crate) fn sse_sse2(a: &[u16], b: &[u16]) -> u64 {
+    let n = a.len().min(b.len()) & !7;
+    // SSE2 是 x86_64 的基线指令集
+    let head = unsafe { sse_sse2_blocks(&a[..n], &b[..n]) };
+    head + scalar::sse(&a[n..], &b[n..])
+}
+
+pub(crate) fn block_stats_sse2(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    let n = out.len() & !1;
+    let (head, tail) = out.split_at_mut(n);
+    unsafe { block_stats_sse2_blocks(a, b, head) };
+    scalar::block_stats(a.map(|r| &r[n * 4..]), b.map(|r| &r[n * 4..]), tail);
+}
+
+pub(crate) fn sse_avx2(a: &[u16], b: &[u16]) -> u64 {
+    let n = a.len().min(b.len()) & !15;
+    // 只在检测到 AVX2 后选用
+    let head = unsafe { sse_avx2_blocks(&a[..n], &b[..n]) };
+    head + sse_sse2(&a[n..], &b[n..])
+}
+
+pub(crate) fn block_stats_avx2(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    let n = out.len() & !3;
+    let (head, tail) = out.split_at_mut(n);
+    unsafe { block_stats_avx2_blocks(a, b, head) };
+    block_stats_sse2(a.map(|r| &r[n * 4..]), b.map(|r| &r[n * 4..]), tail);
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn sse_sse2_blocks(a: &[u16], b: &[u16]) -> u64 {
+    let zero = _mm_setzero_si128();
+    let mut acc = zero;
+    for (a, b) in a.chunks_exact(8).zip(b.chunks_exact(8)) {
+        let x = _mm_loadu_si128(a.as_ptr() as *const __m128i);
+        let y = _mm_loadu_si128(b.as_ptr() as *const __m128i);
+        let d = _mm_sub_epi16(x, y);
+        let sq = _mm_madd_epi16(d, d);
+        // 平方和非负，零扩展到 64 位累加，整行也不会溢出
+        acc = _mm_add_epi64(acc, _mm_unpacklo_epi32(sq, zero));
+        acc = _mm_add_epi64(acc, _mm_unpackhi_epi32(sq, zero));
+    }
+    let mut lanes = [0u64; 2];
+    _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, acc);
+    lanes[0] + lanes[1]
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn block_stats_sse2_blocks(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    let ones = _mm_set1_epi16(1);
+    for (i, blocks) in out.chunks_exact_mut(2).enumerate() {
+        let mut s1 = _mm_setzero_si128();
+        let mut s2 = _mm_setzero_si128();
+        let mut ss = _mm_setzero_si128();
+        let mut s12 = _mm_setzero_si128();
+        for (ra, rb) in a.iter().zip(b.iter()) {
+            let x = _mm_loadu_si128(ra.as_ptr().add(i * 8) as *const __m128i);
+            let y = _mm_loadu_si128(rb.as_ptr().add(i * 8) as *const __m128i);
+            s1 = _mm_add_epi16(s1, x);
+            s2 = _mm_add_epi16(s2, y);
+            ss = _mm_add_epi32(ss, _mm_madd_epi16(x, x));
+            ss = _mm_add_epi32(ss, _mm_madd_epi16(y, y));
+            s12 = _mm_add_epi32(s12, _mm_madd_epi16(x, y));
+        }
+        let s1 = _mm_madd_epi16(s1, ones);
+        let s2 = _mm_madd_epi16(s2, ones);
+        let (first, second) = sum_pairs_sse2([s1, s2, ss, s12]);
+        _mm_storeu_si128(blocks.as_mut_ptr() as *mut __m128i, first);
+        _mm_storeu_si128(blocks.as_mut_ptr().add(1) as *mut __m128i, second);
+    }
+}
+
+// 四个统计量的 32 位通道 0、1 属于第一块，2、3 属于第二块；转置后相加得到两块各自的
+// [s1, s2, ss, s12]
+#[target_feature(enable = "sse2")]
+unsafe fn sum_pairs_sse2(v: [__m128i; 4]) -> (__m128i, __m128i) {
+    let sum = |ab: __m128i, cd: __m128i| {
+        _mm_add_epi32(_mm_unpacklo_epi64(ab, cd), _mm_unpackhi_epi64(ab, cd))
+    };
+    let first = sum(
+        _mm_unpacklo_epi32(v[0], v[1]),
+        _mm_unpacklo_epi32(v[2], v[3]),
+    );
+    let second = sum(
+        _mm_unpackhi_epi32(v[0], v[1]),
+        _mm_unpackhi_epi32(v[2], v[3]),
+    );
+    (first, second)
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn sse_avx2_blocks(a: &[u16], b: &[u16]) -> u64 {
+    let zero = _mm256_setzero_si256();
+    let mut acc = zero;
+    for (a, b) in a.chunks_exact(16).zip(b.chunks_exact(16)) {
+        let x = _mm256_loadu_si256(a.as_ptr() as *const __m256i);
+        let y = _mm256_loadu_si256(b.as_ptr() as *const __m256i);
+        let d = _mm256_sub_epi16(x, y);
+        let sq = _mm256_madd_epi16(d, d);
+        acc = _mm256_add_epi64(acc, _mm256_unpacklo_epi32(sq, zero));
+        acc = _mm256_add_epi64(acc, _mm256_unpackhi_epi32(sq, zero));
+    }
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc);
+    lanes.iter().sum()
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn block_stats_avx2_blocks(a: [&[u16]; 4], b: [&[u16]; 4], out: &mut [[u32; 4]]) {
+    let ones = _mm256_set1_epi16(1);
+    for (i, blocks) in out.chunks_exact_mut(4).enumerate() {
+        let mut s1 = _mm256_setzero_si256();
+        let mut s2 = _mm256_setzero_si256();
+        let mut ss = _mm256_setzero_si256();
+        let mut s12 = _mm256_setzero_si256();
+        for (ra, rb) in a.iter().zip(b.iter()) {
+            let x = _mm256_loadu_si256(ra.as_ptr().add(i * 16) as *const __m256i);
+            let y = _mm256_loadu_si256(rb.as_ptr().add(i * 16) as *const __m256i);
+            s1 = _mm256_add_epi16(s1, x);
+            s2 = _mm256_add_epi16(s2, y);
+            ss = _mm256_add_epi32(ss, _mm256_madd_epi16(x, x));
+            ss = _mm256_add_epi32(ss, _mm256_madd_epi16(y, y));
+            s12 = _mm256_add_epi32(s12, _mm256_madd_epi16(x, y));
+        }
+        let s1 = _mm256_madd_epi16(s1, ones);
+        let s2 = _mm256_madd_epi16(s2, ones);
+        // unpack 在 128 位内进行：first 为块 0 与 2，second 为块 1 与 3
+        let sum = |ab: __m256i, cd: __m256i| {
+            _mm256_add_epi32(_mm256_unpacklo_epi64(ab, cd), _mm256_unpackhi_epi64(ab, cd))
+        };
+        let first = sum(
+            _mm256_unpacklo_epi32(s1, s2),
+            _mm256_unpacklo_epi32(ss, s12),
+        );
+        let second = sum(
+            _mm256_unpackhi_epi32(s1, s2),
+            _mm256_unpackhi_epi32(ss, s12),
+        );
+        let low = _mm256_permute2x128_si256::<0x20>(first, second);
+        let high = _mm256_permute2x128_si256::<0x31>(first, second);
+        _mm256_storeu_si256(blocks.as_mut_ptr() as *mut __m256i, low);
+        _mm256_storeu_si256(blocks.as_mut_ptr().add(2) as *mut __m256i, high);
+    }
+}
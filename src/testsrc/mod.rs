@@ -0,0 +1,538 @@
+//! Synthetic test patterns for encoder tests and demos.
+//!
+//! ```ignore
+//! let mut source = TestSource::new(Pattern::ZonePlate, 1920, 1080);
+//! for frame in source.by_ref().take(300) {
+//!     let texture = encoder.upload(&frame)?;
+//!     // ...
+//! }
+//! assert_eq!(testsrc::read_counter(&decoded), Some(41));
+//! ```
+//!
+//! Frames are drawn straight into YUV samples, so the SMPTE bars keep their below-black PLUGE
+//! step. Every frame is a pure function of the pattern, the seed and its index.
+//!
+//! The counter overlay in the top-left corner carries the frame index as two rows of 34
+//! black/white cells: a start marker, 32 bits with the most significant first and an end
+//! marker, then the same row inverted. The cells are up to 16 pixels wide, large enough to
+//! survive lossy encoding, and `read_counter` rejects a read whose rows do not mirror each other.
+
+mod font;
+mod pattern;
+
+use crate::common::BitDepth;
+use crate::convert::{ColorSpace, ConvertError, Matrix, PixelFormat, Range};
+use crate::frame::{self, Frame, Subsampling};
+use serde_derive::{Deserialize, Serialize};
+
+#[cfg(windows)]
+use crate::platform::win::{error::Result as WinResult, Device, Texture};
+#[cfg(windows)]
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT;
+
+// 计数条：起始标记 + 32 位 + 结束标记，两行互为反码
+const COUNTER_BITS: usize = 32;
+const COUNTER_CELLS: usize = COUNTER_BITS + 2;
+const MAX_COUNTER_CELL: usize = 16;
+const MIN_COUNTER_CELL: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Pattern {
+    /// SMPTE color bars with castellations and PLUGE. Static apart from the counter.
+    #[default]
+    ColorBars,
+    /// Circular zone plate drifting across the frame, with phase rolling every frame.
+    ZonePlate,
+    /// Lines of text scrolling up, and a ticker scrolling left.
+    ScrollingText,
+    /// Independent uniform noise in every sample of every frame.
+    Noise,
+    /// Windows, text and a taskbar that stay still, and a mouse cursor that moves.
+    Desktop,
+}
+
+/// Endless sequence of test-pattern frames; the iterator never returns `None`.
+#[derive(Debug, Clone)]
+pub struct TestSource {
+    pattern: Pattern,
+    width: usize,
+    height: usize,
+    subsampling: Subsampling,
+    bit_depth: BitDepth,
+    color: ColorSpace,
+    counter: bool,
+    seed: u64,
+    position: u32,
+}
+
+impl TestSource {
+    /// 8-bit 4:2:0 BT.709 limited-range frames with the counter overlay.
+    pub fn new(pattern: Pattern, width: usize, height: usize) -> Self {
+        Self {
+            pattern,
+            width,
+            height,
+            subsampling: Subsampling::Yuv420,
+            bit_depth: BitDepth::Eight,
+            color: ColorSpace::new(Matrix::Bt709, Range::Limited),
+            counter: true,
+            seed: 0,
+            position: 0,
+        }
+    }
+
+    pub fn with_format(self, subsampling: Subsampling, bit_depth: BitDepth) -> Self {
+        Self {
+            subsampling,
+            bit_depth,
+            ..self
+        }
+    }
+
+    /// Matrix and range of the samples. The matrix also applies when packing to RGB.
+    pub fn with_color(self, color: ColorSpace) -> Self {
+        Self { color, ..self }
+    }
+
+    pub fn with_counter(self, counter: bool) -> Self {
+        Self { counter, ..self }
+    }
+
+    /// Seed of `Pattern::Noise`.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// Index of the frame the next `next_frame` call returns.
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    pub fn seek(&mut self, position: u32) {
+        self.position = position;
+    }
+
+    /// Frame `index` of the sequence.
+    pub fn render(&self, index: u32) -> Frame {
+        let mut frame = Frame::new(
+            self.width,
+            self.height,
+            self.subsampling,
+            self.bit_depth,
+            self.color.range,
+        );
+        let mut painter = Painter::new(&frame, self.color);
+        match self.pattern {
+            Pattern::ColorBars => pattern::color_bars(&mut painter),
+            Pattern::ZonePlate => pattern::zone_plate(&mut painter, index),
+            Pattern::ScrollingText => pattern::scrolling_text(&mut painter, index),
+            Pattern::Noise => pattern::noise(&mut painter, index, self.seed),
+            Pattern::Desktop => pattern::desktop(&mut painter, index),
+        }
+        if self.counter {
+            draw_counter(&mut painter, index);
+        }
+        painter.finish(&mut frame);
+        frame
+    }
+
+    pub fn next_frame(&mut self) -> Frame {
+        let frame = self.render(self.position);
+        self.position = self.position.wrapping_add(1);
+        frame
+    }
+
+    /// The next frame as a tightly packed image of `format`; see `Frame::to_packed`.
+    pub fn next_packed(&mut self, format: PixelFormat) -> Result<Vec<u8>, ConvertError> {
+        self.next_frame().to_packed(format, self.color.matrix)
+    }
+
+    /// The next frame uploaded to `device` as a new texture of `format`; see `Texture::upload`.
+    #[cfg(windows)]
+    pub fn next_texture(&mut self, device: &Device, format: DXGI_FORMAT) -> WinResult<Texture> {
+        Texture::upload(device, &self.next_frame(), format, self.color.matrix)
+    }
+}
+
+impl Iterator for TestSource {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        Some(self.next_frame())
+    }
+}
+
+/// Reads the counter overlay of a frame drawn by `TestSource`, e.g. after an encode/decode
+/// round trip. `None` when the frame is too small for the overlay or the cells do not decode
+/// consistently. `frame.range` must match the range the source was drawn in.
+pub fn read_counter(frame: &Frame) -> Option<u32> {
+    let cell = counter_cell(frame.width, frame.height)?;
+    let color = ColorSpace::new(Matrix::default(), frame.range);
+    let (black, scale, _, _) = color.quantization(frame.bit_depth.bits() as u32);
+    let threshold = black + scale / 2.0;
+    // 取单元中间一半的平均亮度，避开编码后边缘的振铃
+    let bit = |column: usize, row: usize| {
+        let (x0, y0) = (column * cell + cell / 4, row * cell + cell / 4);
+        let mut sum = 0u32;
+        for y in y0..y0 + cell / 2 {
+            for x in x0..x0 + cell / 2 {
+                sum += frame.sample(0, x, y) as u32;
+            }
+        }
+        sum as f64 / (cell * cell / 4) as f64 > threshold
+    };
+    let last = COUNTER_CELLS - 1;
+    if !bit(0, 0) || bit(last, 0) || bit(0, 1) || !bit(last, 1) {
+        return None;
+    }
+    let mut value = 0u32;
+    for column in 1..=COUNTER_BITS {
+        let b = bit(column, 0);
+        if b == bit(column, 1) {
+            return None;
+        }
+        value = (value << 1) | b as u32;
+    }
+    Some(value)
+}
+
+// 单元边长随宽度缩小，保持偶数以对齐 4:2:0 色度
+fn counter_cell(width: usize, height: usize) -> Option<usize> {
+    let cell = (width / COUNTER_CELLS).min(MAX_COUNTER_CELL) & !1;
+    (cell >= MIN_COUNTER_CELL && height >= 2 * cell).then_some(cell)
+}
+
+fn draw_counter(p: &mut Painter, value: u32) {
+    let Some(cell) = counter_cell(p.width, p.height) else {
+        return;
+    };
+    let (black, white) = (p.gray(0.0), p.gray(1.0));
+    let last = COUNTER_CELLS - 1;
+    for column in 0..COUNTER_CELLS {
+        let on = match column {
+            0 => true,
+            c if c == last => false,
+            c => value >> (COUNTER_BITS - c) & 1 == 1,
+        };
+        for (row, on) in [(0, on), (1, !on)] {
+            let c = if on { white } else { black };
+            let (x, y, size) = ((column * cell) as i64, (row * cell) as i64, cell as i64);
+            p.fill(x, y, size, size, c);
+        }
+    }
+    // 计数条下方给人看的十进制帧号
+    let scale = (cell / 8).max(1) as i64;
+    let text = format!("#{}", value);
+    let y = 2 * cell as i64;
+    let width = (text.len() as i64 * font::ADVANCE + 1) * scale;
+    p.fill(0, y, width, (font::HEIGHT + 2) * scale, black);
+    p.text(scale, y + scale, scale, &text, white);
+}
+
+/// YUV code values of one sample.
+type Yuv = [u16; 3];
+
+/// Normalized `[y, cb, cr]` of gamma-encoded RGB in 0..=1.
+fn ycbcr(matrix: Matrix, [r, g, b]: [f64; 3]) -> [f64; 3] {
+    let (kr, kb) = matrix.weights();
+    let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+    [
+        y,
+        (b - y) / (2.0 * (1.0 - kb)),
+        (r - y) / (2.0 * (1.0 - kr)),
+    ]
+}
+
+/// Draws into three planes of 16-bit samples at the frame's size, depth and subsampling.
+/// Chroma samples are point-sampled at the top-left luma sample of their block.
+struct Painter {
+    width: usize,
+    height: usize,
+    divisors: (usize, usize),
+    chroma: (usize, usize),
+    planes: [Vec<u16>; 3],
+    color: ColorSpace,
+    depth: u32,
+}
+
+impl Painter {
+    fn new(frame: &Frame, color: ColorSpace) -> Self {
+        let chroma = frame.plane_size(1);
+        let planes = [
+            vec![0; frame.width * frame.height],
+            vec![0; chroma.0 * chroma.1],
+            vec![0; chroma.0 * chroma.1],
+        ];
+        Self {
+            width: frame.width,
+            height: frame.height,
+            divisors: frame.subsampling.divisors(),
+            chroma,
+            planes,
+            color,
+            depth: frame.bit_depth.bits() as u32,
+        }
+    }
+
+    fn finish(self, frame: &mut Frame) {
+        let depth = frame.bit_depth;
+        for (plane, samples) in self.planes.iter().enumerate() {
+            let data = frame.plane_mut(plane);
+            for (i, &s) in samples.iter().enumerate() {
+                frame::write_sample(data, i, depth, s);
+            }
+        }
+    }
+
+    /// Code values of normalized `y` in 0..=1 and `cb`, `cr` in -0.5..=0.5. Values beyond,
+    /// such as the PLUGE step below black, are kept as far as the code range allows.
+    fn yuv(&self, [y, cb, cr]: [f64; 3]) -> Yuv {
+        let (y_offset, y_scale, c_offset, c_scale) = self.color.quantization(self.depth);
+        let max = ((1u32 << self.depth) - 1) as f64;
+        let code = |v: f64| v.round().clamp(0.0, max) as u16;
+        [
+            code(y_offset + y_scale * y),
+            code(c_offset + c_scale * cb),
+            code(c_offset + c_scale * cr),
+        ]
+    }
+
+    /// Code values of gamma-encoded RGB in 0..=1.
+    fn rgb(&self, rgb: [f64; 3]) -> Yuv {
+        self.yuv(ycbcr(self.color.matrix, rgb))
+    }
+
+    fn gray(&self, level: f64) -> Yuv {
+        self.rgb([level; 3])
+    }
+
+    // 裁剪到画面内的亮度范围与对应的色度范围
+    fn clip(&self, x: i64, y: i64, w: i64, h: i64) -> Option<[std::ops::Range<usize>; 4]> {
+        let x0 = x.clamp(0, self.width as i64) as usize;
+        let y0 = y.clamp(0, self.height as i64) as usize;
+        let x1 = x.saturating_add(w).clamp(0, self.width as i64) as usize;
+        let y1 = y.saturating_add(h).clamp(0, self.height as i64) as usize;
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        let (dx, dy) = self.divisors;
+        Some([
+            x0..x1,
+            y0..y1,
+            x0.div_ceil(dx)..x1.div_ceil(dx),
+            y0.div_ceil(dy)..y1.div_ceil(dy),
+        ])
+    }
+
+    fn fill(&mut self, x: i64, y: i64, w: i64, h: i64, c: Yuv) {
+        let Some([xs, ys, cxs, cys]) = self.clip(x, y, w, h) else {
+            return;
+        };
+        for y in ys {
+            self.planes[0][y * self.width..][xs.clone()].fill(c[0]);
+        }
+        for cy in cys {
+            let row = cy * self.chroma.0;
+            self.planes[1][row..][cxs.clone()].fill(c[1]);
+            self.planes[2][row..][cxs.clone()].fill(c[2]);
+        }
+    }
+
+    /// Sets every sample of the rectangle to the normalized `[y, cb, cr]` of `f(x, y)`,
+    /// evaluated at luma coordinates.
+    fn shade(&mut self, x: i64, y: i64, w: i64, h: i64, f: impl Fn(usize, usize) -> [f64; 3]) {
+        let Some([xs, ys, cxs, cys]) = self.clip(x, y, w, h) else {
+            return;
+        };
+        for y in ys {
+            for x in xs.clone() {
+                self.planes[0][y * self.width + x] = self.yuv(f(x, y))[0];
+            }
+        }
+        let (dx, dy) = self.divisors;
+        for cy in cys {
+            for cx in cxs.clone() {
+                let c = self.yuv(f(cx * dx, cy * dy));
+                self.planes[1][cy * self.chroma.0 + cx] = c[1];
+                self.planes[2][cy * self.chroma.0 + cx] = c[2];
+            }
+        }
+    }
+
+    /// Draws `text` with its top-left corner at `x`, `y`, each font pixel `scale` wide.
+    fn text(&mut self, x: i64, y: i64, scale: i64, text: &str, c: Yuv) {
+        for (i, ch) in text.chars().enumerate() {
+            let left = x + i as i64 * font::ADVANCE * scale;
+            if left >= self.width as i64 {
+                break;
+            }
+            for (row, bits) in font::glyph(ch).iter().enumerate() {
+                for column in 0..font::WIDTH {
+                    if bits >> (font::WIDTH - 1 - column) & 1 == 1 {
+                        let top = y + row as i64 * scale;
+                        self.fill(left + column * scale, top, scale, scale, c);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: [Pattern; 5] = [
+        Pattern::ColorBars,
+        Pattern::ZonePlate,
+        Pattern::ScrollingText,
+        Pattern::Noise,
+        Pattern::Desktop,
+    ];
+
+    /// 测试计数条在各图案、位深、采样与范围下都能读回
+    #[test]
+    fn test_counter_round_trip() {
+        let formats = [
+            (Subsampling::Yuv420, BitDepth::Eight),
+            (Subsampling::Yuv420, BitDepth::Ten),
+            (Subsampling::Yuv444, BitDepth::Eight),
+        ];
+        for pattern in PATTERNS {
+            for (subsampling, depth) in formats {
+                for range in [Range::Limited, Range::Full] {
+                    let source = TestSource::new(pattern, 640, 360)
+                        .with_format(subsampling, depth)
+                        .with_color(ColorSpace::new(Matrix::Bt709, range));
+                    for index in [0, 1, 2, 0x8000_0001, 123_456_789, u32::MAX] {
+                        let frame = source.render(index);
+                        assert_eq!(
+                            read_counter(&frame),
+                            Some(index),
+                            "{:?} {:?} {:?} {:?}",
+                            pattern,
+                            subsampling,
+                            depth,
+                            range
+                        );
+                    }
+                }
+            }
+        }
+
+        // 迭代器按顺序给出帧号，seek 后从新位置继续
+        let mut source = TestSource::new(Pattern::Desktop, 272, 160);
+        let read: Vec<_> = source.by_ref().take(3).map(|f| read_counter(&f)).collect();
+        assert_eq!(read, [Some(0), Some(1), Some(2)]);
+        source.seek(41);
+        assert_eq!(read_counter(&source.next_frame()), Some(41));
+        assert_eq!(source.position(), 42);
+    }
+
+    /// 测试计数条经有损处理后仍可读，单元损坏或画面过小时返回 None
+    #[test]
+    fn test_counter_robust() {
+        let source = TestSource::new(Pattern::ZonePlate, 1280, 720);
+        let mut frame = source.render(0x1234_5678);
+        // 亮度加 ±40 的扰动，相当于强量化的误差
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let s = frame.sample(0, x, y) as i32 + ((x * 7 + y * 13) % 81) as i32 - 40;
+                frame.set_sample(0, x, y, s.clamp(0, 255) as u16);
+            }
+        }
+        assert_eq!(read_counter(&frame), Some(0x1234_5678));
+
+        // 第 5 个数据单元两行同色
+        let cell = counter_cell(1280, 720).unwrap();
+        assert_eq!(cell, MAX_COUNTER_CELL);
+        for y in cell..2 * cell {
+            for x in 5 * cell..6 * cell {
+                frame.set_sample(0, x, y, frame.sample(0, x, y - cell));
+            }
+        }
+        assert_eq!(read_counter(&frame), None);
+
+        let plain = TestSource::new(Pattern::ColorBars, 1280, 720).with_counter(false);
+        assert_eq!(read_counter(&plain.render(7)), None);
+        // 宽度放不下 4 像素的单元
+        let tiny = TestSource::new(Pattern::ColorBars, 128, 64);
+        assert_eq!(read_counter(&tiny.render(7)), None);
+        assert_eq!(counter_cell(136, 8), Some(4));
+    }
+
+    /// 测试帧只取决于帧号：同一帧号一致，动态图案逐帧变化
+    #[test]
+    fn test_deterministic() {
+        for pattern in PATTERNS {
+            let source = TestSource::new(pattern, 320, 180).with_counter(false);
+            let first = source.render(10);
+            assert_eq!(first, source.render(10), "{:?}", pattern);
+            let moved = first != source.render(11);
+            assert_eq!(moved, pattern != Pattern::ColorBars, "{:?}", pattern);
+        }
+        let noise = TestSource::new(Pattern::Noise, 64, 64);
+        assert_ne!(noise.render(0), noise.clone().with_seed(1).render(0));
+    }
+
+    /// 测试彩条的已知码值，含 PLUGE 的低于黑电平一档
+    #[test]
+    fn test_color_bars() {
+        let (width, height) = (1400, 1200);
+        let frame = TestSource::new(Pattern::ColorBars, width, height)
+            .with_counter(false)
+            .render(0);
+        let bar = width / 7;
+        let at = |x: usize, y: usize| {
+            (
+                frame.sample(0, x, y),
+                frame.sample(1, x / 2, y / 2),
+                frame.sample(2, x / 2, y / 2),
+            )
+        };
+        // 75% 灰：16 + 219·0.75
+        assert_eq!(at(bar / 2, 100), (180, 128, 128));
+        // 75% 黄：BT.709 Y = 16 + 219·0.75·(Kr + Kg)，Cb 低于中点
+        let (y, cb, cr) = at(bar + bar / 2, 100);
+        assert_eq!(y, (16.0_f64 + 219.0 * 0.75 * (1.0 - 0.0722)).round() as u16);
+        assert!(cb < 128 && cr > 128);
+        // 城堞行：第二格为黑
+        assert_eq!(at(bar + bar / 2, height * 2 / 3 + 10), (16, 128, 128));
+        // 底部：100% 白，以及 PLUGE 的 -4%、0、+4%
+        let bottom = height - 10;
+        assert_eq!(at(bar * 5 / 4 + bar / 2, bottom), (235, 128, 128));
+        let pluge = bar * 5;
+        assert_eq!(at(pluge + bar / 6, bottom).0, 7);
+        assert_eq!(at(pluge + bar / 2, bottom).0, 16);
+        assert_eq!(at(pluge + bar * 5 / 6, bottom).0, 25);
+
+        // 全范围 10 bit：100% 白为 1023
+        let frame = TestSource::new(Pattern::ColorBars, width, height)
+            .with_format(Subsampling::Yuv420, BitDepth::Ten)
+            .with_color(ColorSpace::new(Matrix::Bt709, Range::Full))
+            .render(0);
+        assert_eq!(frame.sample(0, bar * 5 / 4 + bar / 2, bottom), 1023);
+    }
+
+    /// 测试打包输出覆盖所有像素格式
+    #[test]
+    fn test_packed() {
+        let mut source = TestSource::new(Pattern::Desktop, 320, 240);
+        for format in [
+            PixelFormat::Bgra,
+            PixelFormat::Rgba,
+            PixelFormat::Nv12,
+            PixelFormat::I420,
+            PixelFormat::P010,
+        ] {
+            let data = source.next_packed(format).unwrap();
+            assert_eq!(data.len(), format.frame_size(320, 240), "{:?}", format);
+        }
+        let mut odd = TestSource::new(Pattern::Noise, 33, 17);
+        assert!(odd.next_packed(PixelFormat::Nv12).is_err());
+    }
+}
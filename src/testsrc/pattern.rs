@@ -0,0 +1,308 @@
+//! The pattern renderers. Each draws the whole frame from its index alone.
+
+use std::f64::consts::PI;
+
+use super::{font, ycbcr, Painter};
+
+/// Lines of `Pattern::ScrollingText` and of the editor window in `Pattern::Desktop`.
+const LINES: [&str; 8] = [
+    "THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG",
+    "0123456789 -/:.,!?#",
+    "PACK MY BOX WITH FIVE DOZEN LIQUOR JUGS",
+    "HOW VEXINGLY QUICK DAFT ZEBRAS JUMP",
+    "SPHINX OF BLACK QUARTZ, JUDGE MY VOW",
+    "HARDWARE ENCODERS: NVENC, AMF, QUICK SYNC",
+    "SCROLLING TEXT STRESSES MOTION SEARCH",
+    "THIN STROKES SHOW RINGING AND CHROMA BLEED",
+];
+
+/// Arrow cursor: `#` outline, `o` fill, blank transparent.
+const CURSOR: [&str; 19] = [
+    "#           ",
+    "##          ",
+    "#o#         ",
+    "#oo#        ",
+    "#ooo#       ",
+    "#oooo#      ",
+    "#ooooo#     ",
+    "#oooooo#    ",
+    "#ooooooo#   ",
+    "#oooooooo#  ",
+    "#ooooooooo# ",
+    "#oooooo#####",
+    "#ooo#oo#    ",
+    "#oo# #oo#   ",
+    "#o#  #oo#   ",
+    "##    #oo#  ",
+    "#     #oo#  ",
+    "       #oo# ",
+    "        ##  ",
+];
+
+/// SMPTE EG 1 bars: seven 75% bars over 2/3 of the height, the reversed castellations, then
+/// -I, 100% white, +Q, black and the PLUGE steps at -4%, 0 and +4%.
+pub(super) fn color_bars(p: &mut Painter) {
+    let (w, h) = (p.width as i64, p.height as i64);
+    // 底部各块宽度不是整条，按 1/12 条宽计：7 条共 84 份
+    let x = |units: i64| units * w / 84;
+    let span = |from: i64, units: i64| (x(from), x(from + units) - x(from));
+    let bars = [
+        [0.75, 0.75, 0.75],
+        [0.75, 0.75, 0.0],
+        [0.0, 0.75, 0.75],
+        [0.0, 0.75, 0.0],
+        [0.75, 0.0, 0.75],
+        [0.75, 0.0, 0.0],
+        [0.0, 0.0, 0.75],
+    ];
+    let black = [0.0; 3];
+    let castellations = [bars[6], black, bars[4], black, bars[2], black, bars[0]];
+    let (top, middle) = (h * 2 / 3, h * 3 / 4);
+    for i in 0..7 {
+        let (left, width) = span(i * 12, 12);
+        let c = p.rgb(bars[i as usize]);
+        p.fill(left, 0, width, top, c);
+        let c = p.rgb(castellations[i as usize]);
+        p.fill(left, top, width, middle - top, c);
+    }
+
+    // -I 与 +Q 为零亮度、20% 幅度的色度，方向取 YIQ 的 I、Q 轴（相对 U 轴 123° 与 33°）
+    let (sin, cos) = 33f64.to_radians().sin_cos();
+    let iq = |i: f64, q: f64| [0.0, -i * sin + q * cos, i * cos + q * sin];
+    let bottom = [
+        (15, p.yuv(iq(-0.2, 0.0))),
+        (15, p.gray(1.0)),
+        (15, p.yuv(iq(0.0, 0.2))),
+        (15, p.gray(0.0)),
+        (4, p.gray(-0.04)),
+        (4, p.gray(0.0)),
+        (4, p.gray(0.04)),
+        (12, p.gray(0.0)),
+    ];
+    let mut from = 0;
+    for (units, c) in bottom {
+        let (left, width) = span(from, units);
+        p.fill(left, middle, width, h - middle, c);
+        from += units;
+    }
+}
+
+/// Circular zone plate whose frequency reaches Nyquist at the inscribed circle. The center
+/// drifts and the phase rolls by 1/16 cycle per frame; chroma carries rings at half the
+/// frequency.
+pub(super) fn zone_plate(p: &mut Painter, index: u32) {
+    let (w, h) = (p.width as f64, p.height as f64);
+    let t = index as f64;
+    let (cx, cy) = (
+        w / 2.0 + w / 8.0 * (t * 0.031).sin(),
+        h / 2.0 + h / 8.0 * (t * 0.043).cos(),
+    );
+    let k = PI / w.min(h).max(2.0);
+    let roll = t * PI / 8.0;
+    p.shade(0, 0, p.width as i64, p.height as i64, |x, y| {
+        let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+        let phase = k * (dx * dx + dy * dy) + roll;
+        [
+            0.5 + 0.5 * phase.sin(),
+            0.2 * (phase / 2.0).sin(),
+            0.2 * (phase / 2.0).cos(),
+        ]
+    });
+}
+
+/// Numbered lines of text scrolling up by one font pixel per frame, over a ticker scrolling
+/// left by two.
+pub(super) fn scrolling_text(p: &mut Painter, index: u32) {
+    let (w, h) = (p.width as i64, p.height as i64);
+    let scale = (h / 216).max(1);
+    let line_height = (font::HEIGHT + 3) * scale;
+    let band = line_height + 2 * scale;
+    let colors = [
+        p.gray(0.95),
+        p.rgb([1.0, 0.9, 0.2]),
+        p.rgb([0.3, 0.9, 1.0]),
+        p.rgb([0.4, 1.0, 0.4]),
+    ];
+    let background = p.rgb([0.05, 0.05, 0.12]);
+    p.fill(0, 0, w, h, background);
+
+    let offset = index as i64 * scale;
+    let first = offset / line_height;
+    for k in 0..=(h - band) / line_height + 1 {
+        let line = first + k;
+        let y = k * line_height - offset % line_height;
+        let text = format!("{:05} {}", line, LINES[line as usize % LINES.len()]);
+        p.text(
+            2 * scale,
+            y,
+            scale,
+            &text,
+            colors[line as usize % colors.len()],
+        );
+    }
+
+    let ticker = "--- BREAKING: FRAME RATE HOLDS STEADY --- ENCODER REPORTS NO DROPS ";
+    let length = ticker.len() as i64 * font::ADVANCE * scale;
+    let (ticker_bg, ticker_fg) = (p.rgb([0.6, 0.05, 0.05]), p.gray(1.0));
+    p.fill(0, h - band, w, band, ticker_bg);
+    let start = -(index as i64 * 2 * scale % length);
+    let mut x = start;
+    while x < w {
+        p.text(x, h - band + 2 * scale, scale, ticker, ticker_fg);
+        x += length;
+    }
+}
+
+/// Uniform noise over every code value, independent per sample and frame.
+pub(super) fn noise(p: &mut Painter, index: u32, seed: u64) {
+    let width = p.width as u64;
+    let frame = hash(seed ^ ((index as u64) << 32));
+    p.shade(0, 0, p.width as i64, p.height as i64, |x, y| {
+        let h = hash(frame ^ (y as u64 * width + x as u64));
+        let unit = |shift: u32| ((h >> shift) & 0xffff) as f64 / 65535.0;
+        [unit(0), unit(16) - 0.5, unit(32) - 0.5]
+    });
+}
+
+/// A screen at rest: wallpaper, an editor and an image viewer window, a taskbar. Only the
+/// mouse cursor moves and the editor's caret blinks.
+pub(super) fn desktop(p: &mut Painter, index: u32) {
+    let (w, h) = (p.width as i64, p.height as i64);
+    let u = (h / 270).max(1);
+    let s = (u / 2).max(1);
+    let wallpaper = p.rgb([0.09, 0.29, 0.47]);
+    p.fill(0, 0, w, h, wallpaper);
+
+    // 桌面图标
+    let icons = [[0.95, 0.8, 0.3], [0.4, 0.75, 0.95], [0.55, 0.85, 0.45]];
+    for (i, rgb) in icons.iter().enumerate() {
+        let c = p.rgb(*rgb);
+        p.fill(4 * u, 24 * u + i as i64 * 22 * u, 12 * u, 12 * u, c);
+    }
+
+    // 编辑器窗口
+    let (x, y, ww, wh) = (w / 6, h / 8, w / 2, h / 2);
+    let body = window(p, x, y, ww, wh, u, "NOTES.TXT - EDITOR");
+    let ink = p.gray(0.1);
+    let line_height = (font::HEIGHT + 4) * s;
+    let lines = ((body.3 - 2 * u) / line_height).max(0);
+    for i in 0..lines {
+        let text = LINES[i as usize % LINES.len()];
+        p.text(
+            body.0 + 2 * u,
+            body.1 + 2 * u + i * line_height,
+            s,
+            text,
+            ink,
+        );
+    }
+    if (index / 15) & 1 == 0 && lines > 0 {
+        let last = LINES[(lines - 1) as usize % LINES.len()].len() as i64;
+        let caret_x = body.0 + 2 * u + last * font::ADVANCE * s;
+        let caret_y = body.1 + 2 * u + (lines - 1) * line_height;
+        p.fill(caret_x, caret_y, s, font::HEIGHT * s, ink);
+    }
+
+    // 看图窗口：色相横向变化、亮度纵向变化的渐变
+    let (x, y, ww, wh) = (w * 9 / 16, h * 3 / 8, w * 3 / 8, h * 3 / 8);
+    let (bx, by, bw, bh) = window(p, x, y, ww, wh, u, "PHOTO.PNG - VIEWER");
+    let matrix = p.color.matrix;
+    p.shade(bx, by, bw, bh, |x, y| {
+        let hue = (x as i64 - bx) as f64 / bw.max(1) as f64;
+        let level = 1.0 - 0.8 * (y as i64 - by) as f64 / bh.max(1) as f64;
+        ycbcr(matrix, hsv(hue, 0.7, level))
+    });
+
+    // 任务栏
+    let bar = 10 * u;
+    let (bar_bg, start, task, clock) = (
+        p.rgb([0.1, 0.1, 0.12]),
+        p.rgb([0.2, 0.5, 0.9]),
+        p.gray(0.3),
+        p.gray(0.9),
+    );
+    p.fill(0, h - bar, w, bar, bar_bg);
+    p.fill(u, h - bar + u, 8 * u, 8 * u, start);
+    for i in 0..2 {
+        p.fill(12 * u + i * 42 * u, h - bar + u, 40 * u, 8 * u, task);
+    }
+    let time = "12:00";
+    let time_x = w - (time.len() as i64 * font::ADVANCE + 2) * s - 2 * u;
+    p.text(
+        time_x,
+        h - bar + (bar - font::HEIGHT * s) / 2,
+        s,
+        time,
+        clock,
+    );
+
+    // 鼠标沿 Lissajous 曲线移动
+    let t = index as f64;
+    let cx = (w as f64 * (0.5 + 0.35 * (t * 0.05).sin())) as i64;
+    let cy = (h as f64 * (0.5 + 0.3 * (t * 0.083).sin())) as i64;
+    let cs = (h / 540).max(1);
+    let (outline, fill) = (p.gray(0.0), p.gray(1.0));
+    for (row, line) in CURSOR.iter().enumerate() {
+        for (column, pixel) in line.chars().enumerate() {
+            let c = match pixel {
+                '#' => outline,
+                'o' => fill,
+                _ => continue,
+            };
+            p.fill(cx + column as i64 * cs, cy + row as i64 * cs, cs, cs, c);
+        }
+    }
+}
+
+// 画带边框与标题栏的窗口，返回客户区 (x, y, 宽, 高)
+fn window(
+    p: &mut Painter,
+    x: i64,
+    y: i64,
+    w: i64,
+    h: i64,
+    u: i64,
+    title: &str,
+) -> (i64, i64, i64, i64) {
+    let s = (u / 2).max(1);
+    let title_height = 8 * u;
+    let (frame, title_bar, text, client) = (
+        p.gray(0.35),
+        p.rgb([0.15, 0.35, 0.75]),
+        p.gray(1.0),
+        p.gray(0.96),
+    );
+    p.fill(x, y, w, h, frame);
+    p.fill(x, y, w, title_height, title_bar);
+    let text_y = y + (title_height - font::HEIGHT * s) / 2;
+    p.text(x + 2 * u, text_y, s, title, text);
+    let body = (x + u / 2, y + title_height, w - u, h - title_height - u / 2);
+    p.fill(body.0, body.1, body.2, body.3, client);
+    body
+}
+
+fn hsv(hue: f64, saturation: f64, value: f64) -> [f64; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let f = h - h.floor();
+    let (p, q, t) = (
+        value * (1.0 - saturation),
+        value * (1.0 - saturation * f),
+        value * (1.0 - saturation * (1.0 - f)),
+    );
+    match h as u32 {
+        0 => [value, t, p],
+        1 => [q, value, p],
+        2 => [p, value, t],
+        3 => [p, q, value],
+        4 => [t, p, value],
+        _ => [value, p, q],
+    }
+}
+
+// splitmix64 的终结步骤，把相邻的输入打散
+fn hash(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,188 @@
+//! Per-adapter enumeration used by the `*_test_encode` / `*_test_decode` probes.
+//!
+//! The selection logic only sees an `AdapterSource`, so it can be exercised on any platform
+//! with a fake multi-GPU list; on Windows `platform::win::Adapters` is the real source.
+
+use std::ffi::c_void;
+
+/// A list of adapters of one vendor that test sessions can be opened on.
+pub trait AdapterSource {
+    /// LUIDs of the adapters, in enumeration order.
+    fn luids(&self) -> Vec<i64>;
+
+    /// Runs `f` with a D3D11 device on adapter `luid`. Returns `false` without calling `f`
+    /// if the adapter is not in the list.
+    fn with_device(&self, luid: i64, f: &mut dyn FnMut(*mut c_void) -> bool) -> bool;
+}
+
+#[cfg(windows)]
+impl AdapterSource for crate::platform::win::Adapters {
+    fn luids(&self) -> Vec<i64> {
+        self.adapters().iter().map(|a| a.luid()).collect()
+    }
+
+    fn with_device(&self, luid: i64, f: &mut dyn FnMut(*mut c_void) -> bool) -> bool {
+        use windows::core::Interface;
+
+        match self.adapters().iter().find(|a| a.luid() == luid) {
+            Some(adapter) => f(adapter.device().as_raw()),
+            None => false,
+        }
+    }
+}
+
+/// Returns the LUIDs of the adapters in `source` on which `open(device, luid)` succeeds, at most
+/// `max` of them. Adapters listed in `excluded` for `data_format` are skipped without opening a
+/// session; the same adapter may still be reported for another format.
+pub(crate) fn test_adapters(
+    source: &dyn AdapterSource,
+    data_format: i32,
+    excluded: &[(i64, i32)],
+    max: usize,
+    open: &mut dyn FnMut(*mut c_void, i64) -> bool,
+) -> Vec<i64> {
+    let mut found = vec![];
+    for luid in source.luids() {
+        if found.len() >= max {
+            break;
+        }
+        if excluded.contains(&(luid, data_format)) {
+            log::debug!("Adapter {} excluded for format {}", luid, data_format);
+            continue;
+        }
+        if source.with_device(luid, &mut |device| open(device, luid)) {
+            found.push(luid);
+        } else {
+            log::debug!("Adapter {} failed test session for format {}", luid, data_format);
+        }
+    }
+    found
+}
+
+/// Collects the `(luid, format)` exclude list passed to the test calls.
+pub(crate) unsafe fn excluded_pairs(
+    excluded_luids: *const i64,
+    exclude_formats: *const i32,
+    exclude_count: i32,
+) -> Vec<(i64, i32)> {
+    if exclude_count <= 0 || excluded_luids.is_null() || exclude_formats.is_null() {
+        return vec![];
+    }
+    (0..exclude_count as usize)
+        .map(|i| (*excluded_luids.add(i), *exclude_formats.add(i)))
+        .collect()
+}
+
+/// Writes `found` to the test calls' output arrays, which must hold at least `found.len()`
+/// entries.
+pub(crate) unsafe fn write_found(
+    found: &[i64],
+    vendor: i32,
+    luids: *mut i64,
+    vendors: *mut i32,
+    desc_count: *mut i32,
+) {
+    for (i, luid) in found.iter().enumerate() {
+        *luids.add(i) = *luid;
+        *vendors.add(i) = vendor;
+    }
+    *desc_count = found.len() as i32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeAdapters {
+        luids: Vec<i64>,
+        broken: Vec<i64>,
+    }
+
+    impl AdapterSource for FakeAdapters {
+        fn luids(&self) -> Vec<i64> {
+            self.luids.clone()
+        }
+
+        fn with_device(&self, luid: i64, f: &mut dyn FnMut(*mut c_void) -> bool) -> bool {
+            if !self.luids.contains(&luid) {
+                return false;
+            }
+            // 用 luid 充当假设备指针，便于检查 open 收到的设备与适配器对应
+            f(luid as *mut c_void) && !self.broken.contains(&luid)
+        }
+    }
+
+    fn open_ok(device: *mut c_void, luid: i64) -> bool {
+        device as i64 == luid
+    }
+
+    /// 测试多 GPU 时返回每个适配器的真实 LUID，并跳过无法建立会话的适配器
+    #[test]
+    fn test_multi_gpu() {
+        let source = FakeAdapters {
+            luids: vec![11, 22, 33],
+            broken: vec![22],
+        };
+        assert_eq!(test_adapters(&source, 0, &[], 16, &mut open_ok), vec![11, 33]);
+        assert_eq!(test_adapters(&source, 0, &[], 1, &mut open_ok), vec![11]);
+        assert_eq!(
+            test_adapters(&source, 0, &[], 16, &mut |_, luid| luid != 11),
+            vec![33]
+        );
+        let empty = FakeAdapters {
+            luids: vec![],
+            broken: vec![],
+        };
+        assert!(test_adapters(&empty, 0, &[], 16, &mut open_ok).is_empty());
+    }
+
+    /// 测试排除列表按 (luid, 格式) 生效，被排除的适配器不会打开会话
+    #[test]
+    fn test_exclude() {
+        let source = FakeAdapters {
+            luids: vec![11, 22, 33],
+            broken: vec![],
+        };
+        let excluded = [(11, 0), (22, 1)];
+        let mut opened = vec![];
+        let found = test_adapters(&source, 0, &excluded, 16, &mut |_, luid| {
+            opened.push(luid);
+            true
+        });
+        assert_eq!(found, vec![22, 33]);
+        assert_eq!(opened, vec![22, 33]);
+        assert_eq!(
+            test_adapters(&source, 1, &excluded, 16, &mut open_ok),
+            vec![11, 33]
+        );
+    }
+
+    /// 测试 C 接口数组的读写
+    #[test]
+    fn test_ffi_arrays() {
+        let luids = [11i64, 22];
+        let formats = [0i32, 1];
+        unsafe {
+            assert_eq!(
+                excluded_pairs(luids.as_ptr(), formats.as_ptr(), 2),
+                vec![(11, 0), (22, 1)]
+            );
+            assert!(excluded_pairs(std::ptr::null(), formats.as_ptr(), 2).is_empty());
+            assert!(excluded_pairs(luids.as_ptr(), formats.as_ptr(), 0).is_empty());
+
+            let mut out_luids = [0i64; 4];
+            let mut out_vendors = [-1i32; 4];
+            let mut count = -1;
+            write_found(
+                &[33, 44],
+                2,
+                out_luids.as_mut_ptr(),
+                out_vendors.as_mut_ptr(),
+                &mut count,
+            );
+            assert_eq!(count, 2);
+            assert_eq!(out_luids, [33, 44, 0, 0]);
+            assert_eq!(out_vendors, [2, 2, -1, -1]);
+        }
+    }
+}
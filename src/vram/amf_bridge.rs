@@ -1,4 +1,5 @@
 #![allow(non_snake_case)]
+#![allow(clippy::too_many_arguments)] // 参数与 C++ 声明一一对应
 #![allow(dead_code)] // GetWidth/GetHeight 等为 C++ 接口预留，Rust 侧暂未使用
 
 #[cxx::bridge]
@@ -18,16 +19,29 @@ mod amf_bridge {
         unsafe fn amf_DestroyEncoder(encoder: *mut AmfEncoder);
         unsafe fn amf_SetBitrate(encoder: *mut AmfEncoder, bitrate: i32);
         unsafe fn amf_SetFramerate(encoder: *mut AmfEncoder, framerate: i32);
+        unsafe fn amf_CreateEncoderAsync(device: *mut u8, width: i32, height: i32, codec_id: i32, bitrate: i32, framerate: i32, gop: i32, async_depth: i32, bframes: i32, intra_refresh_period: i32, intra_refresh_duration: i32, ltr_frames: i32, color: ColorDesc, bit_depth: i32, yuv444: bool) -> *mut AmfEncoder;
+        unsafe fn amf_GetAsyncDepth(encoder: *mut AmfEncoder) -> i32;
+        unsafe fn amf_PendingFrames(encoder: *mut AmfEncoder) -> i32;
+        unsafe fn amf_GetReorderDepth(encoder: *mut AmfEncoder) -> i32;
+        unsafe fn amf_FlushEncoder(encoder: *mut AmfEncoder) -> i32;
+        unsafe fn amf_MarkLtr(encoder: *mut AmfEncoder) -> i32;
+        unsafe fn amf_InvalidateRefFrames(encoder: *mut AmfEncoder, first: i64, last: i64) -> i32;
+        unsafe fn amf_SubmitFrame(encoder: *mut AmfEncoder, texture: *mut u8, timestamp: i64) -> i32;
+        unsafe fn amf_ReceiveFrame(encoder: *mut AmfEncoder, wait_ms: u32, status: *mut i32) -> *mut EncodedFrame;
         
         // AmfDecoder 方法
-        unsafe fn amf_CreateDecoder(device: *mut u8, codec_id: i32) -> *mut AmfDecoder;
+        unsafe fn amf_CreateDecoder(device: *mut u8, codec_id: i32, bit_depth: i32) -> *mut AmfDecoder;
         unsafe fn amf_DecodeFrame(decoder: *mut AmfDecoder, data: *mut u8, length: i32) -> *mut DecodedFrame;
+        unsafe fn amf_DrainFrame(decoder: *mut AmfDecoder) -> *mut DecodedFrame;
         unsafe fn amf_DestroyDecoder(decoder: *mut AmfDecoder);
         unsafe fn amf_GetWidth(decoder: *mut AmfDecoder) -> i32;
         unsafe fn amf_GetHeight(decoder: *mut AmfDecoder) -> i32;
 
         unsafe fn amf_FreeEncodedFrame(frame: *mut EncodedFrame);
         unsafe fn amf_FreeDecodedFrame(frame: *mut DecodedFrame);
+
+        unsafe fn amf_GetEncodeCaps(device: *mut u8, codec_id: i32) -> EncodeCaps;
+        unsafe fn amf_GetDecodeCaps(device: *mut u8, codec_id: i32) -> DecodeCaps;
     }
     
     struct EncodedFrame {
@@ -35,6 +49,11 @@ mod amf_bridge {
         size: i32,
         is_keyframe: bool,
         timestamp: i64,
+        picture_type: i32,
+        avg_qp: i32,
+        temporal_id: i32,
+        dts: i64,
+        has_dts: bool,
     }
     
     struct DecodedFrame {
@@ -42,6 +61,43 @@ mod amf_bridge {
         width: i32,
         height: i32,
     }
+
+    struct ColorDesc {
+        present: bool,
+        primaries: i32,
+        transfer: i32,
+        matrix: i32,
+        full_range: bool,
+        chroma_location: i32,
+    }
+
+    struct EncodeCaps {
+        supported: bool,
+        min_width: i32,
+        min_height: i32,
+        max_width: i32,
+        max_height: i32,
+        profiles: u32,
+        max_level: i32,
+        ten_bit: bool,
+        yuv444: bool,
+        max_bframes: i32,
+        lookahead: bool,
+        max_sessions: i32,
+        rate_control: u32,
+        max_ltr_frames: i32,
+    }
+
+    struct DecodeCaps {
+        supported: bool,
+        min_width: i32,
+        min_height: i32,
+        max_width: i32,
+        max_height: i32,
+        profiles: u32,
+        ten_bit: bool,
+        yuv444: bool,
+    }
 }
 
 pub use amf_bridge::*;
@@ -0,0 +1,111 @@
+//! Backend traits and frame types shared by the driver backends and the encode/decode API.
+
+use crate::vram::hdr::HdrMetadata;
+use crate::vram::metadata::PictureType;
+use crate::vram::ERR_UNSUPPORTED;
+use std::ffi::c_void;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EncodeFrame {
+    pub data: Vec<u8>,
+    pub pts: i64,
+    pub key: i32,
+    /// Decode timestamp, in the unit of `pts`. Reported by MFX, derived from the submission
+    /// order otherwise.
+    pub dts: Option<i64>,
+    pub picture_type: PictureType,
+    /// Average QP of the picture, where the SDK reports it (NVENC, AMF).
+    pub avg_qp: Option<i32>,
+    /// Temporal layer, 0 without temporal scalability.
+    pub temporal_id: Option<u8>,
+    /// Time from submitting the frame to receiving its packet.
+    pub latency: Duration,
+    /// Byte offset of the packet in the encoder's output stream.
+    pub offset: u64,
+    /// The packet carries a recovery point SEI: with intra refresh, decoding can start here and
+    /// is clean once the refresh wave has passed.
+    pub recovery_point: bool,
+}
+
+#[derive(Default)]
+pub struct DecodeFrame {
+    pub texture: *mut c_void,
+    pub width: i32,
+    pub height: i32,
+    /// HDR10 metadata of the stream, from the latest SEI messages up to this frame.
+    pub hdr: Option<HdrMetadata>,
+}
+
+/// Backend trait for encoding: Rust-owned API instead of C function table.
+pub trait EncodeBackend: Send {
+    fn encode(
+        &mut self,
+        tex: *mut c_void,
+        ms: i64,
+        frames: &mut Vec<EncodeFrame>,
+    ) -> Result<(), i32>;
+    fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32>;
+    fn set_framerate(&mut self, framerate: i32) -> Result<(), i32>;
+    fn destroy(&mut self);
+
+    /// Queues `tex` without waiting for its packet, which a later `receive` returns. Backends
+    /// without a pipelined mode encode synchronously and append the packet to `frames`.
+    /// Returns `ERR_QUEUE_FULL` when the driver cannot take another frame yet.
+    fn submit(
+        &mut self,
+        tex: *mut c_void,
+        ms: i64,
+        frames: &mut Vec<EncodeFrame>,
+    ) -> Result<(), i32> {
+        self.encode(tex, ms, frames)
+    }
+
+    /// Appends the packets of submitted frames that are done, in decode order, waiting up to
+    /// `timeout_ms` for the next one. An error consumes the oldest in-flight frame.
+    fn receive(&mut self, _timeout_ms: u32, _frames: &mut Vec<EncodeFrame>) -> Result<(), i32> {
+        Ok(())
+    }
+
+    /// Number of submitted frames whose packets have not been received yet.
+    fn pending(&self) -> usize {
+        0
+    }
+
+    /// Ends the stream so that frames held back for reordering get encoded; their packets are
+    /// returned by `receive`.
+    fn flush(&mut self) -> Result<(), i32> {
+        Ok(())
+    }
+
+    /// Frames the session holds back before a packet comes out (its B-frame count). Packets
+    /// then come in decode order, with `pts` out of order.
+    fn reorder_depth(&self) -> usize {
+        0
+    }
+
+    /// Marks the next submitted frame as a long-term reference, replacing the oldest one once
+    /// all slots are used.
+    fn mark_ltr(&mut self) -> Result<(), i32> {
+        Err(ERR_UNSUPPORTED)
+    }
+
+    /// Stops referencing the frames with `pts` in `first..=last` (lost on the receiver side).
+    /// The next frame references a long-term reference marked before them, or is an IDR.
+    fn invalidate(&mut self, _first: i64, _last: i64) -> Result<(), i32> {
+        Err(ERR_UNSUPPORTED)
+    }
+}
+
+/// Backend trait for decoding: Rust-owned API instead of C function table.
+pub trait DecodeBackend: Send {
+    fn decode(&mut self, data: &[u8], frames: &mut Vec<DecodeFrame>) -> Result<(), i32>;
+
+    /// Ends the stream and appends every picture the session still holds back for reordering,
+    /// in display order. The decoder must not be fed further packets afterwards.
+    fn flush(&mut self, _frames: &mut Vec<DecodeFrame>) -> Result<(), i32> {
+        Ok(())
+    }
+
+    fn destroy(&mut self);
+}
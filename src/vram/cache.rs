@@ -0,0 +1,232 @@
+//! Persistent cache of `Available`, so startup does not re-probe every driver.
+//!
+//! The cache file records a format version and the GPU signature it was probed on; a mismatch
+//! in either (new driver, different GPU, newer hwcodec layout) invalidates it.
+
+use crate::vram::Available;
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the serialized `Available` layout changes incompatibly.
+pub const CACHE_VERSION: u32 = 3;
+
+const CACHE_FILE_NAME: &str = "hwcodec_available.json";
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheFile {
+    version: u32,
+    signature: u64,
+    available: Available,
+}
+
+pub struct AvailableCache {
+    dir: PathBuf,
+    signature: u64,
+}
+
+impl AvailableCache {
+    /// Cache stored in `dir`, validated against the current `get_gpu_signature()`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_signature(dir, crate::common::get_gpu_signature())
+    }
+
+    /// Cache stored in `dir`, validated against `signature` instead of querying the GPU.
+    /// A signature of 0 means "unknown" and never matches.
+    pub fn with_signature(dir: impl Into<PathBuf>, signature: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            signature,
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(CACHE_FILE_NAME)
+    }
+
+    pub fn signature(&self) -> u64 {
+        self.signature
+    }
+
+    /// Returns the cached `Available` if the file exists and its version and signature match.
+    pub fn load(&self) -> Option<Available> {
+        if self.signature == 0 {
+            return None;
+        }
+        let path = self.path();
+        let s = std::fs::read_to_string(&path).ok()?;
+        let cache: CacheFile = match serde_json::from_str(&s) {
+            Ok(cache) => cache,
+            Err(e) => {
+                warn!("Ignoring corrupt capability cache {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if cache.version != CACHE_VERSION {
+            debug!(
+                "Capability cache version {} != {}, re-probing",
+                cache.version, CACHE_VERSION
+            );
+            return None;
+        }
+        if cache.signature != self.signature {
+            debug!(
+                "GPU signature changed ({} -> {}), re-probing",
+                cache.signature, self.signature
+            );
+            return None;
+        }
+        Some(cache.available)
+    }
+
+    /// Writes `available` to the cache. Does nothing when the signature is unknown (0).
+    pub fn store(&self, available: &Available) -> std::io::Result<()> {
+        if self.signature == 0 {
+            return Ok(());
+        }
+        let cache = CacheFile {
+            version: CACHE_VERSION,
+            signature: self.signature,
+            available: available.clone(),
+        };
+        let s = serde_json::to_string_pretty(&cache)?;
+        std::fs::create_dir_all(&self.dir)?;
+        // 先写临时文件再改名，避免进程中途退出留下半个文件
+        let tmp = self.dir.join(format!("{}.tmp", CACHE_FILE_NAME));
+        std::fs::write(&tmp, s)?;
+        std::fs::rename(&tmp, self.path())
+    }
+
+    /// Removes the cache file, if any.
+    pub fn clear(&self) -> std::io::Result<()> {
+        remove_if_exists(&self.path())
+    }
+
+    /// Returns the cached `Available`, or runs `probe` and caches its result.
+    pub fn get_or_probe(&self, probe: impl FnOnce() -> Available) -> Available {
+        if let Some(available) = self.load() {
+            debug!("Using capability cache {}", self.path().display());
+            return available;
+        }
+        let available = probe();
+        if let Err(e) = self.store(&available) {
+            warn!(
+                "Failed to write capability cache {}: {}",
+                self.path().display(),
+                e
+            );
+        }
+        available
+    }
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
+
+/// `Available::probe(d)` through a cache in `dir` keyed by the current GPU signature.
+#[cfg(windows)]
+pub fn available(dir: impl Into<PathBuf>, d: crate::vram::DynamicContext) -> Available {
+    AvailableCache::new(dir).get_or_probe(|| Available::probe(d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{DataFormat, Driver};
+    use crate::vram::FeatureContext;
+    use std::cell::Cell;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "hwcodec-cache-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn sample() -> Available {
+        Available {
+            e: vec![FeatureContext {
+                driver: Driver::MFX,
+                vendor: Driver::MFX,
+                luid: 42,
+                data_format: DataFormat::H264,
+            }],
+            d: vec![],
+            ec: vec![],
+            dc: vec![],
+            failed: vec![],
+        }
+    }
+
+    /// 测试签名一致时复用缓存，不再探测
+    #[test]
+    fn test_cache_hit() {
+        let dir = temp_dir("hit");
+        let cache = AvailableCache::with_signature(&dir, 7);
+        let probes = Cell::new(0);
+        let probe = || {
+            probes.set(probes.get() + 1);
+            sample()
+        };
+
+        assert_eq!(cache.get_or_probe(probe), sample());
+        assert_eq!(cache.get_or_probe(probe), sample());
+        assert_eq!(probes.get(), 1);
+        assert!(cache.path().exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// 测试签名变化、版本变化或文件损坏时重新探测
+    #[test]
+    fn test_cache_invalidation() {
+        let dir = temp_dir("invalidate");
+        AvailableCache::with_signature(&dir, 7).store(&sample()).unwrap();
+
+        assert!(AvailableCache::with_signature(&dir, 8).load().is_none());
+        assert_eq!(AvailableCache::with_signature(&dir, 7).load(), Some(sample()));
+
+        let cache = AvailableCache::with_signature(&dir, 7);
+        let s = std::fs::read_to_string(cache.path()).unwrap();
+        let old_version = s.replace(
+            &format!("\"version\": {}", CACHE_VERSION),
+            &format!("\"version\": {}", CACHE_VERSION + 1),
+        );
+        std::fs::write(cache.path(), old_version).unwrap();
+        assert!(cache.load().is_none());
+
+        std::fs::write(cache.path(), "not json").unwrap();
+        assert!(cache.load().is_none());
+        let mut probed = sample();
+        probed.e[0].luid = 43;
+        assert_eq!(cache.get_or_probe(|| probed.clone()), probed);
+        assert_eq!(cache.load(), Some(probed));
+
+        cache.clear().unwrap();
+        cache.clear().unwrap();
+        assert!(cache.load().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// 测试未知签名（0）既不读也不写缓存
+    #[test]
+    fn test_cache_unknown_signature() {
+        let dir = temp_dir("unknown");
+        let cache = AvailableCache::with_signature(&dir, 0);
+        let probes = Cell::new(0);
+        let probe = || {
+            probes.set(probes.get() + 1);
+            sample()
+        };
+        cache.get_or_probe(probe);
+        cache.get_or_probe(probe);
+        assert_eq!(probes.get(), 2);
+        assert!(!cache.path().exists());
+    }
+}
@@ -1,14 +1,29 @@
 use crate::{
-    common::{DataFormat::*, Driver::*},
-    vram::{amf, inner::DecodeBackend, mfx, nv, DecodeContext},
+    common::{BitDepth, DataFormat::*, Driver, Driver::*},
+    convert::{ColorSpace, Matrix, Range},
+    frame::Frame,
+    platform::win::{Device, TextureRef, WinPlatformError},
+    vram::{
+        amf,
+        hdr::HdrParser,
+        inner::DecodeBackend,
+        mfx, nv,
+        stats::{CodecStats, StatsRecorder},
+        DecodeContext, DecoderCaps, ERR_DEVICE_LOST,
+    },
 };
 use log::trace;
+use std::time::Instant;
 
 pub use crate::vram::inner::DecodeFrame;
 
 pub struct Decoder {
     backend: Box<dyn DecodeBackend>,
     frames: Vec<DecodeFrame>,
+    // Keeps the device passed to `with_device` alive for the decoder's lifetime.
+    device: Option<Device>,
+    stats: StatsRecorder,
+    hdr: HdrParser,
     pub ctx: DecodeContext,
 }
 
@@ -17,23 +32,109 @@ unsafe impl Sync for Decoder {}
 
 impl Decoder {
     pub fn new(ctx: DecodeContext) -> Result<Self, ()> {
-        let device = ctx.device.unwrap_or(std::ptr::null_mut());
-        let backend: Box<dyn DecodeBackend> = match ctx.driver {
-            NV => nv::create_decode_backend(device, ctx.luid, ctx.data_format as i32)?,
-            AMF => amf::create_decode_backend(device, ctx.luid, ctx.data_format as i32)?,
-            MFX => mfx::create_decode_backend(device, ctx.luid, ctx.data_format as i32)?,
-        };
-        Ok(Self {
+        let backend = create_backend(&ctx)?;
+        Ok(Self::with_backend(ctx, backend))
+    }
+
+    /// Decoder over an existing backend, e.g. a mock in tests.
+    pub(crate) fn with_backend(ctx: DecodeContext, backend: Box<dyn DecodeBackend>) -> Self {
+        Self {
             backend,
             frames: Vec::new(),
+            device: None,
+            stats: StatsRecorder::new(),
+            hdr: HdrParser::new(ctx.data_format),
             ctx,
-        })
+        }
+    }
+
+    /// Creates a decoder bound to `device`, holding a reference to it until the decoder is dropped.
+    pub fn with_device(mut ctx: DecodeContext, device: &Device) -> Result<Self, ()> {
+        ctx.device = Some(device.as_raw());
+        let mut decoder = Self::new(ctx)?;
+        decoder.device = Some(device.clone());
+        Ok(decoder)
     }
 
     pub fn decode(&mut self, packet: &[u8]) -> Result<&mut Vec<DecodeFrame>, i32> {
         self.frames.clear();
-        self.backend.decode(packet, &mut self.frames)?;
-        Ok(&mut self.frames)
+        let start = Instant::now();
+        let result = self.backend.decode(packet, &mut self.frames).map_err(|e| {
+            if self.device_lost() {
+                ERR_DEVICE_LOST
+            } else {
+                e
+            }
+        });
+        self.stats
+            .decoded(start, packet.len(), result.map(|()| self.frames.len()));
+        let hdr = self.hdr.update(packet);
+        self.frames.iter_mut().for_each(|frame| frame.hdr = hdr);
+        result.map(|()| &mut self.frames)
+    }
+
+    /// Ends the stream and returns every picture the decoder still holds back for reordering,
+    /// in display order. No packets may be decoded afterwards.
+    pub fn flush(&mut self) -> Result<&mut Vec<DecodeFrame>, i32> {
+        self.frames.clear();
+        let start = Instant::now();
+        let result = self.backend.flush(&mut self.frames).map_err(|e| {
+            if self.device_lost() {
+                ERR_DEVICE_LOST
+            } else {
+                e
+            }
+        });
+        self.stats
+            .decoder_flushed(start, result.map(|()| self.frames.len()));
+        let hdr = self.hdr.update(&[]);
+        self.frames.iter_mut().for_each(|frame| frame.hdr = hdr);
+        result.map(|()| &mut self.frames)
+    }
+
+    /// Snapshot of the counters since the decoder was created.
+    pub fn stats(&self) -> CodecStats {
+        self.stats.snapshot()
+    }
+
+    /// Whether the device the decoder was created on has been removed.
+    pub fn device_lost(&self) -> bool {
+        self.ctx
+            .device
+            .and_then(|device| unsafe { Device::from_raw(device) })
+            .is_some_and(|device| device.is_lost())
+    }
+}
+
+pub(crate) fn create_backend(ctx: &DecodeContext) -> Result<Box<dyn DecodeBackend>, ()> {
+    // 10 bit 仅支持 HEVC Main10
+    if ctx.bit_depth == BitDepth::Ten && ctx.data_format != H265 {
+        return Err(());
+    }
+    let device = ctx.device.unwrap_or(std::ptr::null_mut());
+    let (luid, codec_id, bit_depth) = (ctx.luid, ctx.data_format as i32, ctx.bit_depth);
+    match ctx.driver {
+        NV => nv::create_decode_backend(device, luid, codec_id, bit_depth),
+        AMF => amf::create_decode_backend(device, luid, codec_id, bit_depth),
+        MFX => mfx::create_decode_backend(device, luid, codec_id, bit_depth),
+    }
+}
+
+impl DecodeFrame {
+    /// Borrows the decoded texture. The borrow ends with the frame, i.e. before the next
+    /// `Decoder::decode` call may reuse the surface; use `TextureRef::to_owned` to keep it longer.
+    pub fn texture_ref(&self) -> Option<TextureRef<'_>> {
+        unsafe { TextureRef::from_raw(self.texture) }
+    }
+
+    /// Copies the decoded picture into system memory: 8-bit for NV12 output, 10-bit for P010.
+    /// The samples are tagged with `range`, which the decoder does not report.
+    pub fn read_frame(&self, range: Range) -> crate::platform::win::error::Result<Frame> {
+        let texture = self.texture_ref().ok_or_else(|| {
+            WinPlatformError::InvalidParameter("the frame has no texture".to_string())
+        })?;
+        let color = ColorSpace::new(Matrix::default(), range);
+        texture.read_frame(self.width as u32, self.height as u32, color)
     }
 }
 
@@ -44,28 +145,52 @@ impl Drop for Decoder {
     }
 }
 
+/// Queries detailed limits of the decoder described by `ctx`, using a temporary device on the
+/// adapter with `ctx.luid`.
+pub fn capabilities(ctx: &DecodeContext) -> Result<DecoderCaps, ()> {
+    let caps = crate::vram::inner::with_adapter_device(&ctx.vendor, ctx.luid, |device| {
+        match ctx.driver {
+            NV => nv::decoder_caps(device, ctx),
+            AMF => amf::decoder_caps(device, ctx),
+            MFX => mfx::decoder_caps(device, ctx),
+        }
+    });
+    caps.flatten().ok_or(())
+}
+
 pub fn available() -> Vec<DecodeContext> {
+    available_for(&[NV, AMF, MFX])
+}
+
+/// Like `available`, but only tests the given drivers.
+pub fn available_for(drivers: &[Driver]) -> Vec<DecodeContext> {
     use log::debug;
 
     let mut codecs: Vec<_> = vec![];
-    codecs.append(
-        &mut nv::possible_support_decoders()
-            .drain(..)
-            .map(|n| (NV, n))
-            .collect(),
-    );
-    codecs.append(
-        &mut amf::possible_support_decoders()
-            .drain(..)
-            .map(|n| (AMF, n))
-            .collect(),
-    );
-    codecs.append(
-        &mut mfx::possible_support_decoders()
-            .drain(..)
-            .map(|n| (MFX, n))
-            .collect(),
-    );
+    if drivers.contains(&NV) {
+        codecs.append(
+            &mut nv::possible_support_decoders()
+                .drain(..)
+                .map(|n| (NV, n))
+                .collect(),
+        );
+    }
+    if drivers.contains(&AMF) {
+        codecs.append(
+            &mut amf::possible_support_decoders()
+                .drain(..)
+                .map(|n| (AMF, n))
+                .collect(),
+        );
+    }
+    if drivers.contains(&MFX) {
+        codecs.append(
+            &mut mfx::possible_support_decoders()
+                .drain(..)
+                .map(|n| (MFX, n))
+                .collect(),
+        );
+    }
 
     let inputs: Vec<DecodeContext> = codecs
         .drain(..)
@@ -75,6 +200,7 @@ pub fn available() -> Vec<DecodeContext> {
             vendor: driver, // Initially set vendor same as driver, will be updated by test results
             data_format: n.data_format,
             luid: 0,
+            bit_depth: BitDepth::Eight,
         })
         .collect();
 
@@ -162,3 +288,120 @@ pub fn available() -> Vec<DecodeContext> {
 
     outputs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vram::decode_pipeline::tests::MockBackend;
+    use std::collections::BTreeMap;
+
+    fn decoder(backend: MockBackend) -> Decoder {
+        let ctx = DecodeContext {
+            device: None,
+            driver: NV,
+            vendor: NV,
+            luid: 0,
+            data_format: H264,
+            bit_depth: BitDepth::Eight,
+        };
+        Decoder::with_backend(ctx, Box::new(backend))
+    }
+
+    /// 测试 decode 更新统计：缓存不算丢帧，失败的包计入错误码
+    #[test]
+    fn test_stats() {
+        let mut decoder = decoder(MockBackend::new());
+        // 模拟后端：首字节为产出的帧数，空包报错
+        assert_eq!(decoder.decode(&[1]).unwrap().len(), 1);
+        assert!(decoder.decode(&[0]).unwrap().is_empty());
+        assert_eq!(decoder.decode(&[2, 16]).unwrap().len(), 2);
+        assert_eq!(decoder.decode(&[]).err(), Some(-1));
+
+        let stats = decoder.stats();
+        assert_eq!((stats.frames_in, stats.frames_out), (4, 3));
+        assert_eq!((stats.bytes, stats.dropped), (4, 1));
+        assert_eq!(stats.errors, BTreeMap::from([(-1, 1)]));
+    }
+
+    /// 测试 flush 送出重排扣住的帧：送入 N 帧得到 N 帧，按显示顺序，flush 不计入输入
+    #[test]
+    fn test_flush() {
+        let mut backend = MockBackend::new();
+        backend.hold = 2;
+        let mut decoder = decoder(backend);
+        let ids = |frames: &mut Vec<DecodeFrame>| -> Vec<usize> {
+            frames.iter().map(|f| f.texture as usize).collect()
+        };
+        let mut out = Vec::new();
+        for _ in 0..5 {
+            out.extend(ids(decoder.decode(&[1]).unwrap()));
+        }
+        assert_eq!(out, vec![1, 2, 3]);
+        out.extend(ids(decoder.flush().unwrap()));
+        assert_eq!(out, vec![1, 2, 3, 4, 5]);
+        assert!(decoder.flush().unwrap().is_empty());
+
+        let stats = decoder.stats();
+        assert_eq!((stats.frames_in, stats.frames_out), (5, 5));
+        assert_eq!((stats.bytes, stats.dropped), (5, 0));
+    }
+
+    /// 测试 B 帧码流逐包解码再 flush，每个驱动送入 N 帧都得到 N 帧（需要 GPU）
+    #[test]
+    #[ignore] // 需要 GPU，默认忽略
+    fn test_flush_bframes() {
+        use crate::platform::win::NativeDevice;
+        use crate::testsrc::{Pattern, TestSource};
+        use crate::vram::{encode, encode::Encoder, DynamicContext, EncodeContext};
+
+        const FRAMES: usize = 16;
+        let d = DynamicContext {
+            device: None,
+            width: 320,
+            height: 240,
+            kbitrate: 2000,
+            framerate: 30,
+            gop: 60,
+            bframes: 2,
+            intra_refresh: None,
+            ltr_frames: 0,
+            color: None,
+            bit_depth: BitDepth::Eight,
+            yuv444: false,
+            hdr: None,
+            scale: None,
+        };
+        for f in encode::available(d) {
+            let native = NativeDevice::new(f.luid, None, 0).unwrap();
+            let device = Device::from(native.device().clone());
+            let decode = DecodeContext {
+                device: None,
+                driver: f.driver.clone(),
+                vendor: f.vendor.clone(),
+                luid: f.luid,
+                data_format: f.data_format,
+                bit_depth: BitDepth::Eight,
+            };
+            let ctx = EncodeContext { f: f.clone(), d };
+            // 不支持 B 帧的驱动创建失败，跳过
+            let Ok(mut encoder) = Encoder::with_device(ctx, &device) else {
+                continue;
+            };
+            let mut decoder = Decoder::with_device(decode, &device).unwrap();
+            let mut source = TestSource::new(Pattern::ZonePlate, 320, 240);
+            let mut packets = Vec::new();
+            for i in 0..FRAMES {
+                let texture = encoder.upload(&source.next_frame()).unwrap();
+                packets.append(encoder.encode_texture(&texture, i as i64 * 33).unwrap());
+            }
+            packets.append(encoder.flush().unwrap());
+
+            let mut decoded = 0;
+            for packet in &packets {
+                decoded += decoder.decode(&packet.data).unwrap().len();
+            }
+            decoded += decoder.flush().unwrap().len();
+            assert_eq!(decoded, FRAMES, "{:?}", f);
+        }
+    }
+}
@@ -0,0 +1,702 @@
+//! Pipelined decoding into a bounded pool of output surfaces.
+//!
+//! `Decoder::decode` hands out the driver's own textures, which the next call may overwrite.
+//! `AsyncDecoder` copies every decoded frame into a surface it owns and returns it as a
+//! `PooledFrame`; dropping the frame puts the surface back. When every surface is held by the
+//! consumer, decoded frames wait in the driver's texture and `submit` returns `ERR_QUEUE_FULL`
+//! instead of decoding over them.
+//!
+//! ```ignore
+//! let mut decoder = AsyncDecoder::new(ctx, DEFAULT_POOL_SIZE)?;
+//! for frame in decoder.frames(packets) {
+//!     render(frame?.texture());
+//! } // 帧在此释放，表面回到池中
+//! ```
+
+use crate::vram::backend::{DecodeBackend, DecodeFrame};
+use crate::vram::hdr::{HdrMetadata, HdrParser};
+use crate::vram::{ERR_QUEUE_FULL, ERR_TIMEOUT};
+use log::trace;
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Output surfaces when the caller has no preference.
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/// Upper bound for the pool size.
+pub const MAX_POOL_SIZE: usize = 32;
+
+// frames 迭代器等待消费者归还表面的时长
+const SURFACE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Creates and fills the pool's output surfaces.
+pub trait SurfaceAllocator: Send + Sync {
+    /// Creates a surface that frames like `template` can be copied into.
+    ///
+    /// # Safety
+    /// `template` must be a texture produced by the decode backend.
+    unsafe fn alloc(&self, template: *mut c_void) -> Result<*mut c_void, i32>;
+
+    /// Copies the decoded texture `src` into `dst`.
+    ///
+    /// # Safety
+    /// `src` must be a texture produced by the decode backend and `dst` a live surface from
+    /// `alloc` with the same size.
+    unsafe fn copy(&self, src: *mut c_void, dst: *mut c_void) -> Result<(), i32>;
+
+    /// Releases a surface.
+    ///
+    /// # Safety
+    /// `surface` must come from `alloc` and must not be used afterwards.
+    unsafe fn free(&self, surface: *mut c_void);
+}
+
+/// Allocates D3D11 textures on the decoder's device and fills them with `CopyResource`.
+#[cfg(windows)]
+pub struct TextureAllocator;
+
+#[cfg(windows)]
+impl SurfaceAllocator for TextureAllocator {
+    unsafe fn alloc(&self, template: *mut c_void) -> Result<*mut c_void, i32> {
+        use windows::core::Interface;
+        use windows::Win32::Graphics::Direct3D11::*;
+
+        let template = crate::platform::win::Texture::from_raw(template).ok_or(-1)?;
+        let mut desc = template.desc();
+        desc.MipLevels = 1;
+        desc.ArraySize = 1;
+        desc.Usage = D3D11_USAGE_DEFAULT;
+        desc.BindFlags &= !(D3D11_BIND_DECODER.0 as u32);
+        desc.CPUAccessFlags = 0;
+        desc.MiscFlags = 0;
+        let device = template.device().map_err(|_| -1)?;
+        let mut texture = None;
+        device
+            .inner()
+            .CreateTexture2D(&desc, None, Some(&mut texture))
+            .map_err(|_| -1)?;
+        Ok(texture.ok_or(-1)?.into_raw())
+    }
+
+    unsafe fn copy(&self, src: *mut c_void, dst: *mut c_void) -> Result<(), i32> {
+        use crate::platform::win::Texture;
+
+        let src = Texture::from_raw(src).ok_or(-1)?;
+        let dst = Texture::from_raw(dst).ok_or(-1)?;
+        let device = dst.device().map_err(|_| -1)?;
+        let context = device.inner().GetImmediateContext().map_err(|_| -1)?;
+        context.CopyResource(dst.inner(), src.inner());
+        Ok(())
+    }
+
+    unsafe fn free(&self, surface: *mut c_void) {
+        use windows::core::Interface;
+        use windows::Win32::Graphics::Direct3D11::ID3D11Texture2D;
+
+        if !surface.is_null() {
+            drop(ID3D11Texture2D::from_raw(surface));
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Surface {
+    texture: *mut c_void,
+    width: i32,
+    height: i32,
+}
+
+struct PoolState {
+    free: Vec<Surface>,
+    // 已创建的表面总数，含空闲、待交付与消费者持有的
+    allocated: usize,
+}
+
+struct Pool {
+    size: usize,
+    allocator: Box<dyn SurfaceAllocator>,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+// 表面只在解码线程上创建与拷贝，归还与释放（Release）可在任意线程
+unsafe impl Send for Pool {}
+unsafe impl Sync for Pool {}
+
+impl Pool {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A surface for `frame`, or `None` when all `size` surfaces are in use.
+    fn take(&self, frame: &DecodeFrame) -> Result<Option<Surface>, i32> {
+        let mut state = self.lock();
+        if let Some(i) = state
+            .free
+            .iter()
+            .position(|s| s.width == frame.width && s.height == frame.height)
+        {
+            return Ok(Some(state.free.swap_remove(i)));
+        }
+        // 分辨率变化后旧尺寸的空闲表面不再可用，释放后按新尺寸重建
+        if state.allocated >= self.size {
+            match state.free.pop() {
+                Some(stale) => {
+                    unsafe { self.allocator.free(stale.texture) };
+                    state.allocated -= 1;
+                }
+                None => return Ok(None),
+            }
+        }
+        let texture = unsafe { self.allocator.alloc(frame.texture) }?;
+        state.allocated += 1;
+        Ok(Some(Surface {
+            texture,
+            width: frame.width,
+            height: frame.height,
+        }))
+    }
+
+    fn put(&self, surface: Surface) {
+        self.lock().free.push(surface);
+        self.returned.notify_all();
+    }
+
+    /// Waits up to `timeout` for a surface to become available.
+    fn wait(&self, timeout: Duration) {
+        let state = self.lock();
+        let _ = self
+            .returned
+            .wait_timeout_while(state, timeout, |s| {
+                s.free.is_empty() && s.allocated >= self.size
+            })
+            .unwrap_or_else(|e| e.into_inner());
+    }
+
+    fn in_use(&self) -> usize {
+        let state = self.lock();
+        state.allocated - state.free.len()
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        for surface in state.free.drain(..) {
+            unsafe { self.allocator.free(surface.texture) };
+        }
+    }
+}
+
+/// A decoded frame in a pool surface. The surface goes back to the pool when the frame is
+/// dropped, so the frame may be kept across `submit` calls and sent to other threads.
+pub struct PooledFrame {
+    surface: Surface,
+    hdr: Option<HdrMetadata>,
+    pool: Arc<Pool>,
+}
+
+unsafe impl Send for PooledFrame {}
+
+impl PooledFrame {
+    pub fn texture(&self) -> *mut c_void {
+        self.surface.texture
+    }
+
+    pub fn width(&self) -> i32 {
+        self.surface.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.surface.height
+    }
+
+    /// HDR10 metadata of the stream, see `DecodeFrame::hdr`.
+    pub fn hdr(&self) -> Option<HdrMetadata> {
+        self.hdr
+    }
+
+    /// Borrows the texture for the lifetime of the frame.
+    #[cfg(windows)]
+    pub fn texture_ref(&self) -> Option<crate::platform::win::TextureRef<'_>> {
+        unsafe { crate::platform::win::TextureRef::from_raw(self.surface.texture) }
+    }
+}
+
+impl Drop for PooledFrame {
+    fn drop(&mut self) {
+        self.pool.put(self.surface);
+    }
+}
+
+pub struct AsyncDecoder {
+    backend: Box<dyn DecodeBackend>,
+    pool: Arc<Pool>,
+    // 驱动已解出、等待空闲表面的帧；其纹理在下次调用驱动前有效，因此清空前不再 decode
+    staged: VecDeque<DecodeFrame>,
+    // 已拷入表面、尚未交给调用方的帧
+    ready: VecDeque<PooledFrame>,
+    // 仅 new 知道码流格式；with_backend 创建的解码器不解析 HDR 元数据
+    hdr: Option<HdrParser>,
+    #[cfg(windows)]
+    device: Option<*mut c_void>,
+}
+
+unsafe impl Send for AsyncDecoder {}
+
+impl AsyncDecoder {
+    /// Opens a decoder whose frames live in at most `pool_size` surfaces, clamped to
+    /// `1..=MAX_POOL_SIZE`.
+    #[cfg(windows)]
+    pub fn new(ctx: crate::vram::DecodeContext, pool_size: usize) -> Result<Self, ()> {
+        let backend = crate::vram::decode::create_backend(&ctx)?;
+        let mut decoder = Self::with_backend(backend, Box::new(TextureAllocator), pool_size);
+        decoder.device = ctx.device;
+        decoder.hdr = Some(HdrParser::new(ctx.data_format));
+        Ok(decoder)
+    }
+
+    /// Like `new`, over an already created backend and surface allocator.
+    pub fn with_backend(
+        backend: Box<dyn DecodeBackend>,
+        allocator: Box<dyn SurfaceAllocator>,
+        pool_size: usize,
+    ) -> Self {
+        let pool = Pool {
+            size: pool_size.clamp(1, MAX_POOL_SIZE),
+            allocator,
+            state: Mutex::new(PoolState {
+                free: Vec::new(),
+                allocated: 0,
+            }),
+            returned: Condvar::new(),
+        };
+        Self {
+            backend,
+            pool: Arc::new(pool),
+            staged: VecDeque::new(),
+            ready: VecDeque::new(),
+            hdr: None,
+            #[cfg(windows)]
+            device: None,
+        }
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool.size
+    }
+
+    /// Surfaces currently held by frames, whether already returned by `receive` or not.
+    pub fn in_use(&self) -> usize {
+        self.pool.in_use()
+    }
+
+    /// Decoded frames not returned by `receive` yet.
+    pub fn buffered(&self) -> usize {
+        self.staged.len() + self.ready.len()
+    }
+
+    /// Decodes `packet`; its frames come out of `receive`. Returns `ERR_QUEUE_FULL` without
+    /// decoding while earlier frames still wait for a free surface.
+    pub fn submit(&mut self, packet: &[u8]) -> Result<(), i32> {
+        self.stage()?;
+        if !self.staged.is_empty() {
+            return Err(ERR_QUEUE_FULL);
+        }
+        let mut frames = Vec::new();
+        let result = self.backend.decode(packet, &mut frames);
+        if let Some(parser) = &mut self.hdr {
+            let hdr = parser.update(packet);
+            frames.iter_mut().for_each(|frame| frame.hdr = hdr);
+        }
+        self.staged.extend(frames);
+        result.map_err(|e| self.map_err(e))?;
+        self.stage()
+    }
+
+    /// Ends the stream; the frames the driver holds back for reordering come out of `receive`.
+    /// Returns `ERR_QUEUE_FULL` without flushing while earlier frames still wait for a free
+    /// surface. No packets may be submitted afterwards.
+    pub fn flush(&mut self) -> Result<(), i32> {
+        self.stage()?;
+        if !self.staged.is_empty() {
+            return Err(ERR_QUEUE_FULL);
+        }
+        let mut frames = Vec::new();
+        let result = self.backend.flush(&mut frames);
+        if let Some(parser) = &mut self.hdr {
+            let hdr = parser.update(&[]);
+            frames.iter_mut().for_each(|frame| frame.hdr = hdr);
+        }
+        self.staged.extend(frames);
+        result.map_err(|e| self.map_err(e))?;
+        self.stage()
+    }
+
+    /// Returns the oldest decoded frame, waiting up to `timeout` for a surface if all are in
+    /// use. `Ok(None)` when nothing is buffered or no surface came back in time.
+    pub fn receive(&mut self, timeout: Duration) -> Result<Option<PooledFrame>, i32> {
+        self.stage()?;
+        if self.ready.is_empty() && !self.staged.is_empty() {
+            self.pool.wait(timeout);
+            self.stage()?;
+        }
+        Ok(self.ready.pop_front())
+    }
+
+    /// Blocking iterator that decodes `packets` and yields their frames in order, flushing
+    /// the decoder once they run out. Yields `ERR_TIMEOUT` when no surface is returned for a
+    /// few seconds, e.g. because the frames are collected instead of dropped; the frame is
+    /// yielded by the next call.
+    pub fn frames<I>(&mut self, packets: I) -> Frames<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Frames {
+            decoder: self,
+            packets: packets.into_iter(),
+            flushed: false,
+        }
+    }
+
+    /// Whether the device the decoder was created on has been removed.
+    #[cfg(windows)]
+    pub fn device_lost(&self) -> bool {
+        self.device
+            .and_then(|device| unsafe { crate::platform::win::Device::from_raw(device) })
+            .is_some_and(|device| device.is_lost())
+    }
+
+    // 将等待中的帧依次拷入空闲表面；拷贝失败的帧被丢弃
+    fn stage(&mut self) -> Result<(), i32> {
+        while let Some(frame) = self.staged.front() {
+            let surface = match self.pool.take(frame) {
+                Ok(Some(surface)) => surface,
+                Ok(None) => break,
+                Err(e) => {
+                    self.staged.pop_front();
+                    return Err(self.map_err(e));
+                }
+            };
+            let frame = self.staged.pop_front().unwrap_or_default();
+            if let Err(e) = unsafe { self.pool.allocator.copy(frame.texture, surface.texture) } {
+                self.pool.put(surface);
+                return Err(self.map_err(e));
+            }
+            self.ready.push_back(PooledFrame {
+                surface,
+                hdr: frame.hdr,
+                pool: self.pool.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn map_err(&self, e: i32) -> i32 {
+        #[cfg(windows)]
+        if self.device_lost() {
+            return crate::vram::ERR_DEVICE_LOST;
+        }
+        e
+    }
+}
+
+impl Drop for AsyncDecoder {
+    fn drop(&mut self) {
+        self.staged.clear();
+        self.ready.clear();
+        self.backend.destroy();
+        trace!("AsyncDecoder dropped");
+    }
+}
+
+/// Iterator returned by `AsyncDecoder::frames`.
+pub struct Frames<'a, I> {
+    decoder: &'a mut AsyncDecoder,
+    packets: I,
+    flushed: bool,
+}
+
+impl<I> Iterator for Frames<'_, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = Result<PooledFrame, i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.decoder.buffered() > 0 {
+                return Some(match self.decoder.receive(SURFACE_TIMEOUT) {
+                    Ok(Some(frame)) => Ok(frame),
+                    Ok(None) => Err(ERR_TIMEOUT),
+                    Err(e) => Err(e),
+                });
+            }
+            let result = match self.packets.next() {
+                Some(packet) => self.decoder.submit(packet.as_ref()),
+                // 包已取完：送出驱动为重排扣住的帧，之后结束
+                None if !self.flushed => {
+                    self.flushed = true;
+                    self.decoder.flush()
+                }
+                None => return None,
+            };
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 模拟驱动：包 [n, w] 解出 n 帧，宽为 w；纹理指针即帧序号，每次 decode 后被覆盖。
+    /// 最多扣住 hold 帧模拟 B 帧重排，flush 时全部送出
+    pub(crate) struct MockBackend {
+        next_id: usize,
+        pub hold: usize,
+        // (帧序号, 宽)
+        held: VecDeque<(usize, i32)>,
+        pub destroyed: Arc<Mutex<bool>>,
+    }
+
+    impl MockBackend {
+        pub(crate) fn new() -> Self {
+            Self {
+                next_id: 1,
+                hold: 0,
+                held: VecDeque::new(),
+                destroyed: Arc::default(),
+            }
+        }
+    }
+
+    impl DecodeBackend for MockBackend {
+        fn decode(&mut self, data: &[u8], frames: &mut Vec<DecodeFrame>) -> Result<(), i32> {
+            if data.is_empty() {
+                return Err(-1);
+            }
+            for _ in 0..data[0] {
+                let width = data.get(1).copied().unwrap_or(16) as i32;
+                self.held.push_back((self.next_id, width));
+                self.next_id += 1;
+            }
+            while self.held.len() > self.hold {
+                frames.extend(self.held.pop_front().map(frame));
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self, frames: &mut Vec<DecodeFrame>) -> Result<(), i32> {
+            frames.extend(self.held.drain(..).map(frame));
+            Ok(())
+        }
+
+        fn destroy(&mut self) {
+            *self.destroyed.lock().unwrap() = true;
+        }
+    }
+
+    fn frame((id, width): (usize, i32)) -> DecodeFrame {
+        DecodeFrame {
+            texture: id as *mut c_void,
+            width,
+            height: 16,
+            hdr: None,
+        }
+    }
+
+    /// 表面为堆上的 usize，拷贝即写入源帧序号；记录存活表面数
+    #[derive(Default)]
+    pub(crate) struct MockAllocator {
+        pub live: Arc<Mutex<usize>>,
+    }
+
+    impl SurfaceAllocator for MockAllocator {
+        unsafe fn alloc(&self, _template: *mut c_void) -> Result<*mut c_void, i32> {
+            *self.live.lock().unwrap() += 1;
+            Ok(Box::into_raw(Box::new(0usize)) as *mut c_void)
+        }
+
+        unsafe fn copy(&self, src: *mut c_void, dst: *mut c_void) -> Result<(), i32> {
+            *(dst as *mut usize) = src as usize;
+            Ok(())
+        }
+
+        unsafe fn free(&self, surface: *mut c_void) {
+            *self.live.lock().unwrap() -= 1;
+            drop(Box::from_raw(surface as *mut usize));
+        }
+    }
+
+    pub(crate) fn id(frame: &PooledFrame) -> usize {
+        unsafe { *(frame.texture() as *const usize) }
+    }
+
+    fn decoder(pool_size: usize) -> (AsyncDecoder, Arc<Mutex<usize>>) {
+        reorder_decoder(pool_size, 0)
+    }
+
+    // 驱动扣住 hold 帧用于重排
+    fn reorder_decoder(pool_size: usize, hold: usize) -> (AsyncDecoder, Arc<Mutex<usize>>) {
+        let mut backend = MockBackend::new();
+        backend.hold = hold;
+        let allocator = MockAllocator::default();
+        let live = allocator.live.clone();
+        let decoder = AsyncDecoder::with_backend(Box::new(backend), Box::new(allocator), pool_size);
+        (decoder, live)
+    }
+
+    const WAIT: Duration = Duration::from_millis(10);
+
+    /// 测试帧在消费者持有期间不被覆盖，表面用尽时 submit 反压，释放帧后继续
+    #[test]
+    fn test_backpressure() {
+        let (mut decoder, live) = decoder(2);
+
+        decoder.submit(&[1]).unwrap();
+        let first = decoder.receive(WAIT).unwrap().unwrap();
+        decoder.submit(&[2]).unwrap();
+        // 第二个包的第 2 帧没有空闲表面，留在驱动纹理中
+        assert_eq!(decoder.buffered(), 2);
+        assert_eq!(decoder.submit(&[1]), Err(ERR_QUEUE_FULL));
+        let second = decoder.receive(WAIT).unwrap().unwrap();
+        assert!(decoder.receive(WAIT).unwrap().is_none());
+        assert_eq!((id(&first), id(&second)), (1, 2));
+        assert_eq!(decoder.in_use(), 2);
+
+        drop(first);
+        let third = decoder.receive(WAIT).unwrap().unwrap();
+        assert_eq!(id(&third), 3);
+        assert_eq!(id(&second), 2);
+        decoder.submit(&[1]).unwrap();
+        assert!(decoder.receive(WAIT).unwrap().is_none());
+        drop((second, third));
+        assert_eq!(decoder.receive(WAIT).unwrap().map(|f| id(&f)), Some(4));
+        assert_eq!(*live.lock().unwrap(), 2);
+
+        let destroyed = {
+            let backend = MockBackend::new();
+            let destroyed = backend.destroyed.clone();
+            let allocator = MockAllocator::default();
+            let live = allocator.live.clone();
+            let mut decoder = AsyncDecoder::with_backend(Box::new(backend), Box::new(allocator), 2);
+            decoder.submit(&[1]).unwrap();
+            let kept = decoder.receive(WAIT).unwrap().unwrap();
+            drop(decoder);
+            // 解码器释放后，消费者持有的帧仍然有效
+            assert_eq!(id(&kept), 1);
+            drop(kept);
+            assert_eq!(*live.lock().unwrap(), 0);
+            destroyed
+        };
+        assert!(*destroyed.lock().unwrap());
+    }
+
+    /// 测试分辨率变化时重建表面，池大小不超过上限
+    #[test]
+    fn test_resize() {
+        let (mut decoder, live) = decoder(2);
+        decoder.submit(&[2, 16]).unwrap();
+        drop(decoder.receive(WAIT).unwrap());
+        drop(decoder.receive(WAIT).unwrap());
+        decoder.submit(&[1, 32]).unwrap();
+        let frame = decoder.receive(WAIT).unwrap().unwrap();
+        assert_eq!((id(&frame), frame.width()), (3, 32));
+        assert_eq!(*live.lock().unwrap(), 2);
+        assert_eq!(decoder.submit(&[]), Err(-1));
+
+        let (decoder, _) = super::tests::decoder(99);
+        assert_eq!(decoder.pool_size(), MAX_POOL_SIZE);
+    }
+
+    /// 测试阻塞迭代器按顺序输出，并在其他线程释放帧后继续
+    #[test]
+    fn test_frames_iter() {
+        let (mut decoder, _) = decoder(2);
+        let ids: Vec<_> = decoder
+            .frames([[1u8], [2], [0], [1]])
+            .map(|f| id(&f.unwrap()))
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        let (tx, rx) = std::sync::mpsc::channel::<PooledFrame>();
+        let consumer = std::thread::spawn(move || {
+            rx.into_iter()
+                .map(|f| {
+                    std::thread::sleep(Duration::from_millis(5));
+                    id(&f)
+                })
+                .collect::<Vec<_>>()
+        });
+        // 前一个迭代器结束时已 flush，换新的解码器
+        let (mut decoder, _) = super::tests::decoder(2);
+        for frame in decoder.frames([[3u8], [2]]) {
+            tx.send(frame.unwrap()).unwrap();
+        }
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), vec![1, 2, 3, 4, 5]);
+    }
+
+    /// 测试驱动为重排扣住的帧在 flush 后送出：送入 N 帧得到 N 帧，表面用尽时 flush 反压
+    #[test]
+    fn test_flush() {
+        let (mut decoder, _) = reorder_decoder(2, 2);
+        for _ in 0..3 {
+            decoder.submit(&[1]).unwrap();
+        }
+        let first = decoder.receive(WAIT).unwrap().unwrap();
+        assert!(decoder.receive(WAIT).unwrap().is_none());
+        decoder.flush().unwrap();
+        let second = decoder.receive(WAIT).unwrap().unwrap();
+        // 第 3 帧等待空闲表面
+        assert_eq!(decoder.buffered(), 1);
+        assert!(decoder.receive(WAIT).unwrap().is_none());
+        assert_eq!(decoder.flush(), Err(ERR_QUEUE_FULL));
+        drop(first);
+        let third = decoder.receive(WAIT).unwrap().unwrap();
+        assert_eq!((id(&second), id(&third)), (2, 3));
+
+        let (mut decoder, _) = reorder_decoder(2, 2);
+        let ids: Vec<_> = decoder
+            .frames([[1u8], [2], [0], [1]])
+            .map(|f| id(&f.unwrap()))
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+    }
+
+    /// 测试帧携带码流中最近的 HDR10 元数据
+    #[test]
+    fn test_hdr() {
+        use crate::common::DataFormat;
+        use crate::vram::hdr::{self, ContentLightLevel};
+
+        let (mut decoder, _) = decoder(2);
+        decoder.submit(&[1]).unwrap();
+        assert_eq!(decoder.receive(WAIT).unwrap().unwrap().hdr(), None);
+
+        decoder.hdr = Some(HdrParser::new(DataFormat::H265));
+        let metadata = HdrMetadata {
+            mastering_display: None,
+            content_light_level: Some(ContentLightLevel {
+                max_cll: 1000,
+                max_fall: 400,
+            }),
+        };
+        // 首字节为 mock 解出的帧数，其后是 SEI NAL
+        let sei = hdr::sei_nal(DataFormat::H265, &metadata).unwrap();
+        decoder.submit(&[&[1], &sei[..]].concat()).unwrap();
+        let frame = decoder.receive(WAIT).unwrap().unwrap();
+        assert_eq!(frame.hdr(), Some(metadata));
+        // 之后不带 SEI 的包沿用同一元数据
+        decoder.submit(&[1]).unwrap();
+        let frame = decoder.receive(WAIT).unwrap().unwrap();
+        assert_eq!(frame.hdr(), Some(metadata));
+    }
+}
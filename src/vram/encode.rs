@@ -1,18 +1,46 @@
 use crate::{
-    common::Driver::*,
+    common::{BitDepth, DataFormat, Driver, Driver::*},
+    convert::{Matrix, Rect, Size, Transfer},
+    frame::{Frame, Subsampling},
+    platform::win::{
+        texture::get_texture_width_height, Device, NativeDevice, Texture, WinPlatformError,
+    },
     vram::{
-        amf, inner::EncodeBackend, mfx, nv,
-        DynamicContext, EncodeContext, FeatureContext,
+        amf,
+        inner::EncodeBackend,
+        metadata::Annotator,
+        mfx, nv,
+        stats::{CodecStats, StatsRecorder},
+        DynamicContext, EncodeContext, EncodeScale, EncoderCaps, FeatureContext, ERR_DEVICE_LOST,
+        ERR_TEXTURE_MISMATCH, ERR_TIMEOUT,
     },
 };
 use log::trace;
 use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::time::Instant;
+use windows::core::Interface;
+use windows::Win32::Graphics::Direct3D11::{
+    ID3D11Texture2D, D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE, D3D11_TEXTURE2D_DESC,
+    D3D11_USAGE_DEFAULT,
+};
+use windows::Win32::Graphics::Dxgi::Common::*;
 
 pub use crate::vram::inner::EncodeFrame;
 
+// B 帧会话中等待下一个包的时长，与同步编码的等待一致
+const RECEIVE_TIMEOUT_MS: u32 = 3000;
+
 pub struct Encoder {
     backend: Box<dyn EncodeBackend>,
     frames: Vec<EncodeFrame>,
+    // Keeps the device passed to `with_device` alive for the encoder's lifetime.
+    device: Option<Device>,
+    stats: StatsRecorder,
+    annotator: Annotator,
+    // 0 时 encode 一帧进一帧出；否则为 B 帧会话，包按解码顺序滞后输出
+    reorder_depth: usize,
+    scaler: Option<Scaler>,
     pub ctx: EncodeContext,
 }
 
@@ -24,26 +52,255 @@ impl Encoder {
         if ctx.d.width % 2 == 1 || ctx.d.height % 2 == 1 {
             return Err(());
         }
-        let device = ctx.d.device.unwrap_or(std::ptr::null_mut());
-        let backend: Box<dyn EncodeBackend> = match ctx.f.driver {
-            NV => nv::create_encode_backend(device, ctx.f.luid, ctx.f.data_format as i32,
-                ctx.d.width, ctx.d.height, ctx.d.kbitrate, ctx.d.framerate, ctx.d.gop)?,
-            AMF => amf::create_encode_backend(device, ctx.f.luid, ctx.f.data_format as i32,
-                ctx.d.width, ctx.d.height, ctx.d.kbitrate, ctx.d.framerate, ctx.d.gop)?,
-            MFX => mfx::create_encode_backend(device, ctx.f.luid, ctx.f.data_format as i32,
-                ctx.d.width, ctx.d.height, ctx.d.kbitrate, ctx.d.framerate, ctx.d.gop)?,
+        // 缩放纹理环的大小覆盖驱动可能仍在读取的在途帧
+        let scaler = match ctx.d.scale {
+            Some(scale) => {
+                let count = ctx.d.bframes.max(0) as usize + 2;
+                let scaler = Scaler::new(&ctx, scale, count).map_err(|e| {
+                    log::error!("Encoder::new: scaling stage: {}", e);
+                })?;
+                Some(scaler)
+            }
+            None => None,
         };
-        Ok(Self {
+        let backend = create_session(&ctx.f, &ctx.d)?;
+        let mut encoder = Self::with_backend(ctx, backend);
+        encoder.scaler = scaler;
+        Ok(encoder)
+    }
+
+    /// Encoder over an existing backend, e.g. a mock in tests. `ctx.d.scale` is ignored.
+    pub(crate) fn with_backend(ctx: EncodeContext, backend: Box<dyn EncodeBackend>) -> Self {
+        let reorder_depth = backend.reorder_depth();
+        let mut stats = StatsRecorder::new();
+        stats.set_reorder_depth(reorder_depth);
+        let mut annotator = Annotator::new(ctx.f.data_format);
+        annotator.set_reorder_delay(reorder_depth);
+        annotator.set_hdr(ctx.d.hdr.as_ref());
+        Self {
             backend,
             frames: Vec::new(),
+            device: None,
+            stats,
+            annotator,
+            reorder_depth,
+            scaler: None,
             ctx,
-        })
+        }
     }
 
+    /// Creates an encoder bound to `device`, holding a reference to it until the encoder is dropped.
+    pub fn with_device(mut ctx: EncodeContext, device: &Device) -> Result<Self, ()> {
+        ctx.d.device = Some(device.as_raw());
+        let mut encoder = Self::new(ctx)?;
+        encoder.device = Some(device.clone());
+        Ok(encoder)
+    }
+
+    /// Raw-pointer escape hatch: `tex` is passed to the driver unchecked.
+    /// Prefer `encode_texture`, which validates the texture first.
+    ///
+    /// With B-frames (`reorder_depth() > 0`) packets come in decode order and lag the input by
+    /// up to `reorder_depth` frames; `flush` returns the remaining ones at the end of the stream.
     pub fn encode(&mut self, tex: *mut std::ffi::c_void, ms: i64) -> Result<&mut Vec<EncodeFrame>, i32> {
         self.frames.clear();
-        self.backend.encode(tex, ms, &mut self.frames)?;
-        Ok(&mut self.frames)
+        let start = Instant::now();
+        self.annotator.submitted(ms);
+        let result = self.scale_input(tex).and_then(|tex| {
+            if self.reorder_depth > 0 {
+                self.encode_reordered(tex, ms)
+            } else {
+                self.backend.encode(tex, ms, &mut self.frames)
+            }
+        });
+        let result = result.map_err(|e| self.map_err(e));
+        match result {
+            Ok(()) => self
+                .frames
+                .iter_mut()
+                .for_each(|frame| self.annotator.annotate(frame)),
+            Err(_) => self.annotator.discard(ms),
+        }
+        self.stats
+            .encoded(start, result.map(|()| self.frames.as_slice()));
+        result.map(|()| &mut self.frames)
+    }
+
+    /// Returns the packets of frames the driver still holds back for reordering, in decode
+    /// order. Call at the end of the stream; without B-frames there are none.
+    pub fn flush(&mut self) -> Result<&mut Vec<EncodeFrame>, i32> {
+        self.frames.clear();
+        let start = Instant::now();
+        let result = self.drain().map_err(|e| self.map_err(e));
+        self.frames
+            .iter_mut()
+            .for_each(|frame| self.annotator.annotate(frame));
+        self.stats
+            .flushed(start, result.map(|()| self.frames.as_slice()));
+        result.map(|()| &mut self.frames)
+    }
+
+    /// B-frames the session encodes: packets lag the input by up to this many frames.
+    pub fn reorder_depth(&self) -> usize {
+        self.reorder_depth
+    }
+
+    /// Marks the next encoded frame as a long-term reference. Returns `ERR_UNSUPPORTED` unless
+    /// the encoder was created with `DynamicContext::ltr_frames`.
+    pub fn mark_ltr(&mut self) -> Result<(), i32> {
+        self.backend.mark_ltr().map_err(|e| self.map_err(e))
+    }
+
+    /// Reports frames the receiver lost, by `pts`. The next frame references only a long-term
+    /// reference marked before `pts.start()`, or is encoded as an IDR if none is left.
+    pub fn invalidate(&mut self, pts: RangeInclusive<i64>) -> Result<(), i32> {
+        self.backend
+            .invalidate(*pts.start(), *pts.end())
+            .map_err(|e| self.map_err(e))
+    }
+
+    // 设置了 DynamicContext::scale 时先把输入缩放进编码尺寸的纹理
+    fn scale_input(&mut self, tex: *mut std::ffi::c_void) -> Result<*mut std::ffi::c_void, i32> {
+        let Some(scaler) = self.scaler.as_mut() else {
+            return Ok(tex);
+        };
+        scaler.scale(tex).map_err(|e| {
+            log::error!("encode: scaling stage: {}", e);
+            match e {
+                WinPlatformError::DeviceLost(_) => ERR_DEVICE_LOST,
+                _ => ERR_TEXTURE_MISMATCH,
+            }
+        })
+    }
+
+    fn encode_reordered(&mut self, tex: *mut std::ffi::c_void, ms: i64) -> Result<(), i32> {
+        self.backend.submit(tex, ms, &mut self.frames)?;
+        // 驱动最多推迟 reorder_depth 帧，超出的在途帧必然会产出
+        while self.backend.pending() > self.reorder_depth {
+            self.receive()?;
+        }
+        Ok(())
+    }
+
+    fn drain(&mut self) -> Result<(), i32> {
+        self.backend.flush()?;
+        while self.backend.pending() > 0 {
+            self.receive()?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<(), i32> {
+        let received = self.frames.len();
+        self.backend.receive(RECEIVE_TIMEOUT_MS, &mut self.frames)?;
+        if self.frames.len() == received {
+            return Err(ERR_TIMEOUT);
+        }
+        Ok(())
+    }
+
+    fn map_err(&self, e: i32) -> i32 {
+        if self.device_lost() {
+            ERR_DEVICE_LOST
+        } else {
+            e
+        }
+    }
+
+    /// Snapshot of the counters since the encoder was created.
+    pub fn stats(&self) -> CodecStats {
+        self.stats.snapshot()
+    }
+
+    /// Whether the device the encoder was created on has been removed.
+    pub fn device_lost(&self) -> bool {
+        self.ctx
+            .d
+            .device
+            .and_then(|device| unsafe { Device::from_raw(device) })
+            .is_some_and(|device| device.is_lost())
+    }
+
+    /// Encodes `tex` after checking that it belongs to the encoder's device and matches
+    /// the configured input format and size. Returns `ERR_TEXTURE_MISMATCH` otherwise.
+    pub fn encode_texture(&mut self, tex: &Texture, ms: i64) -> Result<&mut Vec<EncodeFrame>, i32> {
+        if let Err(e) = self.check_texture(tex) {
+            log::error!("encode_texture: {}", e);
+            return Err(ERR_TEXTURE_MISMATCH);
+        }
+        self.encode(tex.as_raw(), ms)
+    }
+
+    /// Texture format the driver expects as encoder input. 8-bit NVENC sessions with
+    /// `DynamicContext::yuv444` also take `DXGI_FORMAT_AYUV`.
+    ///
+    /// With `DynamicContext::scale` this is the format of the scaled textures; the input itself
+    /// may be any size and any format the video processor reads.
+    pub fn input_format(&self) -> DXGI_FORMAT {
+        input_format(&self.ctx.f, &self.ctx.d)
+    }
+
+    fn check_texture(&self, tex: &Texture) -> crate::platform::win::error::Result<()> {
+        use crate::platform::win::WinPlatformError::TextureMismatch;
+
+        let device = tex.device()?;
+        if Some(device.as_raw()) != self.ctx.d.device {
+            return Err(TextureMismatch(
+                "texture was created on a different device than the encoder".to_string(),
+            ));
+        }
+        // 缩放时输入由视频处理器读取，尺寸与格式不限
+        if self.scaler.is_some() {
+            return Ok(());
+        }
+        let format = if self.takes_ayuv() && tex.desc().Format == DXGI_FORMAT_AYUV {
+            DXGI_FORMAT_AYUV
+        } else {
+            self.input_format()
+        };
+        tex.check(
+            format,
+            self.ctx.d.width as u32,
+            self.ctx.d.height as u32,
+        )
+    }
+
+    // 8 bit 4:4:4 的 NVENC 会话另接受 AYUV
+    fn takes_ayuv(&self) -> bool {
+        self.ctx.f.driver == NV && self.ctx.d.yuv444 && self.ctx.d.bit_depth == BitDepth::Eight
+    }
+
+    /// Uploads `frame` to the encoder's device as a texture for `encode_texture`: in
+    /// `input_format`, or AYUV for 8-bit 4:4:4 frames when the session takes it. RGB formats use
+    /// the matrix of `DynamicContext::color`, BT.601 without one. Returns `ERR_TEXTURE_MISMATCH`
+    /// when the format cannot be produced from a frame (10-bit RGB).
+    pub fn upload(&self, frame: &Frame) -> Result<Texture, i32> {
+        let device = self
+            .ctx
+            .d
+            .device
+            .and_then(|d| unsafe { Device::from_raw(d) });
+        let Some(device) = device else {
+            log::error!("upload: the encoder has no device");
+            return Err(ERR_TEXTURE_MISMATCH);
+        };
+        let format = if self.takes_ayuv()
+            && frame.subsampling == Subsampling::Yuv444
+            && frame.bit_depth == BitDepth::Eight
+        {
+            DXGI_FORMAT_AYUV
+        } else {
+            self.input_format()
+        };
+        let matrix = self.ctx.d.color.map(|c| c.matrix).unwrap_or_default();
+        Texture::upload(&device, frame, format, matrix).map_err(|e| {
+            log::error!("upload: {}", e);
+            if device.is_lost() {
+                ERR_DEVICE_LOST
+            } else {
+                ERR_TEXTURE_MISMATCH
+            }
+        })
     }
 
     pub fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32> {
@@ -55,6 +312,199 @@ impl Encoder {
     }
 }
 
+/// Backend of `Encoder::new`: a pipelined session when `d` asks for B-frames, since only
+/// those can return the held-back packets later.
+pub(crate) fn create_session(
+    f: &FeatureContext,
+    d: &DynamicContext,
+) -> Result<Box<dyn EncodeBackend>, ()> {
+    if d.bframes > 0 {
+        create_async_backend(f, d, d.bframes as usize + 1)
+    } else {
+        create_backend(f, d)
+    }
+}
+
+/// Creates the driver backend for `f` with the settings of `d`.
+pub(crate) fn create_backend(
+    f: &FeatureContext,
+    d: &DynamicContext,
+) -> Result<Box<dyn EncodeBackend>, ()> {
+    if !hdr_supported(f, d) {
+        return Err(());
+    }
+    // 帧内刷新、长期参考、颜色描述、位深、4:4:4 等选项只经由异步接口传入，depth 0 即同步会话
+    if d.intra_refresh.is_some()
+        || d.ltr_frames > 0
+        || d.color.is_some()
+        || d.bit_depth != BitDepth::Eight
+        || d.yuv444
+    {
+        return create_async_backend(f, d, 0);
+    }
+    let device = d.device.unwrap_or(std::ptr::null_mut());
+    match f.driver {
+        NV => nv::create_encode_backend(device, f.luid, f.data_format as i32,
+            d.width, d.height, d.kbitrate, d.framerate, d.gop),
+        AMF => amf::create_encode_backend(device, f.luid, f.data_format as i32,
+            d.width, d.height, d.kbitrate, d.framerate, d.gop),
+        MFX => mfx::create_encode_backend(device, f.luid, f.data_format as i32,
+            d.width, d.height, d.kbitrate, d.framerate, d.gop),
+    }
+}
+
+/// Like `create_backend`, but opens a pipelined session with up to `depth` frames in flight.
+/// Sessions the driver cannot pipeline encode synchronously on `submit`.
+pub(crate) fn create_async_backend(
+    f: &FeatureContext,
+    d: &DynamicContext,
+    depth: usize,
+) -> Result<Box<dyn EncodeBackend>, ()> {
+    if d.intra_refresh.is_some_and(|r| !r.is_valid()) || !hdr_supported(f, d) {
+        return Err(());
+    }
+    // 10 bit 仅支持 HEVC Main10
+    if d.bit_depth == BitDepth::Ten && f.data_format != DataFormat::H265 {
+        return Err(());
+    }
+    let device = d.device.unwrap_or(std::ptr::null_mut());
+    let depth = depth as i32;
+    match f.driver {
+        NV => nv::create_async_encode_backend(device, f.data_format as i32, d, depth),
+        AMF => amf::create_async_encode_backend(device, f.data_format as i32, d, depth),
+        MFX => mfx::create_async_encode_backend(device, f.data_format as i32, d, depth),
+    }
+}
+
+// HDR10 元数据以 SEI 写入，仅 H.264/H.265 有 SEI
+fn hdr_supported(f: &FeatureContext, d: &DynamicContext) -> bool {
+    d.hdr.is_none() || matches!(f.data_format, DataFormat::H264 | DataFormat::H265)
+}
+
+fn input_format(f: &FeatureContext, d: &DynamicContext) -> DXGI_FORMAT {
+    match (&f.driver, d.bit_depth) {
+        (NV | AMF, BitDepth::Eight) => DXGI_FORMAT_B8G8R8A8_UNORM,
+        (NV | AMF, BitDepth::Ten) => DXGI_FORMAT_R10G10B10A2_UNORM,
+        (MFX, BitDepth::Eight) => DXGI_FORMAT_NV12,
+        (MFX, BitDepth::Ten) => DXGI_FORMAT_P010,
+    }
+}
+
+// 缩放输出为 YUV 时按 DynamicContext::color 选取视频处理器的输出颜色空间，使像素与写入 VUI 的
+// 描述一致；RGB 输出或未指定颜色描述时为 None，沿用按格式选取的默认值
+fn scaled_color_space(f: &FeatureContext, d: &DynamicContext) -> Option<DXGI_COLOR_SPACE_TYPE> {
+    let format = input_format(f, d);
+    if format != DXGI_FORMAT_NV12 && format != DXGI_FORMAT_P010 {
+        return None;
+    }
+    let color = d.color?;
+    let full = color.full_range;
+    Some(match (color.matrix, color.transfer) {
+        (Matrix::Bt601, _) if full => DXGI_COLOR_SPACE_YCBCR_FULL_G22_LEFT_P601,
+        (Matrix::Bt601, _) => DXGI_COLOR_SPACE_YCBCR_STUDIO_G22_LEFT_P601,
+        (Matrix::Bt709, _) if full => DXGI_COLOR_SPACE_YCBCR_FULL_G22_LEFT_P709,
+        (Matrix::Bt709, _) => DXGI_COLOR_SPACE_YCBCR_STUDIO_G22_LEFT_P709,
+        // DXGI 没有全范围的 PQ YCbCr 颜色空间
+        (Matrix::Bt2020, Transfer::Pq) => DXGI_COLOR_SPACE_YCBCR_STUDIO_G2084_LEFT_P2020,
+        (Matrix::Bt2020, Transfer::Hlg) if full => DXGI_COLOR_SPACE_YCBCR_FULL_GHLG_TOPLEFT_P2020,
+        (Matrix::Bt2020, Transfer::Hlg) => DXGI_COLOR_SPACE_YCBCR_STUDIO_GHLG_TOPLEFT_P2020,
+        (Matrix::Bt2020, _) if full => DXGI_COLOR_SPACE_YCBCR_FULL_G22_LEFT_P2020,
+        (Matrix::Bt2020, _) => DXGI_COLOR_SPACE_YCBCR_STUDIO_G22_LEFT_P2020,
+    })
+}
+
+// 编码前的 GPU 缩放：输入纹理经视频处理器缩放进编码尺寸的纹理，再交给驱动
+struct Scaler {
+    native: NativeDevice,
+    // 轮流使用，驱动读完在途帧之前不会被覆盖
+    textures: Vec<ID3D11Texture2D>,
+    next: usize,
+    scale: EncodeScale,
+    color_space: Option<DXGI_COLOR_SPACE_TYPE>,
+}
+
+impl Scaler {
+    fn new(
+        ctx: &EncodeContext,
+        scale: EncodeScale,
+        count: usize,
+    ) -> crate::platform::win::error::Result<Self> {
+        let device = ctx
+            .d
+            .device
+            .and_then(|device| unsafe { Device::from_raw(device) })
+            .ok_or_else(|| {
+                WinPlatformError::InvalidParameter(
+                    "scaling needs DynamicContext::device".to_string(),
+                )
+            })?;
+        // NativeDevice::new 接管传入的引用
+        let raw = device.inner().clone().into_raw();
+        let native = NativeDevice::new(ctx.f.luid, Some(raw as _), 0)?;
+        let desc = D3D11_TEXTURE2D_DESC {
+            Width: ctx.d.width as u32,
+            Height: ctx.d.height as u32,
+            MipLevels: 1,
+            ArraySize: 1,
+            Format: input_format(&ctx.f, &ctx.d),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Usage: D3D11_USAGE_DEFAULT,
+            BindFlags: (D3D11_BIND_RENDER_TARGET.0 | D3D11_BIND_SHADER_RESOURCE.0) as u32,
+            CPUAccessFlags: 0,
+            MiscFlags: 0,
+        };
+        let mut textures = Vec::with_capacity(count);
+        for _ in 0..count {
+            let texture = unsafe {
+                let mut texture = None;
+                device
+                    .inner()
+                    .CreateTexture2D(&desc, None, Some(&mut texture))?;
+                texture.unwrap()
+            };
+            textures.push(texture);
+        }
+        Ok(Self {
+            native,
+            textures,
+            next: 0,
+            scale,
+            color_space: scaled_color_space(&ctx.f, &ctx.d),
+        })
+    }
+
+    fn scale(
+        &mut self,
+        input: *mut std::ffi::c_void,
+    ) -> crate::platform::win::error::Result<*mut std::ffi::c_void> {
+        let input = unsafe { ID3D11Texture2D::from_raw_borrowed(&input) }
+            .ok_or_else(|| WinPlatformError::InvalidParameter("null texture".to_string()))?;
+        let (width, height) = get_texture_width_height(input)?;
+        let src = self
+            .scale
+            .crop
+            .unwrap_or(Rect::full(Size::new(width, height)));
+        let output = &self.textures[self.next];
+        self.next = (self.next + 1) % self.textures.len();
+        let (fit, filter) = (self.scale.fit, self.scale.filter);
+        match self.color_space {
+            Some(color_space) => self.native.scale_fit_to_color_space(
+                input,
+                src,
+                output,
+                fit,
+                filter,
+                color_space,
+            )?,
+            None => self.native.scale_fit(input, src, output, fit, filter)?,
+        };
+        Ok(output.as_raw())
+    }
+}
+
 impl Drop for Encoder {
     fn drop(&mut self) {
         self.backend.destroy();
@@ -68,28 +518,50 @@ impl Display for EncodeFrame {
     }
 }
 
+/// Queries detailed limits of the encoder described by `f`, using a temporary device on the
+/// adapter with `f.luid`.
+pub fn capabilities(f: &FeatureContext) -> Result<EncoderCaps, ()> {
+    let caps = crate::vram::inner::with_adapter_device(&f.vendor, f.luid, |device| match f.driver {
+        NV => nv::encoder_caps(device, f),
+        AMF => amf::encoder_caps(device, f),
+        MFX => mfx::encoder_caps(device, f),
+    });
+    caps.flatten().ok_or(())
+}
+
 pub fn available(d: DynamicContext) -> Vec<FeatureContext> {
+    available_for(&[NV, AMF, MFX], d)
+}
+
+/// Like `available`, but only tests the given drivers.
+pub fn available_for(drivers: &[Driver], d: DynamicContext) -> Vec<FeatureContext> {
     use log::debug;
 
     let mut natives: Vec<_> = vec![];
-    natives.append(
-        &mut nv::possible_support_encoders()
-            .drain(..)
-            .map(|n| (NV, n))
-            .collect(),
-    );
-    natives.append(
-        &mut amf::possible_support_encoders()
-            .drain(..)
-            .map(|n| (AMF, n))
-            .collect(),
-    );
-    natives.append(
-        &mut mfx::possible_support_encoders()
-            .drain(..)
-            .map(|n| (MFX, n))
-            .collect(),
-    );
+    if drivers.contains(&NV) {
+        natives.append(
+            &mut nv::possible_support_encoders()
+                .drain(..)
+                .map(|n| (NV, n))
+                .collect(),
+        );
+    }
+    if drivers.contains(&AMF) {
+        natives.append(
+            &mut amf::possible_support_encoders()
+                .drain(..)
+                .map(|n| (AMF, n))
+                .collect(),
+        );
+    }
+    if drivers.contains(&MFX) {
+        natives.append(
+            &mut mfx::possible_support_encoders()
+                .drain(..)
+                .map(|n| (MFX, n))
+                .collect(),
+        );
+    }
     debug!(
         "编码器候选: {} 个 (driver+format) -> 将逐项 test，通过后可用；纹理与编码器须使用同一 D3D11 设备",
         natives.len()
@@ -185,3 +657,118 @@ pub fn available(d: DynamicContext) -> Vec<FeatureContext> {
     let result: Vec<_> = outputs.drain(..).map(|e| e.f).collect();
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::DataFormat::H264;
+    use crate::vram::pipeline::tests::{Gpu, MockBackend};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    fn dynamic(width: i32, height: i32, bframes: usize) -> DynamicContext {
+        DynamicContext {
+            device: None,
+            width,
+            height,
+            kbitrate: 1000,
+            framerate: 30,
+            gop: 60,
+            bframes: bframes as i32,
+            intra_refresh: None,
+            ltr_frames: 0,
+            color: None,
+            bit_depth: BitDepth::Eight,
+            yuv444: false,
+            hdr: None,
+            scale: None,
+        }
+    }
+
+    fn encoder(bframes: usize) -> (Encoder, Arc<Mutex<Gpu>>) {
+        let (mut backend, gpu) = MockBackend::new(true);
+        backend.bframes = bframes;
+        let ctx = EncodeContext {
+            f: FeatureContext {
+                driver: NV,
+                vendor: NV,
+                luid: 0,
+                data_format: H264,
+            },
+            d: dynamic(64, 64, bframes),
+        };
+        (Encoder::with_backend(ctx, Box::new(backend)), gpu)
+    }
+
+    /// 测试 encode 与 flush 更新统计：推迟的帧不算丢帧，失败计入错误码与丢帧
+    #[test]
+    fn test_stats() {
+        let (mut encoder, gpu) = encoder(1);
+        assert_eq!(encoder.reorder_depth(), 1);
+        // 取回 pts 2 时报错
+        gpu.lock().unwrap().fail = vec![2];
+        let tex = std::ptr::null_mut();
+        let mut out = Vec::new();
+        for pts in 0..5 {
+            match encoder.encode(tex, pts) {
+                Ok(frames) => out.extend(frames.iter().map(|f| f.pts)),
+                Err(e) => assert_eq!((pts, e), (2, -1)),
+            }
+        }
+        out.extend(encoder.flush().unwrap().iter().map(|f| f.pts));
+        assert_eq!(out, vec![0, 1, 4, 3]);
+
+        let stats = encoder.stats();
+        assert_eq!((stats.frames_in, stats.frames_out), (5, 4));
+        assert_eq!((stats.keyframes, stats.bytes, stats.dropped), (1, 4, 1));
+        assert_eq!(stats.errors, BTreeMap::from([(-1, 1)]));
+    }
+
+    /// 测试 flush 失败计入错误码，但不重复计入输入
+    #[test]
+    fn test_flush_error() {
+        let (mut encoder, gpu) = encoder(1);
+        let tex = std::ptr::null_mut();
+        assert!(encoder.encode(tex, 0).unwrap().is_empty());
+        assert_eq!(encoder.encode(tex, 1).unwrap().len(), 1);
+        gpu.lock().unwrap().fail = vec![1];
+        assert_eq!(encoder.flush().err(), Some(-1));
+
+        let stats = encoder.stats();
+        assert_eq!((stats.frames_in, stats.frames_out), (2, 1));
+        assert_eq!(stats.errors, BTreeMap::from([(-1, 1)]));
+    }
+
+    /// 测试各驱动把 DynamicContext::color 写入 SPS，关键帧包解析回同一颜色描述（需要 GPU）
+    #[test]
+    #[ignore] // 需要 GPU，默认忽略
+    fn test_color_description() {
+        use crate::convert::{ColorDescription, ColorSpace, Matrix, Range};
+        use crate::testsrc::{Pattern, TestSource};
+        use crate::vram::metadata::color_description;
+
+        let color = ColorSpace::new(Matrix::Bt601, Range::Full);
+        let description = ColorDescription::from(color);
+        let mut d = dynamic(320, 240, 0);
+        d.color = Some(description);
+        let frame = TestSource::new(Pattern::ColorBars, 320, 240)
+            .with_color(color)
+            .next_frame();
+        for f in available(d) {
+            let native = NativeDevice::new(f.luid, None, 0).unwrap();
+            let device = Device::from(native.device().clone());
+            let ctx = EncodeContext { f: f.clone(), d };
+            let mut encoder = Encoder::with_device(ctx, &device).unwrap();
+            let texture = encoder.upload(&frame).unwrap();
+            let mut packets = std::mem::take(encoder.encode_texture(&texture, 0).unwrap());
+            packets.append(encoder.flush().unwrap());
+            let key = packets.iter().find(|p| p.key == 1).expect("no key packet");
+            assert_eq!(
+                color_description(f.data_format, &key.data),
+                Some(description),
+                "{:?}",
+                f
+            );
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Encode → decode → compare loop for measuring an encoder's quality at a given bitrate.
+//!
+//! ```ignore
+//! let mut harness = EncodeDecodeHarness::new(ctx)?;
+//! for frame in Y4mReader::open("foreman.y4m")? {
+//!     harness.push(&frame?)?;
+//! }
+//! let report = harness.finish()?;
+//! println!("{:.2} dB at {} kbit/s", report.mean().unwrap().psnr.all, report.kbitrate());
+//! ```
+//!
+//! Encoder and decoder share one device on the encoder's adapter. Decoded frames come back in
+//! display order. Frames drawn by `testsrc::TestSource` are matched to their source by the
+//! counter overlay, so frames the decoder drops show up as `missing`; other frames are matched
+//! in submission order.
+
+use crate::{
+    convert::Range,
+    frame::Frame,
+    platform::win::{Device, NativeDevice, WinPlatformError},
+    quality::{self, QualityScores},
+    testsrc,
+    vram::{
+        decode::{DecodeFrame, Decoder},
+        encode::{EncodeFrame, Encoder},
+        stats::CodecStats,
+        DecodeContext, EncodeContext, ERR_DEVICE_LOST, ERR_TEXTURE_MISMATCH,
+    },
+};
+use std::collections::{HashMap, VecDeque};
+
+/// Quality and cost of one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameQuality {
+    /// Position in the submitted sequence.
+    pub index: usize,
+    pub pts: i64,
+    /// Bytes of the frame's packet.
+    pub bytes: usize,
+    pub key: bool,
+    pub scores: QualityScores,
+}
+
+#[derive(Debug, Clone)]
+pub struct HarnessReport {
+    /// One entry per decoded frame, in display order.
+    pub frames: Vec<FrameQuality>,
+    /// Submitted frames the decoder never returned.
+    pub missing: usize,
+    pub framerate: i32,
+    pub encoder: CodecStats,
+    pub decoder: CodecStats,
+}
+
+impl HarnessReport {
+    /// Mean of the per-frame scores, `None` without decoded frames.
+    pub fn mean(&self) -> Option<QualityScores> {
+        let scores: Vec<_> = self.frames.iter().map(|f| f.scores).collect();
+        QualityScores::mean(&scores)
+    }
+
+    /// Bitrate of the decoded frames' packets at the configured framerate.
+    pub fn kbitrate(&self) -> f64 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+        let bytes: usize = self.frames.iter().map(|f| f.bytes).sum();
+        bytes as f64 * 8.0 * self.framerate as f64 / self.frames.len() as f64 / 1000.0
+    }
+}
+
+// 等待解码输出的源帧
+struct Source {
+    index: usize,
+    pts: i64,
+    // 源帧的计数条，没有时按提交顺序匹配
+    counter: Option<u32>,
+    frame: Frame,
+}
+
+pub struct EncodeDecodeHarness {
+    encoder: Encoder,
+    decoder: Decoder,
+    range: Range,
+    sources: VecDeque<Source>,
+    // pts -> (包大小, 是否关键帧)
+    packets: HashMap<i64, (usize, bool)>,
+    frames: Vec<FrameQuality>,
+    submitted: usize,
+}
+
+impl EncodeDecodeHarness {
+    /// Creates the encoder of `ctx` and a decoder of the same driver on a new device on the
+    /// encoder's adapter. `ctx.d.device` is replaced; scaling (`DynamicContext::scale`) is not
+    /// supported, since decoded frames are compared with the source at its size.
+    pub fn new(ctx: EncodeContext) -> Result<Self, ()> {
+        if ctx.d.scale.is_some() {
+            log::error!("EncodeDecodeHarness::new: scaling is not supported");
+            return Err(());
+        }
+        let native = NativeDevice::new(ctx.f.luid, None, 0).map_err(|e| {
+            log::error!("EncodeDecodeHarness::new: {}", e);
+        })?;
+        let device = Device::from(native.device().clone());
+        let decode = DecodeContext {
+            device: None,
+            driver: ctx.f.driver.clone(),
+            vendor: ctx.f.vendor.clone(),
+            luid: ctx.f.luid,
+            data_format: ctx.f.data_format,
+            bit_depth: ctx.d.bit_depth,
+        };
+        let range = ctx
+            .d
+            .color
+            .map(|c| c.color_space().range)
+            .unwrap_or_default();
+        Ok(Self {
+            encoder: Encoder::with_device(ctx, &device)?,
+            decoder: Decoder::with_device(decode, &device)?,
+            range,
+            sources: VecDeque::new(),
+            packets: HashMap::new(),
+            frames: Vec::new(),
+            submitted: 0,
+        })
+    }
+
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// Encodes `frame`, decodes the packets that come out and scores the frames decoded so
+    /// far. `frame` must have the encoder's size.
+    pub fn push(&mut self, frame: &Frame) -> Result<(), i32> {
+        let framerate = self.encoder.ctx.d.framerate.max(1) as i64;
+        let pts = self.submitted as i64 * 1000 / framerate;
+        let texture = self.encoder.upload(frame)?;
+        self.sources.push_back(Source {
+            index: self.submitted,
+            pts,
+            counter: testsrc::read_counter(frame),
+            frame: frame.clone(),
+        });
+        self.submitted += 1;
+        let packets = std::mem::take(self.encoder.encode_texture(&texture, pts)?);
+        self.decode(packets)
+    }
+
+    /// Drains the encoder and the decoder and returns the scores of every decoded frame.
+    pub fn finish(mut self) -> Result<HarnessReport, i32> {
+        let packets = std::mem::take(self.encoder.flush()?);
+        self.decode(packets)?;
+        // 解码器为重排扣住的帧
+        let decoded = read_back(self.decoder.flush()?, self.range)?;
+        for frame in decoded {
+            self.score(frame)?;
+        }
+        Ok(HarnessReport {
+            frames: self.frames,
+            missing: self.sources.len(),
+            framerate: self.encoder.ctx.d.framerate,
+            encoder: self.encoder.stats(),
+            decoder: self.decoder.stats(),
+        })
+    }
+
+    fn decode(&mut self, packets: Vec<EncodeFrame>) -> Result<(), i32> {
+        for packet in packets {
+            self.packets
+                .insert(packet.pts, (packet.data.len(), packet.key == 1));
+            let decoded = read_back(self.decoder.decode(&packet.data)?, self.range)?;
+            for frame in decoded {
+                self.score(frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn score(&mut self, decoded: Frame) -> Result<(), i32> {
+        // 计数条可读时按计数匹配，跳过的源帧留在队列中计为 missing
+        let position = testsrc::read_counter(&decoded)
+            .and_then(|c| self.sources.iter().position(|s| s.counter == Some(c)))
+            .unwrap_or(0);
+        let Some(Source {
+            index,
+            pts,
+            frame: source,
+            ..
+        }) = self.sources.remove(position)
+        else {
+            log::warn!("EncodeDecodeHarness: decoder returned more frames than were encoded");
+            return Ok(());
+        };
+        // 4:4:4 源帧与 4:2:0 解码输出比较时先下采样色度
+        let source = if source.subsampling != decoded.subsampling {
+            source.to_420()
+        } else {
+            source
+        };
+        let scores = quality::compare(&source, &decoded).map_err(|e| {
+            log::error!("EncodeDecodeHarness: frame {}: {}", index, e);
+            ERR_TEXTURE_MISMATCH
+        })?;
+        let (bytes, key) = self.packets.remove(&pts).unwrap_or_default();
+        self.frames.push(FrameQuality {
+            index,
+            pts,
+            bytes,
+            key,
+            scores,
+        });
+        Ok(())
+    }
+}
+
+fn read_back(frames: &[DecodeFrame], range: Range) -> Result<Vec<Frame>, i32> {
+    frames
+        .iter()
+        .map(|f| f.read_frame(range))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            log::error!("EncodeDecodeHarness: read back: {}", e);
+            match e {
+                WinPlatformError::DeviceLost(_) => ERR_DEVICE_LOST,
+                _ => ERR_TEXTURE_MISMATCH,
+            }
+        })
+}
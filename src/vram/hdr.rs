@@ -0,0 +1,393 @@
+//! HDR10 static metadata: SMPTE ST 2086 mastering display colour volume and the content light
+//! level (MaxCLL/MaxFALL) of CTA-861.3.
+//!
+//! Both travel as SEI messages, with the same payload in H.264 and H.265. Encoders set
+//! `DynamicContext::hdr`; `Annotator` then splices one SEI NAL into every keyframe and recovery
+//! point packet, whatever the driver. Decoders read them back from the packets and attach the
+//! latest ones to `DecodeFrame::hdr`. The transfer function (PQ) is signalled separately, in the
+//! VUI through `DynamicContext::color`.
+
+use crate::common::DataFormat;
+use serde_derive::{Deserialize, Serialize};
+
+// 两种编码格式中的 payloadType 相同
+const SEI_MASTERING_DISPLAY: u32 = 137;
+const SEI_CONTENT_LIGHT_LEVEL: u32 = 144;
+
+const MASTERING_DISPLAY_SIZE: usize = 24;
+const CONTENT_LIGHT_LEVEL_SIZE: usize = 4;
+
+/// Either message may be absent; each is written and parsed on its own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct HdrMetadata {
+    pub mastering_display: Option<MasteringDisplay>,
+    pub content_light_level: Option<ContentLightLevel>,
+}
+
+/// Colour volume of the display the content was graded on. Chromaticities are CIE 1931 `[x, y]`
+/// in units of 0.00002, luminances in units of 0.0001 cd/m².
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct MasteringDisplay {
+    pub red: [u16; 2],
+    pub green: [u16; 2],
+    pub blue: [u16; 2],
+    pub white_point: [u16; 2],
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+impl MasteringDisplay {
+    /// BT.2020 primaries with a D65 white point, the usual HDR10 mastering display, peaking at
+    /// `max_nits` cd/m² with a black level of `min_nits`.
+    pub fn bt2020(max_nits: f64, min_nits: f64) -> Self {
+        let luminance = |nits: f64| (nits * 10000.0).round().clamp(0.0, u32::MAX as f64) as u32;
+        Self {
+            red: [35400, 14600],
+            green: [8500, 39850],
+            blue: [6550, 2300],
+            white_point: [15635, 16450],
+            max_luminance: luminance(max_nits),
+            min_luminance: luminance(min_nits),
+        }
+    }
+}
+
+/// Light level of the content, in cd/m². 0 where unknown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct ContentLightLevel {
+    /// Maximum content light level: the brightest pixel of the stream.
+    pub max_cll: u16,
+    /// Maximum frame-average light level: the brightest frame on average.
+    pub max_fall: u16,
+}
+
+impl HdrMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.mastering_display.is_none() && self.content_light_level.is_none()
+    }
+
+    /// Takes the messages `other` carries, keeping those it does not.
+    pub(crate) fn merge(&mut self, other: HdrMetadata) {
+        if other.mastering_display.is_some() {
+            self.mastering_display = other.mastering_display;
+        }
+        if other.content_light_level.is_some() {
+            self.content_light_level = other.content_light_level;
+        }
+    }
+
+    /// SEI messages in RBSP form: payload type, size and payload of each message, then the
+    /// trailing bits.
+    fn sei_rbsp(&self) -> Vec<u8> {
+        let mut rbsp = vec![];
+        if let Some(m) = &self.mastering_display {
+            rbsp.extend([SEI_MASTERING_DISPLAY as u8, MASTERING_DISPLAY_SIZE as u8]);
+            // 三原色按 G、B、R 的顺序
+            for [x, y] in [m.green, m.blue, m.red, m.white_point] {
+                rbsp.extend(x.to_be_bytes());
+                rbsp.extend(y.to_be_bytes());
+            }
+            rbsp.extend(m.max_luminance.to_be_bytes());
+            rbsp.extend(m.min_luminance.to_be_bytes());
+        }
+        if let Some(c) = &self.content_light_level {
+            rbsp.extend([
+                SEI_CONTENT_LIGHT_LEVEL as u8,
+                CONTENT_LIGHT_LEVEL_SIZE as u8,
+            ]);
+            rbsp.extend(c.max_cll.to_be_bytes());
+            rbsp.extend(c.max_fall.to_be_bytes());
+        }
+        rbsp.push(0x80);
+        rbsp
+    }
+}
+
+/// The metadata as one Annex B SEI NAL unit, 4-byte start code included. `None` for empty
+/// metadata and for formats other than H.264 and H.265.
+pub fn sei_nal(data_format: DataFormat, hdr: &HdrMetadata) -> Option<Vec<u8>> {
+    if hdr.is_empty() {
+        return None;
+    }
+    let header: &[u8] = match data_format {
+        DataFormat::H264 => &[0x06],
+        // 前缀 SEI (39)，nuh_layer_id 0，nuh_temporal_id_plus1 1
+        DataFormat::H265 => &[39 << 1, 0x01],
+        _ => return None,
+    };
+    let mut nal = vec![0, 0, 0, 1];
+    nal.extend_from_slice(header);
+    escape(&hdr.sei_rbsp(), &mut nal);
+    Some(nal)
+}
+
+/// The mastering display and content light level SEI messages of an Annex B packet. `None` when
+/// the packet carries neither.
+pub fn parse(data_format: DataFormat, packet: &[u8]) -> Option<HdrMetadata> {
+    let mut hdr = HdrMetadata::default();
+    for nal in crate::vram::metadata::nal_units(packet) {
+        let payload = match data_format {
+            DataFormat::H264 if nal.len() > 1 && nal[0] & 0x1f == 6 => &nal[1..],
+            DataFormat::H265 if nal.len() > 2 && (nal[0] >> 1) & 0x3f == 39 => &nal[2..],
+            _ => continue,
+        };
+        hdr.merge(parse_sei(&unescape(payload)));
+    }
+    (!hdr.is_empty()).then_some(hdr)
+}
+
+/// Places `sei` (a NAL unit with its start code) before the first slice of `packet`, after the
+/// access unit delimiter, parameter sets and SEI already there. Appended when there is no slice.
+pub(crate) fn insert_sei(data_format: DataFormat, packet: &mut Vec<u8>, sei: &[u8]) {
+    let base = packet.as_ptr() as usize;
+    let slice = crate::vram::metadata::nal_units(packet).find(|nal| match data_format {
+        DataFormat::H264 => nal.first().is_some_and(|h| (1..=5).contains(&(h & 0x1f))),
+        DataFormat::H265 => nal.first().is_some_and(|h| (h >> 1) & 0x3f < 32),
+        _ => false,
+    });
+    let Some(slice) = slice else {
+        packet.extend_from_slice(sei);
+        return;
+    };
+    // 回退到起始码（含前导 0）之前
+    let mut at = slice.as_ptr() as usize - base - 3;
+    while at > 0 && packet[at - 1] == 0 {
+        at -= 1;
+    }
+    packet.splice(at..at, sei.iter().copied());
+}
+
+/// Tracks the metadata of a decoded stream. It persists across packets until the stream sends
+/// new messages.
+pub(crate) struct HdrParser {
+    data_format: DataFormat,
+    current: Option<HdrMetadata>,
+}
+
+impl HdrParser {
+    pub(crate) fn new(data_format: DataFormat) -> Self {
+        Self {
+            data_format,
+            current: None,
+        }
+    }
+
+    /// Reads the messages of `packet` and returns the metadata in effect for its frames.
+    pub(crate) fn update(&mut self, packet: &[u8]) -> Option<HdrMetadata> {
+        if let Some(hdr) = parse(self.data_format, packet) {
+            self.current.get_or_insert_with(Default::default).merge(hdr);
+        }
+        self.current
+    }
+}
+
+fn parse_sei(rbsp: &[u8]) -> HdrMetadata {
+    // 0xff 前缀累加的变长值
+    fn value(data: &[u8], pos: &mut usize) -> Option<usize> {
+        let mut v = 0;
+        loop {
+            let b = *data.get(*pos)?;
+            *pos += 1;
+            v += b as usize;
+            if b != 0xff {
+                return Some(v);
+            }
+        }
+    }
+    let be16 = |b: &[u8], i: usize| u16::from_be_bytes([b[i], b[i + 1]]);
+    let be32 = |b: &[u8], i: usize| u32::from_be_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+    let mut hdr = HdrMetadata::default();
+    let mut pos = 0;
+    // 剩下的 0x80 为 rbsp_trailing_bits，之后没有消息
+    while rbsp
+        .get(pos..)
+        .is_some_and(|rest| !rest.is_empty() && rest != [0x80])
+    {
+        let (Some(payload_type), Some(size)) = (value(rbsp, &mut pos), value(rbsp, &mut pos))
+        else {
+            break;
+        };
+        let Some(p) = rbsp.get(pos..pos + size) else {
+            break;
+        };
+        pos += size;
+        match payload_type as u32 {
+            SEI_MASTERING_DISPLAY if size >= MASTERING_DISPLAY_SIZE => {
+                let xy = |i: usize| [be16(p, i * 4), be16(p, i * 4 + 2)];
+                hdr.mastering_display = Some(MasteringDisplay {
+                    green: xy(0),
+                    blue: xy(1),
+                    red: xy(2),
+                    white_point: xy(3),
+                    max_luminance: be32(p, 16),
+                    min_luminance: be32(p, 20),
+                });
+            }
+            SEI_CONTENT_LIGHT_LEVEL if size >= CONTENT_LIGHT_LEVEL_SIZE => {
+                hdr.content_light_level = Some(ContentLightLevel {
+                    max_cll: be16(p, 0),
+                    max_fall: be16(p, 2),
+                });
+            }
+            _ => {}
+        }
+    }
+    hdr
+}
+
+// RBSP → NAL 负载：00 00 后接 00..=03 时插入防竞争字节 03
+fn escape(rbsp: &[u8], out: &mut Vec<u8>) {
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros >= 2 && b <= 3 {
+            out.push(3);
+            zeros = 0;
+        }
+        out.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+}
+
+fn unescape(payload: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(payload.len());
+    let mut zeros = 0;
+    for &b in payload {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        rbsp.push(b);
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+    }
+    rbsp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> HdrMetadata {
+        HdrMetadata {
+            mastering_display: Some(MasteringDisplay::bt2020(1000.0, 0.005)),
+            content_light_level: Some(ContentLightLevel {
+                max_cll: 1000,
+                max_fall: 400,
+            }),
+        }
+    }
+
+    /// 测试 SEI 序列化与解析往返，以及防竞争字节
+    #[test]
+    fn test_sei_round_trip() {
+        let full = metadata();
+        let only_cll = HdrMetadata {
+            mastering_display: None,
+            ..full
+        };
+        // 全 0 的色度与亮度在 RBSP 中形成 00 00 00，必须转义
+        let zeros = HdrMetadata {
+            mastering_display: Some(MasteringDisplay {
+                red: [0, 0],
+                green: [0, 0],
+                blue: [0, 0],
+                white_point: [0, 0],
+                max_luminance: 0,
+                min_luminance: 0,
+            }),
+            content_light_level: None,
+        };
+        for format in [DataFormat::H264, DataFormat::H265] {
+            for hdr in [full, only_cll, zeros] {
+                let nal = sei_nal(format, &hdr).unwrap();
+                assert_eq!(&nal[..4], &[0, 0, 0, 1]);
+                assert!(!nal[4..]
+                    .windows(3)
+                    .any(|w| w[0] == 0 && w[1] == 0 && w[2] <= 2));
+                assert_eq!(parse(format, &nal), Some(hdr), "{:?}", format);
+            }
+        }
+        let mastering = full.mastering_display.unwrap();
+        assert_eq!(mastering.max_luminance, 10_000_000);
+        assert_eq!(mastering.min_luminance, 50);
+
+        let nal = sei_nal(DataFormat::H264, &full).unwrap();
+        assert_eq!(&nal[4..7], &[0x06, 137, 24]);
+        // G 原色在前
+        assert_eq!(&nal[7..11], &[0x21, 0x34, 0x9b, 0xaa]);
+        assert_eq!(*nal.last().unwrap(), 0x80);
+        let nal = sei_nal(DataFormat::H265, &full).unwrap();
+        assert_eq!(&nal[4..6], &[0x4e, 0x01]);
+
+        assert_eq!(sei_nal(DataFormat::H264, &HdrMetadata::default()), None);
+        assert_eq!(sei_nal(DataFormat::AV1, &full), None);
+        // 截断的消息被忽略
+        let nal = sei_nal(DataFormat::H265, &only_cll).unwrap();
+        assert_eq!(parse(DataFormat::H265, &nal[..nal.len() - 2]), None);
+    }
+
+    /// 测试 SEI 插入第一个 slice 之前，以及解码端的元数据延续
+    #[test]
+    fn test_insert_and_track() {
+        let hdr = metadata();
+        // AUD、SPS、PPS、recovery point SEI、IDR slice、非 IDR slice
+        let h264 = [
+            &[0, 0, 0, 1, 0x09, 0xf0][..],
+            &[0, 0, 0, 1, 0x67, 0x64, 0x00, 0x28],
+            &[0, 0, 1, 0x68, 0xee, 0x3c, 0x80],
+            &[0, 0, 1, 0x06, 0x06, 0x01, 0x84, 0x80],
+            &[0, 0, 0, 1, 0x65, 0x88, 0x84],
+            &[0, 0, 1, 0x41, 0x9a],
+        ]
+        .concat();
+        let sei = sei_nal(DataFormat::H264, &hdr).unwrap();
+        let mut packet = h264.clone();
+        insert_sei(DataFormat::H264, &mut packet, &sei);
+        let at = h264
+            .windows(5)
+            .position(|w| w == [0, 0, 0, 1, 0x65])
+            .unwrap();
+        assert_eq!(packet, [&h264[..at], &sei, &h264[at..]].concat());
+        assert_eq!(parse(DataFormat::H264, &packet), Some(hdr));
+
+        // VPS、SPS、PPS、IDR_W_RADL
+        let h265 = [
+            &[0, 0, 0, 1, 0x40, 0x01, 0x0c][..],
+            &[0, 0, 0, 1, 0x42, 0x01, 0x01],
+            &[0, 0, 0, 1, 0x44, 0x01, 0xc1],
+            &[0, 0, 0, 1, 0x26, 0x01, 0xaf],
+        ]
+        .concat();
+        let sei = sei_nal(DataFormat::H265, &hdr).unwrap();
+        let mut packet = h265.clone();
+        insert_sei(DataFormat::H265, &mut packet, &sei);
+        assert_eq!(packet, [&h265[..21], &sei, &h265[21..]].concat());
+
+        // 没有 slice 时追加到末尾
+        let mut packet = h265[..14].to_vec();
+        insert_sei(DataFormat::H265, &mut packet, &sei);
+        assert_eq!(packet, [&h265[..14], &sei].concat());
+
+        let mut parser = HdrParser::new(DataFormat::H264);
+        assert_eq!(parser.update(&h264), None);
+        let only_cll = HdrMetadata {
+            mastering_display: None,
+            content_light_level: Some(ContentLightLevel {
+                max_cll: 600,
+                max_fall: 200,
+            }),
+        };
+        let mut packet = h264.clone();
+        insert_sei(
+            DataFormat::H264,
+            &mut packet,
+            &sei_nal(DataFormat::H264, &hdr).unwrap(),
+        );
+        assert_eq!(parser.update(&packet), Some(hdr));
+        // 后续不带 SEI 的包沿用之前的元数据，新消息只替换自身字段
+        assert_eq!(parser.update(&[0, 0, 1, 0x41, 0x9a]), Some(hdr));
+        let packet = sei_nal(DataFormat::H264, &only_cll).unwrap();
+        let updated = parser.update(&packet).unwrap();
+        assert_eq!(updated.mastering_display, hdr.mastering_display);
+        assert_eq!(updated.content_light_level, only_cll.content_light_level);
+    }
+}
@@ -1,41 +1,9 @@
 #![allow(non_snake_case)]
 
-use crate::common::{DataFormat, DecodeCallback, EncodeCallback};
+use crate::common::{AdapterVendor, DataFormat, DecodeCallback, Driver, EncodeCallback};
 use std::os::raw::{c_int, c_void};
 
-// Frame types used by backends and by encode/decode API (moved here to avoid circular deps)
-#[derive(Clone, Default)]
-pub struct EncodeFrame {
-    pub data: Vec<u8>,
-    pub pts: i64,
-    pub key: i32,
-}
-
-#[derive(Default)]
-pub struct DecodeFrame {
-    pub texture: *mut c_void,
-    pub width: i32,
-    pub height: i32,
-}
-
-/// Backend trait for encoding: Rust-owned API instead of C function table.
-pub trait EncodeBackend: Send {
-    fn encode(
-        &mut self,
-        tex: *mut c_void,
-        ms: i64,
-        frames: &mut Vec<EncodeFrame>,
-    ) -> Result<(), i32>;
-    fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32>;
-    fn set_framerate(&mut self, framerate: i32) -> Result<(), i32>;
-    fn destroy(&mut self);
-}
-
-/// Backend trait for decoding: Rust-owned API instead of C function table.
-pub trait DecodeBackend: Send {
-    fn decode(&mut self, data: &[u8], frames: &mut Vec<DecodeFrame>) -> Result<(), i32>;
-    fn destroy(&mut self);
-}
+pub use crate::vram::backend::{DecodeBackend, DecodeFrame, EncodeBackend, EncodeFrame};
 
 /// C-compatible callback used by backends when calling into C++ encode (obj = *mut Vec<EncodeFrame>).
 #[no_mangle]
@@ -55,10 +23,38 @@ pub extern "C" fn hwcodec_encode_frame_callback(
             data: std::slice::from_raw_parts(data, size.max(0) as usize).to_vec(),
             pts,
             key,
+            ..Default::default()
         });
     }
 }
 
+// 三个 bridge 各自生成同布局的 EncodedFrame；负值表示 SDK 未报告该字段
+macro_rules! impl_from_encoded_frame {
+    ($($bridge:ident),*) => {$(
+        impl From<&crate::vram::$bridge::EncodedFrame> for EncodeFrame {
+            fn from(frame: &crate::vram::$bridge::EncodedFrame) -> Self {
+                let data = if frame.data.is_null() || frame.size <= 0 {
+                    vec![]
+                } else {
+                    unsafe { std::slice::from_raw_parts(frame.data, frame.size as usize) }.to_vec()
+                };
+                EncodeFrame {
+                    data,
+                    pts: frame.timestamp,
+                    key: frame.is_keyframe as i32,
+                    dts: frame.has_dts.then_some(frame.dts),
+                    picture_type: crate::vram::metadata::PictureType::from_raw(frame.picture_type),
+                    avg_qp: (frame.avg_qp >= 0).then_some(frame.avg_qp),
+                    temporal_id: u8::try_from(frame.temporal_id).ok(),
+                    ..Default::default()
+                }
+            }
+        }
+    )*};
+}
+
+impl_from_encoded_frame!(amf_bridge, mfx_bridge, nv_bridge);
+
 // C-compatible callback used by backends when calling into C++ decode (obj = *mut Vec<DecodeFrame>).
 extern "C" {
     fn hwcodec_get_d3d11_texture_width_height(
@@ -83,6 +79,7 @@ pub extern "C" fn hwcodec_decode_frame_callback(texture: *mut c_void, obj: *mut
         texture,
         width,
         height,
+        hdr: None,
     });
 }
 
@@ -168,6 +165,37 @@ pub struct DecodeCalls {
     pub test: TestDecodeCall,
 }
 
+/// Runs `f` with a D3D11 device on the adapter identified by `vendor`/`luid`, for queries that
+/// need a device but no caller-provided one. `luid == 0` (not yet resolved) picks the vendor's
+/// first adapter.
+pub(crate) fn with_adapter_device<R>(
+    vendor: &Driver,
+    luid: i64,
+    f: impl FnOnce(*mut c_void) -> R,
+) -> Option<R> {
+    use crate::platform::win::Adapters;
+    use windows::core::Interface;
+
+    let adapter_vendor = match vendor {
+        Driver::NV => AdapterVendor::ADAPTER_VENDOR_NVIDIA,
+        Driver::AMF => AdapterVendor::ADAPTER_VENDOR_AMD,
+        Driver::MFX => AdapterVendor::ADAPTER_VENDOR_INTEL,
+    };
+    let adapters = Adapters::new(adapter_vendor).ok()?;
+    let adapter = adapters
+        .adapters()
+        .iter()
+        .find(|a| a.luid() == luid)
+        .or_else(|| {
+            if luid == 0 {
+                adapters.adapters().first()
+            } else {
+                None
+            }
+        })?;
+    Some(f(adapter.device().as_raw()))
+}
+
 pub struct InnerEncodeContext {
     pub format: DataFormat,
 }
@@ -0,0 +1,979 @@
+//! Per-packet metadata of `EncodeFrame`.
+//!
+//! The bridges report what the SDK knows (NVENC `NV_ENC_LOCK_BITSTREAM`, AMF output properties,
+//! `mfxBitstream`). `Annotator` runs on every packet the encoder returns: it measures the
+//! submit→output latency and the byte offset, derives the dts, and parses the NAL headers for
+//! the picture type and temporal layer the SDK did not report, and for recovery point SEI.
+//! `color_description` reads back the colour description an SPS signals. With HDR metadata set
+//! it also splices the HDR10 SEI (`hdr`) into keyframe and recovery point packets.
+
+use crate::common::DataFormat;
+use crate::convert::ColorDescription;
+use crate::vram::backend::EncodeFrame;
+use crate::vram::hdr::{self, HdrMetadata};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Coding type of a packet. The order matches `HWCODEC_PIC_*` in `cpp/caps.h`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PictureType {
+    #[default]
+    Unknown,
+    Idr,
+    I,
+    P,
+    B,
+}
+
+impl PictureType {
+    pub(crate) fn from_raw(raw: i32) -> Self {
+        match raw {
+            1 => PictureType::Idr,
+            2 => PictureType::I,
+            3 => PictureType::P,
+            4 => PictureType::B,
+            _ => PictureType::Unknown,
+        }
+    }
+}
+
+// H.265 PPS 中 num_extra_slice_header_bits 以 pps_id 为下标，pps_id 最大 63
+const HEVC_MAX_PPS: usize = 64;
+
+pub(crate) struct Annotator {
+    data_format: DataFormat,
+    // 提交顺序的 (pts, 提交时刻)
+    submitted: VecDeque<(i64, Instant)>,
+    // 提交顺序的 pts，每输出一个包取出一个作为 dts
+    dts: VecDeque<i64>,
+    reorder_delay: usize,
+    // 已标注的包数
+    emitted: usize,
+    offset: u64,
+    hevc_extra_bits: [u8; HEVC_MAX_PPS],
+    // 写入关键帧与恢复点的 HDR10 SEI NAL
+    hdr_sei: Option<Vec<u8>>,
+}
+
+impl Annotator {
+    pub(crate) fn new(data_format: DataFormat) -> Self {
+        Self {
+            data_format,
+            submitted: VecDeque::new(),
+            dts: VecDeque::new(),
+            reorder_delay: 0,
+            emitted: 0,
+            offset: 0,
+            hevc_extra_bits: [0; HEVC_MAX_PPS],
+            hdr_sei: None,
+        }
+    }
+
+    /// HDR10 metadata to write into every keyframe and recovery point packet.
+    pub(crate) fn set_hdr(&mut self, hdr: Option<&HdrMetadata>) {
+        self.hdr_sei = hdr.and_then(|m| hdr::sei_nal(self.data_format, m));
+    }
+
+    /// Frames the encoder holds back for reordering (its B-frames). The dts of the first
+    /// packets is extrapolated before the first pts, by the pts step of the submitted frames.
+    pub(crate) fn set_reorder_delay(&mut self, frames: usize) {
+        self.reorder_delay = frames;
+    }
+
+    /// Records that the frame with `pts` was handed to the driver.
+    pub(crate) fn submitted(&mut self, pts: i64) {
+        self.submitted_at(pts, Instant::now());
+    }
+
+    /// Drops the record of the frame with `pts`, e.g. when its submit failed.
+    pub(crate) fn discard(&mut self, pts: i64) {
+        if let Some(i) = self.submitted.iter().rposition(|(p, _)| *p == pts) {
+            self.submitted.remove(i);
+        }
+        if let Some(i) = self.dts.iter().rposition(|p| *p == pts) {
+            self.dts.remove(i);
+        }
+    }
+
+    pub(crate) fn annotate(&mut self, frame: &mut EncodeFrame) {
+        self.annotate_at(frame, Instant::now());
+    }
+
+    fn submitted_at(&mut self, pts: i64, now: Instant) {
+        self.submitted.push_back((pts, now));
+        self.dts.push_back(pts);
+    }
+
+    fn annotate_at(&mut self, frame: &mut EncodeFrame, now: Instant) {
+        if let Some(i) = self.submitted.iter().position(|(p, _)| *p == frame.pts) {
+            let (_, at) = self.submitted.remove(i).unwrap_or((frame.pts, now));
+            frame.latency = now.saturating_duration_since(at);
+        }
+        // dts 取提交顺序中的 pts，滞后 reorder_delay 个包；此前的包外推到首帧之前，保证 dts <= pts 且单调
+        let derived = if self.emitted < self.reorder_delay {
+            self.extrapolate(self.reorder_delay - self.emitted)
+        } else {
+            self.dts.pop_front()
+        };
+        self.emitted += 1;
+        frame.dts.get_or_insert(derived.unwrap_or(frame.pts));
+        frame.recovery_point |= self.has_recovery_point(&frame.data);
+        // 先插入 SEI，offset 才与调用方写出的字节一致
+        if let Some(sei) = &self.hdr_sei {
+            if frame.key != 0 || frame.recovery_point {
+                hdr::insert_sei(self.data_format, &mut frame.data, sei);
+            }
+        }
+        frame.offset = self.offset;
+        self.offset += frame.data.len() as u64;
+
+        if frame.picture_type == PictureType::Unknown || frame.temporal_id.is_none() {
+            let (picture_type, temporal_id) = self.parse(&frame.data);
+            if frame.picture_type == PictureType::Unknown {
+                frame.picture_type = picture_type;
+            }
+            frame.temporal_id.get_or_insert(temporal_id);
+        }
+        if frame.picture_type == PictureType::Unknown && frame.key != 0 {
+            frame.picture_type = PictureType::Idr;
+        }
+    }
+
+    /// The dts `steps` frames before the first submitted pts, by the average pts step of the
+    /// frames submitted so far (at most `reorder_delay + 1` of them).
+    fn extrapolate(&self, steps: usize) -> Option<i64> {
+        let first = *self.dts.front()?;
+        let n = self.dts.len().min(self.reorder_delay + 1) - 1;
+        let step = if n > 0 {
+            ((self.dts[n] - first) / n as i64).max(0)
+        } else {
+            0
+        };
+        Some(first - steps as i64 * step)
+    }
+
+    /// Picture type and temporal id from the NAL headers of an Annex B packet.
+    fn parse(&mut self, data: &[u8]) -> (PictureType, u8) {
+        let mut picture_type = PictureType::Unknown;
+        let mut temporal_id = 0;
+        for nal in nal_units(data) {
+            match self.data_format {
+                DataFormat::H264 => match h264_nal(nal) {
+                    Nal::Slice(t) if picture_type == PictureType::Unknown => picture_type = t,
+                    Nal::TemporalId(id) => temporal_id = id,
+                    _ => {}
+                },
+                DataFormat::H265 => {
+                    if nal.len() >= 2 {
+                        temporal_id = (nal[1] & 0x07).saturating_sub(1);
+                    }
+                    match self.hevc_nal(nal) {
+                        Nal::Slice(t) if picture_type == PictureType::Unknown => picture_type = t,
+                        _ => {}
+                    }
+                }
+                _ => return (PictureType::Unknown, 0),
+            }
+        }
+        (picture_type, temporal_id)
+    }
+
+    /// Whether an SEI NAL of the packet carries a recovery point message.
+    fn has_recovery_point(&self, data: &[u8]) -> bool {
+        nal_units(data).any(|nal| match self.data_format {
+            DataFormat::H264 => nal.len() > 1 && nal[0] & 0x1f == 6 && sei_recovery_point(&nal[1..]),
+            // 仅前缀 SEI (39) 可携带 recovery point
+            DataFormat::H265 => {
+                nal.len() > 2 && (nal[0] >> 1) & 0x3f == 39 && sei_recovery_point(&nal[2..])
+            }
+            _ => false,
+        })
+    }
+
+    fn hevc_nal(&mut self, nal: &[u8]) -> Nal {
+        if nal.len() < 3 {
+            return Nal::Other;
+        }
+        let nal_type = (nal[0] >> 1) & 0x3f;
+        let mut r = BitReader::new(&nal[2..]);
+        match nal_type {
+            19 | 20 => Nal::Slice(PictureType::Idr),
+            0..=21 => {
+                let first_slice = r.bit() == Some(1);
+                if !first_slice {
+                    return Nal::Other;
+                }
+                if (16..=23).contains(&nal_type) {
+                    r.skip(1); // no_output_of_prior_pics_flag
+                }
+                let Some(pps_id) = r.ue() else {
+                    return Nal::Other;
+                };
+                let extra = self
+                    .hevc_extra_bits
+                    .get(pps_id as usize)
+                    .copied()
+                    .unwrap_or(0);
+                r.skip(extra as usize);
+                match r.ue() {
+                    Some(0) => Nal::Slice(PictureType::B),
+                    Some(1) => Nal::Slice(PictureType::P),
+                    Some(2) => Nal::Slice(PictureType::I),
+                    _ => Nal::Other,
+                }
+            }
+            // PPS：记下 num_extra_slice_header_bits
+            34 => {
+                if let (Some(pps_id), Some(_sps_id)) = (r.ue(), r.ue()) {
+                    r.skip(2); // dependent_slice_segments_enabled_flag, output_flag_present_flag
+                    if let (Some(extra), Some(slot)) =
+                        (r.bits(3), self.hevc_extra_bits.get_mut(pps_id as usize))
+                    {
+                        *slot = extra as u8;
+                    }
+                }
+                Nal::Other
+            }
+            _ => Nal::Other,
+        }
+    }
+}
+
+enum Nal {
+    Slice(PictureType),
+    TemporalId(u8),
+    Other,
+}
+
+fn h264_nal(nal: &[u8]) -> Nal {
+    if nal.len() < 2 {
+        return Nal::Other;
+    }
+    match nal[0] & 0x1f {
+        5 => Nal::Slice(PictureType::Idr),
+        1 => {
+            let mut r = BitReader::new(&nal[1..]);
+            r.ue(); // first_mb_in_slice
+            match r.ue().map(|t| t % 5) {
+                Some(0) | Some(3) => Nal::Slice(PictureType::P),
+                Some(1) => Nal::Slice(PictureType::B),
+                Some(2) | Some(4) => Nal::Slice(PictureType::I),
+                _ => Nal::Other,
+            }
+        }
+        // SVC 前缀 NAL：nal_unit_header_svc_extension 第三字节高 3 位为 temporal_id
+        14 | 20 if nal.len() >= 4 && nal[1] & 0x80 != 0 => Nal::TemporalId(nal[3] >> 5),
+        _ => Nal::Other,
+    }
+}
+
+// H.264 与 H.265 中 recovery point SEI 的 payloadType 均为 6
+const SEI_RECOVERY_POINT: u32 = 6;
+
+/// Whether the SEI messages of `payload` (the NAL without its header) include a recovery point.
+fn sei_recovery_point(payload: &[u8]) -> bool {
+    // 0xff 前缀累加的变长值
+    fn value(r: &mut BitReader) -> Option<u32> {
+        let mut v = 0;
+        loop {
+            let b = r.bits(8)?;
+            v += b;
+            if b != 0xff {
+                return Some(v);
+            }
+        }
+    }
+    let mut r = BitReader::new(payload);
+    // 剩下的 0x80 为 rbsp_trailing_bits，之后没有消息
+    while payload.get(r.byte..).is_some_and(|rest| rest != [0x80]) {
+        let (Some(payload_type), Some(size)) = (value(&mut r), value(&mut r)) else {
+            return false;
+        };
+        if payload_type == SEI_RECOVERY_POINT {
+            return true;
+        }
+        r.skip(size as usize * 8);
+    }
+    false
+}
+
+/// Colour description in the VUI of the first SPS of an Annex B packet that has one. `None`
+/// when no SPS signals a colour description, or it uses code points `ColorDescription` has no
+/// variant for.
+pub fn color_description(data_format: DataFormat, data: &[u8]) -> Option<ColorDescription> {
+    nal_units(data).find_map(|nal| match data_format {
+        DataFormat::H264 if nal.len() > 1 && nal[0] & 0x1f == 7 => h264_sps_color(&nal[1..]),
+        DataFormat::H265 if nal.len() > 2 && (nal[0] >> 1) & 0x3f == 33 => {
+            hevc_sps_color(&nal[2..])
+        }
+        _ => None,
+    })
+}
+
+fn h264_sps_color(payload: &[u8]) -> Option<ColorDescription> {
+    let mut r = BitReader::new(payload);
+    let profile_idc = r.bits(8)?;
+    r.bits(16)?; // constraint_set 标志与 level_idc
+    r.ue()?; // seq_parameter_set_id
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        let chroma_format_idc = r.ue()?;
+        if chroma_format_idc == 3 {
+            r.bits(1)?; // separate_colour_plane_flag
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.bits(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.bit()? == 1 {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bit()? == 1 {
+                    h264_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.bits(1)?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?;
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.bits(1)?; // gaps_in_frame_num_value_allowed_flag
+    r.ue()?; // pic_width_in_mbs_minus1
+    r.ue()?; // pic_height_in_map_units_minus1
+    if r.bit()? == 0 {
+        r.bits(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.bits(1)?; // direct_8x8_inference_flag
+    if r.bit()? == 1 {
+        for _ in 0..4 {
+            r.ue()?; // frame_crop_*_offset
+        }
+    }
+    if r.bit()? == 0 {
+        return None; // vui_parameters_present_flag
+    }
+    vui_color(&mut r)
+}
+
+fn h264_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let (mut last, mut next) = (8, 8);
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + r.se()? + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Some(())
+}
+
+fn hevc_sps_color(payload: &[u8]) -> Option<ColorDescription> {
+    let mut r = BitReader::new(payload);
+    r.bits(4)?; // sps_video_parameter_set_id
+    let sub_layers = r.bits(3)? as usize; // sps_max_sub_layers_minus1
+    r.bits(1)?; // sps_temporal_id_nesting_flag
+    r.skip_bits(96)?; // profile_tier_level：通用档次 88 位与 general_level_idc
+    let mut present = [(false, false); 7];
+    for p in present.iter_mut().take(sub_layers) {
+        *p = (r.bit()? == 1, r.bit()? == 1);
+    }
+    if sub_layers > 0 {
+        r.skip_bits(2 * (8 - sub_layers))?; // reserved_zero_2bits
+    }
+    for &(profile, level) in &present[..sub_layers] {
+        if profile {
+            r.skip_bits(88)?;
+        }
+        if level {
+            r.skip_bits(8)?;
+        }
+    }
+    r.ue()?; // sps_seq_parameter_set_id
+    if r.ue()? == 3 {
+        r.bits(1)?; // separate_colour_plane_flag
+    }
+    r.ue()?; // pic_width_in_luma_samples
+    r.ue()?; // pic_height_in_luma_samples
+    if r.bit()? == 1 {
+        for _ in 0..4 {
+            r.ue()?; // conf_win_*_offset
+        }
+    }
+    r.ue()?; // bit_depth_luma_minus8
+    r.ue()?; // bit_depth_chroma_minus8
+    let poc_lsb_bits = r.ue()? as usize + 4;
+    let first = if r.bit()? == 1 { 0 } else { sub_layers };
+    for _ in first..=sub_layers {
+        r.ue()?; // sps_max_dec_pic_buffering_minus1
+        r.ue()?; // sps_max_num_reorder_pics
+        r.ue()?; // sps_max_latency_increase_plus1
+    }
+    for _ in 0..6 {
+        r.ue()?; // 编码块与变换块尺寸、变换层级深度
+    }
+    // scaling_list_enabled_flag 与 sps_scaling_list_data_present_flag
+    if r.bit()? == 1 && r.bit()? == 1 {
+        hevc_scaling_list(&mut r)?;
+    }
+    r.bits(2)?; // amp_enabled_flag, sample_adaptive_offset_enabled_flag
+    if r.bit()? == 1 {
+        r.bits(8)?; // pcm_sample_bit_depth_luma/chroma_minus1
+        r.ue()?;
+        r.ue()?;
+        r.bits(1)?; // pcm_loop_filter_disabled_flag
+    }
+    let sets = r.ue()? as usize;
+    if sets > 64 {
+        return None;
+    }
+    // 各短期参考集的 NumDeltaPocs；SPS 中的预测总是基于前一个集
+    let mut delta_pocs: Vec<u32> = Vec::with_capacity(sets);
+    for i in 0..sets {
+        let n = if i != 0 && r.bit()? == 1 {
+            r.bits(1)?; // delta_rps_sign
+            r.ue()?; // abs_delta_rps_minus1
+            let mut n = 0;
+            for _ in 0..=delta_pocs[i - 1] {
+                // used_by_curr_pic_flag 为 0 时才有 use_delta_flag
+                if r.bit()? == 1 || r.bit()? == 1 {
+                    n += 1;
+                }
+            }
+            n
+        } else {
+            let (negative, positive) = (r.ue()?, r.ue()?);
+            if negative > 16 || positive > 16 {
+                return None;
+            }
+            for _ in 0..negative + positive {
+                r.ue()?; // delta_poc_s*_minus1
+                r.bits(1)?; // used_by_curr_pic_s*_flag
+            }
+            negative + positive
+        };
+        delta_pocs.push(n);
+    }
+    if r.bit()? == 1 {
+        for _ in 0..r.ue()? {
+            r.bits(poc_lsb_bits)?; // lt_ref_pic_poc_lsb_sps
+            r.bits(1)?; // used_by_curr_pic_lt_sps_flag
+        }
+    }
+    r.bits(2)?; // sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+    if r.bit()? == 0 {
+        return None; // vui_parameters_present_flag
+    }
+    vui_color(&mut r)
+}
+
+fn hevc_scaling_list(r: &mut BitReader) -> Option<()> {
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            if r.bit()? == 0 {
+                r.ue()?; // scaling_list_pred_matrix_id_delta
+            } else {
+                if size_id > 1 {
+                    r.se()?; // scaling_list_dc_coef_minus8
+                }
+                for _ in 0..64.min(1 << (4 + (size_id << 1))) {
+                    r.se()?;
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+/// The start of the VUI, which H.264 and HEVC share, up to the chroma sample location.
+fn vui_color(r: &mut BitReader) -> Option<ColorDescription> {
+    // aspect_ratio_info_present_flag；255 为 Extended_SAR，带 sar_width/sar_height
+    if r.bit()? == 1 && r.bits(8)? == 255 {
+        r.bits(32)?;
+    }
+    if r.bit()? == 1 {
+        r.bits(1)?; // overscan_appropriate_flag
+    }
+    if r.bit()? == 0 {
+        return None; // video_signal_type_present_flag
+    }
+    r.bits(3)?; // video_format
+    let full_range = r.bit()? == 1;
+    if r.bit()? == 0 {
+        return None; // colour_description_present_flag
+    }
+    let (primaries, transfer, matrix) = (r.bits(8)?, r.bits(8)?, r.bits(8)?);
+    // chroma_loc_info_present_flag；取顶场位置，缺省为 0
+    let chroma_location = if r.bit()? == 1 { r.ue()? } else { 0 };
+    ColorDescription::from_codes(
+        primaries as u8,
+        transfer as u8,
+        matrix as u8,
+        full_range,
+        chroma_location.min(u8::MAX as u32) as u8,
+    )
+}
+
+/// Splits an Annex B stream at its start codes. NAL units keep their header byte(s).
+pub(crate) fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = vec![];
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push((i, i + 3));
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    let ends: Vec<usize> = starts
+        .iter()
+        .skip(1)
+        .map(|(code, _)| *code)
+        .chain(std::iter::once(data.len()))
+        .collect();
+    starts.into_iter().zip(ends).map(move |((_, begin), end)| {
+        // 四字节起始码的前导 0 与尾随的 trailing_zero 都属于上一个 NAL 之外
+        let mut end = end;
+        while end > begin && data[end - 1] == 0 {
+            end -= 1;
+        }
+        &data[begin..end]
+    })
+}
+
+/// Exp-Golomb reader over a NAL payload, skipping emulation prevention bytes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    byte: usize,
+    bit: u8,
+    zeros: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            byte: 0,
+            bit: 0,
+            zeros: 0,
+        }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        if self.bit == 0 {
+            // 00 00 03 中的 03 为防竞争字节，不属于 RBSP
+            if self.zeros >= 2 && self.data.get(self.byte) == Some(&3) {
+                self.byte += 1;
+                self.zeros = 0;
+            }
+            let b = *self.data.get(self.byte)?;
+            self.zeros = if b == 0 { self.zeros + 1 } else { 0 };
+        }
+        let b = self.data[self.byte];
+        let value = (b >> (7 - self.bit)) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.byte += 1;
+        }
+        Some(value as u32)
+    }
+
+    fn bits(&mut self, n: usize) -> Option<u32> {
+        (0..n).try_fold(0, |v, _| Some((v << 1) | self.bit()?))
+    }
+
+    fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.bit();
+        }
+    }
+
+    /// Like `skip`, but `None` past the end of the data.
+    fn skip_bits(&mut self, n: usize) -> Option<()> {
+        for _ in 0..n {
+            self.bit()?;
+        }
+        Some(())
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                return None;
+            }
+        }
+        Some((1 << zeros) - 1 + self.bits(zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let k = self.ue()?;
+        Some(if k & 1 == 1 {
+            (k / 2 + 1) as i32
+        } else {
+            -((k / 2) as i32)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{ChromaLocation, ColorSpace, Matrix, Primaries, Range, Transfer};
+    use std::time::Duration;
+
+    fn packet(pts: i64, data: &[u8]) -> EncodeFrame {
+        EncodeFrame {
+            data: data.to_vec(),
+            pts,
+            ..Default::default()
+        }
+    }
+
+    // H.264：SPS、PPS、IDR 片；P 片（slice_type 5）；B 片（slice_type 6）
+    const H264_IDR: &[u8] = &[0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x68, 0xce, 0, 0, 1, 0x65, 0x88];
+    const H264_P: &[u8] = &[0, 0, 0, 1, 0x41, 0x9a, 0x80];
+    const H264_B: &[u8] = &[0, 0, 0, 1, 0x01, 0x9c];
+
+    /// 测试 H.264 片类型解析与 SVC 前缀 NAL 中的 temporal_id
+    #[test]
+    fn test_h264() {
+        let mut a = Annotator::new(DataFormat::H264);
+        assert_eq!(a.parse(H264_IDR), (PictureType::Idr, 0));
+        assert_eq!(a.parse(H264_P), (PictureType::P, 0));
+        assert_eq!(a.parse(H264_B), (PictureType::B, 0));
+        // first_mb_in_slice = 0，slice_type = 7 (I)
+        assert_eq!(a.parse(&[0, 0, 1, 0x21, 0x88]), (PictureType::I, 0));
+        let svc = [0, 0, 1, 0x6e, 0x80, 0x00, 0x40, 0, 0, 1, 0x41, 0x9a];
+        assert_eq!(a.parse(&svc), (PictureType::P, 2));
+        assert_eq!(a.parse(&[]), (PictureType::Unknown, 0));
+    }
+
+    /// 测试 H.265 IDR/CRA/P/B 片、temporal_id 及 PPS 中的额外片头比特
+    #[test]
+    fn test_h265() {
+        let mut a = Annotator::new(DataFormat::H265);
+        // IDR_W_RADL
+        assert_eq!(a.parse(&[0, 0, 1, 0x26, 0x01, 0xaf]), (PictureType::Idr, 0));
+        // CRA：first_slice 1, no_output 0, pps_id 0 (1), slice_type 2 (011)
+        assert_eq!(a.parse(&[0, 0, 1, 0x2a, 0x01, 0xac]), (PictureType::I, 0));
+        // TRAIL_R, tid 1：first_slice 1, pps_id 0 (1), slice_type 1 (010)
+        assert_eq!(a.parse(&[0, 0, 1, 0x02, 0x02, 0xd0]), (PictureType::P, 1));
+        // slice_type 0 (1)
+        assert_eq!(a.parse(&[0, 0, 1, 0x02, 0x01, 0xe0]), (PictureType::B, 0));
+        // 非首个片段不解析
+        assert_eq!(a.parse(&[0, 0, 1, 0x02, 0x01, 0x60]), (PictureType::Unknown, 0));
+
+        // PPS：pps_id 0, sps_id 0, 两个标志 0, num_extra_slice_header_bits 2
+        a.parse(&[0, 0, 1, 0x44, 0x01, 0xc4]);
+        // first_slice 1, pps_id 0 (1), 2 个额外比特 11, slice_type 1 (010)
+        assert_eq!(a.parse(&[0, 0, 1, 0x02, 0x01, 0xf4]), (PictureType::P, 0));
+    }
+
+    /// 测试防竞争字节被跳过
+    #[test]
+    fn test_emulation_prevention() {
+        // 00 00 03 01 → RBSP 00 00 01
+        let mut r = BitReader::new(&[0x00, 0x00, 0x03, 0x01, 0x80]);
+        assert_eq!(r.bits(16), Some(0));
+        assert_eq!(r.bits(8), Some(1));
+        assert_eq!(r.bit(), Some(1));
+        let nals: Vec<_> = nal_units(&[0, 0, 0, 1, 0x65, 0, 0, 0, 1, 0x41, 0, 0, 1, 0x01]).collect();
+        assert_eq!(nals, vec![&[0x65][..], &[0x41][..], &[0x01][..]]);
+    }
+
+    /// 测试延迟、字节偏移与 dts；SDK 已给出的字段不被覆盖
+    #[test]
+    fn test_annotate() {
+        let mut a = Annotator::new(DataFormat::H264);
+        let t0 = Instant::now();
+        a.submitted_at(0, t0);
+        a.submitted_at(40, t0 + Duration::from_millis(10));
+
+        let mut idr = packet(0, H264_IDR);
+        a.annotate_at(&mut idr, t0 + Duration::from_millis(7));
+        assert_eq!(idr.latency, Duration::from_millis(7));
+        assert_eq!((idr.dts, idr.offset), (Some(0), 0));
+        assert_eq!((idr.picture_type, idr.temporal_id), (PictureType::Idr, Some(0)));
+
+        let mut p = packet(40, H264_P);
+        p.picture_type = PictureType::I;
+        p.avg_qp = Some(30);
+        p.dts = Some(39);
+        a.annotate_at(&mut p, t0 + Duration::from_millis(15));
+        assert_eq!(p.latency, Duration::from_millis(5));
+        assert_eq!((p.dts, p.offset), (Some(39), H264_IDR.len() as u64));
+        assert_eq!((p.picture_type, p.avg_qp), (PictureType::I, Some(30)));
+
+        // 一个 B 帧：提交 0 40 80 120 160，按解码顺序输出 0 80 40 160 120
+        let mut a = Annotator::new(DataFormat::H264);
+        a.set_reorder_delay(1);
+        for pts in [0, 40, 80, 120, 160] {
+            a.submitted(pts);
+        }
+        let dts: Vec<_> = [0, 80, 40, 160, 120]
+            .into_iter()
+            .map(|pts| {
+                let mut f = packet(pts, H264_P);
+                a.annotate(&mut f);
+                assert!(f.dts.unwrap() <= pts);
+                f.dts.unwrap()
+            })
+            .collect();
+        assert_eq!(dts, vec![-40, 0, 40, 80, 120]);
+
+        // AV1 不解析 NAL，关键帧标记为 IDR
+        let mut a = Annotator::new(DataFormat::AV1);
+        let mut key = packet(0, &[0x12, 0x00]);
+        key.key = 1;
+        a.annotate(&mut key);
+        assert_eq!((key.picture_type, key.dts), (PictureType::Idr, Some(0)));
+    }
+
+    /// 测试 recovery point SEI 的识别：跳过其他 SEI 消息，只认 H.265 前缀 SEI
+    #[test]
+    fn test_recovery_point() {
+        let mut a = Annotator::new(DataFormat::H264);
+        // SEI：user_data_unregistered (5) 大小 2，recovery_point (6) 大小 1，rbsp_trailing_bits
+        let sei = [0, 0, 1, 0x06, 0x05, 0x02, 0xaa, 0xbb, 0x06, 0x01, 0x84, 0x80];
+        let mut p = packet(0, &[&sei[..], H264_P].concat());
+        a.annotate(&mut p);
+        assert!(p.recovery_point);
+        // 只有 user_data_unregistered
+        let mut p = packet(40, &[0, 0, 1, 0x06, 0x05, 0x01, 0x06, 0x80, 0, 0, 1, 0x41, 0x9a]);
+        a.annotate(&mut p);
+        assert!(!p.recovery_point);
+        // payloadType 经 0xff 累加：255 + 6 不是 recovery point
+        let mut p = packet(80, &[0, 0, 1, 0x06, 0xff, 0x06, 0x01, 0x00, 0x80]);
+        a.annotate(&mut p);
+        assert!(!p.recovery_point);
+
+        let mut a = Annotator::new(DataFormat::H265);
+        let mut p = packet(0, &[0, 0, 1, 0x4e, 0x01, 0x06, 0x01, 0x84, 0x80]);
+        a.annotate(&mut p);
+        assert!(p.recovery_point);
+        // 后缀 SEI (40)
+        let mut p = packet(40, &[0, 0, 1, 0x50, 0x01, 0x06, 0x01, 0x84, 0x80]);
+        a.annotate(&mut p);
+        assert!(!p.recovery_point);
+    }
+
+    /// 测试 HDR10 SEI 只写入关键帧与恢复点，字节偏移计入插入的 SEI
+    #[test]
+    fn test_hdr_sei() {
+        let metadata = HdrMetadata {
+            mastering_display: Some(crate::vram::hdr::MasteringDisplay::bt2020(1000.0, 0.005)),
+            content_light_level: None,
+        };
+        let sei = hdr::sei_nal(DataFormat::H264, &metadata).unwrap();
+        let mut a = Annotator::new(DataFormat::H264);
+        a.set_hdr(Some(&metadata));
+        let mut idr = packet(0, H264_IDR);
+        idr.key = 1;
+        a.annotate(&mut idr);
+        assert_eq!(idr.data, [&H264_IDR[..11], &sei, &H264_IDR[11..]].concat());
+        assert_eq!(idr.picture_type, PictureType::Idr);
+        assert_eq!(hdr::parse(DataFormat::H264, &idr.data), Some(metadata));
+
+        let mut p = packet(40, H264_P);
+        a.annotate(&mut p);
+        assert_eq!(p.data, H264_P);
+        assert_eq!(p.offset, idr.data.len() as u64);
+        let recovery = [&[0, 0, 1, 0x06, 0x06, 0x01, 0x84, 0x80][..], H264_P].concat();
+        let mut p = packet(80, &recovery);
+        a.annotate(&mut p);
+        assert_eq!(p.data, [&recovery[..8], &sei, H264_P].concat());
+
+        a.set_hdr(None);
+        let mut idr = packet(120, H264_IDR);
+        idr.key = 1;
+        a.annotate(&mut idr);
+        assert_eq!(idr.data, H264_IDR);
+    }
+
+    /// 组装 SPS 的位写入器，结束时补 rbsp 尾位与防竞争字节
+    #[derive(Default)]
+    struct BitWriter {
+        bits: Vec<bool>,
+    }
+
+    impl BitWriter {
+        fn u(&mut self, n: usize, v: u32) -> &mut Self {
+            for i in (0..n).rev() {
+                self.bits.push(v >> i & 1 == 1);
+            }
+            self
+        }
+
+        fn ue(&mut self, v: u32) -> &mut Self {
+            let n = 32 - (v + 1).leading_zeros() as usize;
+            self.u(n - 1, 0).u(n, v + 1)
+        }
+
+        fn se(&mut self, v: i32) -> &mut Self {
+            self.ue(if v > 0 { 2 * v as u32 - 1 } else { 2 * v.unsigned_abs() })
+        }
+
+        fn nal(&mut self, header: &[u8]) -> Vec<u8> {
+            self.u(1, 1);
+            while self.bits.len() % 8 != 0 {
+                self.bits.push(false);
+            }
+            let mut out = vec![0, 0, 0, 1];
+            out.extend_from_slice(header);
+            let mut zeros = 0;
+            for byte in self.bits.chunks(8) {
+                let b = byte.iter().fold(0u8, |b, &bit| b << 1 | bit as u8);
+                if zeros >= 2 && b <= 3 {
+                    out.push(3);
+                    zeros = 0;
+                }
+                zeros = if b == 0 { zeros + 1 } else { 0 };
+                out.push(b);
+            }
+            out
+        }
+
+        fn vui(&mut self, c: &ColorDescription) -> &mut Self {
+            self.u(1, 1).u(8, 255).u(16, 4).u(16, 3); // Extended_SAR 4:3
+            self.u(1, 0); // overscan_info_present_flag
+            self.u(1, 1).u(3, 5).u(1, c.full_range as u32).u(1, 1);
+            self.u(8, c.primaries.code() as u32);
+            self.u(8, c.transfer.code() as u32);
+            self.u(8, c.matrix.code() as u32);
+            let loc = c.chroma_location.code() as u32;
+            if loc != 0 {
+                self.u(1, 1).ue(loc).ue(loc);
+            } else {
+                self.u(1, 0);
+            }
+            self.u(1, 0).u(1, 0).u(1, 0) // 其后的字段不影响解析
+        }
+    }
+
+    fn h264_sps(c: Option<&ColorDescription>) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.u(8, 100).u(8, 0).u(8, 41).ue(0); // High，level 4.1
+        w.ue(1).ue(0).ue(0).u(1, 0); // 4:2:0，8 位
+        w.u(1, 1); // seq_scaling_matrix_present_flag
+        w.u(1, 1).se(-8); // 第一个 4x4 列表：首项使 nextScale 为 0，列表提前结束
+        w.u(1, 0).u(1, 0).u(1, 0).u(1, 0).u(1, 0);
+        w.u(1, 1); // 8x8 帧内列表写满 64 项
+        for i in 0..64 {
+            w.se(if i % 2 == 0 { 3 } else { -2 });
+        }
+        w.u(1, 0);
+        w.ue(0).ue(1).u(1, 0).se(-2).se(1).ue(2).se(4).se(-4); // pic_order_cnt_type 1
+        w.ue(4).u(1, 0).ue(119).ue(67); // 1920x1088
+        w.u(1, 0).u(1, 0).u(1, 1); // 场编码，带 mb_adaptive_frame_field_flag
+        w.u(1, 1).ue(0).ue(0).ue(0).ue(4); // 裁剪到 1080
+        match c {
+            Some(c) => w.u(1, 1).vui(c),
+            None => w.u(1, 0),
+        };
+        w.nal(&[0x67])
+    }
+
+    fn hevc_sps(c: &ColorDescription) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.u(4, 0).u(3, 1).u(1, 1); // 两个时域子层
+        w.u(8, 0x01).u(32, 0x6000_0000).u(32, 0x9000_0000).u(16, 0).u(8, 120);
+        w.u(1, 1).u(1, 1).u(2 * 7, 0); // 子层档次与级别
+        w.u(32, 0xffff_ffff).u(32, 0).u(24, 0).u(8, 90);
+        w.ue(0).ue(1).ue(1920).ue(1080).u(1, 1).ue(0).ue(0).ue(0).ue(4);
+        w.ue(2).ue(2).ue(4); // 10 位，log2_max_pic_order_cnt_lsb = 8
+        w.u(1, 1).ue(3).ue(0).ue(0).ue(3).ue(0).ue(0);
+        w.ue(0).ue(3).ue(0).ue(3).ue(2).ue(2);
+        w.u(1, 1).u(1, 1); // scaling_list_data
+        for size_id in 0..4 {
+            for matrix_id in (0..6).step_by(if size_id == 3 { 3 } else { 1 }) {
+                if matrix_id % 2 == 0 {
+                    w.u(1, 0).ue(0);
+                } else {
+                    w.u(1, 1);
+                    if size_id > 1 {
+                        w.se(8);
+                    }
+                    for _ in 0..64.min(1 << (4 + (size_id << 1))) {
+                        w.se(-1);
+                    }
+                }
+            }
+        }
+        w.u(1, 0).u(1, 1).u(1, 1).u(8, 0x77).ue(0).ue(1).u(1, 0); // PCM
+        w.ue(3);
+        w.ue(2).ue(1).ue(0).u(1, 1).ue(1).u(1, 0).ue(3).u(1, 1); // 3 个 deltaPoc
+        w.u(1, 1).u(1, 0).ue(0); // 由前一个集预测：遍历 4 项，留下 3 个
+        w.u(1, 1).u(1, 0).u(1, 1).u(1, 0).u(1, 0).u(1, 1);
+        w.u(1, 1).u(1, 1).ue(1); // 再次预测：遍历 4 项
+        for used in [1, 0, 1, 1] {
+            w.u(1, used);
+            if used == 0 {
+                w.u(1, 1);
+            }
+        }
+        w.u(1, 1).ue(2).u(8, 17).u(1, 1).u(8, 200).u(1, 0); // 长期参考帧
+        w.u(1, 1).u(1, 0).u(1, 1).vui(c);
+        w.nal(&[0x42, 0x01])
+    }
+
+    /// 测试从 SPS 的 VUI 解析颜色描述
+    #[test]
+    fn test_color_description() {
+        let descriptions = [
+            ColorDescription::from(ColorSpace::default()),
+            ColorDescription {
+                primaries: Primaries::Bt601,
+                transfer: Transfer::Srgb,
+                matrix: Matrix::Bt601,
+                full_range: true,
+                chroma_location: ChromaLocation::Center,
+            },
+            ColorDescription {
+                primaries: Primaries::Bt2020,
+                transfer: Transfer::Pq,
+                matrix: Matrix::Bt2020,
+                full_range: false,
+                chroma_location: ChromaLocation::TopLeft,
+            },
+            ColorDescription::from(
+                ColorSpace::new(Matrix::Bt2020, Range::Full).with_transfer(Transfer::Hlg),
+            ),
+        ];
+        for c in &descriptions {
+            let sps = h264_sps(Some(c));
+            let mut packet = sps.clone();
+            packet.extend_from_slice(&H264_IDR[4..]);
+            assert_eq!(color_description(DataFormat::H264, &packet), Some(*c));
+            assert_eq!(color_description(DataFormat::H265, &sps), None);
+
+            let sps = hevc_sps(c);
+            assert!(sps.windows(4).any(|w| w[..3] == [0, 0, 3]));
+            assert_eq!(color_description(DataFormat::H265, &sps), Some(*c));
+            // 截断的 SPS 不会越界
+            for len in 5..sps.len() {
+                let _ = color_description(DataFormat::H265, &sps[..len]);
+            }
+        }
+        assert_eq!(color_description(DataFormat::H264, &h264_sps(None)), None);
+        assert_eq!(color_description(DataFormat::H264, H264_P), None);
+    }
+}
@@ -6,18 +6,49 @@
 use std::ffi::c_void;
 
 use crate::{
-    common::DataFormat::*,
+    common::{AdapterVendor, BitDepth, DataFormat::*},
+    convert::ColorDescription,
+    platform::win::Adapters,
+    vram::adapter,
     vram::inner::{
         DecodeBackend, DecodeCalls, DecodeFrame, EncodeBackend, EncodeCalls, EncodeFrame,
         InnerDecodeContext, InnerEncodeContext,
     },
+    vram::{
+        DecodeContext, DecoderCaps, DynamicContext, EncoderCaps, FeatureContext, Profile,
+        RateControl, ERR_QUEUE_FULL, ERR_UNSUPPORTED,
+    },
     vram::mfx_bridge,
 };
 use mfx_bridge::*;
 
+// H.273 码点；未指定时 present 为 false，不写 VUI 颜色描述
+fn color_desc(color: Option<ColorDescription>) -> ColorDesc {
+    match color {
+        Some(c) => ColorDesc {
+            present: true,
+            primaries: c.primaries.code() as i32,
+            transfer: c.transfer.code() as i32,
+            matrix: c.matrix.code() as i32,
+            full_range: c.full_range,
+            chroma_location: c.chroma_location.code() as i32,
+        },
+        None => ColorDesc {
+            present: false,
+            primaries: 2,
+            transfer: 2,
+            matrix: 2,
+            full_range: false,
+            chroma_location: 0,
+        },
+    }
+}
+
 /// Backend implementation for MFX encoding (trait-based, Rust-owned).
 pub struct MfxEncodeBackend {
     codec: *mut c_void,
+    // 0 表示同步会话，submit 退化为 encode
+    async_depth: i32,
 }
 unsafe impl Send for MfxEncodeBackend {}
 
@@ -38,7 +69,57 @@ impl MfxEncodeBackend {
         if codec.is_null() {
             return Err(());
         }
-        Ok(Box::new(MfxEncodeBackend { codec }))
+        Ok(Box::new(MfxEncodeBackend {
+            codec,
+            async_depth: 0,
+        }))
+    }
+
+    fn create_async(
+        device: *mut c_void,
+        data_format: i32,
+        d: &DynamicContext,
+        depth: i32,
+    ) -> Result<Box<dyn EncodeBackend>, ()> {
+        let codec_id = match data_format {
+            0 => 0,
+            1 => 1,
+            _ => return Err(()),
+        };
+        let codec = unsafe {
+            mfx_CreateEncoderAsync(
+                device as *mut u8,
+                d.width,
+                d.height,
+                codec_id,
+                d.kbitrate,
+                d.framerate,
+                d.gop,
+                depth,
+                d.bframes,
+                d.intra_refresh.map_or(0, |r| r.period as i32),
+                d.intra_refresh.map_or(0, |r| r.duration as i32),
+                d.ltr_frames,
+                color_desc(d.color),
+                d.bit_depth.bits(),
+                d.yuv444,
+            )
+        };
+        if codec.is_null() {
+            return Err(());
+        }
+        let async_depth = unsafe { mfx_GetAsyncDepth(codec) };
+        if async_depth == 0 {
+            log::debug!("MFX session is not pipelined, submit encodes synchronously");
+        }
+        let reorder_depth = unsafe { mfx_GetReorderDepth(codec) };
+        if reorder_depth < d.bframes {
+            log::debug!("MFX session uses {} of {} requested B-frames", reorder_depth, d.bframes);
+        }
+        Ok(Box::new(MfxEncodeBackend {
+            codec: codec as *mut c_void,
+            async_depth,
+        }))
     }
 }
 
@@ -49,20 +130,15 @@ impl EncodeBackend for MfxEncodeBackend {
         ms: i64,
         frames: &mut Vec<EncodeFrame>,
     ) -> Result<(), i32> {
-        let result = unsafe {
-            mfx_encode(
-                self.codec,
-                tex,
-                crate::vram::inner::hwcodec_encode_frame_callback,
-                frames as *mut Vec<EncodeFrame> as *mut c_void,
-                ms,
-            )
-        };
-        if result != 0 {
-            Err(result)
-        } else {
-            Ok(())
+        unsafe {
+            let frame = mfx_EncodeFrame(self.codec as *mut MfxEncoder, tex as *mut u8, ms);
+            if frame.is_null() {
+                return Err(-1);
+            }
+            frames.push(EncodeFrame::from(&*frame));
+            mfx_FreeEncodedFrame(frame);
         }
+        Ok(())
     }
 
     fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32> {
@@ -87,6 +163,81 @@ impl EncodeBackend for MfxEncodeBackend {
             self.codec = std::ptr::null_mut();
         }
     }
+
+    fn submit(
+        &mut self,
+        tex: *mut c_void,
+        ms: i64,
+        frames: &mut Vec<EncodeFrame>,
+    ) -> Result<(), i32> {
+        if self.async_depth == 0 {
+            return self.encode(tex, ms, frames);
+        }
+        match unsafe { mfx_SubmitFrame(self.codec as *mut MfxEncoder, tex as *mut u8, ms) } {
+            0 => Ok(()),
+            1 => Err(ERR_QUEUE_FULL),
+            err => Err(err),
+        }
+    }
+
+    fn receive(&mut self, timeout_ms: u32, frames: &mut Vec<EncodeFrame>) -> Result<(), i32> {
+        let mut wait_ms = timeout_ms;
+        while self.pending() > 0 {
+            let mut status = 0;
+            let frame =
+                unsafe { mfx_ReceiveFrame(self.codec as *mut MfxEncoder, wait_ms, &mut status) };
+            if frame.is_null() {
+                return if status == 1 { Ok(()) } else { Err(status) };
+            }
+            unsafe {
+                frames.push(EncodeFrame::from(&*frame));
+                mfx_FreeEncodedFrame(frame);
+            }
+            // 最早的一帧到达后只收集已经完成的帧，不再等待
+            wait_ms = 0;
+        }
+        Ok(())
+    }
+
+    fn pending(&self) -> usize {
+        if self.async_depth == 0 || self.codec.is_null() {
+            return 0;
+        }
+        unsafe { mfx_PendingFrames(self.codec as *mut MfxEncoder) }.max(0) as usize
+    }
+
+    fn flush(&mut self) -> Result<(), i32> {
+        if self.async_depth == 0 || self.codec.is_null() {
+            return Ok(());
+        }
+        match unsafe { mfx_FlushEncoder(self.codec as *mut MfxEncoder) } {
+            0 => Ok(()),
+            err => Err(err),
+        }
+    }
+
+    fn reorder_depth(&self) -> usize {
+        if self.async_depth == 0 || self.codec.is_null() {
+            return 0;
+        }
+        unsafe { mfx_GetReorderDepth(self.codec as *mut MfxEncoder) }.max(0) as usize
+    }
+
+    fn mark_ltr(&mut self) -> Result<(), i32> {
+        match unsafe { mfx_MarkLtr(self.codec as *mut MfxEncoder) } {
+            0 => Ok(()),
+            1 => Err(ERR_UNSUPPORTED),
+            err => Err(err),
+        }
+    }
+
+    fn invalidate(&mut self, first: i64, last: i64) -> Result<(), i32> {
+        match unsafe { mfx_InvalidateRefFrames(self.codec as *mut MfxEncoder, first, last) } {
+            0 => Ok(()),
+            1 => Err(ERR_UNSUPPORTED),
+            err => Err(err),
+        }
+    }
 }
 
 pub fn create_encode_backend(
@@ -102,6 +253,16 @@ pub fn create_encode_backend(
     MfxEncodeBackend::create(device, luid, data_format, width, height, bitrate, framerate, gop)
 }
 
+/// Opens a pipelined session with up to `depth` frames in flight; see `EncodeBackend::submit`.
+pub fn create_async_encode_backend(
+    device: *mut c_void,
+    data_format: i32,
+    d: &DynamicContext,
+    depth: i32,
+) -> Result<Box<dyn EncodeBackend>, ()> {
+    MfxEncodeBackend::create_async(device, data_format, d, depth)
+}
+
 /// Backend implementation for MFX decoding (trait-based).
 pub struct MfxDecodeBackend {
     codec: *mut c_void,
@@ -109,8 +270,14 @@ pub struct MfxDecodeBackend {
 unsafe impl Send for MfxDecodeBackend {}
 
 impl MfxDecodeBackend {
-    pub fn create(device: *mut c_void, _luid: i64, codec_id: i32) -> Result<Box<dyn DecodeBackend>, ()> {
-        let codec = unsafe { mfx_new_decoder(device, _luid, codec_id) };
+    pub fn create(
+        device: *mut c_void,
+        _luid: i64,
+        codec_id: i32,
+        bit_depth: BitDepth,
+    ) -> Result<Box<dyn DecodeBackend>, ()> {
+        let codec =
+            unsafe { mfx_CreateDecoder(device as *mut u8, codec_id, bit_depth.bits()) as *mut c_void };
         if codec.is_null() {
             return Err(());
         }
@@ -136,6 +303,26 @@ impl DecodeBackend for MfxDecodeBackend {
         }
     }
 
+    fn flush(&mut self, frames: &mut Vec<DecodeFrame>) -> Result<(), i32> {
+        if self.codec.is_null() {
+            return Ok(());
+        }
+        // 逐帧取出解码器扣住的图像，直到取空
+        loop {
+            let frame = unsafe { mfx_DrainFrame(self.codec as *mut MfxDecoder) };
+            if frame.is_null() {
+                return Ok(());
+            }
+            unsafe {
+                crate::vram::inner::hwcodec_decode_frame_callback(
+                    (*frame).texture as *mut c_void,
+                    frames as *mut Vec<DecodeFrame> as *mut c_void,
+                );
+                mfx_FreeDecodedFrame(frame);
+            }
+        }
+    }
+
     fn destroy(&mut self) {
         if !self.codec.is_null() {
             unsafe {
@@ -150,8 +337,9 @@ pub fn create_decode_backend(
     device: *mut c_void,
     luid: i64,
     codec_id: i32,
+    bit_depth: BitDepth,
 ) -> Result<Box<dyn DecodeBackend>, ()> {
-    MfxDecodeBackend::create(device, luid, codec_id)
+    MfxDecodeBackend::create(device, luid, codec_id, bit_depth)
 }
 
 pub fn encode_calls() -> EncodeCalls {
@@ -202,6 +390,62 @@ pub fn possible_support_decoders() -> Vec<InnerDecodeContext> {
     v
 }
 
+pub fn encoder_caps(device: *mut c_void, f: &FeatureContext) -> Option<EncoderCaps> {
+    let codec_id = match f.data_format {
+        H264 => 0,
+        H265 => 1,
+        _ => return None,
+    };
+    let caps = unsafe { mfx_GetEncodeCaps(device as *mut u8, codec_id) };
+    if !caps.supported {
+        return None;
+    }
+    Some(EncoderCaps {
+        driver: f.driver.clone(),
+        vendor: f.vendor.clone(),
+        luid: f.luid,
+        data_format: f.data_format,
+        min_width: caps.min_width,
+        min_height: caps.min_height,
+        max_width: caps.max_width,
+        max_height: caps.max_height,
+        profiles: Profile::from_mask(caps.profiles),
+        max_level: caps.max_level,
+        ten_bit: caps.ten_bit,
+        yuv444: caps.yuv444,
+        max_bframes: caps.max_bframes,
+        lookahead: caps.lookahead,
+        max_sessions: caps.max_sessions,
+        rate_control: RateControl::from_mask(caps.rate_control),
+        max_ltr_frames: caps.max_ltr_frames,
+    })
+}
+
+pub fn decoder_caps(device: *mut c_void, d: &DecodeContext) -> Option<DecoderCaps> {
+    let codec_id = match d.data_format {
+        H264 => 0,
+        H265 => 1,
+        _ => return None,
+    };
+    let caps = unsafe { mfx_GetDecodeCaps(device as *mut u8, codec_id) };
+    if !caps.supported {
+        return None;
+    }
+    Some(DecoderCaps {
+        driver: d.driver.clone(),
+        vendor: d.vendor.clone(),
+        luid: d.luid,
+        data_format: d.data_format,
+        min_width: caps.min_width,
+        min_height: caps.min_height,
+        max_width: caps.max_width,
+        max_height: caps.max_height,
+        profiles: Profile::from_mask(caps.profiles),
+        ten_bit: caps.ten_bit,
+        yuv444: caps.yuv444,
+    })
+}
+
 pub(crate) fn mfx_driver_support() -> i32 {
     if mfx_IsDriverAvailable() {
         0
@@ -274,11 +518,11 @@ pub unsafe extern "C" fn mfx_test_encode(
     luids_count: i32,
     desc_count: *mut i32,
     data_format: i32,
-    _width: i32,
-    _height: i32,
-    _bitrate: i32,
-    _framerate: i32,
-    _gop: i32,
+    width: i32,
+    height: i32,
+    bitrate: i32,
+    framerate: i32,
+    gop: i32,
     excluded_luids: *const i64,
     exclude_formats: *const i32,
     exclude_count: i32,
@@ -290,20 +534,27 @@ pub unsafe extern "C" fn mfx_test_encode(
     if !mfx_IsDriverAvailable() || luids_count < 1 {
         return 0;
     }
-    const MFX_LUID: i64 = 0;
     const VENDOR_MFX: i32 = 2;
-    if exclude_count > 0 && !excluded_luids.is_null() && !exclude_formats.is_null() {
-        for i in 0..exclude_count {
-            if *excluded_luids.add(i as usize) == MFX_LUID
-                && *exclude_formats.add(i as usize) == data_format
-            {
-                return 0;
+    let Ok(adapters) = Adapters::new(AdapterVendor::ADAPTER_VENDOR_INTEL) else {
+        return 0;
+    };
+    let excluded = adapter::excluded_pairs(excluded_luids, exclude_formats, exclude_count);
+    let found = adapter::test_adapters(
+        &adapters,
+        data_format,
+        &excluded,
+        luids_count as usize,
+        &mut |device, luid| {
+            let encoder =
+                mfx_new_encoder(device, luid, data_format, width, height, bitrate, framerate, gop);
+            if encoder.is_null() {
+                return false;
             }
-        }
-    }
-    *luids = MFX_LUID;
-    *vendors = VENDOR_MFX;
-    *desc_count = 1;
+            mfx_destroy_encoder(encoder);
+            true
+        },
+    );
+    adapter::write_found(&found, VENDOR_MFX, luids, vendors, desc_count);
     0
 }
 
@@ -312,7 +563,7 @@ pub unsafe extern "C" fn mfx_new_decoder(
     _luid: i64,
     codec_id: i32,
 ) -> *mut c_void {
-    mfx_CreateDecoder(device as *mut u8, codec_id) as *mut c_void
+    mfx_CreateDecoder(device as *mut u8, codec_id, 8) as *mut c_void
 }
 
 pub unsafe extern "C" fn mfx_decode(
@@ -357,19 +608,25 @@ pub unsafe extern "C" fn mfx_test_decode(
     if !mfx_IsDriverAvailable() || luids_count < 1 {
         return 0;
     }
-    const MFX_LUID: i64 = 0;
     const VENDOR_MFX: i32 = 2;
-    if exclude_count > 0 && !excluded_luids.is_null() && !exclude_formats.is_null() {
-        for i in 0..exclude_count {
-            if *excluded_luids.add(i as usize) == MFX_LUID
-                && *exclude_formats.add(i as usize) == data_format
-            {
-                return 0;
+    let Ok(adapters) = Adapters::new(AdapterVendor::ADAPTER_VENDOR_INTEL) else {
+        return 0;
+    };
+    let excluded = adapter::excluded_pairs(excluded_luids, exclude_formats, exclude_count);
+    let found = adapter::test_adapters(
+        &adapters,
+        data_format,
+        &excluded,
+        luids_count as usize,
+        &mut |device, luid| {
+            let decoder = mfx_new_decoder(device, luid, data_format);
+            if decoder.is_null() {
+                return false;
             }
-        }
-    }
-    *luids = MFX_LUID;
-    *vendors = VENDOR_MFX;
-    *desc_count = 1;
+            mfx_destroy_decoder(decoder);
+            true
+        },
+    );
+    adapter::write_found(&found, VENDOR_MFX, luids, vendors, desc_count);
     0
 }
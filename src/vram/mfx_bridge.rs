@@ -1,4 +1,5 @@
 #![allow(non_snake_case)]
+#![allow(clippy::too_many_arguments)] // 参数与 C++ 声明一一对应
 #![allow(dead_code)] // GetWidth/GetHeight 等为 C++ 接口预留，Rust 侧暂未使用
 
 #[cxx::bridge]
@@ -17,16 +18,29 @@ mod mfx_bridge {
         unsafe fn mfx_DestroyEncoder(encoder: *mut MfxEncoder);
         unsafe fn mfx_SetBitrate(encoder: *mut MfxEncoder, bitrate: i32);
         unsafe fn mfx_SetFramerate(encoder: *mut MfxEncoder, framerate: i32);
+        unsafe fn mfx_CreateEncoderAsync(device: *mut u8, width: i32, height: i32, codec_id: i32, bitrate: i32, framerate: i32, gop: i32, async_depth: i32, bframes: i32, intra_refresh_period: i32, intra_refresh_duration: i32, ltr_frames: i32, color: ColorDesc, bit_depth: i32, yuv444: bool) -> *mut MfxEncoder;
+        unsafe fn mfx_GetAsyncDepth(encoder: *mut MfxEncoder) -> i32;
+        unsafe fn mfx_PendingFrames(encoder: *mut MfxEncoder) -> i32;
+        unsafe fn mfx_GetReorderDepth(encoder: *mut MfxEncoder) -> i32;
+        unsafe fn mfx_FlushEncoder(encoder: *mut MfxEncoder) -> i32;
+        unsafe fn mfx_MarkLtr(encoder: *mut MfxEncoder) -> i32;
+        unsafe fn mfx_InvalidateRefFrames(encoder: *mut MfxEncoder, first: i64, last: i64) -> i32;
+        unsafe fn mfx_SubmitFrame(encoder: *mut MfxEncoder, texture: *mut u8, timestamp: i64) -> i32;
+        unsafe fn mfx_ReceiveFrame(encoder: *mut MfxEncoder, wait_ms: u32, status: *mut i32) -> *mut EncodedFrame;
         
         // MfxDecoder 方法
-        unsafe fn mfx_CreateDecoder(device: *mut u8, codec_id: i32) -> *mut MfxDecoder;
+        unsafe fn mfx_CreateDecoder(device: *mut u8, codec_id: i32, bit_depth: i32) -> *mut MfxDecoder;
         unsafe fn mfx_DecodeFrame(decoder: *mut MfxDecoder, data: *mut u8, length: i32) -> *mut DecodedFrame;
+        unsafe fn mfx_DrainFrame(decoder: *mut MfxDecoder) -> *mut DecodedFrame;
         unsafe fn mfx_DestroyDecoder(decoder: *mut MfxDecoder);
         unsafe fn mfx_GetWidth(decoder: *mut MfxDecoder) -> i32;
         unsafe fn mfx_GetHeight(decoder: *mut MfxDecoder) -> i32;
 
         unsafe fn mfx_FreeEncodedFrame(frame: *mut EncodedFrame);
         unsafe fn mfx_FreeDecodedFrame(frame: *mut DecodedFrame);
+
+        unsafe fn mfx_GetEncodeCaps(device: *mut u8, codec_id: i32) -> EncodeCaps;
+        unsafe fn mfx_GetDecodeCaps(device: *mut u8, codec_id: i32) -> DecodeCaps;
     }
     
     struct EncodedFrame {
@@ -34,6 +48,11 @@ mod mfx_bridge {
         size: i32,
         is_keyframe: bool,
         timestamp: i64,
+        picture_type: i32,
+        avg_qp: i32,
+        temporal_id: i32,
+        dts: i64,
+        has_dts: bool,
     }
     
     struct DecodedFrame {
@@ -41,6 +60,43 @@ mod mfx_bridge {
         width: i32,
         height: i32,
     }
+
+    struct ColorDesc {
+        present: bool,
+        primaries: i32,
+        transfer: i32,
+        matrix: i32,
+        full_range: bool,
+        chroma_location: i32,
+    }
+
+    struct EncodeCaps {
+        supported: bool,
+        min_width: i32,
+        min_height: i32,
+        max_width: i32,
+        max_height: i32,
+        profiles: u32,
+        max_level: i32,
+        ten_bit: bool,
+        yuv444: bool,
+        max_bframes: i32,
+        lookahead: bool,
+        max_sessions: i32,
+        rate_control: u32,
+        max_ltr_frames: i32,
+    }
+
+    struct DecodeCaps {
+        supported: bool,
+        min_width: i32,
+        min_height: i32,
+        max_width: i32,
+        max_height: i32,
+        profiles: u32,
+        ten_bit: bool,
+        yuv444: bool,
+    }
 }
 
 pub use mfx_bridge::*;
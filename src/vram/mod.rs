@@ -1,19 +1,72 @@
+// 驱动后端与 D3D11 相关部分仅 Windows 编译；上下文类型、Available 与缓存跨平台，便于在 Linux 上测试
+#[cfg(windows)]
 mod amf_bridge;
+#[cfg(windows)]
 mod mfx_bridge;
+#[cfg(windows)]
 mod nv_bridge;
 
+pub mod adapter;
+pub mod backend;
+#[cfg(windows)]
 pub(crate) mod amf;
+pub mod cache;
+pub mod hdr;
+pub mod metadata;
+pub mod probe;
+pub mod resilient;
+pub mod select;
+pub mod stats;
+#[cfg(windows)]
 pub mod decode;
+pub mod decode_pipeline;
+#[cfg(windows)]
 pub mod encode;
+#[cfg(windows)]
+pub mod harness;
+#[cfg(windows)]
 mod inner;
+#[cfg(windows)]
 pub(crate) mod mfx;
+#[cfg(windows)]
 pub(crate) mod nv;
+pub mod pipeline;
+#[cfg(feature = "stream")]
+pub mod stream;
 
 // cxx 的 extern "Rust" 由各 *_bridge.rs 内同名函数实现，此处无需再包装
 
+#[cfg(windows)]
 pub(crate) const MAX_ADATERS: usize = 16;
 
-use crate::common::{DataFormat, Driver};
+/// Error code returned by `Encoder::encode_texture` when the texture does not match the
+/// encoder's device, input format or size, and by `Encoder::encode` when the scaling stage
+/// (`DynamicContext::scale`) cannot read it.
+pub const ERR_TEXTURE_MISMATCH: i32 = -100;
+
+/// Error code returned by `ResilientEncoder::encode` once no candidate encoder is left.
+pub const ERR_ENCODER_EXHAUSTED: i32 = -101;
+
+/// Error code returned by `encode`/`decode` when the D3D11 device was removed (TDR, driver reset).
+/// The codec cannot recover; recreate the device (`NativeDevice::recreate`) and the codec.
+pub const ERR_DEVICE_LOST: i32 = -102;
+
+/// Error code returned by `AsyncEncoder::submit` when `depth` frames are already in flight, and
+/// by `AsyncDecoder::submit` when decoded frames wait for a free surface. Receive first (and
+/// drop held frames), then submit again.
+pub const ERR_QUEUE_FULL: i32 = -103;
+
+/// Error code returned by `AsyncEncoder::flush` when the driver stops producing packets for
+/// frames still in flight, and by `AsyncDecoder::frames` when no surface comes back to the pool.
+pub const ERR_TIMEOUT: i32 = -104;
+
+/// Error code returned by `mark_ltr`/`invalidate` when the session was created without
+/// long-term references (`DynamicContext::ltr_frames`) or the driver does not support them.
+pub const ERR_UNSUPPORTED: i32 = -105;
+
+use crate::common::{BitDepth, DataFormat, Driver};
+use crate::convert::{ColorDescription, Fit, Rect, ScaleFilter};
+use crate::vram::hdr::HdrMetadata;
 pub use serde;
 pub use serde_derive;
 use serde_derive::{Deserialize, Serialize};
@@ -36,11 +89,80 @@ pub struct DynamicContext {
     pub kbitrate: i32,
     pub framerate: i32,
     pub gop: i32,
+    /// B-frames between reference frames. Only pipelined sessions use them, and the driver may
+    /// use fewer; query `reorder_depth` on the encoder. 0 encodes IPPP.
+    #[serde(default)]
+    pub bframes: i32,
+    /// Gradual decoder refresh instead of periodic IDR frames. `gop` and `bframes` are ignored
+    /// while it is set.
+    #[serde(default)]
+    pub intra_refresh: Option<IntraRefresh>,
+    /// Long-term reference slots for `mark_ltr`/`invalidate`, capped by
+    /// `EncoderCaps::max_ltr_frames`. Disables B-frames. 0 creates the session without them.
+    #[serde(default)]
+    pub ltr_frames: i32,
+    /// Colour description written into the SPS VUI. NVENC and AMF also convert the RGB input
+    /// with its matrix and range; MFX takes NV12 and only signals it. AMF HEVC ignores it.
+    /// `None` writes none, leaving decoders to guess.
+    #[serde(default)]
+    pub color: Option<ColorDescription>,
+    /// `Ten` encodes HEVC Main10 from the texture format `Encoder::input_format` reports. NVENC
+    /// and MFX only, see `EncoderCaps::ten_bit`; H.264 is always 8-bit.
+    #[serde(default)]
+    pub bit_depth: BitDepth,
+    /// Encodes 4:4:4 (H.264 High 4:4:4 Predictive, HEVC RExt) so that small coloured text
+    /// keeps its chroma. NVENC only, see `EncoderCaps::yuv444`; it takes the usual RGB input
+    /// and, at 8 bits, AYUV textures too.
+    #[serde(default)]
+    pub yuv444: bool,
+    /// HDR10 mastering display and content light level, written as SEI messages into every
+    /// keyframe and recovery point packet. H.264 and H.265 only; pair it with a PQ `color`.
+    #[serde(default)]
+    pub hdr: Option<HdrMetadata>,
+    /// Scales each input texture to `width` x `height` on the GPU before encoding, so that the
+    /// encoder takes captures of any size and format the video processor reads. Needs `device`;
+    /// `Encoder` only, `AsyncEncoder::new` rejects it.
+    #[serde(default)]
+    pub scale: Option<EncodeScale>,
 }
 
 unsafe impl Send for DynamicContext {}
 unsafe impl Sync for DynamicContext {}
 
+/// Intra refresh schedule, in frames. Every `period` frames a wave of intra-coded columns
+/// sweeps the picture over `duration` frames; the first frame of a wave carries a recovery
+/// point SEI (`EncodeFrame::recovery_point`) where decoding can start. Only the first frame is
+/// an IDR.
+///
+/// AMF cannot schedule refresh waves or write the SEI, so creating an AMF encoder with intra
+/// refresh fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IntraRefresh {
+    pub period: u32,
+    /// Must be less than `period`.
+    pub duration: u32,
+}
+
+impl IntraRefresh {
+    pub(crate) fn is_valid(&self) -> bool {
+        self.duration > 0 && self.duration < self.period
+    }
+}
+
+/// GPU scaling stage in front of the encoder, see `DynamicContext::scale`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EncodeScale {
+    /// Region of the input texture to encode, e.g. one window of a desktop capture; clipped to
+    /// the texture. `None` takes the whole texture.
+    #[serde(default)]
+    pub crop: Option<Rect>,
+    /// How the region is mapped onto the encode size when the aspect ratios differ.
+    #[serde(default)]
+    pub fit: Fit,
+    #[serde(default)]
+    pub filter: ScaleFilter,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct EncodeContext {
     pub f: FeatureContext,
@@ -55,15 +177,153 @@ pub struct DecodeContext {
     pub vendor: Driver,
     pub luid: i64,
     pub data_format: DataFormat,
+    /// `Ten` decodes HEVC Main10 into P010 textures instead of NV12, see
+    /// `DecoderCaps::ten_bit`. Streams of the other depth fail to decode.
+    #[serde(default)]
+    pub bit_depth: BitDepth,
 }
 
 unsafe impl Send for DecodeContext {}
 unsafe impl Sync for DecodeContext {}
 
+/// Codec profile reported by `encode::capabilities` / `decode::capabilities`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Profile {
+    H264Baseline,
+    H264Main,
+    H264High,
+    H264High10,
+    H264High444,
+    HevcMain,
+    HevcMain10,
+    HevcRext,
+}
+
+impl Profile {
+    // 顺序即 cpp/caps.h 中 HWCODEC_PROFILE_* 的位序
+    const ALL: [Profile; 8] = [
+        Profile::H264Baseline,
+        Profile::H264Main,
+        Profile::H264High,
+        Profile::H264High10,
+        Profile::H264High444,
+        Profile::HevcMain,
+        Profile::HevcMain10,
+        Profile::HevcRext,
+    ];
+
+    pub(crate) fn from_mask(mask: u32) -> Vec<Profile> {
+        Self::ALL
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, p)| *p)
+            .collect()
+    }
+}
+
+/// Rate-control mode reported by `encode::capabilities`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum RateControl {
+    CQP,
+    CBR,
+    VBR,
+    QVBR,
+}
+
+impl RateControl {
+    // 顺序即 cpp/caps.h 中 HWCODEC_RC_* 的位序
+    const ALL: [RateControl; 4] = [
+        RateControl::CQP,
+        RateControl::CBR,
+        RateControl::VBR,
+        RateControl::QVBR,
+    ];
+
+    pub(crate) fn from_mask(mask: u32) -> Vec<RateControl> {
+        Self::ALL
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, r)| *r)
+            .collect()
+    }
+}
+
+/// Detailed limits of one encoder, as returned by `encode::capabilities`.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EncoderCaps {
+    pub driver: Driver,
+    pub vendor: Driver,
+    pub luid: i64,
+    pub data_format: DataFormat,
+    pub min_width: i32,
+    pub min_height: i32,
+    pub max_width: i32,
+    pub max_height: i32,
+    pub profiles: Vec<Profile>,
+    /// Highest level as `level * 10` for both H.264 and H.265, e.g. 51 for 5.1. 0 if unknown.
+    pub max_level: i32,
+    pub ten_bit: bool,
+    /// `DynamicContext::yuv444` is accepted.
+    pub yuv444: bool,
+    pub max_bframes: i32,
+    pub lookahead: bool,
+    /// Maximum concurrent sessions. 0 if the driver does not report it (NVENC, MFX).
+    pub max_sessions: i32,
+    pub rate_control: Vec<RateControl>,
+    /// Long-term reference slots; 0 if the encoder supports neither `mark_ltr` nor
+    /// `invalidate`. MFX implements them for H.264 only.
+    pub max_ltr_frames: i32,
+}
+
+impl EncoderCaps {
+    pub fn supports_size(&self, width: i32, height: i32) -> bool {
+        width >= self.min_width
+            && height >= self.min_height
+            && width <= self.max_width
+            && height <= self.max_height
+    }
+}
+
+/// Detailed limits of one decoder, as returned by `decode::capabilities`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DecoderCaps {
+    pub driver: Driver,
+    pub vendor: Driver,
+    pub luid: i64,
+    pub data_format: DataFormat,
+    pub min_width: i32,
+    pub min_height: i32,
+    pub max_width: i32,
+    pub max_height: i32,
+    pub profiles: Vec<Profile>,
+    pub ten_bit: bool,
+    pub yuv444: bool,
+}
+
+impl DecoderCaps {
+    pub fn supports_size(&self, width: i32, height: i32) -> bool {
+        width >= self.min_width
+            && height >= self.min_height
+            && width <= self.max_width
+            && height <= self.max_height
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Available {
     pub e: Vec<FeatureContext>,
     pub d: Vec<DecodeContext>,
+    /// Capabilities of the entries in `e`, filled by `query_capabilities`.
+    #[serde(default)]
+    pub ec: Vec<EncoderCaps>,
+    /// Capabilities of the entries in `d`, filled by `query_capabilities`.
+    #[serde(default)]
+    pub dc: Vec<DecoderCaps>,
+    /// Drivers whose out-of-process probe crashed or hung; see `probe::probe_isolated`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<probe::ProbeFailure>,
 }
 
 impl Available {
@@ -92,6 +352,86 @@ impl Available {
                 .any(|d| d.vendor == vendor && d.data_format == data_format)
         }
     }
+
+    pub fn encoder_caps(&self, f: &FeatureContext) -> Option<&EncoderCaps> {
+        self.ec.iter().find(|c| {
+            c.driver == f.driver && c.luid == f.luid && c.data_format == f.data_format
+        })
+    }
+
+    pub fn decoder_caps(&self, d: &DecodeContext) -> Option<&DecoderCaps> {
+        self.dc.iter().find(|c| {
+            c.driver == d.driver && c.luid == d.luid && c.data_format == d.data_format
+        })
+    }
+
+    /// Probes every driver with real encoders/decoders and queries their capabilities.
+    /// Takes seconds; see `cache::AvailableCache` to avoid doing it on every startup.
+    #[cfg(windows)]
+    pub fn probe(d: DynamicContext) -> Self {
+        Self::probe_drivers(&[Driver::NV, Driver::AMF, Driver::MFX], d)
+    }
+
+    /// Like `probe`, but only tests the given drivers.
+    #[cfg(windows)]
+    pub fn probe_drivers(drivers: &[Driver], d: DynamicContext) -> Self {
+        let mut available = Self {
+            e: encode::available_for(drivers, d),
+            d: decode::available_for(drivers),
+            ..Default::default()
+        };
+        available.query_capabilities();
+        available
+    }
+
+    /// Appends the entries of `other`, skipping encoders/decoders on an adapter and format
+    /// already present, the same way `encode::available` gives earlier drivers priority.
+    pub fn merge(&mut self, other: Available) {
+        for f in other.e {
+            if !self
+                .e
+                .iter()
+                .any(|e| e.luid == f.luid && e.data_format == f.data_format)
+            {
+                if let Some(c) = other.ec.iter().find(|c| {
+                    c.driver == f.driver && c.luid == f.luid && c.data_format == f.data_format
+                }) {
+                    self.ec.push(c.clone());
+                }
+                self.e.push(f);
+            }
+        }
+        for d in other.d {
+            if !self
+                .d
+                .iter()
+                .any(|e| e.luid == d.luid && e.data_format == d.data_format)
+            {
+                if let Some(c) = other.dc.iter().find(|c| {
+                    c.driver == d.driver && c.luid == d.luid && c.data_format == d.data_format
+                }) {
+                    self.dc.push(c.clone());
+                }
+                self.d.push(d);
+            }
+        }
+        self.failed.extend(other.failed);
+    }
+
+    /// Queries `ec`/`dc` for every entry of `e`/`d`. Entries whose query fails are skipped.
+    #[cfg(windows)]
+    pub fn query_capabilities(&mut self) {
+        self.ec = self
+            .e
+            .iter()
+            .filter_map(|f| encode::capabilities(f).ok())
+            .collect();
+        self.dc = self
+            .d
+            .iter()
+            .filter_map(|d| decode::capabilities(d).ok())
+            .collect();
+    }
 }
 
 #[cfg(test)]
@@ -125,6 +465,14 @@ mod tests {
             kbitrate: 5000,
             framerate: 30,
             gop: 60,
+            bframes: 0,
+            intra_refresh: None,
+            ltr_frames: 0,
+            color: None,
+            bit_depth: BitDepth::Eight,
+            yuv444: false,
+            hdr: None,
+            scale: None,
         };
         
         assert_eq!(context.width, 1920);
@@ -133,7 +481,39 @@ mod tests {
         assert_eq!(context.framerate, 30);
         assert_eq!(context.gop, 60);
     }
+
+    /// 测试帧内刷新参数校验，以及旧配置缺少该字段时默认关闭
+    #[test]
+    fn test_intra_refresh() {
+        assert!(IntraRefresh { period: 60, duration: 10 }.is_valid());
+        assert!(!IntraRefresh { period: 10, duration: 10 }.is_valid());
+        assert!(!IntraRefresh { period: 60, duration: 0 }.is_valid());
+
+        let json = r#"{"width":1920,"height":1080,"kbitrate":5000,"framerate":30,"gop":60}"#;
+        let context: DynamicContext = serde_json::from_str(json).unwrap();
+        assert_eq!((context.bframes, context.intra_refresh), (0, None));
+        let json = r#"{"width":1920,"height":1080,"kbitrate":5000,"framerate":30,"gop":60,
+            "intra_refresh":{"period":60,"duration":10}}"#;
+        let context: DynamicContext = serde_json::from_str(json).unwrap();
+        assert_eq!(context.intra_refresh, Some(IntraRefresh { period: 60, duration: 10 }));
+    }
     
+    /// 测试位深：旧配置缺少该字段时为 8 bit，10 bit 可往返序列化
+    #[test]
+    fn test_bit_depth() {
+        let json = r#"{"width":1920,"height":1080,"kbitrate":5000,"framerate":30,"gop":60}"#;
+        let context: DynamicContext = serde_json::from_str(json).unwrap();
+        assert_eq!(context.bit_depth, BitDepth::Eight);
+        let json = r#"{"driver":"NV","vendor":"NV","luid":1,"data_format":"H265"}"#;
+        let mut context: DecodeContext = serde_json::from_str(json).unwrap();
+        assert_eq!(context.bit_depth, BitDepth::Eight);
+
+        context.bit_depth = BitDepth::Ten;
+        let json = serde_json::to_string(&context).unwrap();
+        let parsed: DecodeContext = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.bit_depth.bits(), 10);
+    }
+
     /// 测试 EncodeContext 结构体
     #[test]
     fn test_encode_context() {
@@ -151,6 +531,14 @@ mod tests {
             kbitrate: 5000,
             framerate: 30,
             gop: 60,
+            bframes: 0,
+            intra_refresh: None,
+            ltr_frames: 0,
+            color: None,
+            bit_depth: BitDepth::Eight,
+            yuv444: false,
+            hdr: None,
+            scale: None,
         };
         
         let context = EncodeContext {
@@ -171,6 +559,7 @@ mod tests {
             vendor: Driver::NV,
             luid: 12345,
             data_format: DataFormat::H264,
+            bit_depth: BitDepth::Eight,
         };
         
         assert_eq!(context.driver, Driver::NV);
@@ -195,11 +584,15 @@ mod tests {
             vendor: Driver::NV,
             luid: 12345,
             data_format: DataFormat::H264,
+            bit_depth: BitDepth::Eight,
         };
         
         let available = Available {
             e: vec![feature_context],
             d: vec![decode_context],
+            ec: vec![],
+            dc: vec![],
+            failed: vec![],
         };
         
         assert_eq!(available.e.len(), 1);
@@ -227,11 +620,15 @@ mod tests {
             vendor: Driver::NV,
             luid: 12345,
             data_format: DataFormat::H264,
+            bit_depth: BitDepth::Eight,
         };
         
         let available = Available {
             e: vec![feature_context],
             d: vec![decode_context],
+            ec: vec![],
+            dc: vec![],
+            failed: vec![],
         };
         
         // 测试序列化
@@ -244,4 +641,122 @@ mod tests {
             assert!(deserialized.is_ok());
         }
     }
+
+    fn sample_encoder_caps() -> EncoderCaps {
+        EncoderCaps {
+            driver: Driver::NV,
+            vendor: Driver::NV,
+            luid: 12345,
+            data_format: DataFormat::H265,
+            min_width: 129,
+            min_height: 33,
+            max_width: 8192,
+            max_height: 8192,
+            profiles: Profile::from_mask(0b0110_0000),
+            max_level: 62,
+            ten_bit: true,
+            yuv444: false,
+            max_bframes: 4,
+            lookahead: true,
+            max_sessions: 0,
+            rate_control: RateControl::from_mask(0b0111),
+            max_ltr_frames: 4,
+        }
+    }
+
+    /// 测试能力位掩码与 cpp/caps.h 的位序一致
+    #[test]
+    fn test_caps_masks() {
+        assert_eq!(Profile::from_mask(0), vec![]);
+        assert_eq!(
+            Profile::from_mask(0b0110_0000),
+            vec![Profile::HevcMain, Profile::HevcMain10]
+        );
+        assert_eq!(
+            Profile::from_mask(0b1_0000_0101),
+            vec![Profile::H264Baseline, Profile::H264High]
+        );
+        assert_eq!(
+            RateControl::from_mask(0b1010),
+            vec![RateControl::CBR, RateControl::QVBR]
+        );
+
+        let caps = sample_encoder_caps();
+        assert!(caps.supports_size(1920, 1080));
+        assert!(!caps.supports_size(128, 1080));
+        assert!(!caps.supports_size(1920, 8194));
+    }
+
+    /// 测试能力随 Available 一起序列化，且旧格式（无 ec/dc）仍可反序列化
+    #[test]
+    fn test_available_caps_serialization() {
+        let feature_context = FeatureContext {
+            driver: Driver::NV,
+            vendor: Driver::NV,
+            luid: 12345,
+            data_format: DataFormat::H265,
+        };
+        let available = Available {
+            e: vec![feature_context.clone()],
+            d: vec![],
+            ec: vec![sample_encoder_caps()],
+            dc: vec![],
+            failed: vec![],
+        };
+
+        let s = available.serialize().unwrap();
+        let deserialized = Available::deserialize(&s).unwrap();
+        assert_eq!(deserialized, available);
+        assert_eq!(
+            deserialized.encoder_caps(&feature_context),
+            Some(&sample_encoder_caps())
+        );
+        let other = FeatureContext {
+            luid: 1,
+            ..feature_context
+        };
+        assert!(deserialized.encoder_caps(&other).is_none());
+
+        let legacy = r#"{"e":[],"d":[]}"#;
+        let legacy = Available::deserialize(legacy).unwrap();
+        assert!(legacy.ec.is_empty() && legacy.dc.is_empty());
+    }
+
+    /// 测试 merge 按 (luid, 格式) 去重，先合并的驱动优先，能力随条目一起保留
+    #[test]
+    fn test_available_merge() {
+        let nv = FeatureContext {
+            driver: Driver::NV,
+            vendor: Driver::NV,
+            luid: 12345,
+            data_format: DataFormat::H265,
+        };
+        let mut available = Available {
+            e: vec![nv.clone()],
+            ..Default::default()
+        };
+        let mut other_caps = sample_encoder_caps();
+        other_caps.driver = Driver::MFX;
+        other_caps.luid = 1;
+        let other = Available {
+            e: vec![
+                FeatureContext {
+                    driver: Driver::MFX,
+                    ..nv.clone()
+                },
+                FeatureContext {
+                    driver: Driver::MFX,
+                    luid: 1,
+                    ..nv.clone()
+                },
+            ],
+            ec: vec![other_caps.clone()],
+            ..Default::default()
+        };
+        available.merge(other);
+        assert_eq!(available.e.len(), 2);
+        assert_eq!(available.e[0].driver, Driver::NV);
+        assert_eq!(available.e[1].luid, 1);
+        assert_eq!(available.ec, vec![other_caps]);
+    }
 }
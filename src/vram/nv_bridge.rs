@@ -1,4 +1,5 @@
 #![allow(non_snake_case)]
+#![allow(clippy::too_many_arguments)] // 参数与 C++ 声明一一对应
 
 #[cxx::bridge]
 mod nv_bridge {
@@ -17,13 +18,26 @@ mod nv_bridge {
         unsafe fn nv_DestroyEncoder(encoder: *mut NvEncoder);
         unsafe fn nv_SetBitrate(encoder: *mut NvEncoder, bitrate: i32);
         unsafe fn nv_SetFramerate(encoder: *mut NvEncoder, framerate: i32);
+        unsafe fn nv_CreateEncoderAsync(device: *mut u8, width: i32, height: i32, codec_id: i32, bitrate: i32, framerate: i32, gop: i32, async_depth: i32, bframes: i32, intra_refresh_period: i32, intra_refresh_duration: i32, ltr_frames: i32, color: ColorDesc, bit_depth: i32, yuv444: bool) -> *mut NvEncoder;
+        unsafe fn nv_GetAsyncDepth(encoder: *mut NvEncoder) -> i32;
+        unsafe fn nv_PendingFrames(encoder: *mut NvEncoder) -> i32;
+        unsafe fn nv_GetReorderDepth(encoder: *mut NvEncoder) -> i32;
+        unsafe fn nv_FlushEncoder(encoder: *mut NvEncoder) -> i32;
+        unsafe fn nv_MarkLtr(encoder: *mut NvEncoder) -> i32;
+        unsafe fn nv_InvalidateRefFrames(encoder: *mut NvEncoder, first: i64, last: i64) -> i32;
+        unsafe fn nv_SubmitFrame(encoder: *mut NvEncoder, texture: *mut u8, timestamp: i64) -> i32;
+        unsafe fn nv_ReceiveFrame(encoder: *mut NvEncoder, wait_ms: u32, status: *mut i32) -> *mut EncodedFrame;
 
-        unsafe fn nv_CreateDecoder(device: *mut u8, codec_id: i32) -> *mut NvDecoder;
-        unsafe fn nv_DecodeFrame(decoder: *mut NvDecoder, data: *mut u8, length: i32) -> *mut DecodedFrame;
+        unsafe fn nv_CreateDecoder(device: *mut u8, codec_id: i32, bit_depth: i32) -> *mut NvDecoder;
+        unsafe fn nv_DecodePacket(decoder: *mut NvDecoder, data: *mut u8, length: i32) -> i32;
+        unsafe fn nv_NextDecodedFrame(decoder: *mut NvDecoder) -> *mut DecodedFrame;
         unsafe fn nv_DestroyDecoder(decoder: *mut NvDecoder);
 
         unsafe fn nv_FreeEncodedFrame(frame: *mut EncodedFrame);
         unsafe fn nv_FreeDecodedFrame(frame: *mut DecodedFrame);
+
+        unsafe fn nv_GetEncodeCaps(device: *mut u8, codec_id: i32) -> EncodeCaps;
+        unsafe fn nv_GetDecodeCaps(device: *mut u8, codec_id: i32) -> DecodeCaps;
     }
 
     struct EncodedFrame {
@@ -31,6 +45,11 @@ mod nv_bridge {
         size: i32,
         is_keyframe: bool,
         timestamp: i64,
+        picture_type: i32,
+        avg_qp: i32,
+        temporal_id: i32,
+        dts: i64,
+        has_dts: bool,
     }
 
     struct DecodedFrame {
@@ -38,6 +57,43 @@ mod nv_bridge {
         width: i32,
         height: i32,
     }
+
+    struct ColorDesc {
+        present: bool,
+        primaries: i32,
+        transfer: i32,
+        matrix: i32,
+        full_range: bool,
+        chroma_location: i32,
+    }
+
+    struct EncodeCaps {
+        supported: bool,
+        min_width: i32,
+        min_height: i32,
+        max_width: i32,
+        max_height: i32,
+        profiles: u32,
+        max_level: i32,
+        ten_bit: bool,
+        yuv444: bool,
+        max_bframes: i32,
+        lookahead: bool,
+        max_sessions: i32,
+        rate_control: u32,
+        max_ltr_frames: i32,
+    }
+
+    struct DecodeCaps {
+        supported: bool,
+        min_width: i32,
+        min_height: i32,
+        max_width: i32,
+        max_height: i32,
+        profiles: u32,
+        ten_bit: bool,
+        yuv444: bool,
+    }
 }
 
 pub use nv_bridge::*;
@@ -0,0 +1,520 @@
+//! Pipelined encoding: `submit` returns as soon as the driver has the frame and packets are
+//! collected later with `receive`, so the next frame can be prepared while the GPU encodes.
+//!
+//! NVENC runs in async mode with one completion event per in-flight frame, AMF queues through
+//! `SubmitInput`/`QueryOutput` and MFX keeps one sync point per frame. A session the driver
+//! cannot pipeline encodes on `submit`; its packet is handed out by the next `receive`.
+//!
+//! ```ignore
+//! let mut encoder = AsyncEncoder::new(ctx, DEFAULT_DEPTH)?;
+//! for (tex, pts) in frames {
+//!     while encoder.submit(&tex, pts) == Err(ERR_QUEUE_FULL) {
+//!         if let Some(packet) = encoder.receive(Duration::from_millis(10))? {
+//!             send(packet);
+//!         }
+//!     }
+//! }
+//! for packet in encoder.flush()? {
+//!     send(packet);
+//! }
+//! ```
+
+use crate::common::DataFormat;
+#[cfg(windows)]
+use crate::platform::win::Texture;
+use crate::vram::backend::{EncodeBackend, EncodeFrame};
+use crate::vram::metadata::Annotator;
+use crate::vram::{ERR_QUEUE_FULL, ERR_TIMEOUT};
+use log::trace;
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// Frames in flight when the caller has no preference.
+pub const DEFAULT_DEPTH: usize = 3;
+
+/// Upper bound for the depth; drivers keep one output buffer per in-flight frame.
+pub const MAX_DEPTH: usize = 16;
+
+// flush 中等待每个剩余包的时长
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct AsyncEncoder {
+    backend: Box<dyn EncodeBackend>,
+    depth: usize,
+    // 已从驱动取回但尚未交给调用方的包（同步会话在 submit 时即产出）
+    ready: VecDeque<EncodeFrame>,
+    annotator: Annotator,
+    #[cfg(windows)]
+    device: Option<*mut c_void>,
+    // submit 传入的纹理，按 pts 持有到其包被取回，驱动读完之前不会被释放
+    #[cfg(windows)]
+    inputs: VecDeque<(i64, Texture)>,
+}
+
+unsafe impl Send for AsyncEncoder {}
+
+impl AsyncEncoder {
+    /// Opens a pipelined encoder with at most `depth` frames in flight, clamped to
+    /// `1..=MAX_DEPTH`. With B-frames the depth is raised to hold a full mini-GOP.
+    #[cfg(windows)]
+    pub fn new(ctx: crate::vram::EncodeContext, depth: usize) -> Result<Self, ()> {
+        // 缩放阶段只在 Encoder 中实现
+        if ctx.d.width % 2 == 1 || ctx.d.height % 2 == 1 || ctx.d.scale.is_some() {
+            return Err(());
+        }
+        let bframes = ctx.d.bframes.max(0) as usize;
+        let depth = depth.max(bframes + 1).clamp(1, MAX_DEPTH);
+        let backend = crate::vram::encode::create_async_backend(&ctx.f, &ctx.d, depth)?;
+        let mut encoder = Self::with_backend(backend, ctx.f.data_format, depth);
+        encoder.device = ctx.d.device;
+        encoder.annotator.set_hdr(ctx.d.hdr.as_ref());
+        Ok(encoder)
+    }
+
+    /// Like `new`, over an already created backend producing `data_format`.
+    pub fn with_backend(
+        backend: Box<dyn EncodeBackend>,
+        data_format: DataFormat,
+        depth: usize,
+    ) -> Self {
+        let reorder_depth = backend.reorder_depth();
+        let mut annotator = Annotator::new(data_format);
+        annotator.set_reorder_delay(reorder_depth);
+        Self {
+            backend,
+            // 驱动扣住的 B 帧也占在途名额，深度不足时 submit 永远等不到输出
+            depth: depth.max(reorder_depth + 1).clamp(1, MAX_DEPTH),
+            ready: VecDeque::new(),
+            annotator,
+            #[cfg(windows)]
+            device: None,
+            #[cfg(windows)]
+            inputs: VecDeque::new(),
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// B-frames the session encodes. Packets come in decode order, and the last `reorder_depth`
+    /// frames submitted are only returned by `receive` once later frames arrive or on `flush`.
+    pub fn reorder_depth(&self) -> usize {
+        self.backend.reorder_depth()
+    }
+
+    /// Frames submitted whose packets have not been returned by `receive` yet.
+    pub fn in_flight(&self) -> usize {
+        self.backend.pending() + self.ready.len()
+    }
+
+    /// Queues `tex` for encoding without waiting for its packet. The encoder keeps a reference
+    /// to `tex` until the packet with this `pts` has been received; `tex` must not be modified
+    /// before then. Returns `ERR_QUEUE_FULL` when `depth` frames are in flight.
+    #[cfg(windows)]
+    pub fn submit(&mut self, tex: &Texture, pts: i64) -> Result<(), i32> {
+        self.submit_raw(tex.as_raw(), pts)?;
+        self.inputs.push_back((pts, tex.clone()));
+        Ok(())
+    }
+
+    /// Like `submit`, for a raw `ID3D11Texture2D` pointer. The caller keeps `tex` alive and
+    /// unmodified until the packet has been received.
+    pub fn submit_raw(&mut self, tex: *mut c_void, pts: i64) -> Result<(), i32> {
+        if self.in_flight() >= self.depth {
+            return Err(ERR_QUEUE_FULL);
+        }
+        let mut frames = Vec::new();
+        self.annotator.submitted(pts);
+        let result = self.backend.submit(tex, pts, &mut frames);
+        if result.is_err() {
+            self.annotator.discard(pts);
+        }
+        self.push_ready(frames);
+        result.map_err(|e| self.map_err(e))
+    }
+
+    /// Returns the oldest packet, waiting up to `timeout` for the driver. `Ok(None)` when
+    /// nothing is in flight or the packet is not ready in time.
+    pub fn receive(&mut self, timeout: Duration) -> Result<Option<EncodeFrame>, i32> {
+        if self.ready.is_empty() && self.backend.pending() > 0 {
+            let timeout_ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+            let mut frames = Vec::new();
+            let result = self.backend.receive(timeout_ms, &mut frames);
+            self.push_ready(frames);
+            result.map_err(|e| self.map_err(e))?;
+        }
+        let frame = self.ready.pop_front();
+        #[cfg(windows)]
+        self.release(frame.as_ref().map(|f| f.pts));
+        Ok(frame)
+    }
+
+    /// Ends the stream, waits for every frame in flight and returns their packets in decode
+    /// order. On error the packets collected so far stay queued for `receive`.
+    pub fn flush(&mut self) -> Result<Vec<EncodeFrame>, i32> {
+        self.backend.flush().map_err(|e| self.map_err(e))?;
+        let mut frames = Vec::new();
+        let result = loop {
+            if self.in_flight() == 0 {
+                break Ok(());
+            }
+            match self.receive(FLUSH_TIMEOUT) {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break Err(ERR_TIMEOUT),
+                Err(e) => break Err(e),
+            }
+        };
+        match result {
+            Ok(()) => Ok(frames),
+            Err(e) => {
+                for frame in frames.into_iter().rev() {
+                    self.ready.push_front(frame);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Marks the next submitted frame as a long-term reference. Returns `ERR_UNSUPPORTED` unless
+    /// the session was created with `DynamicContext::ltr_frames`.
+    pub fn mark_ltr(&mut self) -> Result<(), i32> {
+        self.backend.mark_ltr().map_err(|e| self.map_err(e))
+    }
+
+    /// Reports frames the receiver lost, by `pts`. The next submitted frame references only a
+    /// long-term reference marked before them, or is encoded as an IDR if none is left.
+    pub fn invalidate(&mut self, pts: RangeInclusive<i64>) -> Result<(), i32> {
+        self.backend
+            .invalidate(*pts.start(), *pts.end())
+            .map_err(|e| self.map_err(e))
+    }
+
+    pub fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32> {
+        self.backend.set_bitrate(kbs)
+    }
+
+    pub fn set_framerate(&mut self, framerate: i32) -> Result<(), i32> {
+        self.backend.set_framerate(framerate)
+    }
+
+    /// Whether the device the encoder was created on has been removed.
+    #[cfg(windows)]
+    pub fn device_lost(&self) -> bool {
+        self.device
+            .and_then(|device| unsafe { crate::platform::win::Device::from_raw(device) })
+            .is_some_and(|device| device.is_lost())
+    }
+
+    // 释放已取回的包对应的输入纹理；出错丢失的帧没有包，在途清空时一并释放
+    #[cfg(windows)]
+    fn release(&mut self, pts: Option<i64>) {
+        if let Some(i) = pts.and_then(|pts| self.inputs.iter().position(|(p, _)| *p == pts)) {
+            self.inputs.remove(i);
+        }
+        if self.in_flight() == 0 {
+            self.inputs.clear();
+        }
+    }
+
+    fn push_ready(&mut self, frames: Vec<EncodeFrame>) {
+        for mut frame in frames {
+            self.annotator.annotate(&mut frame);
+            self.ready.push_back(frame);
+        }
+    }
+
+    fn map_err(&self, e: i32) -> i32 {
+        #[cfg(windows)]
+        if self.device_lost() {
+            return crate::vram::ERR_DEVICE_LOST;
+        }
+        e
+    }
+}
+
+impl Drop for AsyncEncoder {
+    fn drop(&mut self) {
+        self.backend.destroy();
+        trace!("AsyncEncoder dropped");
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::vram::ERR_UNSUPPORTED;
+    use std::sync::{Arc, Mutex};
+
+    /// 模拟驱动状态：stalled 时 GPU 不产出，fail 中的 pts 在取回时报错
+    #[derive(Default)]
+    pub(crate) struct Gpu {
+        pub stalled: bool,
+        pub fail: Vec<i64>,
+        pub destroyed: bool,
+    }
+
+    pub(crate) struct MockBackend {
+        pub pipelined: bool,
+        // 参考帧之间的 B 帧数，仅流水线会话生效
+        pub bframes: usize,
+        pub queue: VecDeque<i64>,
+        // 等待后一个参考帧的 B 帧
+        pub held: Vec<i64>,
+        pub submitted: usize,
+        pub gpu: Arc<Mutex<Gpu>>,
+    }
+
+    impl MockBackend {
+        pub(crate) fn new(pipelined: bool) -> (Self, Arc<Mutex<Gpu>>) {
+            let gpu = Arc::new(Mutex::new(Gpu::default()));
+            let backend = Self {
+                pipelined,
+                bframes: 0,
+                queue: VecDeque::new(),
+                held: Vec::new(),
+                submitted: 0,
+                gpu: gpu.clone(),
+            };
+            (backend, gpu)
+        }
+    }
+
+    fn packet(pts: i64) -> EncodeFrame {
+        EncodeFrame {
+            data: vec![pts as u8],
+            pts,
+            key: (pts == 0) as i32,
+            ..Default::default()
+        }
+    }
+
+    impl EncodeBackend for MockBackend {
+        fn encode(
+            &mut self,
+            _tex: *mut c_void,
+            ms: i64,
+            frames: &mut Vec<EncodeFrame>,
+        ) -> Result<(), i32> {
+            frames.push(packet(ms));
+            Ok(())
+        }
+
+        fn set_bitrate(&mut self, _kbs: i32) -> Result<(), i32> {
+            Ok(())
+        }
+
+        fn set_framerate(&mut self, _framerate: i32) -> Result<(), i32> {
+            Ok(())
+        }
+
+        fn destroy(&mut self) {
+            self.gpu.lock().unwrap().destroyed = true;
+        }
+
+        fn submit(
+            &mut self,
+            tex: *mut c_void,
+            ms: i64,
+            frames: &mut Vec<EncodeFrame>,
+        ) -> Result<(), i32> {
+            if !self.pipelined {
+                return self.encode(tex, ms, frames);
+            }
+            // 按解码顺序排队：参考帧先出，其前面扣住的 B 帧随后
+            self.submitted += 1;
+            if self.submitted == 1 || self.held.len() == self.bframes {
+                self.queue.push_back(ms);
+                self.queue.extend(self.held.drain(..));
+            } else {
+                self.held.push(ms);
+            }
+            Ok(())
+        }
+
+        fn receive(&mut self, _timeout_ms: u32, frames: &mut Vec<EncodeFrame>) -> Result<(), i32> {
+            let gpu = self.gpu.lock().unwrap();
+            if gpu.stalled {
+                return Ok(());
+            }
+            // 每次只完成最早的一帧
+            match self.queue.pop_front() {
+                Some(pts) if gpu.fail.contains(&pts) => Err(-1),
+                Some(pts) => {
+                    frames.push(packet(pts));
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+
+        fn pending(&self) -> usize {
+            self.queue.len() + self.held.len()
+        }
+
+        fn flush(&mut self) -> Result<(), i32> {
+            self.queue.extend(self.held.drain(..));
+            Ok(())
+        }
+
+        fn reorder_depth(&self) -> usize {
+            self.bframes
+        }
+    }
+
+    fn pts(frames: &[EncodeFrame]) -> Vec<i64> {
+        frames.iter().map(|f| f.pts).collect()
+    }
+
+    const WAIT: Duration = Duration::from_millis(10);
+
+    /// 测试提交不等待输出、队列深度限制与按提交顺序取回
+    #[test]
+    fn test_pipelined() {
+        let (backend, gpu) = MockBackend::new(true);
+        let mut encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 2);
+        let tex = std::ptr::null_mut();
+
+        assert_eq!(encoder.receive(WAIT), Ok(None));
+        assert_eq!(encoder.submit_raw(tex, 0), Ok(()));
+        assert_eq!(encoder.submit_raw(tex, 1), Ok(()));
+        assert_eq!(encoder.submit_raw(tex, 2), Err(ERR_QUEUE_FULL));
+        assert_eq!(encoder.in_flight(), 2);
+
+        assert_eq!(encoder.receive(WAIT).unwrap().map(|f| f.pts), Some(0));
+        assert_eq!(encoder.in_flight(), 1);
+        assert_eq!(encoder.submit_raw(tex, 2), Ok(()));
+        assert_eq!(pts(&encoder.flush().unwrap()), vec![1, 2]);
+        assert_eq!(encoder.in_flight(), 0);
+        assert_eq!(encoder.receive(WAIT), Ok(None));
+
+        drop(encoder);
+        assert!(gpu.lock().unwrap().destroyed);
+    }
+
+    /// 测试不支持流水线的会话在 submit 时同步编码，接口行为不变
+    #[test]
+    fn test_sync_fallback() {
+        let (backend, _) = MockBackend::new(false);
+        let mut encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 1);
+        let tex = std::ptr::null_mut();
+
+        assert_eq!(encoder.submit_raw(tex, 0), Ok(()));
+        assert_eq!(encoder.in_flight(), 1);
+        assert_eq!(encoder.submit_raw(tex, 1), Err(ERR_QUEUE_FULL));
+        assert_eq!(encoder.receive(WAIT).unwrap().map(|f| f.pts), Some(0));
+        assert_eq!(encoder.submit_raw(tex, 1), Ok(()));
+        assert_eq!(pts(&encoder.flush().unwrap()), vec![1]);
+    }
+
+    /// 测试驱动停止产出时 flush 超时，恢复后包不丢失；取回出错时报告错误码
+    #[test]
+    fn test_flush_and_errors() {
+        let (backend, gpu) = MockBackend::new(true);
+        let mut encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 4);
+        let tex = std::ptr::null_mut();
+
+        gpu.lock().unwrap().stalled = true;
+        encoder.submit_raw(tex, 0).unwrap();
+        encoder.submit_raw(tex, 1).unwrap();
+        assert_eq!(encoder.receive(WAIT), Ok(None));
+        assert_eq!(encoder.flush(), Err(ERR_TIMEOUT));
+        assert_eq!(encoder.in_flight(), 2);
+
+        gpu.lock().unwrap().stalled = false;
+        assert_eq!(pts(&encoder.flush().unwrap()), vec![0, 1]);
+
+        gpu.lock().unwrap().fail = vec![3];
+        for i in 2..5 {
+            encoder.submit_raw(tex, i).unwrap();
+        }
+        assert_eq!(encoder.flush(), Err(-1));
+        // 出错前已取回的包保留给后续 receive，出错的帧不再计入在途
+        assert_eq!(encoder.in_flight(), 2);
+        assert_eq!(encoder.receive(WAIT).unwrap().map(|f| f.pts), Some(2));
+        assert_eq!(encoder.receive(WAIT).unwrap().map(|f| f.pts), Some(4));
+
+        let (backend, _) = MockBackend::new(true);
+        let encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 99);
+        assert_eq!(encoder.depth(), MAX_DEPTH);
+    }
+
+    /// 测试未启用长期参考的会话拒绝标记与失效，且不影响后续编码
+    #[test]
+    fn test_ltr_unsupported() {
+        let (backend, _) = MockBackend::new(true);
+        let mut encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 2);
+        let tex = std::ptr::null_mut();
+
+        assert_eq!(encoder.mark_ltr(), Err(ERR_UNSUPPORTED));
+        assert_eq!(encoder.invalidate(0..=40), Err(ERR_UNSUPPORTED));
+        encoder.submit_raw(tex, 0).unwrap();
+        assert_eq!(pts(&encoder.flush().unwrap()), vec![0]);
+    }
+
+    /// 测试提交的纹理由编码器持有，直到对应 pts 的包被取回
+    #[cfg(windows)]
+    #[test]
+    #[ignore] // 需要 GPU，默认忽略
+    fn test_texture_held() {
+        use crate::convert::Matrix;
+        use crate::platform::win::{Device, NativeDevice};
+        use crate::testsrc::{Pattern, TestSource};
+        use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_NV12;
+
+        let native = NativeDevice::new(0, None, 0).unwrap();
+        let device = Device::from(native.device().clone());
+        let frame = TestSource::new(Pattern::ColorBars, 64, 64).next_frame();
+        let tex = Texture::upload(&device, &frame, DXGI_FORMAT_NV12, Matrix::Bt709).unwrap();
+        let (backend, _) = MockBackend::new(true);
+        let mut encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 2);
+
+        encoder.submit(&tex, 0).unwrap();
+        encoder.submit(&tex, 1).unwrap();
+        let held = |encoder: &AsyncEncoder| -> Vec<i64> {
+            encoder.inputs.iter().map(|(pts, _)| *pts).collect()
+        };
+        assert_eq!(held(&encoder), vec![0, 1]);
+        assert_eq!(encoder.receive(WAIT).unwrap().map(|f| f.pts), Some(0));
+        assert_eq!(held(&encoder), vec![1]);
+        assert_eq!(pts(&encoder.flush().unwrap()), vec![1]);
+        assert!(held(&encoder).is_empty());
+    }
+
+    /// 测试 B 帧会话按解码顺序输出：dts 单调不减且不大于 pts，flush 后所有帧都已输出
+    #[test]
+    fn test_bframes_order() {
+        let (mut backend, _) = MockBackend::new(true);
+        backend.bframes = 2;
+        let mut encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 1);
+        assert_eq!(encoder.reorder_depth(), 2);
+        assert_eq!(encoder.depth(), 3);
+        let tex = std::ptr::null_mut();
+
+        let mut frames = Vec::new();
+        for i in 0..11 {
+            while let Err(e) = encoder.submit_raw(tex, i * 40) {
+                assert_eq!(e, ERR_QUEUE_FULL);
+                frames.push(encoder.receive(WAIT).unwrap().unwrap());
+            }
+        }
+        // 最后一帧被扣住等待后续参考帧
+        while let Some(frame) = encoder.receive(WAIT).unwrap() {
+            frames.push(frame);
+        }
+        assert_eq!(encoder.in_flight(), 1);
+        frames.extend(encoder.flush().unwrap());
+        assert_eq!(encoder.in_flight(), 0);
+
+        assert_eq!(
+            pts(&frames),
+            vec![0, 120, 40, 80, 240, 160, 200, 360, 280, 320, 400]
+        );
+        let dts: Vec<i64> = frames.iter().map(|f| f.dts.unwrap()).collect();
+        assert!(dts.windows(2).all(|w| w[0] <= w[1]), "{dts:?}");
+        assert!(frames.iter().all(|f| f.dts.unwrap() <= f.pts), "{dts:?}");
+        assert_eq!(dts[2..], [0, 40, 80, 120, 160, 200, 240, 280, 320]);
+    }
+}
@@ -0,0 +1,319 @@
+//! Out-of-process probing, so a driver that hangs or crashes in its test encode/decode cannot
+//! take the application down with it.
+//!
+//! The application re-launches itself once per driver with `PROBE_ARG`; the helper probes that
+//! driver only and prints the resulting `Available` on stdout. Drivers whose helper crashed,
+//! hung past the timeout or printed garbage are left out and recorded in `Available::failed`.
+//!
+//! ```ignore
+//! fn main() {
+//!     if let Some(code) = hwcodec::vram::probe::child_main() {
+//!         std::process::exit(code);
+//!     }
+//!     let available = hwcodec::vram::probe::probe_isolated_current_exe(d, DEFAULT_TIMEOUT);
+//! }
+//! ```
+
+use crate::common::Driver;
+use crate::vram::{Available, DynamicContext};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Command-line flag that switches the executable into probe-helper mode.
+pub const PROBE_ARG: &str = "--hwcodec-probe";
+
+/// Default time a single driver's helper may take before it is killed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 驱动可能向 stdout 打印杂项，结果行加前缀以便父进程识别
+const RESULT_PREFIX: &str = "HWCODEC_PROBE_RESULT:";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ProbeFailureReason {
+    /// The helper process could not be started.
+    Spawn(String),
+    /// The helper did not finish within the timeout (in milliseconds) and was killed.
+    Timeout(u64),
+    /// The helper exited abnormally. `None` when it was terminated by a signal.
+    Crashed(Option<i32>),
+    /// The helper exited cleanly but did not print a valid result.
+    InvalidOutput(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProbeFailure {
+    pub driver: Driver,
+    pub reason: ProbeFailureReason,
+}
+
+/// Probes every driver in a separate helper process started from `exe`, which must call
+/// `child_main` at startup. Each helper is killed after `timeout`.
+pub fn probe_isolated(exe: &Path, d: DynamicContext, timeout: Duration) -> Available {
+    let mut available = Available::default();
+    for driver in [Driver::NV, Driver::AMF, Driver::MFX] {
+        let mut cmd = Command::new(exe);
+        cmd.arg(PROBE_ARG)
+            .arg(driver_name(&driver))
+            .arg(serde_json::to_string(&d).unwrap_or_default());
+        match run_probe(cmd, timeout) {
+            Ok(a) => {
+                debug!(
+                    "Probe helper for {:?}: {} encoders, {} decoders",
+                    driver,
+                    a.e.len(),
+                    a.d.len()
+                );
+                available.merge(a);
+            }
+            Err(reason) => {
+                warn!("Probe helper for {:?} failed: {:?}", driver, reason);
+                available.failed.push(ProbeFailure { driver, reason });
+            }
+        }
+    }
+    available
+}
+
+/// `probe_isolated` using the current executable as the helper.
+pub fn probe_isolated_current_exe(d: DynamicContext, timeout: Duration) -> Available {
+    match std::env::current_exe() {
+        Ok(exe) => probe_isolated(&exe, d, timeout),
+        Err(e) => Available {
+            failed: [Driver::NV, Driver::AMF, Driver::MFX]
+                .into_iter()
+                .map(|driver| ProbeFailure {
+                    driver,
+                    reason: ProbeFailureReason::Spawn(e.to_string()),
+                })
+                .collect(),
+            ..Default::default()
+        },
+    }
+}
+
+/// Helper-side entry point. Returns `None` when the process was not started with `PROBE_ARG`;
+/// otherwise probes the requested driver, prints the result and returns the exit code.
+pub fn child_main() -> Option<i32> {
+    let args: Vec<String> = std::env::args().collect();
+    let pos = args.iter().position(|a| a == PROBE_ARG)?;
+    Some(run_child(&args[pos + 1..]))
+}
+
+fn run_child(args: &[String]) -> i32 {
+    let driver = match args.first().and_then(|s| parse_driver(s)) {
+        Some(driver) => driver,
+        None => {
+            eprintln!("{}: expected driver NV, AMF or MFX", PROBE_ARG);
+            return 2;
+        }
+    };
+    let d: DynamicContext = match args.get(1).map(|s| serde_json::from_str(s)) {
+        Some(Ok(d)) => d,
+        _ => {
+            eprintln!("{}: expected DynamicContext JSON", PROBE_ARG);
+            return 2;
+        }
+    };
+    let available = probe_local(driver, d);
+    match serde_json::to_string(&available) {
+        Ok(s) => {
+            println!("{}{}", RESULT_PREFIX, s);
+            0
+        }
+        Err(_) => 1,
+    }
+}
+
+#[cfg(windows)]
+fn probe_local(driver: Driver, d: DynamicContext) -> Available {
+    Available::probe_drivers(&[driver], d)
+}
+
+#[cfg(not(windows))]
+fn probe_local(_driver: Driver, _d: DynamicContext) -> Available {
+    Available::default()
+}
+
+fn driver_name(driver: &Driver) -> &'static str {
+    match driver {
+        Driver::NV => "NV",
+        Driver::AMF => "AMF",
+        Driver::MFX => "MFX",
+    }
+}
+
+fn parse_driver(s: &str) -> Option<Driver> {
+    match s {
+        "NV" => Some(Driver::NV),
+        "AMF" => Some(Driver::AMF),
+        "MFX" => Some(Driver::MFX),
+        _ => None,
+    }
+}
+
+/// Runs `cmd`, killing it after `timeout`, and parses the `Available` it prints.
+fn run_probe(mut cmd: Command, timeout: Duration) -> Result<Available, ProbeFailureReason> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| ProbeFailureReason::Spawn(e.to_string()))?;
+    // 父进程退出时子进程随之结束，避免残留挂死的探测进程
+    #[cfg(windows)]
+    if !crate::common::child_exit_when_parent_exit(child.id()) {
+        warn!("Failed to bind probe helper {} to a job object", child.id());
+    }
+
+    // 在线程中读取 stdout，防止管道写满导致子进程阻塞
+    let mut stdout = child.stdout.take();
+    let reader = std::thread::spawn(move || {
+        let mut s = String::new();
+        if let Some(stdout) = stdout.as_mut() {
+            let _ = stdout.read_to_string(&mut s);
+        }
+        s
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if start.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                let _ = reader.join();
+                return Err(ProbeFailureReason::Timeout(timeout.as_millis() as u64));
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = reader.join();
+                return Err(ProbeFailureReason::Spawn(e.to_string()));
+            }
+        }
+    };
+    let output = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(ProbeFailureReason::Crashed(status.code()));
+    }
+    parse_output(&output)
+}
+
+fn parse_output(output: &str) -> Result<Available, ProbeFailureReason> {
+    let line = output
+        .lines()
+        .rev()
+        .find_map(|l| l.trim_end().strip_prefix(RESULT_PREFIX))
+        .ok_or_else(|| ProbeFailureReason::InvalidOutput("no result line".to_string()))?;
+    serde_json::from_str(line).map_err(|e| ProbeFailureReason::InvalidOutput(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::DataFormat;
+    use crate::vram::FeatureContext;
+
+    fn sample() -> Available {
+        Available {
+            e: vec![FeatureContext {
+                driver: Driver::AMF,
+                vendor: Driver::AMF,
+                luid: 7,
+                data_format: DataFormat::H265,
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// 测试结果行解析：忽略驱动打印的杂项输出
+    #[test]
+    fn test_parse_output() {
+        let json = serde_json::to_string(&sample()).unwrap();
+        let output = format!("driver noise\n{}{}\r\nmore noise\n", RESULT_PREFIX, json);
+        assert_eq!(parse_output(&output), Ok(sample()));
+        assert!(matches!(
+            parse_output("driver noise\n"),
+            Err(ProbeFailureReason::InvalidOutput(_))
+        ));
+        assert!(matches!(
+            parse_output(&format!("{}{{", RESULT_PREFIX)),
+            Err(ProbeFailureReason::InvalidOutput(_))
+        ));
+    }
+
+    /// 测试子进程参数解析
+    #[test]
+    fn test_run_child_args() {
+        assert_eq!(run_child(&[]), 2);
+        assert_eq!(run_child(&["XYZ".to_string()]), 2);
+        assert_eq!(run_child(&["NV".to_string(), "{".to_string()]), 2);
+        for driver in [Driver::NV, Driver::AMF, Driver::MFX] {
+            assert_eq!(parse_driver(driver_name(&driver)), Some(driver));
+        }
+    }
+
+    #[cfg(unix)]
+    fn sh(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    /// 测试子进程正常输出、崩溃、超时与无法启动
+    #[cfg(unix)]
+    #[test]
+    fn test_run_probe() {
+        let json = serde_json::to_string(&sample()).unwrap();
+        let ok = sh(&format!("echo noise; echo '{}{}'", RESULT_PREFIX, json));
+        assert_eq!(run_probe(ok, DEFAULT_TIMEOUT), Ok(sample()));
+
+        assert_eq!(
+            run_probe(sh("exit 3"), DEFAULT_TIMEOUT),
+            Err(ProbeFailureReason::Crashed(Some(3)))
+        );
+        assert_eq!(
+            run_probe(sh("kill -9 $$"), DEFAULT_TIMEOUT),
+            Err(ProbeFailureReason::Crashed(None))
+        );
+        assert!(matches!(
+            run_probe(sh("echo nothing"), DEFAULT_TIMEOUT),
+            Err(ProbeFailureReason::InvalidOutput(_))
+        ));
+
+        let start = Instant::now();
+        assert_eq!(
+            run_probe(sh("exec sleep 10"), Duration::from_millis(200)),
+            Err(ProbeFailureReason::Timeout(200))
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert!(matches!(
+            run_probe(
+                Command::new("/nonexistent/hwcodec-probe"),
+                DEFAULT_TIMEOUT
+            ),
+            Err(ProbeFailureReason::Spawn(_))
+        ));
+    }
+
+    /// 测试失败原因随 Available 序列化
+    #[test]
+    fn test_failure_serialization() {
+        let mut available = sample();
+        available.failed.push(ProbeFailure {
+            driver: Driver::NV,
+            reason: ProbeFailureReason::Timeout(30000),
+        });
+        let s = available.serialize().unwrap();
+        assert_eq!(Available::deserialize(&s), Ok(available));
+        assert!(!sample().serialize().unwrap().contains("failed"));
+    }
+}
@@ -0,0 +1,667 @@
+//! Encoder that survives runtime failures by rebuilding its backend or failing over to the next
+//! candidate.
+//!
+//! After `max_errors` consecutive `encode` errors the current backend is rebuilt once; if that
+//! does not help, the next candidate is tried. A fresh session always starts with an IDR frame,
+//! so the stream stays decodable across the switch. The first frame of the new session takes
+//! the input pts, or the last output pts plus the usual step when the input would not move
+//! forward. A successful frame after a rebuild allows another rebuild before a switch.
+//!
+//! Device loss is returned as `ERR_DEVICE_LOST` instead: every candidate shares the lost device,
+//! so the caller has to recreate the device and the encoder. Backends report it with raw driver
+//! codes, so after an error the device itself is asked as well (see `with_device_check`).
+
+use crate::vram::backend::{EncodeBackend, EncodeFrame};
+use crate::vram::{FeatureContext, ERR_DEVICE_LOST, ERR_ENCODER_EXHAUSTED, ERR_TIMEOUT};
+use log::{trace, warn};
+use std::ffi::c_void;
+
+/// Builds the backend for a candidate.
+pub type BackendFactory =
+    Box<dyn FnMut(&FeatureContext) -> Result<Box<dyn EncodeBackend>, ()> + Send>;
+
+// flush 后等待下一个包的时长，与 Encoder 一致
+const RECEIVE_TIMEOUT_MS: u32 = 3000;
+
+/// Tells whether the device the candidates encode on has been removed.
+pub type DeviceCheck = Box<dyn Fn() -> bool + Send>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailoverEvent {
+    /// The backend of `feature` was rebuilt after repeated `error`s.
+    Recreated { feature: FeatureContext, error: i32 },
+    /// Encoding moved from `from` to `to` after repeated `error`s.
+    Switched {
+        from: FeatureContext,
+        to: FeatureContext,
+        error: i32,
+    },
+    /// No candidate is left; `encode` returns `ERR_ENCODER_EXHAUSTED` from now on.
+    Exhausted { error: i32 },
+}
+
+pub struct ResilientEncoder {
+    candidates: Vec<FeatureContext>,
+    current: usize,
+    backend: Option<Box<dyn EncodeBackend>>,
+    factory: BackendFactory,
+    device_check: Option<DeviceCheck>,
+    max_errors: u32,
+    errors: u32,
+    // 当前候选重建后是否还没有成功编码过；此时再失败则切换到下一个候选
+    recreated: bool,
+    kbitrate: Option<i32>,
+    framerate: Option<i32>,
+    last_pts: Option<i64>,
+    // 相邻两帧输出 pts 的差
+    pts_step: i64,
+    pts_offset: i64,
+    // 切换后首帧需要校正 pts 偏移
+    rebase_pts: bool,
+    frames: Vec<EncodeFrame>,
+    events: Vec<FailoverEvent>,
+}
+
+impl ResilientEncoder {
+    /// Encoder over `candidates` (best first, e.g. from `select::select_encoder`), failing over
+    /// after `max_errors` consecutive errors. Candidates must accept textures from `d.device`;
+    /// ones that cannot be created on it are skipped.
+    ///
+    /// Each candidate runs as an `Encoder`, so B-frames, scaling and the packet annotations
+    /// (dts, latency, offsets, HDR SEI) work as there.
+    #[cfg(windows)]
+    pub fn new(
+        candidates: Vec<FeatureContext>,
+        d: crate::vram::DynamicContext,
+        max_errors: u32,
+    ) -> Result<Self, ()> {
+        if d.width % 2 == 1 || d.height % 2 == 1 {
+            return Err(());
+        }
+        // 裸指针不是 Send，以地址保存，检查时再借用
+        let device = d.device.map(|device| device as usize);
+        let encoder = Self::with_factory(
+            candidates,
+            max_errors,
+            Box::new(move |f| {
+                // Encoder::new 同时建立缩放阶段
+                let ctx = crate::vram::EncodeContext { f: f.clone(), d };
+                let encoder = crate::vram::encode::Encoder::new(ctx)?;
+                Ok(Box::new(EncoderBackend::new(encoder)) as Box<dyn EncodeBackend>)
+            }),
+        )?;
+        Ok(encoder.with_device_check(Box::new(move || {
+            device
+                .and_then(|device| unsafe {
+                    crate::platform::win::Device::from_raw(device as *mut c_void)
+                })
+                .is_some_and(|device| device.is_lost())
+        })))
+    }
+
+    /// Like `new`, with backends built by `factory`.
+    pub fn with_factory(
+        candidates: Vec<FeatureContext>,
+        max_errors: u32,
+        factory: BackendFactory,
+    ) -> Result<Self, ()> {
+        let mut encoder = Self {
+            candidates,
+            current: 0,
+            backend: None,
+            factory,
+            device_check: None,
+            max_errors: max_errors.max(1),
+            errors: 0,
+            recreated: false,
+            kbitrate: None,
+            framerate: None,
+            last_pts: None,
+            pts_step: 1,
+            pts_offset: 0,
+            rebase_pts: false,
+            frames: Vec::new(),
+            events: Vec::new(),
+        };
+        while encoder.current < encoder.candidates.len() {
+            if encoder.create_current() {
+                return Ok(encoder);
+            }
+            encoder.current += 1;
+        }
+        Err(())
+    }
+
+    /// Asks `check` after each backend error whether the device was lost, in which case
+    /// `encode` returns `ERR_DEVICE_LOST` without rebuilding or switching. `new` checks
+    /// `d.device`.
+    pub fn with_device_check(mut self, check: DeviceCheck) -> Self {
+        self.device_check = Some(check);
+        self
+    }
+
+    /// Whether the device check reports the device as removed.
+    pub fn device_lost(&self) -> bool {
+        self.device_check.as_ref().is_some_and(|check| check())
+    }
+
+    /// The candidate currently encoding, `None` once all candidates are exhausted.
+    pub fn current(&self) -> Option<&FeatureContext> {
+        self.backend.as_ref().map(|_| &self.candidates[self.current])
+    }
+
+    /// Returns and clears the failover events since the last call.
+    pub fn take_events(&mut self) -> Vec<FailoverEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn encode(&mut self, tex: *mut c_void, ms: i64) -> Result<&mut Vec<EncodeFrame>, i32> {
+        self.frames.clear();
+        let Some(backend) = self.backend.as_mut() else {
+            return Err(ERR_ENCODER_EXHAUSTED);
+        };
+        if let Err(e) = backend.encode(tex, ms, &mut self.frames) {
+            if e == ERR_DEVICE_LOST || self.device_lost() {
+                return Err(ERR_DEVICE_LOST);
+            }
+            self.errors += 1;
+            if self.errors < self.max_errors {
+                return Err(e);
+            }
+            if !self.failover(e) {
+                return Err(ERR_ENCODER_EXHAUSTED);
+            }
+            // 新会话以 IDR 开始，重新编码当前帧
+            self.frames.clear();
+            let backend = self.backend.as_mut().ok_or(ERR_ENCODER_EXHAUSTED)?;
+            if let Err(e) = backend.encode(tex, ms, &mut self.frames) {
+                self.errors = 1;
+                return Err(e);
+            }
+        }
+        self.errors = 0;
+        self.recreated = false;
+        self.fix_pts(Some(ms));
+        Ok(&mut self.frames)
+    }
+
+    /// Returns the packets of frames the current encoder still holds back for B-frame
+    /// reordering. Call at the end of the stream; without B-frames there are none.
+    pub fn flush(&mut self) -> Result<&mut Vec<EncodeFrame>, i32> {
+        self.frames.clear();
+        let backend = self.backend.as_mut().ok_or(ERR_ENCODER_EXHAUSTED)?;
+        let result = drain(backend.as_mut(), &mut self.frames);
+        if let Err(e) = result {
+            if e == ERR_DEVICE_LOST || self.device_lost() {
+                return Err(ERR_DEVICE_LOST);
+            }
+            return Err(e);
+        }
+        self.fix_pts(None);
+        Ok(&mut self.frames)
+    }
+
+    pub fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32> {
+        self.kbitrate = Some(kbs);
+        match self.backend.as_mut() {
+            Some(backend) => backend.set_bitrate(kbs),
+            None => Err(ERR_ENCODER_EXHAUSTED),
+        }
+    }
+
+    pub fn set_framerate(&mut self, framerate: i32) -> Result<(), i32> {
+        self.framerate = Some(framerate);
+        match self.backend.as_mut() {
+            Some(backend) => backend.set_framerate(framerate),
+            None => Err(ERR_ENCODER_EXHAUSTED),
+        }
+    }
+
+    /// Rebuilds the current backend or moves to the next candidate. Returns `false` when no
+    /// candidate could be created.
+    fn failover(&mut self, error: i32) -> bool {
+        self.destroy_backend();
+        self.errors = 0;
+        let from = self.candidates[self.current].clone();
+        if !self.recreated {
+            self.recreated = true;
+            if self.create_current() {
+                warn!("Encoder {:?} recreated after error {}", from.driver, error);
+                self.events.push(FailoverEvent::Recreated {
+                    feature: from,
+                    error,
+                });
+                return true;
+            }
+        }
+        while self.current + 1 < self.candidates.len() {
+            self.current += 1;
+            self.recreated = false;
+            if self.create_current() {
+                let to = self.candidates[self.current].clone();
+                warn!(
+                    "Encoder switched from {:?} to {:?} after error {}",
+                    from.driver, to.driver, error
+                );
+                self.events.push(FailoverEvent::Switched { from, to, error });
+                return true;
+            }
+        }
+        warn!("No encoder left after error {}", error);
+        self.events.push(FailoverEvent::Exhausted { error });
+        false
+    }
+
+    fn create_current(&mut self) -> bool {
+        let f = &self.candidates[self.current];
+        let Ok(mut backend) = (self.factory)(f) else {
+            trace!("Failed to create encoder {:?} on {}", f.driver, f.luid);
+            return false;
+        };
+        // 重建后恢复运行中调整过的码率与帧率
+        if let Some(kbs) = self.kbitrate {
+            let _ = backend.set_bitrate(kbs);
+        }
+        if let Some(framerate) = self.framerate {
+            let _ = backend.set_framerate(framerate);
+        }
+        self.backend = Some(backend);
+        self.rebase_pts = self.last_pts.is_some();
+        true
+    }
+
+    /// Shifts the pts of a new backend, which may have restarted its clock, so that its first
+    /// frame continues from the input pts `ms`, or one step after the last output.
+    fn fix_pts(&mut self, ms: Option<i64>) {
+        for frame in self.frames.iter_mut() {
+            if let (true, Some(last)) = (self.rebase_pts, self.last_pts) {
+                self.rebase_pts = false;
+                let target = ms.filter(|ms| *ms > last).unwrap_or(last + self.pts_step);
+                self.pts_offset = target - frame.pts;
+            }
+            frame.pts += self.pts_offset;
+            if let Some(last) = self.last_pts {
+                self.pts_step = (frame.pts - last).max(1);
+            }
+            self.last_pts = Some(frame.pts);
+        }
+    }
+
+    fn destroy_backend(&mut self) {
+        if let Some(mut backend) = self.backend.take() {
+            backend.destroy();
+        }
+    }
+}
+
+impl Drop for ResilientEncoder {
+    fn drop(&mut self) {
+        self.destroy_backend();
+    }
+}
+
+// 结束码流并取回被推迟的包
+fn drain(backend: &mut dyn EncodeBackend, frames: &mut Vec<EncodeFrame>) -> Result<(), i32> {
+    backend.flush()?;
+    while backend.pending() > 0 {
+        let received = frames.len();
+        backend.receive(RECEIVE_TIMEOUT_MS, frames)?;
+        if frames.len() == received {
+            return Err(ERR_TIMEOUT);
+        }
+    }
+    Ok(())
+}
+
+// 候选以 Encoder 运行：会话选择、缩放与包的标注都与 Encoder::new 一致
+#[cfg(windows)]
+struct EncoderBackend {
+    encoder: crate::vram::encode::Encoder,
+    // flush 取回的包，由 receive 交出
+    flushed: Vec<EncodeFrame>,
+}
+
+#[cfg(windows)]
+impl EncoderBackend {
+    fn new(encoder: crate::vram::encode::Encoder) -> Self {
+        Self {
+            encoder,
+            flushed: Vec::new(),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl EncodeBackend for EncoderBackend {
+    fn encode(
+        &mut self,
+        tex: *mut c_void,
+        ms: i64,
+        frames: &mut Vec<EncodeFrame>,
+    ) -> Result<(), i32> {
+        frames.append(self.encoder.encode(tex, ms)?);
+        Ok(())
+    }
+
+    fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32> {
+        self.encoder.set_bitrate(kbs)
+    }
+
+    fn set_framerate(&mut self, framerate: i32) -> Result<(), i32> {
+        self.encoder.set_framerate(framerate)
+    }
+
+    // 会话随 Encoder 释放
+    fn destroy(&mut self) {}
+
+    fn receive(&mut self, _timeout_ms: u32, frames: &mut Vec<EncodeFrame>) -> Result<(), i32> {
+        frames.append(&mut self.flushed);
+        Ok(())
+    }
+
+    fn pending(&self) -> usize {
+        self.flushed.len()
+    }
+
+    fn flush(&mut self) -> Result<(), i32> {
+        self.flushed.append(self.encoder.flush()?);
+        Ok(())
+    }
+
+    fn reorder_depth(&self) -> usize {
+        self.encoder.reorder_depth()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{DataFormat, Driver};
+    use std::sync::{Arc, Mutex};
+
+    /// 每个驱动的注入脚本：创建是否失败，以及第几帧（从 0 计）开始失败
+    #[derive(Default)]
+    struct Script {
+        created: Vec<Driver>,
+        fail_create: Vec<Driver>,
+        fail_from: Vec<(Driver, usize)>,
+        device_lost: bool,
+        // 设备检查报告设备已移除，后端仍返回普通错误码
+        removed: bool,
+        bitrates: Vec<(Driver, i32)>,
+    }
+
+    struct MockBackend {
+        driver: Driver,
+        frame: usize,
+        script: Arc<Mutex<Script>>,
+        // flush 后待 receive 取走的包，模拟驱动推迟的 B 帧
+        held: Option<EncodeFrame>,
+    }
+
+    impl EncodeBackend for MockBackend {
+        fn encode(
+            &mut self,
+            _tex: *mut c_void,
+            _ms: i64,
+            frames: &mut Vec<EncodeFrame>,
+        ) -> Result<(), i32> {
+            let script = self.script.lock().unwrap();
+            if script.device_lost {
+                return Err(ERR_DEVICE_LOST);
+            }
+            let failing = script
+                .fail_from
+                .iter()
+                .any(|(d, n)| *d == self.driver && self.frame >= *n);
+            if failing {
+                return Err(-1);
+            }
+            // 模拟驱动自己的时钟：每个会话从 0 开始
+            frames.push(EncodeFrame {
+                data: vec![self.driver.clone() as u8],
+                pts: self.frame as i64 * 10,
+                key: (self.frame == 0) as i32,
+                ..Default::default()
+            });
+            self.frame += 1;
+            Ok(())
+        }
+
+        fn set_bitrate(&mut self, kbs: i32) -> Result<(), i32> {
+            self.script
+                .lock()
+                .unwrap()
+                .bitrates
+                .push((self.driver.clone(), kbs));
+            Ok(())
+        }
+
+        fn set_framerate(&mut self, _framerate: i32) -> Result<(), i32> {
+            Ok(())
+        }
+
+        fn destroy(&mut self) {}
+
+        fn flush(&mut self) -> Result<(), i32> {
+            self.held = Some(EncodeFrame {
+                pts: self.frame as i64 * 10,
+                ..Default::default()
+            });
+            Ok(())
+        }
+
+        fn pending(&self) -> usize {
+            self.held.is_some() as usize
+        }
+
+        fn receive(&mut self, _timeout_ms: u32, frames: &mut Vec<EncodeFrame>) -> Result<(), i32> {
+            frames.extend(self.held.take());
+            Ok(())
+        }
+    }
+
+    fn candidates() -> Vec<FeatureContext> {
+        [Driver::NV, Driver::AMF, Driver::MFX]
+            .into_iter()
+            .map(|driver| FeatureContext {
+                driver: driver.clone(),
+                vendor: driver,
+                luid: 0,
+                data_format: DataFormat::H264,
+            })
+            .collect()
+    }
+
+    fn encoder(script: &Arc<Mutex<Script>>, max_errors: u32) -> Result<ResilientEncoder, ()> {
+        let check = script.clone();
+        let script = script.clone();
+        let encoder = ResilientEncoder::with_factory(
+            candidates(),
+            max_errors,
+            Box::new(move |f| {
+                let mut s = script.lock().unwrap();
+                if s.fail_create.contains(&f.driver) {
+                    return Err(());
+                }
+                s.created.push(f.driver.clone());
+                Ok(Box::new(MockBackend {
+                    driver: f.driver.clone(),
+                    frame: 0,
+                    script: script.clone(),
+                    held: None,
+                }) as Box<dyn EncodeBackend>)
+            }),
+        )?;
+        Ok(encoder.with_device_check(Box::new(move || check.lock().unwrap().removed)))
+    }
+
+    fn encode_ok(encoder: &mut ResilientEncoder, ms: i64) -> EncodeFrame {
+        let frames = encoder.encode(std::ptr::null_mut(), ms).unwrap();
+        assert_eq!(frames.len(), 1);
+        frames[0].clone()
+    }
+
+    /// 测试连续错误达到阈值后先重建当前后端，重建后输出关键帧且 pts 接续输入；重建后成功编码过，
+    /// 再次失败时仍先重建而不是切换
+    #[test]
+    fn test_recreate() {
+        let script = Arc::new(Mutex::new(Script::default()));
+        let mut encoder = encoder(&script, 2).unwrap();
+        for i in 0..3 {
+            assert_eq!(encode_ok(&mut encoder, i * 10).pts, i * 10);
+        }
+        // 当前会话第 3 帧起失败；重建后的新会话从 0 计数，不受影响
+        script.lock().unwrap().fail_from = vec![(Driver::NV, 3)];
+        assert_eq!(encoder.encode(std::ptr::null_mut(), 30).err(), Some(-1));
+        assert!(encoder.take_events().is_empty());
+
+        let frame = encode_ok(&mut encoder, 40);
+        assert_eq!(frame.key, 1);
+        assert_eq!(frame.pts, 40);
+        assert_eq!(frame.data, vec![Driver::NV as u8]);
+        assert_eq!(
+            encoder.take_events(),
+            vec![FailoverEvent::Recreated {
+                feature: candidates()[0].clone(),
+                error: -1
+            }]
+        );
+        assert!(encoder.take_events().is_empty());
+        assert_eq!(encode_ok(&mut encoder, 50).pts, 50);
+
+        // 新会话第 2 帧起失败，已成功编码过，所以再次重建
+        script.lock().unwrap().fail_from = vec![(Driver::NV, 2)];
+        assert!(encoder.encode(std::ptr::null_mut(), 60).is_err());
+        assert_eq!(encode_ok(&mut encoder, 70).pts, 70);
+        assert!(matches!(
+            encoder.take_events()[..],
+            [FailoverEvent::Recreated { .. }]
+        ));
+        assert_eq!(
+            script.lock().unwrap().created,
+            vec![Driver::NV, Driver::NV, Driver::NV]
+        );
+    }
+
+    /// 测试重建无效时切换到下一个驱动，跳过无法创建的候选，并恢复码率设置
+    #[test]
+    fn test_switch() {
+        let script = Arc::new(Mutex::new(Script::default()));
+        let mut encoder = encoder(&script, 1).unwrap();
+        encoder.set_bitrate(3000).unwrap();
+        encode_ok(&mut encoder, 0);
+        encode_ok(&mut encoder, 0);
+        {
+            let mut s = script.lock().unwrap();
+            s.fail_from = vec![(Driver::NV, 0)];
+            s.fail_create = vec![Driver::AMF];
+        }
+        // 重建后 NV 仍失败，返回错误
+        assert!(encoder.encode(std::ptr::null_mut(), 0).is_err());
+        // 输入 pts 没有前进时按上一步长 10 接续
+        let frame = encode_ok(&mut encoder, 0);
+        assert_eq!(frame.data, vec![Driver::MFX as u8]);
+        assert_eq!(frame.key, 1);
+        assert_eq!(frame.pts, 20);
+        assert_eq!(encoder.current(), Some(&candidates()[2]));
+        let events = encoder.take_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            FailoverEvent::Switched {
+                from: candidates()[0].clone(),
+                to: candidates()[2].clone(),
+                error: -1
+            }
+        );
+        let s = script.lock().unwrap();
+        assert_eq!(s.created, vec![Driver::NV, Driver::NV, Driver::MFX]);
+        assert_eq!(s.bitrates.last(), Some(&(Driver::MFX, 3000)));
+    }
+
+    /// 测试所有候选耗尽
+    #[test]
+    fn test_exhausted() {
+        let script = Arc::new(Mutex::new(Script::default()));
+        script.lock().unwrap().fail_create = vec![Driver::NV, Driver::AMF, Driver::MFX];
+        assert!(encoder(&script, 1).is_err());
+
+        script.lock().unwrap().fail_create = vec![Driver::NV, Driver::AMF];
+        let mut encoder = encoder(&script, 1).unwrap();
+        assert_eq!(encoder.current(), Some(&candidates()[2]));
+        script.lock().unwrap().fail_from = vec![(Driver::MFX, 0)];
+        script.lock().unwrap().fail_create = vec![Driver::NV, Driver::AMF, Driver::MFX];
+        assert_eq!(
+            encoder.encode(std::ptr::null_mut(), 0).err(),
+            Some(ERR_ENCODER_EXHAUSTED)
+        );
+        assert_eq!(
+            encoder.take_events(),
+            vec![FailoverEvent::Exhausted { error: -1 }]
+        );
+        assert!(encoder.current().is_none());
+        assert_eq!(
+            encoder.encode(std::ptr::null_mut(), 0).err(),
+            Some(ERR_ENCODER_EXHAUSTED)
+        );
+        assert_eq!(encoder.flush().err(), Some(ERR_ENCODER_EXHAUSTED));
+    }
+
+    /// 测试 flush 取回当前后端推迟的包，切换后的会话 pts 同样被校正
+    #[test]
+    fn test_flush() {
+        let script = Arc::new(Mutex::new(Script::default()));
+        let mut encoder = encoder(&script, 1).unwrap();
+        encode_ok(&mut encoder, 0);
+        let frames = encoder.flush().unwrap();
+        assert_eq!(frames.iter().map(|f| f.pts).collect::<Vec<_>>(), vec![10]);
+
+        // 切换到 AMF：新会话时钟从 0 开始，flush 的包接续其输出
+        script.lock().unwrap().fail_create = vec![Driver::NV];
+        script.lock().unwrap().fail_from = vec![(Driver::NV, 0)];
+        assert_eq!(encode_ok(&mut encoder, 20).pts, 20);
+        let frames = encoder.flush().unwrap();
+        assert_eq!(frames.iter().map(|f| f.pts).collect::<Vec<_>>(), vec![30]);
+    }
+
+    /// 测试设备丢失直接上报，不触发重建或切换
+    #[test]
+    fn test_device_lost() {
+        let script = Arc::new(Mutex::new(Script::default()));
+        let mut encoder = encoder(&script, 1).unwrap();
+        script.lock().unwrap().device_lost = true;
+        for _ in 0..3 {
+            assert_eq!(
+                encoder.encode(std::ptr::null_mut(), 0).err(),
+                Some(ERR_DEVICE_LOST)
+            );
+        }
+        assert!(encoder.take_events().is_empty());
+        assert_eq!(encoder.current(), Some(&candidates()[0]));
+        assert_eq!(script.lock().unwrap().created, vec![Driver::NV]);
+    }
+
+    /// 测试后端返回驱动原始错误码而设备已移除时，同样按设备丢失上报
+    #[test]
+    fn test_device_removed() {
+        let script = Arc::new(Mutex::new(Script::default()));
+        let mut encoder = encoder(&script, 1).unwrap();
+        encode_ok(&mut encoder, 0);
+        {
+            let mut s = script.lock().unwrap();
+            s.fail_from = vec![(Driver::NV, 0)];
+            s.removed = true;
+        }
+        for _ in 0..3 {
+            assert_eq!(
+                encoder.encode(std::ptr::null_mut(), 0).err(),
+                Some(ERR_DEVICE_LOST)
+            );
+        }
+        assert!(encoder.device_lost());
+        assert!(encoder.take_events().is_empty());
+        assert_eq!(encoder.current(), Some(&candidates()[0]));
+        assert_eq!(script.lock().unwrap().created, vec![Driver::NV]);
+    }
+}
@@ -0,0 +1,281 @@
+//! Encoder selection: ranks the encoders of an `Available` against a `Policy`.
+
+use crate::common::{is_format_hybrid_decoded_by_hardware, DataFormat, Driver};
+use crate::vram::{Available, FeatureContext};
+use log::debug;
+use std::fmt::Display;
+
+/// PCI identity of an adapter, used for the hybrid-decoder check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdapterId {
+    pub luid: i64,
+    pub vendor_id: u32,
+    pub device_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    /// Codec the encoder must produce.
+    pub data_format: DataFormat,
+    /// Drivers in order of preference. Drivers not listed rank after all listed ones.
+    pub vendors: Vec<Driver>,
+    /// LUID of the adapter that owns the input textures' device. Encoders on it rank first,
+    /// since an encoder can only consume textures from its own device.
+    pub luid: Option<i64>,
+    /// Skip adapters that decode `data_format` in hybrid (partly shader-based) mode; their
+    /// hardware support for the codec is incomplete.
+    pub avoid_hybrid: bool,
+    /// Adapters known to the policy, for the hybrid check. Adapters missing here pass it.
+    pub adapters: Vec<AdapterId>,
+    /// GPU signatures (`get_gpu_signature`) on which no hardware encoder may be used.
+    pub deny_signatures: Vec<u64>,
+    /// Signature of this machine, checked against `deny_signatures`.
+    pub gpu_signature: u64,
+}
+
+impl Policy {
+    /// Policy for `data_format` preferring NV, then AMF, then MFX, avoiding hybrid decoders,
+    /// with this machine's adapters and GPU signature.
+    pub fn new(data_format: DataFormat) -> Self {
+        Self {
+            data_format,
+            vendors: vec![Driver::NV, Driver::AMF, Driver::MFX],
+            luid: None,
+            avoid_hybrid: true,
+            adapters: adapter_ids(),
+            deny_signatures: vec![],
+            gpu_signature: crate::common::get_gpu_signature(),
+        }
+    }
+
+    /// Prefers encoders on the adapter owning `device`.
+    #[cfg(windows)]
+    pub fn prefer_device(mut self, device: &crate::platform::win::Device) -> Self {
+        self.luid = device.luid().ok();
+        self
+    }
+}
+
+/// Why a candidate ranks where it does. Variants are listed in order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// On the adapter of `Policy::luid`.
+    SameAdapter,
+    /// On another adapter than `Policy::luid`.
+    OtherAdapter,
+    /// Driver at this position in `Policy::vendors`.
+    VendorRank(usize),
+    /// Driver not in `Policy::vendors`.
+    VendorUnranked,
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::SameAdapter => write!(f, "on the input device's adapter"),
+            Reason::OtherAdapter => write!(f, "on another adapter than the input device"),
+            Reason::VendorRank(i) => write!(f, "vendor preference #{}", i + 1),
+            Reason::VendorUnranked => write!(f, "vendor not in preference list"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ranked {
+    pub feature: FeatureContext,
+    pub reasons: Vec<Reason>,
+}
+
+/// Returns the encoders of `available` usable under `policy`, best first. Candidates with the
+/// wrong codec, on a hybrid adapter or on a denied machine are left out.
+pub fn select_encoder(available: &Available, policy: &Policy) -> Vec<Ranked> {
+    if policy.gpu_signature != 0 && policy.deny_signatures.contains(&policy.gpu_signature) {
+        debug!(
+            "GPU signature {} is deny-listed, no hardware encoder selected",
+            policy.gpu_signature
+        );
+        return vec![];
+    }
+
+    let mut ranked: Vec<(usize, Ranked)> = available
+        .e
+        .iter()
+        .filter(|f| f.data_format == policy.data_format)
+        .filter(|f| {
+            let hybrid = policy.avoid_hybrid && is_hybrid(policy, f);
+            if hybrid {
+                debug!("Skipping {:?} on hybrid adapter {}", f.driver, f.luid);
+            }
+            !hybrid
+        })
+        .map(|f| {
+            let mut reasons = vec![];
+            if let Some(luid) = policy.luid {
+                reasons.push(if f.luid == luid {
+                    Reason::SameAdapter
+                } else {
+                    Reason::OtherAdapter
+                });
+            }
+            let vendor = policy.vendors.iter().position(|v| *v == f.driver);
+            reasons.push(vendor.map_or(Reason::VendorUnranked, Reason::VendorRank));
+            let rank = vendor.unwrap_or(policy.vendors.len());
+            (
+                rank,
+                Ranked {
+                    feature: f.clone(),
+                    reasons,
+                },
+            )
+        })
+        .collect();
+    // 稳定排序：同优先级时保留 available 中的顺序
+    ranked.sort_by_key(|(rank, r)| (r.reasons.first() == Some(&Reason::OtherAdapter), *rank));
+    ranked.into_iter().map(|(_, r)| r).collect()
+}
+
+fn is_hybrid(policy: &Policy, f: &FeatureContext) -> bool {
+    policy
+        .adapters
+        .iter()
+        .find(|a| a.luid == f.luid)
+        .is_some_and(|a| {
+            is_format_hybrid_decoded_by_hardware(f.data_format, a.vendor_id, a.device_id)
+        })
+}
+
+#[cfg(windows)]
+fn adapter_ids() -> Vec<AdapterId> {
+    use crate::common::AdapterVendor::*;
+    use crate::platform::win::Adapters;
+
+    [ADAPTER_VENDOR_NVIDIA, ADAPTER_VENDOR_AMD, ADAPTER_VENDOR_INTEL]
+        .into_iter()
+        .filter_map(|vendor| Adapters::new(vendor).ok())
+        .flat_map(|adapters| {
+            adapters
+                .adapters()
+                .iter()
+                .map(|a| AdapterId {
+                    luid: a.luid(),
+                    vendor_id: a.vendor_id(),
+                    device_id: a.device_id(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn adapter_ids() -> Vec<AdapterId> {
+    vec![]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::AdapterVendor;
+    use DataFormat::*;
+    use Driver::*;
+
+    fn feature(driver: Driver, luid: i64, data_format: DataFormat) -> FeatureContext {
+        FeatureContext {
+            driver: driver.clone(),
+            vendor: driver,
+            luid,
+            data_format,
+        }
+    }
+
+    fn policy(data_format: DataFormat) -> Policy {
+        Policy {
+            data_format,
+            vendors: vec![NV, AMF, MFX],
+            luid: None,
+            avoid_hybrid: true,
+            adapters: vec![],
+            deny_signatures: vec![],
+            gpu_signature: 0,
+        }
+    }
+
+    fn available() -> Available {
+        Available {
+            e: vec![
+                feature(MFX, 3, H264),
+                feature(AMF, 2, H264),
+                feature(NV, 1, H265),
+                feature(NV, 1, H264),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn drivers(ranked: &[Ranked]) -> Vec<Driver> {
+        ranked.iter().map(|r| r.feature.driver.clone()).collect()
+    }
+
+    /// 测试按厂商优先级排序并过滤编码格式
+    #[test]
+    fn test_vendor_order() {
+        let ranked = select_encoder(&available(), &policy(H264));
+        assert_eq!(drivers(&ranked), vec![NV, AMF, MFX]);
+        assert_eq!(ranked[0].reasons, vec![Reason::VendorRank(0)]);
+
+        let mut p = policy(H264);
+        p.vendors = vec![MFX];
+        let ranked = select_encoder(&available(), &p);
+        assert_eq!(drivers(&ranked), vec![MFX, AMF, NV]);
+        assert_eq!(ranked[1].reasons, vec![Reason::VendorUnranked]);
+
+        assert_eq!(drivers(&select_encoder(&available(), &policy(H265))), vec![NV]);
+        assert!(select_encoder(&available(), &policy(AV1)).is_empty());
+    }
+
+    /// 测试输入设备所在适配器优先于厂商偏好
+    #[test]
+    fn test_prefer_luid() {
+        let mut p = policy(H264);
+        p.luid = Some(2);
+        let ranked = select_encoder(&available(), &p);
+        assert_eq!(drivers(&ranked), vec![AMF, NV, MFX]);
+        assert_eq!(
+            ranked[0].reasons,
+            vec![Reason::SameAdapter, Reason::VendorRank(1)]
+        );
+        assert_eq!(
+            ranked[1].reasons,
+            vec![Reason::OtherAdapter, Reason::VendorRank(0)]
+        );
+        assert_eq!(ranked[0].reasons[0].to_string(), "on the input device's adapter");
+    }
+
+    /// 测试跳过混合解码的适配器（GM204 上的 H.265）
+    #[test]
+    fn test_avoid_hybrid() {
+        let mut p = policy(H265);
+        p.adapters = vec![AdapterId {
+            luid: 1,
+            vendor_id: AdapterVendor::ADAPTER_VENDOR_NVIDIA as u32,
+            device_id: 0x13C2,
+        }];
+        assert!(select_encoder(&available(), &p).is_empty());
+        p.avoid_hybrid = false;
+        assert_eq!(drivers(&select_encoder(&available(), &p)), vec![NV]);
+
+        p.avoid_hybrid = true;
+        p.data_format = H264;
+        assert_eq!(select_encoder(&available(), &p).len(), 3);
+    }
+
+    /// 测试 GPU 签名黑名单
+    #[test]
+    fn test_deny_signature() {
+        let mut p = policy(H264);
+        p.deny_signatures = vec![0xABCD];
+        p.gpu_signature = 0x1234;
+        assert_eq!(select_encoder(&available(), &p).len(), 3);
+        p.gpu_signature = 0xABCD;
+        assert!(select_encoder(&available(), &p).is_empty());
+    }
+}
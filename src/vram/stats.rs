@@ -0,0 +1,352 @@
+//! Runtime statistics of `Encoder` and `Decoder`.
+//!
+//! The counters are updated around the backend call, so they cover every driver. `stats()`
+//! returns a serializable snapshot, e.g. for a metrics endpoint.
+
+use crate::vram::backend::EncodeFrame;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+// 瞬时码率的统计窗口
+const BITRATE_WINDOW: Duration = Duration::from_secs(1);
+
+// 参与延迟分位数计算的最近调用数
+const LATENCY_SAMPLES: usize = 512;
+
+/// Per-call latency of the backend, over the most recent calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct LatencyStats {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct CodecStats {
+    /// Frames (encoder) or packets (decoder) passed to the codec.
+    pub frames_in: u64,
+    /// Packets (encoder) or frames (decoder) returned by the codec.
+    pub frames_out: u64,
+    /// Keyframes produced. Always 0 for decoders.
+    pub keyframes: u64,
+    /// Bitstream bytes produced (encoder) or consumed (decoder).
+    pub bytes: u64,
+    /// Bitrate over the last second, in kbit/s.
+    pub bitrate_kbps: f64,
+    /// Bitrate since the first call, in kbit/s.
+    pub avg_bitrate_kbps: f64,
+    pub latency: LatencyStats,
+    /// Input the codec produced nothing for: failed calls, and for encoders frames skipped by
+    /// the driver.
+    pub dropped: u64,
+    /// Failed calls by error code.
+    pub errors: BTreeMap<i32, u64>,
+}
+
+pub(crate) struct StatsRecorder {
+    stats: CodecStats,
+    first: Option<Instant>,
+    // (结束时间, 字节数)，用于瞬时码率
+    window: VecDeque<(Instant, u64)>,
+    latencies: VecDeque<Duration>,
+    // 编码器为 B 帧重排而推迟输出的帧数，这些帧暂无输出不算丢帧
+    reorder_depth: u64,
+}
+
+impl StatsRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            stats: CodecStats::default(),
+            first: None,
+            window: VecDeque::new(),
+            latencies: VecDeque::with_capacity(LATENCY_SAMPLES),
+            reorder_depth: 0,
+        }
+    }
+
+    /// Frames the encoder holds back for reordering; calls producing nothing while fewer
+    /// frames are held do not count as dropped.
+    pub(crate) fn set_reorder_depth(&mut self, frames: usize) {
+        self.reorder_depth = frames as u64;
+    }
+
+    /// Records an encode call that started at `start`.
+    pub(crate) fn encoded(&mut self, start: Instant, result: Result<&[EncodeFrame], i32>) {
+        self.encoded_at(start, Instant::now(), result);
+    }
+
+    /// Records a decode call for a packet of `packet_len` bytes that started at `start`;
+    /// `result` is the number of frames it produced.
+    pub(crate) fn decoded(
+        &mut self,
+        start: Instant,
+        packet_len: usize,
+        result: Result<usize, i32>,
+    ) {
+        self.decoded_at(start, Instant::now(), packet_len, result);
+    }
+
+    /// Records a flush of the encoder: packets of frames counted by earlier `encoded` calls.
+    pub(crate) fn flushed(&mut self, start: Instant, result: Result<&[EncodeFrame], i32>) {
+        self.flushed_at(start, Instant::now(), result);
+    }
+
+    /// Records a flush of the decoder: `result` is the number of held-back frames it produced.
+    pub(crate) fn decoder_flushed(&mut self, start: Instant, result: Result<usize, i32>) {
+        match result {
+            Ok(frames) => self.record(start, Instant::now(), 0, 0, frames as u64, None),
+            Err(e) => self.record(start, Instant::now(), 0, 0, 0, Some(e)),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> CodecStats {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn encoded_at(&mut self, start: Instant, end: Instant, result: Result<&[EncodeFrame], i32>) {
+        match result {
+            Ok(frames) => {
+                let bytes = frames.iter().map(|f| f.data.len() as u64).sum();
+                let keyframes = frames.iter().filter(|f| f.key != 0).count() as u64;
+                self.stats.keyframes += keyframes;
+                // 一帧进一帧出；无输出且被推迟的帧已达重排深度，说明驱动丢弃了该帧
+                if frames.is_empty() && self.held() >= self.reorder_depth {
+                    self.stats.dropped += 1;
+                }
+                self.record(start, end, bytes, 1, frames.len() as u64, None);
+            }
+            Err(e) => self.record(start, end, 0, 1, 0, Some(e)),
+        }
+    }
+
+    fn flushed_at(&mut self, start: Instant, end: Instant, result: Result<&[EncodeFrame], i32>) {
+        match result {
+            Ok(frames) => {
+                let bytes = frames.iter().map(|f| f.data.len() as u64).sum();
+                let keyframes = frames.iter().filter(|f| f.key != 0).count() as u64;
+                self.stats.keyframes += keyframes;
+                self.record(start, end, bytes, 0, frames.len() as u64, None);
+            }
+            Err(e) => self.record(start, end, 0, 0, 0, Some(e)),
+        }
+    }
+
+    // 已输入但尚无输出、也未计为丢弃的帧
+    fn held(&self) -> u64 {
+        let done = self.stats.frames_out + self.stats.dropped;
+        self.stats.frames_in.saturating_sub(done)
+    }
+
+    fn decoded_at(
+        &mut self,
+        start: Instant,
+        end: Instant,
+        packet_len: usize,
+        result: Result<usize, i32>,
+    ) {
+        // 解码器可能缓存帧，无输出不算丢帧；失败的包计入字节但不产出帧
+        match result {
+            Ok(frames) => self.record(start, end, packet_len as u64, 1, frames as u64, None),
+            Err(e) => self.record(start, end, packet_len as u64, 1, 0, Some(e)),
+        }
+    }
+
+    fn record(
+        &mut self,
+        start: Instant,
+        end: Instant,
+        bytes: u64,
+        input: u64,
+        out: u64,
+        error: Option<i32>,
+    ) {
+        self.first.get_or_insert(start);
+        self.stats.frames_in += input;
+        self.stats.frames_out += out;
+        self.stats.bytes += bytes;
+        if let Some(e) = error {
+            self.stats.dropped += input;
+            *self.stats.errors.entry(e).or_default() += 1;
+        }
+        while self
+            .window
+            .front()
+            .is_some_and(|(t, _)| end.saturating_duration_since(*t) >= BITRATE_WINDOW)
+        {
+            self.window.pop_front();
+        }
+        self.window.push_back((end, bytes));
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies
+            .push_back(end.saturating_duration_since(start));
+    }
+
+    fn snapshot_at(&self, now: Instant) -> CodecStats {
+        let mut stats = self.stats.clone();
+        let recent: u64 = self
+            .window
+            .iter()
+            .filter(|(t, _)| now.saturating_duration_since(*t) < BITRATE_WINDOW)
+            .map(|(_, bytes)| *bytes)
+            .sum();
+        stats.bitrate_kbps = kbps(recent, BITRATE_WINDOW);
+        if let Some(first) = self.first {
+            stats.avg_bitrate_kbps = kbps(stats.bytes, now.saturating_duration_since(first));
+        }
+        stats.latency = latency(&self.latencies);
+        stats
+    }
+}
+
+fn kbps(bytes: u64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    bytes as f64 * 8.0 / 1000.0 / elapsed.as_secs_f64()
+}
+
+fn latency(samples: &VecDeque<Duration>) -> LatencyStats {
+    if samples.is_empty() {
+        return LatencyStats::default();
+    }
+    let mut ms: Vec<f64> = samples.iter().map(|d| d.as_nanos() as f64 / 1e6).collect();
+    ms.sort_by(|a, b| a.total_cmp(b));
+    // 最近秩法：第 p% 个样本
+    let percentile = |p: usize| ms[((ms.len() * p).div_ceil(100)).max(1) - 1];
+    LatencyStats {
+        p50_ms: percentile(50),
+        p90_ms: percentile(90),
+        p99_ms: percentile(99),
+        max_ms: ms[ms.len() - 1],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(len: usize, key: bool) -> EncodeFrame {
+        EncodeFrame {
+            data: vec![0; len],
+            pts: 0,
+            key: key as i32,
+            ..Default::default()
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    /// 测试编码计数、丢帧、错误码与码率
+    #[test]
+    fn test_encode_stats() {
+        let mut recorder = StatsRecorder::new();
+        let t0 = Instant::now();
+        recorder.encoded_at(t0, t0 + ms(5), Ok(&[packet(1000, true)]));
+        recorder.encoded_at(t0 + ms(500), t0 + ms(505), Ok(&[packet(250, false)]));
+        assert_eq!(recorder.snapshot_at(t0 + ms(600)).bitrate_kbps, 10.0);
+        recorder.encoded_at(t0 + ms(1000), t0 + ms(1001), Ok(&[]));
+        recorder.encoded_at(t0 + ms(1500), t0 + ms(1502), Err(-102));
+        recorder.encoded_at(t0 + ms(1600), t0 + ms(1601), Err(-102));
+        recorder.encoded_at(t0 + ms(1700), t0 + ms(1701), Err(-1));
+
+        let stats = recorder.snapshot_at(t0 + ms(2000));
+        assert_eq!(stats.frames_in, 6);
+        assert_eq!(stats.frames_out, 2);
+        assert_eq!(stats.keyframes, 1);
+        assert_eq!(stats.bytes, 1250);
+        assert_eq!(stats.dropped, 4);
+        assert_eq!(stats.errors, BTreeMap::from([(-102, 2), (-1, 1)]));
+        assert_eq!(stats.avg_bitrate_kbps, 5.0);
+        // 最近 1 秒内没有产出
+        assert_eq!(stats.bitrate_kbps, 0.0);
+        assert_eq!(stats.latency.max_ms, 5.0);
+        assert_eq!(stats.latency.p50_ms, 1.0);
+    }
+
+    /// 测试 B 帧推迟的输出不算丢帧，flush 取回的包只计入输出
+    #[test]
+    fn test_reorder_stats() {
+        let mut recorder = StatsRecorder::new();
+        recorder.set_reorder_depth(2);
+        let t0 = Instant::now();
+        recorder.encoded_at(t0, t0 + ms(1), Ok(&[packet(100, true)]));
+        recorder.encoded_at(t0, t0 + ms(1), Ok(&[]));
+        recorder.encoded_at(t0, t0 + ms(1), Ok(&[]));
+        assert_eq!(recorder.snapshot_at(t0 + ms(1)).dropped, 0);
+        // 已推迟 2 帧，再无输出即为丢帧
+        recorder.encoded_at(t0, t0 + ms(1), Ok(&[]));
+        assert_eq!(recorder.snapshot_at(t0 + ms(1)).dropped, 1);
+
+        recorder.flushed_at(t0, t0 + ms(2), Ok(&[packet(10, false), packet(10, false)]));
+        recorder.flushed_at(t0, t0 + ms(2), Err(-1));
+        let stats = recorder.snapshot_at(t0 + ms(2));
+        assert_eq!((stats.frames_in, stats.frames_out), (4, 3));
+        assert_eq!((stats.bytes, stats.dropped), (120, 1));
+        assert_eq!(stats.errors, BTreeMap::from([(-1, 1)]));
+    }
+
+    /// 测试解码统计：缓存不算丢帧，失败的包计入错误
+    #[test]
+    fn test_decode_stats() {
+        let mut recorder = StatsRecorder::new();
+        let t0 = Instant::now();
+        recorder.decoded_at(t0, t0 + ms(1), 100, Ok(0));
+        recorder.decoded_at(t0, t0 + ms(1), 100, Ok(2));
+        recorder.decoded_at(t0, t0 + ms(1), 50, Err(-1));
+        let stats = recorder.snapshot_at(t0 + ms(500));
+        assert_eq!((stats.frames_in, stats.frames_out), (3, 2));
+        assert_eq!((stats.bytes, stats.keyframes, stats.dropped), (250, 0, 1));
+        assert_eq!(stats.errors, BTreeMap::from([(-1, 1)]));
+        assert_eq!(stats.bitrate_kbps, 2.0);
+
+        let empty = StatsRecorder::new().snapshot_at(t0);
+        assert_eq!(empty, CodecStats::default());
+    }
+
+    /// 测试延迟分位数只统计最近的调用
+    #[test]
+    fn test_latency_percentiles() {
+        let mut recorder = StatsRecorder::new();
+        let t0 = Instant::now();
+        for _ in 0..LATENCY_SAMPLES {
+            recorder.encoded_at(t0, t0 + ms(1000), Ok(&[packet(1, false)]));
+        }
+        for i in 1..=100 {
+            recorder.encoded_at(t0, t0 + ms(i), Ok(&[packet(1, false)]));
+        }
+        let latency = recorder.snapshot_at(t0).latency;
+        assert_eq!(latency.max_ms, 1000.0);
+        assert_eq!(recorder.latencies.len(), LATENCY_SAMPLES);
+
+        let samples: VecDeque<_> = (1..=100).map(ms).collect();
+        let latency = super::latency(&samples);
+        assert_eq!(
+            (
+                latency.p50_ms,
+                latency.p90_ms,
+                latency.p99_ms,
+                latency.max_ms
+            ),
+            (50.0, 90.0, 99.0, 100.0)
+        );
+    }
+
+    /// 测试快照可经 serde 序列化往返
+    #[test]
+    fn test_serde() {
+        let mut recorder = StatsRecorder::new();
+        let t0 = Instant::now();
+        recorder.encoded_at(t0, t0 + ms(2), Ok(&[packet(10, true)]));
+        recorder.encoded_at(t0, t0 + ms(2), Err(-100));
+        let stats = recorder.snapshot_at(t0 + ms(100));
+        let json = serde_json::to_string(&stats).unwrap();
+        assert!(json.contains("\"errors\":{\"-100\":1}"));
+        assert_eq!(serde_json::from_str::<CodecStats>(&json).unwrap(), stats);
+    }
+}
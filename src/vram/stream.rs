@@ -0,0 +1,498 @@
+//! `futures::Stream` adapters for `AsyncEncoder` and `AsyncDecoder`, enabled by the `stream`
+//! feature.
+//!
+//! The codec moves to a worker thread. `FrameSink::submit` hands the encoder textures without
+//! blocking and `PacketStream` yields packets as the driver finishes them, so the encoder fits
+//! into an async (e.g. tokio) pipeline. Dropping the sink flushes the encoder and ends the stream.
+//! The decoder side mirrors this with `PacketSink` and `FrameStream`.
+
+#[cfg(windows)]
+use crate::platform::win::Texture;
+use crate::vram::backend::EncodeFrame;
+use crate::vram::decode_pipeline::{AsyncDecoder, PooledFrame};
+use crate::vram::pipeline::AsyncEncoder;
+use crate::vram::ERR_QUEUE_FULL;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+// 有在途帧时每轮等待驱动的时长，期间新提交的帧需排队
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+enum Input {
+    Raw(*mut c_void),
+    #[cfg(windows)]
+    Texture(Texture),
+}
+
+// 纹理只在工作线程上交给驱动
+unsafe impl Send for Input {}
+
+enum Command {
+    Submit(Input, i64),
+    SetBitrate(i32),
+    SetFramerate(i32),
+}
+
+struct State<T> {
+    items: VecDeque<Result<T, i32>>,
+    closed: bool,
+    // 输出端已丢弃，工作线程应尽快退出
+    cancelled: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+}
+
+impl<T> Shared<T> {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                items: VecDeque::new(),
+                closed: false,
+                cancelled: false,
+                waker: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, item: Result<T, i32>) {
+        let mut state = self.lock();
+        if state.cancelled {
+            return;
+        }
+        state.items.push_back(item);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn cancel(&self) {
+        let items = {
+            let mut state = self.lock();
+            state.cancelled = true;
+            std::mem::take(&mut state.items)
+        };
+        // 在锁外释放，帧的 Drop 会归还表面
+        drop(items);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.lock().cancelled
+    }
+
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<Result<T, i32>>> {
+        let mut state = self.lock();
+        if let Some(item) = state.items.pop_front() {
+            return Poll::Ready(Some(item));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Input side of `AsyncEncoder::into_stream`.
+pub struct FrameSink {
+    tx: SyncSender<Command>,
+}
+
+impl FrameSink {
+    /// Queues `tex` for encoding. The encoder keeps a reference to `tex` until its packet comes
+    /// out of the stream; `tex` must not be modified before then. Returns `ERR_QUEUE_FULL` when
+    /// the worker is `depth` frames behind.
+    #[cfg(windows)]
+    pub fn submit(&self, tex: &Texture, pts: i64) -> Result<(), i32> {
+        self.send(Command::Submit(Input::Texture(tex.clone()), pts))
+    }
+
+    /// Like `submit`, for a raw `ID3D11Texture2D` pointer. The caller keeps `tex` alive and
+    /// unmodified until its packet comes out of the stream.
+    pub fn submit_raw(&self, tex: *mut c_void, pts: i64) -> Result<(), i32> {
+        self.send(Command::Submit(Input::Raw(tex), pts))
+    }
+
+    pub fn set_bitrate(&self, kbs: i32) -> Result<(), i32> {
+        self.send(Command::SetBitrate(kbs))
+    }
+
+    pub fn set_framerate(&self, framerate: i32) -> Result<(), i32> {
+        self.send(Command::SetFramerate(framerate))
+    }
+
+    fn send(&self, command: Command) -> Result<(), i32> {
+        match self.tx.try_send(command) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(ERR_QUEUE_FULL),
+            // 工作线程已退出（仅在其 panic 时发生）
+            Err(TrySendError::Disconnected(_)) => Err(-1),
+        }
+    }
+}
+
+/// Output side of `AsyncEncoder::into_stream`: packets in decode order, which differs from
+/// submission order with B-frames, or the error code of a failed submit/receive. Ends after the sink is dropped and all packets are out. Dropping the
+/// stream stops the worker, which flushes the frames in flight and discards their packets.
+pub struct PacketStream {
+    shared: Arc<Shared<EncodeFrame>>,
+}
+
+impl Stream for PacketStream {
+    type Item = Result<EncodeFrame, i32>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.poll_next(cx)
+    }
+}
+
+impl Drop for PacketStream {
+    fn drop(&mut self) {
+        self.shared.cancel();
+    }
+}
+
+impl AsyncEncoder {
+    /// Moves the encoder to a worker thread and splits it into a sink for textures and a stream
+    /// of packets.
+    pub fn into_stream(self) -> (FrameSink, PacketStream) {
+        let (tx, rx) = mpsc::sync_channel(self.depth());
+        let shared = Arc::new(Shared::new());
+        let worker = shared.clone();
+        std::thread::spawn(move || run(self, rx, worker));
+        (FrameSink { tx }, PacketStream { shared })
+    }
+}
+
+fn run(mut encoder: AsyncEncoder, rx: Receiver<Command>, shared: Arc<Shared<EncodeFrame>>) {
+    loop {
+        // 输出端已丢弃；空闲时要等下一条命令或输入端关闭才会发现
+        if shared.cancelled() {
+            break;
+        }
+        // 有在途帧时轮询驱动，否则阻塞等待下一条命令
+        let command = if encoder.in_flight() > 0 {
+            match rx.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match rx.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            }
+        };
+        match command {
+            Some(Command::Submit(tex, pts)) => {
+                // 驱动队列满时先取回最早的包腾出位置
+                while encoder.in_flight() >= encoder.depth() {
+                    match encoder.receive(POLL_INTERVAL) {
+                        Ok(Some(packet)) => shared.push(Ok(packet)),
+                        Ok(None) => {}
+                        Err(e) => {
+                            shared.push(Err(e));
+                            break;
+                        }
+                    }
+                }
+                let result = match tex {
+                    Input::Raw(tex) => encoder.submit_raw(tex, pts),
+                    #[cfg(windows)]
+                    Input::Texture(tex) => encoder.submit(&tex, pts),
+                };
+                if let Err(e) = result {
+                    shared.push(Err(e));
+                }
+            }
+            Some(Command::SetBitrate(kbs)) => {
+                if let Err(e) = encoder.set_bitrate(kbs) {
+                    shared.push(Err(e));
+                }
+            }
+            Some(Command::SetFramerate(framerate)) => {
+                if let Err(e) = encoder.set_framerate(framerate) {
+                    shared.push(Err(e));
+                }
+            }
+            None => match encoder.receive(POLL_INTERVAL) {
+                Ok(Some(packet)) => shared.push(Ok(packet)),
+                Ok(None) => {}
+                Err(e) => shared.push(Err(e)),
+            },
+        }
+    }
+    match encoder.flush() {
+        Ok(packets) => packets.into_iter().for_each(|p| shared.push(Ok(p))),
+        Err(e) => shared.push(Err(e)),
+    }
+    drop(encoder);
+    shared.close();
+}
+
+/// Input side of `AsyncDecoder::into_stream`.
+pub struct PacketSink {
+    tx: SyncSender<Vec<u8>>,
+}
+
+impl PacketSink {
+    /// Queues a copy of `packet` for decoding. Returns `ERR_QUEUE_FULL` when the worker is
+    /// `pool_size` packets behind, which happens once the consumer holds every surface.
+    pub fn submit(&self, packet: &[u8]) -> Result<(), i32> {
+        match self.tx.try_send(packet.to_vec()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(ERR_QUEUE_FULL),
+            Err(TrySendError::Disconnected(_)) => Err(-1),
+        }
+    }
+}
+
+/// Output side of `AsyncDecoder::into_stream`: frames in display order, or the error code of a
+/// failed packet. Once the sink is dropped the decoder is flushed, and the stream ends when all
+/// frames are out. Dropping the stream stops the worker and returns the frames not yet taken
+/// to the pool.
+pub struct FrameStream {
+    shared: Arc<Shared<PooledFrame>>,
+}
+
+impl Stream for FrameStream {
+    type Item = Result<PooledFrame, i32>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.poll_next(cx)
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        self.shared.cancel();
+    }
+}
+
+impl AsyncDecoder {
+    /// Moves the decoder to a worker thread and splits it into a sink for packets and a stream
+    /// of frames.
+    pub fn into_stream(self) -> (PacketSink, FrameStream) {
+        let (tx, rx) = mpsc::sync_channel(self.pool_size());
+        let shared = Arc::new(Shared::new());
+        let worker = shared.clone();
+        std::thread::spawn(move || run_decoder(self, rx, worker));
+        (PacketSink { tx }, FrameStream { shared })
+    }
+}
+
+fn run_decoder(mut decoder: AsyncDecoder, rx: Receiver<Vec<u8>>, shared: Arc<Shared<PooledFrame>>) {
+    let mut ended = true;
+    while let Ok(packet) = rx.recv() {
+        if !retry(&mut decoder, &shared, |d| d.submit(&packet))
+            || !deliver(&mut decoder, &shared, Duration::ZERO)
+        {
+            ended = false;
+            break;
+        }
+    }
+    // 输入端关闭：送出驱动为重排扣住的帧；输出端已丢弃时不再冲刷
+    if ended {
+        retry(&mut decoder, &shared, AsyncDecoder::flush);
+    }
+    while decoder.buffered() > 0 && deliver(&mut decoder, &shared, POLL_INTERVAL) {}
+    drop(decoder);
+    shared.close();
+}
+
+// 表面全被消费者持有时交付已就绪的帧，等其释放后重试；输出端已丢弃时返回 false
+fn retry(
+    decoder: &mut AsyncDecoder,
+    shared: &Shared<PooledFrame>,
+    mut op: impl FnMut(&mut AsyncDecoder) -> Result<(), i32>,
+) -> bool {
+    loop {
+        match op(decoder) {
+            Err(ERR_QUEUE_FULL) => {}
+            Ok(()) => return true,
+            Err(e) => {
+                shared.push(Err(e));
+                return true;
+            }
+        }
+        if !deliver(decoder, shared, POLL_INTERVAL) {
+            return false;
+        }
+    }
+}
+
+// 交付已就绪的帧，必要时等待空闲表面；输出端已丢弃时返回 false
+fn deliver(decoder: &mut AsyncDecoder, shared: &Shared<PooledFrame>, timeout: Duration) -> bool {
+    loop {
+        if shared.cancelled() {
+            return false;
+        }
+        match decoder.receive(timeout) {
+            Ok(Some(frame)) => shared.push(Ok(frame)),
+            Ok(None) => return true,
+            Err(e) => shared.push(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::DataFormat;
+    use crate::vram::decode_pipeline::tests as decode;
+    use crate::vram::pipeline::tests::MockBackend;
+    use std::time::Instant;
+
+    fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let start = Instant::now();
+        loop {
+            if let Poll::Ready(item) = Pin::new(&mut *stream).poll_next(&mut cx) {
+                return item;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "stream stalled");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// 测试经工作线程编码后按提交顺序输出，关闭输入后流结束
+    #[test]
+    fn test_stream() {
+        let (backend, gpu) = MockBackend::new(true);
+        let encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 2);
+        let (sink, mut stream) = encoder.into_stream();
+
+        for pts in 0..2 {
+            sink.submit_raw(std::ptr::null_mut(), pts).unwrap();
+        }
+        assert_eq!(next(&mut stream).map(|p| p.map(|f| f.pts)), Some(Ok(0)));
+        assert_eq!(next(&mut stream).map(|p| p.map(|f| f.pts)), Some(Ok(1)));
+
+        gpu.lock().unwrap().fail = vec![3];
+        for pts in 2..5 {
+            while sink.submit_raw(std::ptr::null_mut(), pts) == Err(ERR_QUEUE_FULL) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        drop(sink);
+        let rest: Vec<_> = std::iter::from_fn(|| next(&mut stream))
+            .map(|p| p.map(|f| f.pts))
+            .collect();
+        assert_eq!(rest, vec![Ok(2), Err(-1), Ok(4)]);
+        assert!(gpu.lock().unwrap().destroyed);
+    }
+
+    /// 测试输入端仍在时丢弃输出流，工作线程冲刷在途帧后退出，之后提交返回错误
+    #[test]
+    fn test_drop_stream() {
+        let (backend, gpu) = MockBackend::new(true);
+        let encoder = AsyncEncoder::with_backend(Box::new(backend), DataFormat::H264, 2);
+        let (sink, stream) = encoder.into_stream();
+        for pts in 0..2 {
+            sink.submit_raw(std::ptr::null_mut(), pts).unwrap();
+        }
+        drop(stream);
+
+        let start = Instant::now();
+        while !gpu.lock().unwrap().destroyed {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "worker did not exit"
+            );
+            // 工作线程空闲时靠新命令唤醒
+            sink.submit_raw(std::ptr::null_mut(), 2).ok();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let start = Instant::now();
+        while sink.submit_raw(std::ptr::null_mut(), 3) != Err(-1) {
+            assert!(start.elapsed() < Duration::from_secs(5), "sink still open");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// 测试关闭输入端后冲刷解码器，驱动为重排扣住的帧也从流中送出
+    #[test]
+    fn test_decode_stream_flush() {
+        let mut backend = decode::MockBackend::new();
+        backend.hold = 2;
+        let allocator = decode::MockAllocator::default();
+        let decoder = AsyncDecoder::with_backend(Box::new(backend), Box::new(allocator), 4);
+        let (sink, mut stream) = decoder.into_stream();
+        for _ in 0..3 {
+            sink.submit(&[1]).unwrap();
+        }
+        drop(sink);
+        let ids: Vec<_> = std::iter::from_fn(|| next(&mut stream))
+            .map(|f| decode::id(&f.unwrap()))
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    /// 测试解码流按顺序输出帧；消费者持有全部表面时反压，丢弃流后工作线程退出
+    #[test]
+    fn test_decode_stream() {
+        let backend = decode::MockBackend::new();
+        let destroyed = backend.destroyed.clone();
+        let allocator = decode::MockAllocator::default();
+        let live = allocator.live.clone();
+        let decoder = AsyncDecoder::with_backend(Box::new(backend), Box::new(allocator), 2);
+        let (sink, mut stream) = decoder.into_stream();
+
+        sink.submit(&[2]).unwrap();
+        let held: Vec<_> = (0..2)
+            .map(|_| next(&mut stream).unwrap().unwrap())
+            .collect();
+        assert_eq!(held.iter().map(decode::id).collect::<Vec<_>>(), vec![1, 2]);
+
+        // 表面用尽：工作线程阻塞，输入队列随之填满
+        let start = Instant::now();
+        let mut queued = 0;
+        while start.elapsed() < Duration::from_secs(5) {
+            match sink.submit(&[1]) {
+                Ok(()) => queued += 1,
+                Err(ERR_QUEUE_FULL) => break,
+                Err(e) => panic!("unexpected error {}", e),
+            }
+        }
+        // 最多：已解码待表面的一个包、工作线程手中一个、通道中两个
+        assert!(queued <= 4);
+        drop(held);
+        assert_eq!(next(&mut stream).unwrap().map(|f| decode::id(&f)), Ok(3));
+
+        sink.submit(&[]).ok();
+        drop(sink);
+        drop(stream);
+        let start = Instant::now();
+        while !*destroyed.lock().unwrap() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "worker did not exit"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        while *live.lock().unwrap() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "surfaces leaked");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,424 @@
+//! YUV4MPEG2 (`.y4m`) reader and writer for raw test sequences.
+//!
+//! Supports 4:2:0, 4:2:2 and 4:4:4 at 8 and 10 bits (`C420jpeg`, `C422`, `C444`, `C420p10`,
+//! `C422p10`, `C444p10`) and the `XCOLORRANGE` tag. Interlacing, aspect ratio and other `X`
+//! tags are read and ignored. 10-bit samples are two bytes, little-endian, as in `Frame`.
+
+use crate::common::BitDepth;
+use crate::convert::Range;
+use crate::frame::{Frame, Subsampling};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use thiserror::Error;
+
+const MAGIC: &str = "YUV4MPEG2";
+// 头和帧头都是一行文本，超长时视为损坏
+const MAX_LINE: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum Y4mError {
+    #[error("y4m I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid y4m header: {0}")]
+    InvalidHeader(String),
+
+    #[error("unsupported y4m colour space {0}")]
+    UnsupportedColorSpace(String),
+
+    #[error("y4m stream ends inside a frame")]
+    Truncated,
+
+    #[error("frame does not match the stream: {0}")]
+    FrameMismatch(String),
+}
+
+/// Stream header: the parameters shared by every frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Y4mHeader {
+    pub width: usize,
+    pub height: usize,
+    /// Frames per second as numerator and denominator.
+    pub framerate: (u32, u32),
+    pub subsampling: Subsampling,
+    pub bit_depth: BitDepth,
+    /// `XCOLORRANGE`; frames of streams without it are read as limited range.
+    pub range: Option<Range>,
+}
+
+impl Y4mHeader {
+    /// Header for a stream of frames shaped like `frame`.
+    pub fn for_frame(frame: &Frame, framerate: (u32, u32)) -> Self {
+        Self {
+            width: frame.width,
+            height: frame.height,
+            framerate,
+            subsampling: frame.subsampling,
+            bit_depth: frame.bit_depth,
+            range: Some(frame.range),
+        }
+    }
+
+    pub fn frame_size(&self) -> usize {
+        Frame::frame_size(self.width, self.height, self.subsampling, self.bit_depth)
+    }
+
+    fn parse(line: &str) -> Result<Self, Y4mError> {
+        let invalid = |what: &str| Y4mError::InvalidHeader(what.to_string());
+        let mut tokens = line.split(' ').filter(|t| !t.is_empty());
+        if tokens.next() != Some(MAGIC) {
+            return Err(invalid("missing YUV4MPEG2 signature"));
+        }
+        let (mut width, mut height) = (None, None);
+        let mut header = Self {
+            width: 0,
+            height: 0,
+            framerate: (30, 1),
+            subsampling: Subsampling::Yuv420,
+            bit_depth: BitDepth::Eight,
+            range: None,
+        };
+        for token in tokens {
+            let mut chars = token.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = Some(value.parse().map_err(|_| invalid(token))?),
+                Some('H') => height = Some(value.parse().map_err(|_| invalid(token))?),
+                Some('F') => {
+                    let (num, den) = value.split_once(':').ok_or_else(|| invalid(token))?;
+                    let num = num.parse().map_err(|_| invalid(token))?;
+                    let den = den.parse().map_err(|_| invalid(token))?;
+                    if num == 0 || den == 0 {
+                        return Err(invalid(token));
+                    }
+                    header.framerate = (num, den);
+                }
+                Some('C') => (header.subsampling, header.bit_depth) = parse_color_space(value)?,
+                Some('X') => match value {
+                    "COLORRANGE=LIMITED" => header.range = Some(Range::Limited),
+                    "COLORRANGE=FULL" => header.range = Some(Range::Full),
+                    _ => {}
+                },
+                // I（交织）、A（像素宽高比）不影响数据布局
+                _ => {}
+            }
+        }
+        match (width, height) {
+            (Some(w), Some(h)) if w > 0 && h > 0 => {
+                header.width = w;
+                header.height = h;
+                Ok(header)
+            }
+            _ => Err(invalid("missing W or H")),
+        }
+    }
+
+    fn to_line(self) -> String {
+        let color = match (self.subsampling, self.bit_depth) {
+            (Subsampling::Yuv420, BitDepth::Eight) => "420jpeg",
+            (Subsampling::Yuv422, BitDepth::Eight) => "422",
+            (Subsampling::Yuv444, BitDepth::Eight) => "444",
+            (Subsampling::Yuv420, BitDepth::Ten) => "420p10",
+            (Subsampling::Yuv422, BitDepth::Ten) => "422p10",
+            (Subsampling::Yuv444, BitDepth::Ten) => "444p10",
+        };
+        let mut line = format!(
+            "{} W{} H{} F{}:{} Ip A1:1 C{}",
+            MAGIC, self.width, self.height, self.framerate.0, self.framerate.1, color
+        );
+        match self.range {
+            Some(Range::Limited) => line.push_str(" XCOLORRANGE=LIMITED"),
+            Some(Range::Full) => line.push_str(" XCOLORRANGE=FULL"),
+            None => {}
+        }
+        line.push('\n');
+        line
+    }
+}
+
+fn parse_color_space(value: &str) -> Result<(Subsampling, BitDepth), Y4mError> {
+    Ok(match value {
+        "420" | "420jpeg" | "420paldv" | "420mpeg2" => (Subsampling::Yuv420, BitDepth::Eight),
+        "422" => (Subsampling::Yuv422, BitDepth::Eight),
+        "444" => (Subsampling::Yuv444, BitDepth::Eight),
+        "420p10" => (Subsampling::Yuv420, BitDepth::Ten),
+        "422p10" => (Subsampling::Yuv422, BitDepth::Ten),
+        "444p10" => (Subsampling::Yuv444, BitDepth::Ten),
+        _ => return Err(Y4mError::UnsupportedColorSpace(value.to_string())),
+    })
+}
+
+/// Reads frames from a Y4M stream. Wrap unbuffered sources in a `BufReader`.
+pub struct Y4mReader<R> {
+    inner: R,
+    header: Y4mHeader,
+}
+
+impl Y4mReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Y4mError> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Y4mReader<R> {
+    /// Reads the stream header.
+    pub fn new(mut inner: R) -> Result<Self, Y4mError> {
+        let line = read_line(&mut inner)?
+            .ok_or_else(|| Y4mError::InvalidHeader("empty stream".to_string()))?;
+        let header = Y4mHeader::parse(&line)?;
+        Ok(Self { inner, header })
+    }
+
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    /// The next frame, or `None` at the end of the stream.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>, Y4mError> {
+        let Some(line) = read_line(&mut self.inner)? else {
+            return Ok(None);
+        };
+        // FRAME 后可带参数，忽略
+        if line.split(' ').next() != Some("FRAME") {
+            return Err(Y4mError::InvalidHeader(format!(
+                "expected FRAME, got {:?}",
+                line
+            )));
+        }
+        let h = self.header;
+        let mut frame = Frame::new(
+            h.width,
+            h.height,
+            h.subsampling,
+            h.bit_depth,
+            h.range.unwrap_or_default(),
+        );
+        self.inner
+            .read_exact(&mut frame.data)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => Y4mError::Truncated,
+                _ => Y4mError::Io(e),
+            })?;
+        Ok(Some(frame))
+    }
+}
+
+impl<R: Read> Iterator for Y4mReader<R> {
+    type Item = Result<Frame, Y4mError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Writes frames to a Y4M stream. Wrap unbuffered sinks in a `BufWriter`.
+pub struct Y4mWriter<W> {
+    inner: W,
+    header: Y4mHeader,
+}
+
+impl Y4mWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, header: Y4mHeader) -> Result<Self, Y4mError> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header.
+    pub fn new(mut inner: W, header: Y4mHeader) -> Result<Self, Y4mError> {
+        inner.write_all(header.to_line().as_bytes())?;
+        Ok(Self { inner, header })
+    }
+
+    pub fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    /// Appends `frame`, which must have the size, subsampling and bit depth of the header.
+    /// Its range is not checked; the header's `XCOLORRANGE` applies to every frame.
+    pub fn write_frame(&mut self, frame: &Frame) -> Result<(), Y4mError> {
+        let h = &self.header;
+        if (
+            frame.width,
+            frame.height,
+            frame.subsampling,
+            frame.bit_depth,
+        ) != (h.width, h.height, h.subsampling, h.bit_depth)
+        {
+            return Err(Y4mError::FrameMismatch(format!(
+                "{}x{} {:?} {:?}, expected {}x{} {:?} {:?}",
+                frame.width,
+                frame.height,
+                frame.subsampling,
+                frame.bit_depth,
+                h.width,
+                h.height,
+                h.subsampling,
+                h.bit_depth
+            )));
+        }
+        self.inner.write_all(b"FRAME\n")?;
+        self.inner.write_all(&frame.data)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Y4mError> {
+        Ok(self.inner.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// 读一行（不含换行符）；流在行首结束时返回 `None`
+fn read_line(reader: &mut impl Read) -> Result<Option<String>, Y4mError> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            return Err(Y4mError::Truncated);
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        if line.len() == MAX_LINE {
+            return Err(Y4mError::InvalidHeader("line too long".to_string()));
+        }
+        line.push(byte[0]);
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| Y4mError::InvalidHeader("not ASCII".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(subsampling: Subsampling, bit_depth: BitDepth, range: Range, seed: u16) -> Frame {
+        let mut frame = Frame::new(6, 4, subsampling, bit_depth, range);
+        let max = (1u16 << bit_depth.bits()) - 1;
+        for plane in 0..3 {
+            let (w, h) = frame.plane_size(plane);
+            for y in 0..h {
+                for x in 0..w {
+                    let value = (seed + (plane * 97 + y * 31 + x * 7) as u16 * 5) % (max + 1);
+                    frame.set_sample(plane, x, y, value);
+                }
+            }
+        }
+        frame
+    }
+
+    /// 测试六种布局与颜色范围的写入、读回
+    #[test]
+    fn test_round_trip() {
+        for subsampling in [
+            Subsampling::Yuv420,
+            Subsampling::Yuv422,
+            Subsampling::Yuv444,
+        ] {
+            for bit_depth in [BitDepth::Eight, BitDepth::Ten] {
+                for range in [Range::Limited, Range::Full] {
+                    let frames: Vec<_> = (0..3)
+                        .map(|i| pattern(subsampling, bit_depth, range, i * 100))
+                        .collect();
+                    let header = Y4mHeader::for_frame(&frames[0], (30000, 1001));
+                    let mut writer = Y4mWriter::new(Vec::new(), header).unwrap();
+                    for frame in &frames {
+                        writer.write_frame(frame).unwrap();
+                    }
+                    let bytes = writer.into_inner();
+                    assert_eq!(
+                        bytes.len(),
+                        header.to_line().len() + 3 * (6 + header.frame_size())
+                    );
+
+                    let reader = Y4mReader::new(bytes.as_slice()).unwrap();
+                    assert_eq!(*reader.header(), header);
+                    let read: Vec<_> = reader.map(Result::unwrap).collect();
+                    assert_eq!(read, frames);
+                }
+            }
+        }
+    }
+
+    /// 测试解析其他工具写出的头与帧头
+    #[test]
+    fn test_parse() {
+        let header =
+            Y4mHeader::parse("YUV4MPEG2 W352 H288 F25:1 It A128:117 C420mpeg2 XYSCSS=420MPEG2")
+                .unwrap();
+        assert_eq!(
+            (header.width, header.height, header.framerate),
+            (352, 288, (25, 1))
+        );
+        assert_eq!(
+            (header.subsampling, header.bit_depth, header.range),
+            (Subsampling::Yuv420, BitDepth::Eight, None)
+        );
+        let header = Y4mHeader::parse("YUV4MPEG2 C444p10 H2 W2 XCOLORRANGE=FULL").unwrap();
+        assert_eq!(
+            (header.subsampling, header.bit_depth, header.range),
+            (Subsampling::Yuv444, BitDepth::Ten, Some(Range::Full))
+        );
+        assert_eq!(header.frame_size(), 3 * 4 * 2);
+
+        // 没有 XCOLORRANGE 时按有限范围读
+        let mut data = b"YUV4MPEG2 W2 H2 F30:1 C420jpeg\nFRAME Ixyz\n".to_vec();
+        data.extend([16, 17, 18, 19, 128, 129]);
+        let mut reader = Y4mReader::new(data.as_slice()).unwrap();
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(
+            (frame.range, frame.sample(0, 1, 1), frame.sample(2, 0, 0)),
+            (Range::Limited, 19, 129)
+        );
+        assert!(reader.read_frame().unwrap().is_none());
+    }
+
+    /// 测试损坏与不支持的输入
+    #[test]
+    fn test_errors() {
+        let err = |data: &[u8]| Y4mReader::new(data).err().unwrap();
+        assert!(matches!(err(b""), Y4mError::InvalidHeader(_)));
+        assert!(matches!(
+            err(b"YUV4MPEG W2 H2\n"),
+            Y4mError::InvalidHeader(_)
+        ));
+        assert!(matches!(err(b"YUV4MPEG2 W2\n"), Y4mError::InvalidHeader(_)));
+        assert!(matches!(
+            err(b"YUV4MPEG2 W2 H2 F30:0\n"),
+            Y4mError::InvalidHeader(_)
+        ));
+        assert!(matches!(
+            err(b"YUV4MPEG2 W2 H2 Cmono\n"),
+            Y4mError::UnsupportedColorSpace(_)
+        ));
+        assert!(matches!(err(b"YUV4MPEG2 W2 H2"), Y4mError::Truncated));
+
+        let mut reader = Y4mReader::new(&b"YUV4MPEG2 W2 H2\nFRAME\n\x10\x10"[..]).unwrap();
+        assert!(matches!(reader.read_frame(), Err(Y4mError::Truncated)));
+        let mut reader = Y4mReader::new(&b"YUV4MPEG2 W2 H2\nFRAMX\n"[..]).unwrap();
+        assert!(matches!(
+            reader.read_frame(),
+            Err(Y4mError::InvalidHeader(_))
+        ));
+
+        let header = Y4mHeader::for_frame(
+            &pattern(Subsampling::Yuv420, BitDepth::Eight, Range::Full, 0),
+            (30, 1),
+        );
+        let mut writer = Y4mWriter::new(Vec::new(), header).unwrap();
+        let other = pattern(Subsampling::Yuv422, BitDepth::Eight, Range::Full, 0);
+        assert!(matches!(
+            writer.write_frame(&other),
+            Err(Y4mError::FrameMismatch(..))
+        ));
+    }
+}